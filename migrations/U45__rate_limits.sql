@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS rate_limits (key TEXT PRIMARY KEY
+,   tokens REAL NOT NULL
+,   updated REAL NOT NULL
+,   failures INTEGER NOT NULL DEFAULT 0
+,   blocked_until REAL NOT NULL DEFAULT 0
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS totp (username TEXT PRIMARY KEY
+,   secret TEXT NOT NULL
+,   confirmed INTEGER NOT NULL DEFAULT 0
+,   last_step INTEGER
+,   FOREIGN KEY (username) REFERENCES users(username) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS recovery_codes (hash TEXT NOT NULL
+,   username TEXT NOT NULL
+,   PRIMARY KEY (hash, username)
+,   FOREIGN KEY (username) REFERENCES totp(username) ON DELETE CASCADE ON UPDATE CASCADE
+);
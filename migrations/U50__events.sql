@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS events (id INTEGER PRIMARY KEY AUTOINCREMENT
+,   resource TEXT NOT NULL
+,   action TEXT NOT NULL
+,   target TEXT NOT NULL
+,   created INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
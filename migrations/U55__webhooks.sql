@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS webhooks (id TEXT PRIMARY KEY
+,   url TEXT NOT NULL
+,   secret TEXT NOT NULL
+,   creator TEXT NOT NULL
+,   FOREIGN KEY (creator) REFERENCES users(username) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS webhook_events (event TEXT NOT NULL
+,   webhook_id TEXT NOT NULL
+,   PRIMARY KEY (event, webhook_id)
+,   FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (id INTEGER PRIMARY KEY AUTOINCREMENT
+,   webhook_id TEXT NOT NULL
+,   event TEXT NOT NULL
+,   payload TEXT NOT NULL
+,   attempts INTEGER NOT NULL DEFAULT 0
+,   status INTEGER
+,   error TEXT
+,   delivered INTEGER NOT NULL DEFAULT 0
+,   next_attempt INTEGER DEFAULT (strftime('%s', 'now'))
+,   created INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+,   FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS audio_blobs (hash TEXT PRIMARY KEY
+,   size INTEGER NOT NULL
+,   mime TEXT NOT NULL
+,   created INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+,   verified INTEGER
+,   corrupt INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS track_audio (track_id TEXT PRIMARY KEY
+,   hash TEXT NOT NULL
+,   uploaded INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+,   FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (hash) REFERENCES audio_blobs(hash)
+);
+CREATE INDEX IF NOT EXISTS track_audio_hash ON track_audio (hash);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS upload_sessions (id TEXT PRIMARY KEY
+,   username TEXT NOT NULL
+,   track_id TEXT NOT NULL
+,   size INTEGER NOT NULL
+,   received INTEGER NOT NULL DEFAULT 0
+,   sha256 TEXT NOT NULL
+,   mime TEXT NOT NULL
+,   updated INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+,   FOREIGN KEY (username) REFERENCES users(username) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,4 @@
+ALTER TABLE users ADD COLUMN quota INTEGER;
+ALTER TABLE invites ADD COLUMN quota INTEGER;
+ALTER TABLE track_audio ADD COLUMN uploader TEXT REFERENCES users(username) ON DELETE SET NULL ON UPDATE CASCADE;
+CREATE INDEX IF NOT EXISTS track_audio_uploader ON track_audio (uploader);
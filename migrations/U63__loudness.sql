@@ -0,0 +1,4 @@
+ALTER TABLE audio_blobs ADD COLUMN analyzed INTEGER;
+ALTER TABLE audio_blobs ADD COLUMN loudness REAL;
+ALTER TABLE audio_blobs ADD COLUMN true_peak REAL;
+ALTER TABLE audio_blobs ADD COLUMN loudness_blocks INTEGER;
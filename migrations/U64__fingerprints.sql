@@ -0,0 +1,3 @@
+ALTER TABLE audio_blobs ADD COLUMN fingerprint BLOB;
+-- analyzed again to fingerprint them
+UPDATE audio_blobs SET analyzed = NULL;
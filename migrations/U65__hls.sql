@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS signing_key (id INTEGER PRIMARY KEY CHECK (id = 0)
+,   key BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS hls_segments (hash TEXT NOT NULL
+,   segment INTEGER NOT NULL
+,   duration REAL NOT NULL
+,   size INTEGER NOT NULL
+,   PRIMARY KEY (hash, segment)
+,   FOREIGN KEY (hash) REFERENCES audio_blobs(hash) ON DELETE CASCADE
+);
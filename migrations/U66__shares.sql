@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS shares (id TEXT PRIMARY KEY
+,   creator TEXT NOT NULL
+,   track_id TEXT
+,   album_id TEXT
+,   expires INTEGER NOT NULL
+,   max_plays INTEGER
+,   plays INTEGER NOT NULL DEFAULT 0
+,   created INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+,   CHECK ((track_id IS NULL) != (album_id IS NULL))
+,   FOREIGN KEY (creator) REFERENCES users(username) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (album_id) REFERENCES albums(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
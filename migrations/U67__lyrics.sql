@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS track_lyrics (track_id TEXT NOT NULL
+,   language TEXT NOT NULL
+,   line INTEGER NOT NULL
+,   time INTEGER
+,   text TEXT NOT NULL
+,   PRIMARY KEY (track_id, language, line)
+,   FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS genre_parents (genre_id TEXT NOT NULL
+,   parent_id TEXT NOT NULL
+,   PRIMARY KEY (genre_id, parent_id)
+,   CHECK (genre_id != parent_id)
+,   FOREIGN KEY (genre_id) REFERENCES genres(id) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (parent_id) REFERENCES genres(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS genre_parents_parent ON genre_parents (parent_id);
+
+CREATE TABLE IF NOT EXISTS genre_aliases (alias TEXT PRIMARY KEY COLLATE NOCASE
+,   genre_id TEXT NOT NULL
+,   FOREIGN KEY (genre_id) REFERENCES genres(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,22 @@
+ALTER TABLE artists ADD COLUMN sort_name TEXT;
+
+CREATE TABLE IF NOT EXISTS artist_aliases (artist_id TEXT NOT NULL
+,   name TEXT NOT NULL
+,   kind TEXT NOT NULL DEFAULT 'alias'
+,   locale TEXT
+,   PRIMARY KEY (artist_id, name)
+,   FOREIGN KEY (artist_id) REFERENCES artists(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS artist_aliases_name ON artist_aliases (name COLLATE NOCASE);
+
+CREATE TABLE IF NOT EXISTS artist_relations (artist_id TEXT NOT NULL
+,   related_id TEXT NOT NULL
+,   kind TEXT NOT NULL
+,   PRIMARY KEY (artist_id, related_id, kind)
+,   CHECK (artist_id != related_id)
+,   FOREIGN KEY (artist_id) REFERENCES artists(id) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (related_id) REFERENCES artists(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS artist_relations_related ON artist_relations (related_id);
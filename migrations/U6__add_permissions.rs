@@ -1,12 +1,7 @@
-use crate::api::data::permissions::Permission;
-use strum::IntoEnumIterator;
-
+/// The permissions available when this migration was written.
+///
+/// This must never change or the checksum won't match existing databases,
+/// permissions added later are inserted by `MyDatabase::migrations`.
 pub fn migration() -> String {
-    format!(
-        "INSERT OR IGNORE INTO permissions (id) VALUES ('{}');",
-        Permission::iter()
-            .map(|p| p.to_string())
-            .collect::<Vec<String>>()
-            .join("'), ('")
-    )
+    "INSERT OR IGNORE INTO permissions (id) VALUES ('DocsRead'), ('InviteWrite'), ('InviteRead'), ('InviteDelete'), ('UserRead'), ('UserDelete'), ('PermissionAdd'), ('PermissionDelete'), ('TokenDelete'), ('GenreWrite'), ('GenreRead'), ('GenreDelete'), ('ArtistWrite'), ('ArtistRead'), ('ArtistDelete'), ('AlbumWrite'), ('AlbumRead'), ('AlbumDelete'), ('TrackWrite'), ('TrackRead'), ('TrackDelete'), ('AudioWrite'), ('AudioRead'), ('AudioDelete');".to_string()
 }
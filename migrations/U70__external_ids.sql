@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS external_ids (source TEXT NOT NULL
+,   value TEXT NOT NULL
+,   artist_id TEXT
+,   album_id TEXT
+,   track_id TEXT
+,   CHECK ((artist_id IS NOT NULL) + (album_id IS NOT NULL) + (track_id IS NOT NULL) = 1)
+,   FOREIGN KEY (artist_id) REFERENCES artists(id) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (album_id) REFERENCES albums(id) ON DELETE CASCADE ON UPDATE CASCADE
+,   FOREIGN KEY (track_id) REFERENCES tracks(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- an id is unique per source & kind of resource, Discogs numbers artists & releases separately
+CREATE UNIQUE INDEX IF NOT EXISTS external_ids_artist ON external_ids (source, value) WHERE artist_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS external_ids_album ON external_ids (source, value) WHERE album_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS external_ids_track ON external_ids (source, value) WHERE track_id IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS external_ids_artist_id ON external_ids (artist_id);
+CREATE INDEX IF NOT EXISTS external_ids_album_id ON external_ids (album_id);
+CREATE INDEX IF NOT EXISTS external_ids_track_id ON external_ids (track_id);
@@ -0,0 +1,16 @@
+-- responses of the metadata service by url, a 404 is cached with an empty body
+CREATE TABLE IF NOT EXISTS enrichment_cache (url TEXT PRIMARY KEY
+,   status INTEGER NOT NULL
+,   body TEXT NOT NULL
+,   fetched INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS enrichment_suggestions (id INTEGER PRIMARY KEY AUTOINCREMENT
+,   resource TEXT NOT NULL
+,   target TEXT NOT NULL
+,   change TEXT NOT NULL
+,   created INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+,   UNIQUE (resource, target, change)
+);
+
+CREATE INDEX IF NOT EXISTS enrichment_suggestions_target ON enrichment_suggestions (resource, target);
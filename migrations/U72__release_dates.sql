@@ -0,0 +1,22 @@
+-- release dates are ISO 8601 dates with year, year-month or day precision (`1997`, `1997-05`, `1997-05-21`), NULL instead of 0
+-- the text sorts chronologically & a date's prefix is its year or month
+ALTER TABLE albums ADD COLUMN release_date TEXT;
+UPDATE albums SET release_date = printf('%04d', release) WHERE release > 0;
+ALTER TABLE albums DROP COLUMN release;
+ALTER TABLE albums RENAME COLUMN release_date TO release;
+
+ALTER TABLE tracks ADD COLUMN release_date TEXT;
+UPDATE tracks SET release_date = printf('%04d', release) WHERE release > 0;
+ALTER TABLE tracks DROP COLUMN release;
+ALTER TABLE tracks RENAME COLUMN release_date TO release;
+
+-- `album`, `ep`, `single`, `live`, `compilation`, `soundtrack`, `remix` or `other`
+ALTER TABLE albums ADD COLUMN kind TEXT NOT NULL DEFAULT 'album';
+ALTER TABLE albums ADD COLUMN label TEXT;
+ALTER TABLE albums ADD COLUMN catalog_number TEXT;
+-- an edition (reissue, remaster, deluxe, ...) links to the original, never to another edition
+ALTER TABLE albums ADD COLUMN edition_of TEXT CHECK (edition_of != id) REFERENCES albums(id) ON DELETE SET NULL ON UPDATE CASCADE;
+
+CREATE INDEX IF NOT EXISTS albums_release ON albums (release);
+CREATE INDEX IF NOT EXISTS albums_edition_of ON albums (edition_of);
+CREATE INDEX IF NOT EXISTS tracks_release ON tracks (release);
@@ -0,0 +1,12 @@
+-- segments of the variants transcoded from a blob are kept by their bitrate (kbit/s), the blob's own are bitrate 0
+-- the cached segments are dropped to be split again with their sample rate
+DROP TABLE IF EXISTS hls_segments;
+CREATE TABLE IF NOT EXISTS hls_segments (hash TEXT NOT NULL
+,   bitrate INTEGER NOT NULL
+,   segment INTEGER NOT NULL
+,   duration REAL NOT NULL
+,   size INTEGER NOT NULL
+,   rate INTEGER NOT NULL
+,   PRIMARY KEY (hash, bitrate, segment)
+,   FOREIGN KEY (hash) REFERENCES audio_blobs(hash) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- expired responses of the metadata service are deleted by age
+CREATE INDEX IF NOT EXISTS enrichment_cache_fetched ON enrichment_cache (fetched);
@@ -1,15 +1,498 @@
-use rocket::serde::{Deserialize, Serialize};
+use async_graphql::{
+    Enum, InputObject, InputValueError, InputValueResult, Scalar, ScalarType, Value,
+};
+use rocket::{
+    http::Status,
+    serde::{de, Deserialize, Deserializer, Serialize, Serializer},
+};
+use rocket_sync_db_pools::rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Type, ValueRef},
+    Connection, Error, OptionalExtension, Row,
+};
+use std::{fmt, str::FromStr};
+use strum::{Display, EnumString, IntoStaticStr};
+use utoipa::ToSchema;
 
+use crate::{
+    api::data::{audio::Loudness, events::Resource, external_ids::ExternalSource},
+    error::ApiError,
+    external_ids, genres,
+};
+
+/// An ISO 8601 date with year, year-month or day precision: `1997`, `1997-05` or `1997-05-21`.
+///
+/// Stored as that text, so dates sort chronologically & a date's prefix is its year or month.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReleaseDate {
+    pub year: u16,
+    pub month: Option<u8>,
+    /// Only with a month
+    pub day: Option<u8>,
+}
+
+impl ReleaseDate {
+    /// `None` if the month or day don't exist, a day needs a month & the year can't be 0.
+    pub fn new(year: u16, month: Option<u8>, day: Option<u8>) -> Option<Self> {
+        let days = match month {
+            None => 0,
+            Some(1 | 3 | 5 | 7 | 8 | 10 | 12) => 31,
+            Some(4 | 6 | 9 | 11) => 30,
+            Some(2)
+                if year.is_multiple_of(4)
+                    && (!year.is_multiple_of(100) || year.is_multiple_of(400)) =>
+            {
+                29
+            }
+            Some(2) => 28,
+            Some(_) => return None,
+        };
+        ((1..=9999).contains(&year) && day.is_none_or(|day| (1..=days).contains(&day)))
+            .then_some(Self { year, month, day })
+    }
+
+    /// The number of characters of the date, a filter at this precision compares that much of a stored date.
+    pub fn precision(&self) -> usize {
+        match (self.month, self.day) {
+            (None, _) => 4,
+            (Some(_), None) => 7,
+            (Some(_), Some(_)) => 10,
+        }
+    }
+
+    /// Whether `other` is this date, or this date with more precision.
+    pub fn contains(&self, other: &ReleaseDate) -> bool {
+        other.to_string().starts_with(&self.to_string())
+    }
+
+    /// Write a release as a `release` year, 0 for none, & its whole `release_date`, for a flattened field.
+    pub fn serialize_fields<S: Serializer>(
+        date: &Option<Self>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        ReleaseFields {
+            release: Some(date.map_or(0, |date| date.year)),
+            release_date: *date,
+        }
+        .serialize(serializer)
+    }
+
+    /// Read a release from its `release_date`, or from a `release` year with 0 for none.
+    pub fn deserialize_fields<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Self>, D::Error> {
+        let fields = ReleaseFields::deserialize(deserializer)?;
+        Self::from_fields(fields.release, fields.release_date)
+            .map_err(|year| de::Error::custom(format!("invalid release year `{year}`")))
+    }
+
+    /// The whole date, or the year with 0 for none, `Err` with the year if it's invalid.
+    pub fn from_fields(year: Option<u16>, date: Option<Self>) -> Result<Option<Self>, u16> {
+        match (date, year) {
+            (Some(date), _) => Ok(Some(date)),
+            (None, None | Some(0)) => Ok(None),
+            (None, Some(year)) => ReleaseDate::new(year, None, None).map(Some).ok_or(year),
+        }
+    }
+
+    /// A `minrelease` or `maxrelease` filter, `None` for `0` which leaves that end of the range open.
+    pub fn from_filter(value: &str) -> Result<Option<Self>, Status> {
+        match value.trim() {
+            "0" => Ok(None),
+            value => value.parse().map(Some).map_err(|_| Status::BadRequest),
+        }
+    }
+}
+
+/// How albums & tracks send their release, the year is `0` & the date `null` if it's unknown.
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
+struct ReleaseFields {
+    #[serde(default)]
+    release: Option<u16>,
+    #[serde(default)]
+    release_date: Option<ReleaseDate>,
+}
+
+impl fmt::Display for ReleaseDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}", self.year)?;
+        if let Some(month) = self.month {
+            write!(f, "-{month:02}")?;
+        }
+        if let Some(day) = self.day {
+            write!(f, "-{day:02}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ReleaseDate {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('-');
+        let mut part = |len: usize| -> Result<Option<u16>, ()> {
+            match parts.next() {
+                None => Ok(None),
+                Some(part) if part.len() == len && part.bytes().all(|b| b.is_ascii_digit()) => {
+                    part.parse().map(Some).map_err(|_| ())
+                }
+                Some(_) => Err(()),
+            }
+        };
+
+        let year = part(4)?.ok_or(())?;
+        let month = part(2)?.map(|month| month as u8);
+        let day = part(2)?.map(|day| day as u8);
+        if parts.next().is_some() {
+            return Err(());
+        }
+        ReleaseDate::new(year, month, day).ok_or(())
+    }
+}
+
+impl Serialize for ReleaseDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ReleaseDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let date = String::deserialize(deserializer)?;
+        date.parse()
+            .map_err(|_| de::Error::custom(format!("invalid release date `{date}`")))
+    }
+}
+
+/// The same string as in JSON.
+#[Scalar]
+impl ScalarType for ReleaseDate {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(date) => date
+                .parse()
+                .map_err(|_| InputValueError::custom(format!("invalid release date `{date}`"))),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl ToSql for ReleaseDate {
+    fn to_sql(&self) -> rocket_sync_db_pools::rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for ReleaseDate {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|_| FromSqlError::Other("invalid release date".into()))
+    }
+}
+
+/// What kind of release an album is.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Enum,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    IntoStaticStr,
+    ToSchema,
+)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+#[graphql(rename_items = "lowercase")]
+pub enum AlbumKind {
+    #[default]
+    Album,
+    Ep,
+    Single,
+    Live,
+    Compilation,
+    Soundtrack,
+    Remix,
+    Other,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub struct Album {
     pub id: String,
     pub name: String,
-    pub release: u16,
+    /// Sent as the `release` year (0 if unknown) & the whole `release_date`, the date wins if both are sent
+    #[serde(
+        flatten,
+        serialize_with = "ReleaseDate::serialize_fields",
+        deserialize_with = "ReleaseDate::deserialize_fields"
+    )]
+    pub release: Option<ReleaseDate>,
+    #[serde(default)]
+    pub kind: AlbumKind,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub catalog_number: Option<String>,
+    /// The original album this is an edition of (a reissue, remaster, deluxe edition, ...)
+    #[serde(default)]
+    pub edition_of: Option<String>,
+    /// The albums that are editions of this one
+    #[serde(skip_deserializing)]
+    pub editions: Vec<String>,
     #[serde(default)]
     pub artists: Vec<String>,
     #[serde(default)]
     pub tracks: Vec<String>,
     #[serde(default)]
     pub genres: Vec<String>,
+    /// Left out until the audio of at least one of its tracks has been analyzed
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<Loudness>,
+}
+
+impl Album {
+    /// Insert the album with its artists, tracks & genres, `400 Bad Request` if the album it's an edition of is missing.
+    pub fn insert(&mut self, conn: &Connection) -> Result<(), ApiError> {
+        // editions of an edition are editions of its original
+        if let Some(original) = self.edition_of.take() {
+            self.edition_of = Some(
+                conn.prepare_cached("SELECT COALESCE(edition_of, id) FROM albums WHERE id = ?")?
+                    .query_row([original], |row| row.get(0))
+                    .optional()?
+                    .ok_or(Status::BadRequest)?,
+            );
+        }
+
+        conn.prepare_cached("INSERT INTO albums (id, name, release, kind, label, catalog_number, edition_of) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?.execute(params![
+                self.id,
+                self.name,
+                self.release,
+                <&'static str>::from(self.kind),
+                self.label,
+                self.catalog_number,
+                self.edition_of,
+            ])?;
+
+        for artist in self.artists.iter() {
+            conn.prepare_cached("INSERT INTO artist_albums (artist_id, album_id) VALUES (?1, ?2)")?
+                .execute(params![artist, self.id])?;
+        }
+
+        for track in self.tracks.iter() {
+            conn.prepare_cached("INSERT INTO album_tracks (album_id, track_id) VALUES (?1, ?2)")?
+                .execute(params![self.id, track])?;
+        }
+
+        // aliases are stored as the genre they're an alias of
+        for genre in self.genres.iter_mut() {
+            if let Some(id) = genres::resolve(conn, genre)? {
+                *genre = id;
+            }
+            conn.prepare_cached("INSERT INTO album_genres (album_id, genre_id) VALUES (?1, ?2)")?
+                .execute(params![self.id, *genre])?;
+        }
+
+        Ok(())
+    }
+
+    /// The columns [`Album::try_from_row`] reads, add conditions after the `WHERE 1=1` & end with `GROUP BY albums.id`.
+    pub const SELECT: &'static str = "SELECT albums.id, albums.name, albums.release, COALESCE(GROUP_CONCAT(DISTINCT artist_albums.artist_id), ''), COALESCE(GROUP_CONCAT(DISTINCT album_tracks.track_id), ''), COALESCE(GROUP_CONCAT(DISTINCT album_genres.genre_id), '') AS genres, albums.kind, albums.label, albums.catalog_number, albums.edition_of, (SELECT COALESCE(GROUP_CONCAT(editions.id), '') FROM albums AS editions WHERE editions.edition_of = albums.id) AS editions FROM albums
+            LEFT JOIN album_tracks ON albums.id = album_tracks.album_id
+            LEFT JOIN artist_albums ON albums.id = artist_albums.album_id
+            LEFT JOIN album_genres ON albums.id = album_genres.album_id WHERE 1=1";
+
+    /// The loudness is left out, see [`crate::audio::loudness::album`].
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        let artists_str: String = row.get(3)?;
+        let artists: Vec<String> = artists_str
+            .split(',')
+            .filter_map(|s| {
+                if s.trim().is_empty() {
+                    None
+                } else {
+                    Some(s.to_string())
+                }
+            })
+            .collect();
+
+        let tracks_str: String = row.get(4)?;
+        let tracks: Vec<String> = tracks_str
+            .split(',')
+            .filter_map(|s| {
+                if s.trim().is_empty() {
+                    None
+                } else {
+                    Some(s.to_string())
+                }
+            })
+            .collect();
+
+        let genres_str: String = row.get(5)?;
+        let genres: Vec<String> = genres_str
+            .split(',')
+            .filter_map(|s| {
+                if s.trim().is_empty() {
+                    None
+                } else {
+                    Some(s.to_string())
+                }
+            })
+            .collect();
+
+        let kind: String = row.get(6)?;
+        let editions: String = row.get(10)?;
+
+        Ok(Album {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            release: row.get(2)?,
+            kind: kind
+                .parse()
+                .map_err(|_| Error::InvalidColumnType(6, "kind".to_string(), Type::Text))?,
+            label: row.get(7)?,
+            catalog_number: row.get(8)?,
+            edition_of: row.get(9)?,
+            editions: editions
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(str::to_string)
+                .collect(),
+            artists,
+            tracks,
+            genres,
+            loudness: None,
+        })
+    }
+}
+
+/// The filters of `GET /album`, which are also the arguments of the GraphQL `albums` query.
+#[derive(Default, InputObject)]
+pub struct AlbumFilter {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub maxrelease: Option<String>,
+    pub minrelease: Option<String>,
+    pub kind: Option<String>,
+    pub label: Option<String>,
+    pub genres: Option<Vec<String>>,
+    pub descendants: Option<bool>,
+    pub upc: Option<String>,
+    pub mbid: Option<String>,
+    pub discogs: Option<String>,
+    pub maxcount: Option<u16>,
+    pub mincount: Option<u16>,
+}
+
+impl AlbumFilter {
+    /// The matching albums without their loudness, `400 Bad Request` if a filter's value is invalid.
+    pub fn select(
+        self,
+        conn: &Connection,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<Album>, ApiError> {
+        let mut sql = Album::SELECT.to_string();
+        let mut params_vec = Vec::new();
+
+        if let Some(id_val) = self.id {
+            sql += " AND albums.id = ?";
+            params_vec.push(id_val);
+        }
+
+        if let Some(name_val) = self.name {
+            sql += " AND albums.name LIKE ?";
+            params_vec.push(format!("%{}%", name_val));
+        }
+
+        // compared at the filter's precision, so a year includes every date in it
+        if let Some(date) = self
+            .minrelease
+            .as_deref()
+            .map(ReleaseDate::from_filter)
+            .transpose()?
+            .flatten()
+        {
+            sql += &format!(" AND substr(albums.release, 1, {}) >= ?", date.precision());
+            params_vec.push(date.to_string());
+        }
+
+        if let Some(date) = self
+            .maxrelease
+            .as_deref()
+            .map(ReleaseDate::from_filter)
+            .transpose()?
+            .flatten()
+        {
+            sql += &format!(" AND substr(albums.release, 1, {}) <= ?", date.precision());
+            params_vec.push(date.to_string());
+        }
+
+        if let Some(kind_val) = self.kind {
+            let kind: AlbumKind = kind_val.parse().map_err(|_| Status::BadRequest)?;
+            sql += " AND albums.kind = ?";
+            params_vec.push(kind.to_string());
+        }
+
+        if let Some(label_val) = self.label {
+            sql += " AND albums.label LIKE ?";
+            params_vec.push(format!("%{}%", label_val));
+        }
+
+        if let Some(maxcount_val) = self.maxcount {
+            sql += " AND (SELECT COUNT(*) FROM album_tracks WHERE album_tracks.album_id = albums.id) <= ?";
+            params_vec.push(maxcount_val.to_string());
+        }
+
+        if let Some(mincount_val) = self.mincount {
+            sql += " AND (SELECT COUNT(*) FROM album_tracks WHERE album_tracks.album_id = albums.id) >= ?";
+            params_vec.push(mincount_val.to_string());
+        }
+
+        if let Some(genres_val) = self.genres {
+            sql += &format!(
+                " AND album_genres.genre_id IN {}",
+                genres::filter(genres_val.len(), self.descendants.unwrap_or(false))
+            );
+            params_vec.extend(genres_val);
+        }
+
+        for (source, value) in [
+            (ExternalSource::Upc, self.upc),
+            (ExternalSource::Musicbrainz, self.mbid),
+            (ExternalSource::Discogs, self.discogs),
+        ] {
+            if let Some(value) = value {
+                sql += &external_ids::filter(Resource::Album, source);
+                params_vec.push(external_ids::normalize(source, &value).ok_or(Status::BadRequest)?);
+            }
+        }
+
+        sql += &format!(
+            " GROUP BY albums.id LIMIT {} OFFSET {}",
+            limit.unwrap_or(50),
+            offset.unwrap_or(0)
+        );
+
+        let params_sql: Vec<&dyn ToSql> =
+            params_vec.iter().map(|param| param as &dyn ToSql).collect();
+
+        Ok(conn
+            .prepare(&sql)?
+            .query_map(&params_sql[..], Album::try_from_row)?
+            .collect::<Result<Vec<Album>, Error>>()?)
+    }
 }
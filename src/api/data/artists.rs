@@ -1,10 +1,251 @@
-use rocket::serde::{Deserialize, Serialize};
+use async_graphql::{Enum, InputObject, SimpleObject};
+use rocket::{
+    http::Status,
+    serde::{Deserialize, Serialize},
+};
+use rocket_sync_db_pools::rusqlite::{params, types::Type, Connection, Error, Row, ToSql};
+use std::str::FromStr;
+use strum::{Display, EnumString, IntoStaticStr};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize)]
+use crate::{
+    api::data::{events::Resource, external_ids::ExternalSource},
+    error::ApiError,
+    external_ids, genres,
+};
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct Artist {
+    #[schema(example = "0")]
     pub id: String,
+    #[schema(example = "The Beatles")]
     pub name: String,
+    /// The name to sort by, the name itself if not given
+    #[serde(default)]
+    #[schema(example = "Beatles, The")]
+    pub sort_name: Option<String>,
     pub genres: Vec<String>,
     pub bio: String,
 }
+
+impl Artist {
+    /// Insert the artist with its genres, which are replaced by the genre an alias is of.
+    pub fn insert(&mut self, conn: &Connection) -> Result<(), ApiError> {
+        conn.prepare_cached(
+            "INSERT INTO artists (id, name, sort_name, bio) VALUES (?1, ?2, ?3, ?4)",
+        )?
+        .execute(params![self.id, self.name, self.sort_name, self.bio])?;
+
+        // aliases are stored as the genre they're an alias of
+        for genre in self.genres.iter_mut() {
+            *genre = genres::resolve(conn, genre)?.ok_or(ApiError::RusqliteError((
+                Status::BadRequest,
+                "Genre Not Found".to_string(),
+            )))?;
+            conn.prepare_cached("INSERT INTO artist_genres (artist_id, genre_id) VALUES (?1, ?2)")?
+                .execute(params![self.id, *genre])?;
+        }
+
+        Ok(())
+    }
+
+    /// The columns [`Artist::try_from_row`] reads, add conditions after the `WHERE 1=1` & end with `GROUP BY artists.id`.
+    pub const SELECT: &'static str = "SELECT artists.id, artists.name, artists.sort_name, artists.bio, COALESCE(GROUP_CONCAT(artist_genres.genre_id), '') AS genres FROM artists
+            LEFT JOIN artist_genres ON artists.id = artist_genres.artist_id WHERE 1=1";
+
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        let genres: String = row.get(4)?;
+        Ok(Artist {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            sort_name: row.get(2)?,
+            genres: genres
+                .split(',')
+                .filter(|genre| !genre.is_empty())
+                .map(|genre| genre.to_string())
+                .collect(),
+            bio: row.get(3)?,
+        })
+    }
+}
+
+/// The filters of `GET /artist`, which are also the arguments of the GraphQL `artists` query.
+#[derive(Default, InputObject)]
+pub struct ArtistFilter {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub genres: Option<Vec<String>>,
+    pub descendants: Option<bool>,
+    pub mbid: Option<String>,
+    pub discogs: Option<String>,
+}
+
+impl ArtistFilter {
+    /// The matching artists, `400 Bad Request` if a filter's value is invalid.
+    pub fn select(
+        self,
+        conn: &Connection,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<Artist>, ApiError> {
+        let mut sql = Artist::SELECT.to_string();
+        let mut params_vec = Vec::new();
+
+        if let Some(id_val) = self.id {
+            sql += " AND artists.id = ?";
+            params_vec.push(id_val);
+        }
+
+        if let Some(name_val) = self.name {
+            sql += " AND (artists.name LIKE ? OR artists.sort_name LIKE ? OR EXISTS(SELECT 1 FROM artist_aliases WHERE artist_aliases.artist_id = artists.id AND artist_aliases.name LIKE ?))";
+            params_vec.extend(vec![format!("%{}%", name_val); 3]);
+        }
+
+        if let Some(genres_val) = self.genres {
+            sql += &format!(
+                " AND artist_genres.genre_id IN {}",
+                genres::filter(genres_val.len(), self.descendants.unwrap_or(false))
+            );
+            params_vec.extend(genres_val);
+        }
+
+        for (source, value) in [
+            (ExternalSource::Musicbrainz, self.mbid),
+            (ExternalSource::Discogs, self.discogs),
+        ] {
+            if let Some(value) = value {
+                sql += &external_ids::filter(Resource::Artist, source);
+                params_vec.push(external_ids::normalize(source, &value).ok_or(Status::BadRequest)?);
+            }
+        }
+
+        sql += &format!(
+            " GROUP BY artists.id LIMIT {} OFFSET {}",
+            limit.unwrap_or(50),
+            offset.unwrap_or(0)
+        );
+
+        let params_sql: Vec<&dyn ToSql> =
+            params_vec.iter().map(|param| param as &dyn ToSql).collect();
+
+        Ok(conn
+            .prepare(&sql)?
+            .query_map(&params_sql[..], Artist::try_from_row)?
+            .collect::<Result<Vec<Artist>, Error>>()?)
+    }
+}
+
+fn parse<T: FromStr>(row: &Row, column: &str) -> Result<T, Error> {
+    let value: String = row.get(column)?;
+    T::from_str(&value).map_err(|_| Error::InvalidColumnType(0, column.to_string(), Type::Text))
+}
+
+/// What kind of other name an alias is.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Enum,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    IntoStaticStr,
+    ToSchema,
+)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+#[graphql(rename_items = "snake_case")]
+pub enum ArtistAliasKind {
+    /// A name the artist is also known by
+    #[default]
+    Alias,
+    /// The name written in another script
+    Transliteration,
+}
+
+/// Another name for an artist, found by the `name` filter of `GET /artist`.
+#[derive(Clone, Serialize, Deserialize, ToSchema, SimpleObject)]
+#[serde(crate = "rocket::serde")]
+pub struct ArtistAlias {
+    #[schema(example = "Fab Four")]
+    pub name: String,
+    #[serde(default)]
+    pub kind: ArtistAliasKind,
+    /// The language & script of the name, e.g. `ja-Latn`
+    #[serde(default)]
+    #[schema(example = "en")]
+    pub locale: Option<String>,
+}
+
+impl ArtistAlias {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        Ok(ArtistAlias {
+            name: row.get("name")?,
+            kind: parse(row, "kind")?,
+            locale: row.get("locale")?,
+        })
+    }
+}
+
+/// How an artist is related to another, read as "artist is `kind` related artist".
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Enum,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    IntoStaticStr,
+    ToSchema,
+)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+#[graphql(rename_items = "snake_case")]
+pub enum ArtistRelationKind {
+    /// The artist is (or was) in the related group
+    MemberOf,
+    SideProjectOf,
+    /// The artist & the related artist are the same person under different names
+    SamePersonAs,
+}
+
+/// A relationship between two artists.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ArtistRelation {
+    pub kind: ArtistRelationKind,
+    /// The id of the other artist
+    #[schema(example = "1")]
+    pub artist: String,
+}
+
+impl ArtistRelation {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        Ok(ArtistRelation {
+            kind: parse(row, "kind")?,
+            artist: row.get("artist")?,
+        })
+    }
+}
+
+/// An artist with its other names & its relationships to other artists.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ArtistDetails {
+    #[serde(flatten)]
+    pub artist: Artist,
+    pub aliases: Vec<ArtistAlias>,
+    /// How this artist is related to others, e.g. the groups it's a member of
+    pub relations: Vec<ArtistRelation>,
+    /// How others are related to this artist, e.g. its members
+    pub related: Vec<ArtistRelation>,
+}
@@ -0,0 +1,166 @@
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A track's audio file, stored once per distinct SHA-256 & shared by every track that uses it.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AudioInfo {
+    #[schema(example = "0")]
+    pub track: String,
+    /// The SHA-256 of the file, also its key in the storage
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub hash: String,
+    /// The size of the file in bytes
+    pub size: u64,
+    #[schema(example = "audio/mpeg")]
+    pub mime: String,
+    /// When the file was uploaded for this track (unix seconds)
+    pub uploaded: u64,
+    /// Who uploaded it, the file counts towards their quota
+    #[schema(example = "5-pebbles")]
+    pub uploader: Option<String>,
+    /// When the file was last re-hashed by a verification (unix seconds)
+    pub verified: Option<u64>,
+    /// Whether the last verification found the stored file missing or changed
+    pub corrupt: bool,
+}
+
+/// The EBU R128 loudness of a track or album & its ReplayGain 2.0 gain.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Loudness {
+    /// The integrated loudness (LUFS)
+    #[schema(example = -14.2)]
+    pub integrated: f64,
+    /// The true peak (dBTP)
+    #[schema(example = -0.4)]
+    pub true_peak: f64,
+    /// The gain that brings it to the -18 LUFS ReplayGain reference (dB)
+    #[schema(example = -3.8)]
+    pub gain: f64,
+}
+
+/// Which ReplayGain gain is applied to audio on the way out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField, ToSchema)]
+pub enum GainMode {
+    /// The track's own gain, every track plays back equally loud
+    Track,
+    /// The gain of the track's album, so its tracks keep their levels relative to each other
+    Album,
+}
+
+/// A track's waveform, the lowest & highest sample in each of the slices its audio is split into.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Peaks {
+    /// The number of slices, fewer than requested if the audio is shorter than 10ms a slice
+    pub samples: usize,
+    /// The lowest sample of each slice across all channels, from -1 to 1
+    #[schema(example = json!([-0.5, -0.25]))]
+    pub min: Vec<f32>,
+    /// The highest sample of each slice across all channels, from -1 to 1
+    #[schema(example = json!([0.5, 0.25]))]
+    pub max: Vec<f32>,
+}
+
+/// What a garbage collection found (& removed unless it was a dry run).
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AudioGarbage {
+    pub dry_run: bool,
+    /// Keys of files in the storage that no record points to
+    pub orphaned: Vec<String>,
+    /// Hashes of stored files no track uses anymore
+    pub unreferenced: Vec<String>,
+    /// Tracks recorded as having audio whose file is missing from the storage
+    pub dangling: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum VerificationStatus {
+    Running,
+    Finished,
+    Failed,
+}
+
+/// A stored file that no longer matches its hash.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AudioMismatch {
+    pub hash: String,
+    /// The tracks using the file
+    pub tracks: Vec<String>,
+    #[schema(example = "missing")]
+    pub reason: String,
+}
+
+/// A background verification of the stored audio & its report so far.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Verification {
+    #[schema(example = "0d4b2b8e-2f8c-4c53-9f0a-8d9d1c2b3a4e")]
+    pub id: String,
+    pub status: VerificationStatus,
+    /// The number of stored files
+    pub total: usize,
+    /// The number of files re-hashed so far
+    pub checked: usize,
+    pub mismatched: Vec<AudioMismatch>,
+    /// Why the verification failed
+    pub error: Option<String>,
+}
+
+impl Verification {
+    pub fn new(id: String) -> Self {
+        Self {
+            id,
+            status: VerificationStatus::Running,
+            total: 0,
+            checked: 0,
+            mismatched: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+fn default_mime() -> String {
+    "audio/mpeg".to_string()
+}
+
+/// The file a resumable upload will send.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct UploadCreate {
+    #[schema(example = "0")]
+    pub track: String,
+    /// The size of the whole file in bytes
+    pub size: u64,
+    /// The SHA-256 of the whole file, checked before it's stored
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub sha256: String,
+    /// `audio/mpeg` by default
+    #[serde(default = "default_mime")]
+    #[schema(example = "audio/flac")]
+    pub mime: String,
+}
+
+/// A resumable upload & how much of it the server has.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct UploadSession {
+    #[schema(example = "0d4b2b8e-2f8c-4c53-9f0a-8d9d1c2b3a4e")]
+    pub id: String,
+    #[schema(example = "0")]
+    pub track: String,
+    pub size: u64,
+    /// The number of bytes received, the next chunk starts here
+    pub offset: u64,
+    pub sha256: String,
+    #[schema(example = "audio/mpeg")]
+    pub mime: String,
+    /// Once `offset` reaches `size` the file has been checked & stored as the track's audio
+    pub complete: bool,
+    /// When the session is deleted unless more is sent (unix seconds)
+    pub expires: u64,
+}
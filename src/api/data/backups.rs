@@ -0,0 +1,35 @@
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A consistent snapshot of the database, written with SQLite's online backup API.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Backup {
+    /// When the snapshot was taken (UTC), also its file name
+    #[schema(example = "20240618T142205123")]
+    pub id: String,
+    /// The size of the snapshot in bytes
+    pub size: u64,
+    /// Whether a manifest of the audio files was written alongside it
+    pub manifest: bool,
+}
+
+/// The audio files that existed when a snapshot was taken.
+///
+/// Audio isn't copied into backups, the manifest is for checking the audio directory still matches.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct BackupManifest {
+    #[schema(example = "20240618T142205123")]
+    pub backup: String,
+    pub audio: Vec<BackupAudio>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct BackupAudio {
+    #[schema(example = "0")]
+    pub track: String,
+    pub size: u64,
+    pub sha256: String,
+}
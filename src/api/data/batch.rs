@@ -0,0 +1,83 @@
+use rocket::{
+    data::{self, Data, FromData, Limits},
+    http::Status,
+    request::Request,
+    serde::{json, Deserialize, DeserializeOwned, Serialize},
+};
+use strum::{Display, EnumString};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+
+/// Whether a batch is written only if every item can be, or item by item.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    ToSchema,
+)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BatchMode {
+    /// Nothing is written if an item fails
+    #[default]
+    AllOrNothing,
+    /// The items that don't fail are written
+    BestEffort,
+}
+
+/// What happened to an item of a batch, in the order they were sent.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct BatchItem {
+    #[schema(example = "0")]
+    pub id: String,
+    /// The status writing it alone would have had, `424` if it wasn't written because another item failed
+    #[schema(example = 409)]
+    pub status: u16,
+    /// Why it wasn't written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Rusqlite Error: UNIQUE constraint failed: tracks.id")]
+    pub error: Option<String>,
+}
+
+/// The JSON array of items of a batch, limited by the `batch` limit instead of `json` as it can hold a whole library.
+pub struct Batch<T>(pub Vec<T>);
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for Batch<T> {
+    type Error = ApiError;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = request.limits().get("batch").unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                return data::Outcome::Error((
+                    Status::PayloadTooLarge,
+                    ApiError::Status(Status::PayloadTooLarge),
+                ))
+            }
+            Err(e) => return data::Outcome::Error((Status::BadRequest, ApiError::from(e))),
+        };
+
+        // like `Json`, a body that isn't JSON is a bad request & one that doesn't fit is unprocessable
+        match json::from_str(&body) {
+            Ok(items) => data::Outcome::Success(Batch(items)),
+            Err(e) => {
+                let status = match e.is_data() {
+                    true => Status::UnprocessableEntity,
+                    false => Status::BadRequest,
+                };
+                data::Outcome::Error((status, ApiError::Status(status)))
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::data::{
+    albums::Album,
+    artists::{Artist, ArtistAliasKind, ArtistRelationKind},
+    external_ids::ExternalSource,
+    lyrics::LyricLine,
+    tracks::Track,
+};
+
+/// One line of a catalog export, tagged with its `type`.
+///
+/// Artists, albums & tracks are exported without their relationships, those follow as link records.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum CatalogRecord {
+    Genre {
+        id: String,
+    },
+    GenreParent {
+        genre: String,
+        parent: String,
+    },
+    /// Another name for a genre, links to genres can use it once it's been imported.
+    GenreAlias {
+        alias: String,
+        genre: String,
+    },
+    Artist(Artist),
+    /// Another name for an artist, see `POST /artist/<id>/alias`.
+    ArtistAlias {
+        artist: String,
+        name: String,
+        #[serde(default)]
+        kind: ArtistAliasKind,
+        #[serde(default)]
+        locale: Option<String>,
+    },
+    /// Read as "artist is `kind` related", see `POST /artist/<id>/relation`.
+    ArtistRelation {
+        artist: String,
+        related: String,
+        kind: ArtistRelationKind,
+    },
+    Album(Album),
+    Track(Track),
+    /// An id in an outside database, of exactly one of `artist`, `album` or `track`.
+    ExternalId {
+        source: ExternalSource,
+        value: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        artist: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        album: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        track: Option<String>,
+    },
+    ArtistGenre {
+        artist: String,
+        genre: String,
+    },
+    ArtistAlbum {
+        artist: String,
+        album: String,
+    },
+    AlbumGenre {
+        album: String,
+        genre: String,
+    },
+    AlbumTrack {
+        album: String,
+        track: String,
+    },
+    TrackGenre {
+        track: String,
+        genre: String,
+    },
+    /// A track's lyrics in one language, the plain `lyrics` set on the track itself are in its `track` record.
+    Lyrics {
+        track: String,
+        language: String,
+        lines: Vec<LyricLine>,
+    },
+    /// A track's audio file, bundled as `audio/<track>.mp3` when exporting with audio.
+    Audio {
+        track: String,
+        size: u64,
+        sha256: String,
+    },
+    /// The last line, with the number of records before it, a catalog without it was cut short.
+    End {
+        records: usize,
+    },
+}
+
+/// What to do with a record that already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField, ToSchema)]
+pub enum Conflict {
+    Skip,
+    /// Replace the existing genre, artist, album, track, lyrics or audio, links are left as they are
+    Overwrite,
+    /// Stop the import, batches that were already applied are kept
+    #[default]
+    Fail,
+}
+
+/// The result of a catalog import.
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CatalogReport {
+    /// The number of records read
+    pub records: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    /// The number of transactions the records were applied in
+    pub batches: usize,
+}
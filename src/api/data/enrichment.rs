@@ -0,0 +1,97 @@
+use rocket::serde::{json, Deserialize, Serialize};
+use rocket_sync_db_pools::rusqlite::{types::Type, Error, Row};
+use utoipa::ToSchema;
+
+use crate::api::data::{
+    albums::ReleaseDate, artists::ArtistRelationKind, events::Resource,
+    external_ids::ExternalSource,
+};
+
+/// A change to an artist, album or track, tagged with its `kind`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde", tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// Set the release date of an album or track
+    Release {
+        #[schema(value_type = String, example = "1997-05-21")]
+        date: ReleaseDate,
+    },
+    /// Set an artist's sort name
+    SortName { name: String },
+    /// Add an alias to an artist
+    Alias {
+        name: String,
+        locale: Option<String>,
+    },
+    /// Tag an artist, album or track with a genre, the genre is created if it doesn't exist
+    Genre { genre: String },
+    /// Store an id in an outside database
+    ExternalId {
+        source: ExternalSource,
+        value: String,
+    },
+    /// Relate an artist to another artist
+    Relation {
+        relation: ArtistRelationKind,
+        artist: String,
+    },
+    /// Add a track to an album, with the MBID of its recording
+    Track { name: String, mbid: String },
+}
+
+/// A change enrichment proposes for an artist, album or track.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Proposal {
+    pub resource: Resource,
+    /// The id of the artist, album or track
+    #[schema(example = "0")]
+    pub target: String,
+    pub change: Change,
+}
+
+/// A proposal saved for review.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Suggestion {
+    #[schema(example = 1)]
+    pub id: u64,
+    pub resource: Resource,
+    #[schema(example = "0")]
+    pub target: String,
+    pub change: Change,
+    /// When it was suggested as a unix timestamp
+    #[schema(example = 1710000000)]
+    pub created: u64,
+}
+
+impl Suggestion {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        let resource: String = row.get("resource")?;
+        let change: String = row.get("change")?;
+
+        Ok(Suggestion {
+            id: row.get("id")?,
+            resource: resource
+                .parse()
+                .map_err(|_| Error::InvalidColumnType(0, "resource".to_string(), Type::Text))?,
+            target: row.get("target")?,
+            change: json::from_str(&change)
+                .map_err(|_| Error::InvalidColumnType(0, "change".to_string(), Type::Text))?,
+            created: row.get("created")?,
+        })
+    }
+}
+
+/// The result of enriching an artist, album or track.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct EnrichmentReport {
+    /// The MBID it was matched to, missing if no match was found
+    #[schema(example = "a74b1b7f-71a5-4011-9441-d0b5e4122711")]
+    pub mbid: Option<String>,
+    /// The number of requests made to the service, responses are cached
+    pub requests: u32,
+    pub applied: Vec<Proposal>,
+    pub suggested: Vec<Suggestion>,
+}
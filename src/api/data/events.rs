@@ -0,0 +1,117 @@
+use async_graphql::Enum;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_sync_db_pools::rusqlite::{Error, Row};
+use std::str::FromStr;
+use strum::{Display, EnumString, IntoStaticStr};
+use utoipa::ToSchema;
+
+use crate::api::data::permissions::Permission;
+
+/// The kinds of resources that emit change events.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Enum,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    IntoStaticStr,
+    ToSchema,
+)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+#[graphql(rename_items = "lowercase")]
+pub enum Resource {
+    Genre,
+    Artist,
+    Album,
+    Track,
+    Audio,
+    User,
+}
+
+impl Resource {
+    /// The permission required to see events for this resource.
+    pub fn read_permission(&self) -> Permission {
+        match self {
+            Resource::Genre => Permission::GenreRead,
+            Resource::Artist => Permission::ArtistRead,
+            Resource::Album => Permission::AlbumRead,
+            Resource::Track => Permission::TrackRead,
+            Resource::Audio => Permission::AudioRead,
+            Resource::User => Permission::UserRead,
+        }
+    }
+}
+
+/// What happened to a resource.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    IntoStaticStr,
+    ToSchema,
+)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A change to a resource, sent as the data of a server-sent event.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ChangeEvent {
+    /// Increases monotonically, it is also the id of the server-sent event
+    #[schema(example = 42)]
+    pub id: u64,
+    pub resource: Resource,
+    pub action: Action,
+    /// The id of the resource that changed (or username for users)
+    #[schema(example = "0")]
+    pub target: String,
+    /// When the change happened as a unix timestamp
+    #[schema(example = 1710000000)]
+    pub created: u64,
+}
+
+impl ChangeEvent {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        fn parse<T: FromStr>(row: &Row, column: &str) -> Result<T, Error> {
+            let value: String = row.get(column)?;
+            T::from_str(&value).map_err(|_| {
+                Error::InvalidColumnType(
+                    0,
+                    column.to_string(),
+                    rocket_sync_db_pools::rusqlite::types::Type::Text,
+                )
+            })
+        }
+
+        Ok(ChangeEvent {
+            id: row.get("id")?,
+            resource: parse(row, "resource")?,
+            action: parse(row, "action")?,
+            target: row.get("target")?,
+            created: row.get("created")?,
+        })
+    }
+
+    /// The server-sent event type, for example `track.create`.
+    pub fn event_type(&self) -> String {
+        format!("{}.{}", self.resource, self.action)
+    }
+}
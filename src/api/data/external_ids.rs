@@ -0,0 +1,56 @@
+use async_graphql::{Enum, SimpleObject};
+use rocket::serde::{Deserialize, Serialize};
+use rocket_sync_db_pools::rusqlite::{types::Type, Error, Row};
+use std::str::FromStr;
+use strum::{Display, EnumString, IntoStaticStr};
+use utoipa::ToSchema;
+
+/// The outside databases ids can be stored for.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Enum,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+    IntoStaticStr,
+    ToSchema,
+)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+#[graphql(rename_items = "lowercase")]
+pub enum ExternalSource {
+    /// A MusicBrainz MBID, of an artist, a release (album) or a recording (track)
+    Musicbrainz,
+    /// An International Standard Recording Code, only for tracks
+    Isrc,
+    /// A UPC-A or EAN-13 barcode, only for albums & stored as an EAN-13
+    Upc,
+    /// A Discogs artist or release (album) id
+    Discogs,
+}
+
+/// An artist's, album's or track's id in an outside database.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, SimpleObject)]
+#[serde(crate = "rocket::serde")]
+pub struct ExternalId {
+    pub source: ExternalSource,
+    /// Normalized when written: MBIDs lowercase, ISRCs uppercase without dashes & barcodes as 13 digits
+    #[schema(example = "USUM71703861")]
+    pub value: String,
+}
+
+impl ExternalId {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        let source: String = row.get("source")?;
+        Ok(ExternalId {
+            source: ExternalSource::from_str(&source)
+                .map_err(|_| Error::InvalidColumnType(0, "source".to_string(), Type::Text))?,
+            value: row.get("value")?,
+        })
+    }
+}
@@ -0,0 +1,59 @@
+use async_graphql::InputObject;
+use rocket::serde::Serialize;
+use rocket_sync_db_pools::rusqlite::{Connection, Error, ToSql};
+use utoipa::ToSchema;
+
+/// A genre, where it sits in the genre hierarchy & the names that resolve to it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Genre {
+    #[schema(example = "indie rock")]
+    pub id: String,
+    /// The genres this is a kind of
+    #[schema(example = json!(["rock"]))]
+    pub parents: Vec<String>,
+    /// The genres that are a kind of this
+    #[schema(example = json!(["shoegaze"]))]
+    pub children: Vec<String>,
+    /// Other names for this genre, matched case insensitively
+    #[schema(example = json!(["indie-rock"]))]
+    pub aliases: Vec<String>,
+}
+
+/// The filters of `GET /genre`, which are also the arguments of the GraphQL `genres` query.
+#[derive(Default, InputObject)]
+pub struct GenreFilter {
+    /// Part of the genre's name or one of its aliases
+    pub genre: Option<String>,
+}
+
+impl GenreFilter {
+    /// The ids of the matching genres.
+    pub fn select(
+        self,
+        conn: &Connection,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<String>, Error> {
+        let mut sql = "SELECT * FROM genres WHERE 1=1".to_string();
+        let mut params_vec = Vec::new();
+
+        if let Some(genre_val) = self.genre {
+            sql += " AND (id LIKE ?1 OR id IN (SELECT genre_id FROM genre_aliases WHERE alias LIKE ?1))";
+            params_vec.push(format!("%{}%", genre_val));
+        }
+
+        sql += &format!(
+            " LIMIT {} OFFSET {}",
+            limit.unwrap_or(50),
+            offset.unwrap_or(0)
+        );
+
+        let params_sql: Vec<&dyn ToSql> =
+            params_vec.iter().map(|param| param as &dyn ToSql).collect();
+
+        conn.prepare(&sql)?
+            .query_map(&params_sql[..], |row| row.get(0))?
+            .collect()
+    }
+}
@@ -0,0 +1,85 @@
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::data::events::Resource;
+
+/// A server-side directory to import.
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportDirectory {
+    /// Must be inside one of the `import.roots` in `Rocket.toml`
+    #[schema(example = "/srv/music")]
+    pub path: String,
+    /// Report what would happen without writing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum ImportStatus {
+    Running,
+    Finished,
+    Failed,
+}
+
+/// A genre, artist, album, track or audio file the import created (or matched).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportEntry {
+    pub resource: Resource,
+    #[schema(example = "1e9c0cde-7b0a-4d5b-bd0a-5b5c1b0e3c1f")]
+    pub id: String,
+    #[schema(example = "Punisher")]
+    pub name: String,
+}
+
+/// A file the import did not use.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportSkip {
+    #[schema(example = "Phoebe Bridgers/Punisher/cover.jpg")]
+    pub file: String,
+    #[schema(example = "not an mp3 file")]
+    pub reason: String,
+}
+
+/// A background import & its report so far.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportJob {
+    #[schema(example = "0d4b2b8e-2f8c-4c53-9f0a-8d9d1c2b3a4e")]
+    pub id: String,
+    pub creator: String,
+    /// The directory or name of the uploaded archive
+    pub source: String,
+    pub dry_run: bool,
+    pub status: ImportStatus,
+    /// The number of files found
+    pub total: usize,
+    /// The number of files handled so far
+    pub processed: usize,
+    pub created: Vec<ImportEntry>,
+    pub matched: Vec<ImportEntry>,
+    pub skipped: Vec<ImportSkip>,
+    /// Why the import failed
+    pub error: Option<String>,
+}
+
+impl ImportJob {
+    pub fn new(id: String, creator: String, source: String, dry_run: bool) -> Self {
+        Self {
+            id,
+            creator,
+            source,
+            dry_run,
+            status: ImportStatus::Running,
+            total: 0,
+            processed: 0,
+            created: Vec::new(),
+            matched: Vec::new(),
+            skipped: Vec::new(),
+            error: None,
+        }
+    }
+}
@@ -8,6 +8,9 @@ pub struct Invite {
     pub code: String,
     pub permissions: Vec<Permission>,
     pub remaining: u16,
+    /// The storage quota of the users it creates in bytes, unlimited if it's not set
+    #[serde(default)]
+    pub quota: Option<u64>,
     #[serde(skip_deserializing)]
     pub creator: String,
 }
@@ -19,6 +22,7 @@ impl Invite {
             code: row.get("code")?,
             permissions,
             remaining: row.get("remaining")?,
+            quota: row.get("quota")?,
             creator: row.get("creator")?,
         })
     }
@@ -0,0 +1,39 @@
+use async_graphql::SimpleObject;
+use rocket::serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// One line of a track's lyrics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, SimpleObject)]
+#[serde(crate = "rocket::serde")]
+pub struct LyricLine {
+    /// Milliseconds from the start of the track, missing if the lyrics aren't synced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 12340)]
+    pub time: Option<u32>,
+    #[schema(example = "Okay, I told you that I would let you know")]
+    pub text: String,
+}
+
+/// A track's lyrics in one language.
+#[derive(Debug, Clone, Serialize, ToSchema, SimpleObject)]
+#[serde(crate = "rocket::serde")]
+pub struct Lyrics {
+    /// A language code e.g. `en` or `eng`, `und` for the lyrics set on the track itself
+    #[schema(example = "eng")]
+    pub language: String,
+    /// Whether every line has a time
+    pub synced: bool,
+    pub lines: Vec<LyricLine>,
+}
+
+/// How lyrics are read & written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField, ToSchema)]
+pub enum LyricsFormat {
+    /// `Lyrics` when reading, a list of `LyricLine` when writing
+    #[default]
+    Json,
+    /// `[mm:ss.xx]` timestamped lines, plain lines if the lyrics aren't synced
+    Lrc,
+    /// One line per line, without times
+    Text,
+}
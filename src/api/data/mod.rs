@@ -1,6 +1,19 @@
+pub mod audio;
+pub mod backups;
+pub mod batch;
+pub mod catalog;
+pub mod enrichment;
+pub mod events;
+pub mod external_ids;
+pub mod genres;
+pub mod imports;
 pub mod invites;
+pub mod lyrics;
 pub mod permissions;
+pub mod shares;
+pub mod totp;
 pub mod users;
+pub mod webhooks;
 
 pub mod albums;
 pub mod artists;
@@ -1,3 +1,4 @@
+use async_graphql::Enum;
 use rocket::serde::{Deserialize, Serialize};
 use rocket_sync_db_pools::rusqlite::{Error, Row};
 use std::str::FromStr;
@@ -10,8 +11,10 @@ use utoipa::ToSchema;
 #[derive(
     Debug,
     Clone,
+    Copy,
     PartialEq,
     Eq,
+    Enum,
     Serialize,
     Deserialize,
     Display,
@@ -22,6 +25,7 @@ use utoipa::ToSchema;
 )]
 #[serde(crate = "rocket::serde")]
 #[strum(serialize_all = "PascalCase", ascii_case_insensitive)]
+#[graphql(rename_items = "PascalCase")]
 pub enum Permission {
     //Docs
     DocsRead,
@@ -33,11 +37,13 @@ pub enum Permission {
     // Users
     UserRead,
     UserDelete, // Only on users who's permissions are the same or a subset of their own
+    QuotaWrite, // set storage quotas, no higher than their own (only on users who's permissions are a subset of their own)
     // Permissions
     PermissionAdd, // grant permissions (you still need to have the permissions you grant)
     PermissionDelete, // Only on users who's permissions are the same or a subset of their own
     // Sessions
     TokenDelete, // delete another users sessions
+    TotpDelete, // reset another users second factor (only on users who's permissions are a subset of their own)
 
     // Music Stuff
     GenreWrite,
@@ -60,6 +66,24 @@ pub enum Permission {
     AudioWrite,
     AudioRead,
     AudioDelete,
+    AudioVerify, // re-hash every stored file
+
+    ImportWrite, // you also need the write permissions for everything an import creates
+    ImportRead,
+
+    ShareWrite, // create, list & revoke their own share links, only for what they can read
+
+    EnrichWrite, // look up metadata & review suggestions, applying them also needs write permission for what they change
+
+    // Integrations
+    WebhookWrite,
+    WebhookRead,
+    WebhookDelete,
+
+    // Administration
+    BackupWrite,
+    BackupRead,
+    BackupRestore, // replaces every table, including users & permissions
 }
 
 /// Extracts permissions from a rusqlite row and converts them into a `Vec<Permission>`.
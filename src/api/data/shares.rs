@@ -0,0 +1,77 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_sync_db_pools::rusqlite::{Error, Row};
+use utoipa::ToSchema;
+
+use crate::api::data::{albums::Album, tracks::Track};
+
+/// What to share, exactly one of `track` & `album`.
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ShareCreate {
+    #[schema(example = "0")]
+    pub track: Option<String>,
+    pub album: Option<String>,
+    /// Seconds until the link stops working
+    #[schema(example = 604800)]
+    pub expires: u64,
+    /// How many times the audio can be downloaded, unlimited if not given
+    #[schema(example = 10)]
+    pub plays: Option<u32>,
+}
+
+/// A link that lets anyone with it read a track or an album & its audio, without logging in.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Share {
+    #[schema(example = "1e9c0cde-7b0a-4d5b-bd0a-5b5c1b0e3c1f")]
+    pub id: String,
+    #[schema(example = "0")]
+    pub track: Option<String>,
+    pub album: Option<String>,
+    pub creator: String,
+    /// When the link stops working as a unix timestamp
+    pub expires: u64,
+    /// How many times the audio can be downloaded, unlimited if missing
+    pub max_plays: Option<u32>,
+    /// How many times the audio has been downloaded
+    pub plays: u32,
+    pub created: u64,
+    /// The signed link, relative to the server
+    #[schema(
+        example = "/share/1e9c0cde-7b0a-4d5b-bd0a-5b5c1b0e3c1f?expires=1700000000&signature=9f86d0…"
+    )]
+    pub url: String,
+}
+
+impl Share {
+    /// The url is left empty, it's signed by the endpoints.
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        Ok(Share {
+            id: row.get("id")?,
+            track: row.get("track_id")?,
+            album: row.get("album_id")?,
+            creator: row.get("creator")?,
+            expires: row.get("expires")?,
+            max_plays: row.get("max_plays")?,
+            plays: row.get("plays")?,
+            created: row.get("created")?,
+            url: String::new(),
+        })
+    }
+}
+
+/// What a share link gives access to.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Shared {
+    /// Missing if a single track was shared
+    #[schema(value_type = Option<Object>)]
+    pub album: Option<Album>,
+    /// The shared track or the album's tracks
+    #[schema(value_type = Vec<Object>)]
+    pub tracks: Vec<Track>,
+    /// When the link stops working as a unix timestamp
+    pub expires: u64,
+    /// How many more times the audio can be downloaded, unlimited if missing
+    pub plays_left: Option<u32>,
+}
@@ -0,0 +1,176 @@
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use rocket::{
+    http::Status,
+    serde::{Deserialize, Serialize},
+};
+use rocket_sync_db_pools::rusqlite::{params, OptionalExtension, Transaction};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+
+/// The length of a TOTP step in seconds.
+const PERIOD: u64 = 30;
+/// The number of digits in a TOTP code.
+const DIGITS: u32 = 6;
+/// The number of steps before & after the current one that are still accepted.
+const SKEW: u64 = 1;
+/// The number of recovery codes handed out when a second factor is confirmed.
+const RECOVERY_CODES: usize = 10;
+
+/// A freshly generated TOTP secret.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TotpEnrollment {
+    /// The base32 encoded secret
+    #[schema(example = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP")]
+    pub secret: String,
+    /// The secret as a `otpauth://` uri for authenticator apps
+    #[schema(
+        example = "otpauth://totp/Tuna:5-pebbles?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=Tuna&algorithm=SHA1&digits=6&period=30"
+    )]
+    pub uri: String,
+}
+
+impl TotpEnrollment {
+    pub fn generate(username: &str) -> Self {
+        let mut secret = [0u8; 20];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let secret = BASE32_NOPAD.encode(&secret);
+
+        let uri = format!(
+            "otpauth://totp/Tuna:{}?secret={}&issuer=Tuna&algorithm=SHA1&digits={}&period={}",
+            percent_encode(username),
+            secret,
+            DIGITS,
+            PERIOD
+        );
+
+        Self { secret, uri }
+    }
+}
+
+/// A TOTP code used to confirm a second factor.
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TotpCode {
+    #[schema(example = "123456")]
+    pub code: String,
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Generates the RFC 6238 code for a given step.
+fn code_at(key: &[u8], step: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Checks a code against the secret, returning the step it matched.
+///
+/// Only steps after `last_step` are accepted so a code can't be replayed.
+pub fn verify(secret: &str, code: &str, last_step: Option<u64>) -> Option<u64> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() / PERIOD;
+
+    (now.saturating_sub(SKEW)..=now + SKEW)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| code_at(&key, *step) == code)
+}
+
+/// Generates a set of one-time recovery codes.
+pub fn recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(12)
+                .map(|c| (c as char).to_ascii_lowercase())
+                .collect()
+        })
+        .collect()
+}
+
+/// Recovery codes are random enough that a fast hash is fine.
+pub fn hash_recovery_code(code: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(code.trim().as_bytes()))
+}
+
+/// Checks the second factor of a user who has already given the correct password.
+///
+/// Accepts either a TOTP code or one of the user's unused recovery codes.
+pub fn verify_second_factor(
+    tx: &Transaction<'_>,
+    username: &str,
+    code: Option<&str>,
+) -> Result<(), ApiError> {
+    let Some((secret, last_step)) = tx
+        .query_row(
+            "SELECT secret, last_step FROM totp WHERE username = ? AND confirmed = 1",
+            params![username],
+            |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    row.get::<usize, Option<u64>>(1)?,
+                ))
+            },
+        )
+        .optional()?
+    else {
+        return Ok(());
+    };
+
+    let Some(code) = code else {
+        Err(Status::Unauthorized)?
+    };
+
+    if let Some(step) = verify(&secret, code, last_step) {
+        tx.execute(
+            "UPDATE totp SET last_step = ?1 WHERE username = ?2",
+            params![step, username],
+        )?;
+        return Ok(());
+    }
+
+    // recovery codes can only be used once
+    if tx.execute(
+        "DELETE FROM recovery_codes WHERE username = ?1 AND hash = ?2",
+        params![username, hash_recovery_code(code)],
+    )? == 0
+    {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(())
+}
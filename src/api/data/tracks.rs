@@ -1,11 +1,31 @@
-use rocket::serde::{Deserialize, Serialize};
+use async_graphql::InputObject;
+use rocket::{
+    http::Status,
+    serde::{Deserialize, Serialize},
+};
+use rocket_sync_db_pools::rusqlite::{params, Connection, Error, Row, ToSql};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize)]
+use crate::{
+    api::data::{
+        albums::ReleaseDate, audio::Loudness, events::Resource, external_ids::ExternalSource,
+    },
+    error::ApiError,
+    external_ids, genres,
+};
+
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct Track {
     pub id: String,
     pub name: String,
-    pub release: u16,
+    /// Sent as the `release` year (0 if unknown) & the whole `release_date`, the date wins if both are sent
+    #[serde(
+        flatten,
+        serialize_with = "ReleaseDate::serialize_fields",
+        deserialize_with = "ReleaseDate::deserialize_fields"
+    )]
+    pub release: Option<ReleaseDate>,
     #[serde(skip_deserializing)]
     pub duration: u32,
     #[serde(default)]
@@ -16,4 +36,244 @@ pub struct Track {
     pub lyrics: String,
     #[serde(default)]
     pub genres: Vec<String>,
+    #[serde(skip_deserializing)]
+    pub has_audio: bool,
+    /// Left out until the audio has been analyzed
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<Loudness>,
+}
+
+impl Track {
+    /// Insert the track with its albums & genres, adding the albums' artists to it.
+    pub fn insert(&mut self, conn: &Connection) -> Result<(), ApiError> {
+        conn.prepare_cached(
+            "INSERT INTO tracks (id, name, release, lyrics) VALUES (?1, ?2, ?3, ?4)",
+        )?
+        .execute(params![self.id, self.name, self.release, self.lyrics,])?;
+
+        for album in self.albums.iter() {
+            conn.prepare_cached("INSERT INTO album_tracks (album_id, track_id) VALUES (?1, ?2)")?
+                .execute(params![album, self.id])?;
+
+            // return track should contain the track's artists
+            self.artists.extend(
+                conn.prepare_cached("SELECT artist_id FROM artist_albums WHERE album_id = ?")?
+                    .query_map(params![album], |row| row.get::<usize, String>(0))?
+                    .map(|v| v.map_err(ApiError::from))
+                    .collect::<Result<Vec<String>, ApiError>>()?,
+            );
+        }
+
+        // aliases are stored as the genre they're an alias of
+        for genre in self.genres.iter_mut() {
+            if let Some(id) = genres::resolve(conn, genre)? {
+                *genre = id;
+            }
+            conn.prepare_cached("INSERT INTO track_genres (track_id, genre_id) VALUES (?1, ?2)")?
+                .execute(params![self.id, *genre])?;
+        }
+
+        Ok(())
+    }
+
+    /// The columns [`Track::try_from_row`] reads, add conditions after the `WHERE 1=1` & end with `GROUP BY id`.
+    pub const SELECT: &'static str = "SELECT id, name, release, duration, COALESCE(GROUP_CONCAT(DISTINCT album_tracks.album_id), '') AS albums, COALESCE(GROUP_CONCAT(DISTINCT artist_albums.artist_id), '') AS artists, lyrics, COALESCE(GROUP_CONCAT(DISTINCT track_genres.genre_id), '') AS genres, EXISTS(SELECT 1 FROM track_audio WHERE track_id = tracks.id) AS has_audio, audio_blobs.loudness AS loudness, audio_blobs.true_peak AS true_peak FROM tracks
+            LEFT JOIN track_audio ON tracks.id = track_audio.track_id
+            LEFT JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+            LEFT JOIN track_genres ON tracks.id = track_genres.track_id
+            LEFT JOIN album_tracks ON tracks.id = album_tracks.track_id
+            LEFT JOIN artist_albums ON album_tracks.album_id = artist_albums.album_id WHERE 1=1";
+
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        let artists_str: String = row.get("artists")?;
+        let artists: Vec<String> = artists_str
+            .split(',')
+            .filter_map(|s| {
+                if s.trim().is_empty() {
+                    None
+                } else {
+                    Some(s.to_string())
+                }
+            })
+            .collect();
+
+        let albums_str: String = row.get("albums")?;
+        let albums: Vec<String> = albums_str
+            .split(',')
+            .filter_map(|s| {
+                if s.trim().is_empty() {
+                    None
+                } else {
+                    Some(s.to_string())
+                }
+            })
+            .collect();
+
+        let genres_str: String = row.get("genres")?;
+        let genres: Vec<String> = genres_str
+            .split(',')
+            .filter_map(|s| {
+                if s.trim().is_empty() {
+                    None
+                } else {
+                    Some(s.to_string())
+                }
+            })
+            .collect();
+
+        Ok(Track {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            release: row.get("release")?,
+            duration: row.get("duration")?,
+            albums,
+            artists,
+            lyrics: row.get("lyrics")?,
+            genres,
+            has_audio: row.get("has_audio")?,
+            loudness: Loudness::new(row.get("loudness")?, row.get("true_peak")?),
+        })
+    }
+}
+
+/// The filters of `GET /track`, which are also the arguments of the GraphQL `tracks` query.
+#[derive(Default, InputObject)]
+pub struct TrackFilter {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub maxrelease: Option<String>,
+    pub minrelease: Option<String>,
+    pub genres: Option<Vec<String>>,
+    pub descendants: Option<bool>,
+    pub isrc: Option<String>,
+    pub mbid: Option<String>,
+    pub albums: Option<Vec<String>>,
+    pub artists: Option<Vec<String>>,
+    pub lyrics: Option<String>,
+}
+
+impl TrackFilter {
+    /// The matching tracks, `400 Bad Request` if a filter's value is invalid.
+    pub fn select(
+        self,
+        conn: &Connection,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<Track>, ApiError> {
+        let mut sql = Track::SELECT.to_string();
+        let mut params_vec = Vec::new();
+
+        if let Some(id_val) = self.id {
+            sql += " AND id = ?";
+            params_vec.push(id_val);
+        }
+
+        if let Some(name_val) = self.name {
+            sql += " AND name LIKE ?";
+            params_vec.push(format!("%{}%", name_val));
+        }
+
+        // compared at the filter's precision, so a year includes every date in it
+        if let Some(date) = self
+            .maxrelease
+            .as_deref()
+            .map(ReleaseDate::from_filter)
+            .transpose()?
+            .flatten()
+        {
+            sql += &format!(" AND substr(release, 1, {}) <= ?", date.precision());
+            params_vec.push(date.to_string());
+        }
+
+        if let Some(date) = self
+            .minrelease
+            .as_deref()
+            .map(ReleaseDate::from_filter)
+            .transpose()?
+            .flatten()
+        {
+            sql += &format!(" AND substr(release, 1, {}) >= ?", date.precision());
+            params_vec.push(date.to_string());
+        }
+
+        if let Some(artists_val) = self.artists {
+            let artist_placeholders = artists_val
+                .iter()
+                .map(|_| "?")
+                .collect::<Vec<_>>()
+                .join(", ");
+            sql += &format!(" AND album_tracks.album_id IN ({})", artist_placeholders);
+            params_vec.extend(artists_val);
+        }
+
+        if let Some(albums_val) = self.albums {
+            let album_placeholders = albums_val
+                .iter()
+                .map(|_| "?")
+                .collect::<Vec<_>>()
+                .join(", ");
+            sql += &format!(" AND artist_albums.album_id IN ({})", album_placeholders);
+            params_vec.extend(albums_val);
+        }
+
+        if let Some(genres_val) = self.genres {
+            sql += &format!(
+                " AND track_genres.genre_id IN {}",
+                genres::filter(genres_val.len(), self.descendants.unwrap_or(false))
+            );
+            params_vec.extend(genres_val);
+        }
+
+        for (source, value) in [
+            (ExternalSource::Isrc, self.isrc),
+            (ExternalSource::Musicbrainz, self.mbid),
+        ] {
+            if let Some(value) = value {
+                sql += &external_ids::filter(Resource::Track, source);
+                params_vec.push(external_ids::normalize(source, &value).ok_or(Status::BadRequest)?);
+            }
+        }
+
+        if let Some(lyrics_val) = self.lyrics {
+            sql += " AND (lyrics LIKE ? OR EXISTS(SELECT 1 FROM track_lyrics WHERE track_lyrics.track_id = tracks.id AND track_lyrics.text LIKE ?))";
+            params_vec.push(format!("%{}%", lyrics_val));
+            params_vec.push(format!("%{}%", lyrics_val));
+        }
+
+        sql += &format!(
+            " GROUP BY id LIMIT {} OFFSET {}",
+            limit.unwrap_or(50),
+            offset.unwrap_or(0)
+        );
+
+        let params_sql: Vec<&dyn ToSql> =
+            params_vec.iter().map(|param| param as &dyn ToSql).collect();
+
+        Ok(conn
+            .prepare(&sql)?
+            .query_map(&params_sql[..], Track::try_from_row)?
+            .collect::<Result<Vec<Track>, Error>>()?)
+    }
+}
+
+/// A track that's likely the same recording as another.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Duplicate {
+    #[schema(example = "1")]
+    pub track: String,
+    /// How alike their audio is, 1 for the same file & 0 for unrelated audio
+    #[schema(example = 0.92)]
+    pub similarity: f64,
+}
+
+/// Tracks that are likely all the same recording.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DuplicateGroup {
+    #[schema(example = json!(["0", "1"]))]
+    pub tracks: Vec<String>,
+    /// The lowest similarity that put a track in the group
+    #[schema(example = 0.92)]
+    pub similarity: f64,
 }
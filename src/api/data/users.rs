@@ -1,3 +1,4 @@
+use async_graphql::InputObject;
 use bcrypt::{hash, DEFAULT_COST};
 use rocket::serde::{Deserialize, Serialize};
 use rocket::{
@@ -5,8 +6,10 @@ use rocket::{
     outcome::Outcome,
     request::{self, FromRequest, Request},
 };
-use rocket_sync_db_pools::rusqlite::{params, params_from_iter, Error, Row, Transaction};
-use utoipa::{schema, ToSchema};
+use rocket_sync_db_pools::rusqlite::{
+    params, params_from_iter, Connection, Error, Row, Transaction,
+};
+use utoipa::ToSchema;
 
 use crate::{
     api::data::permissions::{permissions_from_row, Permission},
@@ -24,6 +27,10 @@ pub struct DangerousLogin {
     /// Your password
     #[schema(example = "jnoM76raK")]
     pub password: String,
+    /// Your TOTP or recovery code, required once you have confirmed a second factor
+    #[serde(default)]
+    #[schema(example = "123456")]
+    pub code: Option<String>,
 }
 
 impl DangerousLogin {
@@ -66,7 +73,7 @@ impl DangerousLogin {
 }
 
 /// The username and permissions of a user.
-#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct User {
     #[schema(example = "5-pebbles")]
@@ -75,6 +82,50 @@ pub struct User {
     pub permissions: Vec<Permission>,
 }
 
+/// How much audio a user has uploaded & how much they may.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Usage {
+    #[schema(example = "5-pebbles")]
+    pub username: String,
+    /// The bytes of audio they uploaded, a file shared by several tracks counts once per track
+    pub used: u64,
+    /// The number of tracks they uploaded audio for
+    pub files: u64,
+    /// The bytes they may upload, `null` is unlimited
+    #[schema(example = 1073741824)]
+    pub quota: Option<u64>,
+}
+
+impl Usage {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        Ok(Usage {
+            username: row.get(0)?,
+            used: row.get(1)?,
+            files: row.get(2)?,
+            quota: row.get(3)?,
+        })
+    }
+}
+
+/// A user's storage quota.
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Quota {
+    /// The bytes of audio they may upload, `null` is unlimited
+    #[schema(example = 1073741824)]
+    pub quota: Option<u64>,
+}
+
+/// Whether `quota` is no more than `limit`, `None` being unlimited.
+pub fn within_quota(quota: Option<u64>, limit: Option<u64>) -> bool {
+    match (quota, limit) {
+        (_, None) => true,
+        (Some(quota), Some(limit)) => quota <= limit,
+        (None, Some(_)) => false,
+    }
+}
+
 impl User {
     pub fn try_from_row(row: &Row) -> Result<Self, Error> {
         let permissions: Vec<Permission> = permissions_from_row(row)?;
@@ -83,6 +134,189 @@ impl User {
             permissions,
         })
     }
+
+    fn has_all<'a>(&self, mut permissions: impl Iterator<Item = &'a Permission>) -> bool {
+        permissions.all(|permission| self.permissions.contains(permission))
+    }
+
+    /// The permissions of another user, `404 Not Found` if they don't exist.
+    fn permissions_of(conn: &Connection, username: &str) -> Result<Vec<Permission>, ApiError> {
+        // we cant select directly from the user_permissions table because the user might not have any permissions
+        Ok(conn.query_row(
+            "SELECT GROUP_CONCAT(DISTINCT user_permissions.id) AS permissions FROM users
+            LEFT JOIN user_permissions ON users.username = user_permissions.username
+            WHERE users.username = ? GROUP BY users.username",
+            params![username],
+            permissions_from_row,
+        )?)
+    }
+
+    /// Delete a user, you need `UserDelete` & all their permissions unless it's yourself.
+    pub fn delete_user(&self, conn: &Connection, username: &str) -> Result<(), ApiError> {
+        if username != self.username {
+            let required_permissions = Self::permissions_of(conn, username)?;
+            if !self.has_all(required_permissions.iter().chain([&Permission::UserDelete])) {
+                Err(Status::Forbidden)?
+            }
+        }
+
+        conn.execute("DELETE FROM users WHERE username = ?", params![username])?;
+        Ok(())
+    }
+
+    /// Set a user's quota, you need `QuotaWrite` & all their permissions, & it can't be higher than your own.
+    pub fn set_quota(
+        &self,
+        conn: &Connection,
+        username: &str,
+        quota: Option<u64>,
+    ) -> Result<(), ApiError> {
+        if !self.permissions.contains(&Permission::QuotaWrite)
+            || !self.has_all(Self::permissions_of(conn, username)?.iter())
+        {
+            Err(Status::Forbidden)?
+        }
+
+        let own: Option<u64> = conn.query_row(
+            "SELECT quota FROM users WHERE username = ?",
+            params![self.username],
+            |row| row.get(0),
+        )?;
+        if !within_quota(quota, own) {
+            Err(Status::Forbidden)?
+        }
+
+        conn.execute(
+            "UPDATE users SET quota = ?1 WHERE username = ?2",
+            params![quota, username],
+        )?;
+        Ok(())
+    }
+
+    /// Grant a user permissions, you need `PermissionAdd` & the permissions you grant.
+    pub fn grant(
+        &self,
+        conn: &Connection,
+        username: &str,
+        permissions: &[Permission],
+    ) -> Result<(), ApiError> {
+        if !self.has_all(permissions.iter().chain([&Permission::PermissionAdd])) {
+            Err(Status::Forbidden)?
+        }
+        if permissions.is_empty() {
+            return Ok(());
+        }
+
+        let sql = format!(
+            "INSERT OR IGNORE INTO user_permissions (id, username) VALUES {};",
+            permissions
+                .iter()
+                .map(|_| "\n (?, ?)".to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        );
+
+        let params = params_from_iter(
+            permissions
+                .iter()
+                .flat_map(|&p| [<&'static str>::from(p), username]),
+        );
+
+        conn.execute(&sql, params)?;
+        Ok(())
+    }
+
+    /// Revoke a user's permissions, you need `PermissionDelete` & all their permissions.
+    pub fn revoke(
+        &self,
+        conn: &Connection,
+        username: &str,
+        permissions: &[Permission],
+    ) -> Result<(), ApiError> {
+        let required_permissions = conn.query_row(
+            "SELECT GROUP_CONCAT(DISTINCT id) AS permissions FROM user_permissions
+            WHERE username = ?",
+            params![username],
+            permissions_from_row,
+        )?;
+        if !self.has_all(
+            required_permissions
+                .iter()
+                .chain([&Permission::PermissionDelete]),
+        ) {
+            Err(Status::Forbidden)?
+        }
+
+        let permission_placeholders = permissions
+            .iter()
+            .map(|_| "?")
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let params = params_from_iter(
+            std::iter::once(username).chain(permissions.iter().map(|&p| <&'static str>::from(p))),
+        );
+
+        conn.execute(
+            &format!(
+                "DELETE FROM user_permissions WHERE username = ? AND id IN ({})",
+                permission_placeholders
+            ),
+            params,
+        )?;
+        Ok(())
+    }
+}
+
+/// The filters of `GET /user`, which are also the arguments of the GraphQL `users` query.
+#[derive(Default, InputObject)]
+pub struct UserFilter {
+    pub username: Option<String>,
+    /// Permissions the users all have
+    pub permissions: Option<Vec<Permission>>,
+}
+
+impl UserFilter {
+    /// The matching users, all of them unless there's a `limit`.
+    pub fn select(
+        self,
+        conn: &Connection,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<User>, Error> {
+        let mut sql = "SELECT users.username AS username, COALESCE(GROUP_CONCAT(DISTINCT user_permissions.id), '') AS permissions
+        FROM users
+        LEFT JOIN user_permissions ON users.username = user_permissions.username
+        WHERE 1=1".to_string();
+        let mut params = Vec::new();
+
+        if let Some(username_val) = self.username {
+            sql += " AND users.username LIKE ?";
+            params.push(format!("%{}%", username_val));
+        }
+        if let Some(permissions_val) = self.permissions {
+            sql += &format!(
+                " AND users.username IN (SELECT username FROM user_permissions WHERE id IN ({}) GROUP BY username HAVING COUNT(DISTINCT id) = {})",
+                permissions_val.iter().map(|_| "?").collect::<Vec<_>>().join(", "),
+                permissions_val.len()
+            );
+            params.extend(permissions_val.into_iter().map(|p| p.to_string()));
+        }
+
+        sql += " GROUP BY users.username";
+        if limit.is_some() || offset.is_some() {
+            // -1 is no limit, which an offset still needs
+            sql += &format!(
+                " LIMIT {} OFFSET {}",
+                limit.map_or(-1, i32::from),
+                offset.unwrap_or(0)
+            );
+        }
+
+        conn.prepare(&sql)?
+            .query_map(params_from_iter(params), User::try_from_row)?
+            .collect()
+    }
 }
 
 #[rocket::async_trait]
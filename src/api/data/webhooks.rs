@@ -0,0 +1,87 @@
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use rocket::serde::{Deserialize, Serialize};
+use rocket_sync_db_pools::rusqlite::{Error, Row};
+use sha2::Sha256;
+use utoipa::ToSchema;
+
+/// A url that receives a POST for every matching change event.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Webhook {
+    #[serde(skip_deserializing)]
+    #[schema(example = "1e9c0cde-7b0a-4d5b-bd0a-5b5c1b0e3c1f")]
+    pub id: String,
+    #[schema(example = "http://localhost:8080/tagger")]
+    pub url: String,
+    /// The event types to deliver, `<resource>.<action>` e.g. `audio.create`, `<resource>.*` or `*`, all events if empty
+    #[serde(default)]
+    #[schema(example = json!(["audio.create", "audio.update"]))]
+    pub events: Vec<String>,
+    /// The key used to sign deliveries, generated if not given & only ever returned on creation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(skip_deserializing)]
+    pub creator: String,
+}
+
+impl Webhook {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        Ok(Webhook {
+            id: row.get("id")?,
+            url: row.get("url")?,
+            events: row
+                .get::<&str, Option<String>>("events")?
+                .map_or_else(Vec::new, |v| v.split(',').map(|s| s.to_string()).collect()),
+            secret: None,
+            creator: row.get("creator")?,
+        })
+    }
+}
+
+/// A single event sent (or to be sent) to a webhook.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookDelivery {
+    #[schema(example = 7)]
+    pub id: u64,
+    pub webhook: String,
+    #[schema(example = "audio.create")]
+    pub event: String,
+    /// The JSON body that is POSTed
+    pub payload: String,
+    pub attempts: u32,
+    /// The HTTP status of the last attempt
+    pub status: Option<u16>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub delivered: bool,
+    /// When the next attempt is due as a unix timestamp, if there is one
+    pub next_attempt: Option<u64>,
+    pub created: u64,
+}
+
+impl WebhookDelivery {
+    pub fn try_from_row(row: &Row) -> Result<Self, Error> {
+        Ok(WebhookDelivery {
+            id: row.get("id")?,
+            webhook: row.get("webhook_id")?,
+            event: row.get("event")?,
+            payload: row.get("payload")?,
+            attempts: row.get("attempts")?,
+            status: row.get("status")?,
+            error: row.get("error")?,
+            delivered: row.get("delivered")?,
+            next_attempt: row.get("next_attempt")?,
+            created: row.get("created")?,
+        })
+    }
+}
+
+/// The value of the `X-Tuna-Signature` header: `sha256=` followed by the hex HMAC of the body.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(body);
+    format!("sha256={}", HEXLOWER.encode(&mac.finalize().into_bytes()))
+}
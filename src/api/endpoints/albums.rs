@@ -1,67 +1,68 @@
-use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
-use rocket_sync_db_pools::rusqlite::{params, Error::QueryReturnedNoRows, ToSql};
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::{params, Error::QueryReturnedNoRows};
 
 use crate::{
-    api::data::{albums::Album, permissions::Permission, users::User},
+    api::data::{
+        albums::{Album, AlbumFilter},
+        events::{Action, Resource},
+        permissions::Permission,
+        users::User,
+    },
+    audio::loudness,
     database::MyDatabase,
     error::ApiError,
+    events::Events,
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
 
 #[post("/album", data = "<album>")]
-async fn album_write(db: MyDatabase, user: User, album: Json<Album>) -> Result<Json<Album>> {
+async fn album_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    album: Json<Album>,
+) -> Result<Json<Album>> {
     if !user.permissions.contains(&Permission::AlbumWrite) {
         Err(Status::Forbidden)?
     }
 
-    let album = album.into_inner();
+    let mut album = album.into_inner();
+    let log = events.log();
 
-    db.run(move |conn| -> Result<Json<Album>> {
-        let tx = conn.transaction()?;
+    let (album, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        tx.execute(
-            "INSERT INTO albums (id, name, release) VALUES (?1, ?2, ?3)",
-            params![album.id, album.name, album.release],
-        )?;
+            album.insert(&tx)?;
+            let event = log.record(&tx, Resource::Album, Action::Create, album.id.clone())?;
 
-        for artist in album.artists.iter() {
-            tx.execute(
-                "INSERT INTO artist_albums (artist_id, album_id) VALUES (?1, ?2)",
-                params![artist, album.id],
-            )?;
-        }
+            tx.commit()?;
 
-        for track in album.tracks.iter() {
-            tx.execute(
-                "INSERT INTO album_tracks (album_id, track_id) VALUES (?1, ?2)",
-                params![album.id, track],
-            )?;
-        }
+            Ok((album, event))
+        })
+        .await?;
 
-        for genre in album.genres.iter() {
-            tx.execute(
-                "INSERT INTO album_genres (album_id, genre_id) VALUES (?1, ?2)",
-                params![album.id, genre],
-            )?;
-        }
-
-        tx.commit()?;
+    events.send([event]);
 
-        Ok(Json(album))
-    })
-    .await
+    Ok(Json(album))
 }
 
-#[get("/album?<id>&<name>&<maxrelease>&<minrelease>&<genres>&<maxcount>&<mincount>&<limit>")]
+#[get("/album?<id>&<name>&<maxrelease>&<minrelease>&<kind>&<label>&<genres>&<descendants>&<upc>&<mbid>&<discogs>&<maxcount>&<mincount>&<limit>")]
 async fn album_get(
     db: MyDatabase,
     user: User,
     id: Option<String>,
     name: Option<String>,
-    maxrelease: Option<u16>,
-    minrelease: Option<u16>,
+    maxrelease: Option<String>,
+    minrelease: Option<String>,
+    kind: Option<String>,
+    label: Option<String>,
     genres: Option<Json<Vec<String>>>,
+    descendants: Option<bool>,
+    upc: Option<String>,
+    mbid: Option<String>,
+    discogs: Option<String>,
     maxcount: Option<u16>,
     mincount: Option<u16>,
     limit: Option<u16>,
@@ -70,112 +71,67 @@ async fn album_get(
         Err(Status::Forbidden)?
     }
 
-    db.run(move |conn| -> Result<Json<Vec<Album>>> {
-        let mut sql = "SELECT albums.id, albums.name, albums.release, COALESCE(GROUP_CONCAT(DISTINCT artist_albums.artist_id), ''), COALESCE(GROUP_CONCAT(DISTINCT album_tracks.track_id), ''), COALESCE(GROUP_CONCAT(DISTINCT album_genres.genre_id), '') AS genres FROM albums 
-            LEFT JOIN album_tracks ON albums.id = album_tracks.album_id
-            LEFT JOIN artist_albums ON albums.id = artist_albums.album_id
-            LEFT JOIN album_genres ON albums.id = album_genres.album_id WHERE 1=1".to_string();
-        let mut params_vec = Vec::new();
-
-        if let Some(id_val) = id {
-            sql += " AND albums.id = ?";
-            params_vec.push(id_val);
-        }
-
-        if let Some(name_val) = name {
-            sql += " AND albums.name LIKE ?";
-            params_vec.push(format!("%{}%", name_val));
-        }
-
-        if let Some(minrelease_val) = minrelease {
-            sql += " AND albums.release >= ?";
-            params_vec.push(minrelease_val.to_string());
-        }
-
-        if let Some(maxrelease_val) = maxrelease {
-            sql += " AND albums.release <= ?";
-            params_vec.push(maxrelease_val.to_string());
-        }
-
-        if let Some(maxcount_val) = maxcount {
-            sql += " AND (SELECT COUNT(*) FROM album_tracks WHERE album_tracks.album_id = albums.id) <= ?";
-            params_vec.push(maxcount_val.to_string());
-        }
+    let filter = AlbumFilter {
+        id,
+        name,
+        maxrelease,
+        minrelease,
+        kind,
+        label,
+        genres: genres.map(Json::into_inner),
+        descendants,
+        upc,
+        mbid,
+        discogs,
+        maxcount,
+        mincount,
+    };
 
-        if let Some(mincount_val) = mincount {
-            sql += " AND (SELECT COUNT(*) FROM album_tracks WHERE album_tracks.album_id = albums.id) >= ?";
-            params_vec.push(mincount_val.to_string());
-        }
-
-        if let Some(genres_val) = genres {
-            let genres_val = genres_val.into_inner();
-            let genre_placeholders = genres_val.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
-            sql += &format!(" AND album_genres.genre_id IN ({})", genre_placeholders);
-            params_vec.extend(genres_val);
+    db.run(move |conn| -> Result<Json<Vec<Album>>> {
+        let mut albums = filter.select(conn, limit, None)?;
+        for album in albums.iter_mut() {
+            album.loudness = loudness::album(conn, &album.id)?;
         }
 
-        sql += &format!(" GROUP BY albums.id LIMIT {}", limit.unwrap_or(50));
-
-        let params_sql: Vec<&dyn ToSql> =
-            params_vec.iter().map(|param| param as &dyn ToSql).collect();
-
-        Ok(Json(
-            conn.prepare(&sql)?
-                .query_map(&params_sql[..], |row| {
-
-                    let artists_str: String = row.get(3)?;
-                    let artists: Vec<String> = artists_str.split(',')
-                        .filter_map(|s| if s.trim().is_empty() { None } else { Some(s.to_string()) })
-                        .collect();
-
-                    let tracks_str: String = row.get(4)?;
-                    let tracks: Vec<String> = tracks_str.split(',')
-                        .filter_map(|s| if s.trim().is_empty() { None } else { Some(s.to_string()) })
-                        .collect();
-
-                    let genres_str: String = row.get(5)?;
-                    let genres: Vec<String> = genres_str.split(',')
-                        .filter_map(|s| if s.trim().is_empty() { None } else { Some(s.to_string()) })
-                        .collect();
-
-                    Ok(Album {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        release: row.get(2)?,
-                        artists,
-                        tracks,
-                        genres,
-                    })
-                })?
-                .map(|v| v.map_err(ApiError::from))
-                .collect::<Result<Vec<Album>>>()?,
-        ))
+        Ok(Json(albums))
     })
     .await
 }
 
 #[delete("/album/<id>")]
-async fn album_delete(db: MyDatabase, user: User, id: String) -> Result<()> {
+async fn album_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+) -> Result<()> {
     if !user.permissions.contains(&Permission::AlbumDelete) {
         Err(Status::Forbidden)?
     }
 
-    db.run(move |conn| -> Result<()> {
-        let tx = conn.transaction()?;
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        if let Err(QueryReturnedNoRows) =
-            tx.query_row("SELECT 1 FROM albums WHERE id = ?", params![id], |_| Ok(()))
-        {
-            Err(Status::NotFound)?
-        }
+            if let Err(QueryReturnedNoRows) =
+                tx.query_row("SELECT 1 FROM albums WHERE id = ?", params![id], |_| Ok(()))
+            {
+                Err(Status::NotFound)?
+            }
 
-        tx.execute("DELETE FROM albums WHERE id = ?", params![id])?;
+            tx.execute("DELETE FROM albums WHERE id = ?", params![id])?;
+            let event = log.record(&tx, Resource::Album, Action::Delete, id)?;
 
-        tx.commit()?;
+            tx.commit()?;
 
-        Ok(())
-    })
-    .await
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
 }
 
 pub fn fairing() -> AdHoc {
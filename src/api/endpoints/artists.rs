@@ -1,143 +1,490 @@
-use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
-use rocket_sync_db_pools::rusqlite::{params, Error::QueryReturnedNoRows, ToSql};
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::{params, Connection, Error::QueryReturnedNoRows};
 
 use crate::{
-    api::data::{artists::Artist, permissions::Permission, users::User},
+    api::data::{
+        artists::{
+            Artist, ArtistAlias, ArtistDetails, ArtistFilter, ArtistRelation, ArtistRelationKind,
+        },
+        events::{Action, Resource},
+        permissions::Permission,
+        users::User,
+    },
+    artists,
     database::MyDatabase,
     error::ApiError,
+    events::Events,
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
 
 #[post("/artist", data = "<artist>")]
-async fn artist_write(db: MyDatabase, user: User, artist: Json<Artist>) -> Result<Json<Artist>> {
+async fn artist_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    artist: Json<Artist>,
+) -> Result<Json<Artist>> {
     if !user.permissions.contains(&Permission::ArtistWrite) {
         Err(Status::Forbidden)?
     }
 
-    let artist = artist.into_inner();
-
-    db.run(move |conn| -> Result<Json<Artist>> {
-        let tx = conn.transaction()?;
-
-        tx.execute(
-            "INSERT INTO artists (id, name, bio) VALUES (?1, ?2, ?3)",
-            params![artist.id, artist.name, artist.bio],
-        )?;
-
-        for genre in artist.genres.iter() {
-            let genre_id: String = tx
-                .query_row(
-                    "SELECT id FROM genres WHERE id = ?",
-                    params![genre],
-                    |row| row.get(0),
-                )
-                .map_err(|e| match e {
-                    QueryReturnedNoRows => {
-                        ApiError::RusqliteError((Status::BadRequest, "Genre Not Found".to_string()))
-                    }
-                    e => ApiError::from(e),
-                })?;
-            tx.execute(
-                "INSERT INTO artist_genres (artist_id, genre_id) VALUES (?1, ?2)",
-                params![artist.id, genre_id],
-            )?;
-        }
+    let mut artist = artist.into_inner();
+    let log = events.log();
 
-        tx.commit()?;
+    let (artist, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        Ok(Json(artist))
-    })
-    .await
+            artist.insert(&tx)?;
+            let event = log.record(&tx, Resource::Artist, Action::Create, artist.id.clone())?;
+
+            tx.commit()?;
+
+            Ok((artist, event))
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(Json(artist))
 }
 
-#[get("/artist?<id>&<name>&<genres>&<limit>")]
+#[get("/artist?<id>&<name>&<genres>&<descendants>&<mbid>&<discogs>&<limit>")]
 async fn artist_get(
     db: MyDatabase,
     user: User,
     id: Option<String>,
     name: Option<String>,
     genres: Option<Json<Vec<String>>>,
+    descendants: Option<bool>,
+    mbid: Option<String>,
+    discogs: Option<String>,
     limit: Option<u16>,
 ) -> Result<Json<Vec<Artist>>> {
     if !user.permissions.contains(&Permission::ArtistRead) {
         Err(Status::Forbidden)?
     }
 
-    db.run(move |conn| -> Result<Json<Vec<Artist>>> {
-        let mut sql = "SELECT artists.id, artists.name, artists.bio, COALESCE(GROUP_CONCAT(artist_genres.genre_id), '') AS genres FROM artists
-            LEFT JOIN artist_genres ON artists.id = artist_genres.artist_id WHERE 1=1".to_string();
-        let mut params_vec = Vec::new();
-
-        if let Some(id_val) = id {
-            sql += " AND artists.id = ?";
-            params_vec.push(id_val);
-        }
-
-        if let Some(name_val) = name {
-            sql += " AND artists.name LIKE ?";
-            params_vec.push(format!("%{}%", name_val));
-        }
-
-        if let Some(genres_val) = genres {
-            let genres_val = genres_val.into_inner();
-            let genre_placeholders = genres_val.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
-            sql += &format!(" AND artist_genres.genre_id IN ({})", genre_placeholders);
-            params_vec.extend(genres_val);
-        }
-
-        sql += &format!(" GROUP BY artists.id LIMIT {}", limit.unwrap_or(50));
-
-        let params_sql: Vec<&dyn ToSql> =
-            params_vec.iter().map(|param| param as &dyn ToSql).collect();
-
-        Ok(Json(
-            conn.prepare(&sql)?
-                .query_map(&params_sql[..], |row| {
-                    let genres: String = row.get(3)?;
-                    let genres_vec: Vec<String> = genres.split(',').map(|s| s.to_string()).collect();
-                    Ok(Artist {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        bio: row.get(2)?,
-                        genres: genres_vec,
-                    })
-                })?
-                .map(|v| v.map_err(ApiError::from))
-                .collect::<Result<Vec<Artist>>>()?,
-        ))
-    })
-    .await
+    let filter = ArtistFilter {
+        id,
+        name,
+        genres: genres.map(Json::into_inner),
+        descendants,
+        mbid,
+        discogs,
+    };
+
+    db.run(move |conn| -> Result<Json<Vec<Artist>>> { Ok(Json(filter.select(conn, limit, None)?)) })
+        .await
 }
 
 #[delete("/artist/<id>")]
-async fn artist_delete(db: MyDatabase, user: User, id: String) -> Result<()> {
+async fn artist_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+) -> Result<()> {
     if !user.permissions.contains(&Permission::ArtistDelete) {
         Err(Status::Forbidden)?
     }
 
-    db.run(move |conn| -> Result<()> {
-        let tx = conn.transaction()?;
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        if let Err(QueryReturnedNoRows) = tx.query_row(
-            "SELECT 1 FROM artists WHERE id = ?",
-            params![id],
-            |_| Ok(()),
-        ) {
-            Err(Status::NotFound)?
-        }
+            if let Err(QueryReturnedNoRows) = tx.query_row(
+                "SELECT 1 FROM artists WHERE id = ?",
+                params![id],
+                |_| Ok(()),
+            ) {
+                Err(Status::NotFound)?
+            }
 
-        tx.execute("DELETE FROM artists WHERE id = ?", params![id])?;
+            tx.execute("DELETE FROM artists WHERE id = ?", params![id])?;
+            let event = log.record(&tx, Resource::Artist, Action::Delete, id)?;
 
-        tx.commit()?;
+            tx.commit()?;
 
-        Ok(())
-    })
-    .await
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
+}
+
+/// Retrieve an artist with its aliases & relationships to other artists.
+///
+/// Requires: `ArtistRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = ArtistDetails),
+        (status = 403, description = "Forbidden requires permission `ArtistRead`"),
+        (status = 404, description = "Not Found artist does not exist"),
+    ),
+    params(
+        ("id" = String, description = "The id of the artist")
+    ),
+    security(
+        ("permissions" = ["ArtistRead"])
+    ),
+)]
+#[get("/artist/<id>")]
+async fn artist_details_get(db: MyDatabase, user: User, id: String) -> Result<Json<ArtistDetails>> {
+    if !user.permissions.contains(&Permission::ArtistRead) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<Json<ArtistDetails>> { Ok(Json(artists::read(conn, &id)?)) })
+        .await
+}
+
+/// Add another name for an artist, e.g. a transliteration.
+///
+/// Aliases are searched by the `name` filter of `GET /artist` & matched when importing.
+///
+/// Requires: `ArtistWrite` permission.
+#[utoipa::path(
+    request_body = ArtistAlias,
+    responses(
+        (status = 200, description = "Success", body = ArtistDetails),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite`"),
+        (status = 404, description = "Not Found artist does not exist"),
+        (status = 409, description = "Conflict the artist already has that alias"),
+    ),
+    params(
+        ("id" = String, description = "The id of the artist")
+    ),
+    security(
+        ("permissions" = ["ArtistWrite"])
+    ),
+)]
+#[post("/artist/<id>/alias", data = "<alias>")]
+async fn artist_alias_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+    alias: Json<ArtistAlias>,
+) -> Result<Json<ArtistDetails>> {
+    if !user.permissions.contains(&Permission::ArtistWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let alias = alias.into_inner();
+    let log = events.log();
+
+    let (artist, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            exists(&tx, &id)?;
+            tx.execute(
+                "INSERT INTO artist_aliases (artist_id, name, kind, locale) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    id,
+                    alias.name,
+                    <&'static str>::from(alias.kind),
+                    alias.locale
+                ],
+            )?;
+            let artist = artists::read(&tx, &id)?;
+            let event = log.record(&tx, Resource::Artist, Action::Update, id)?;
+
+            tx.commit()?;
+
+            Ok((artist, event))
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(Json(artist))
+}
+
+/// Remove one of an artist's aliases.
+///
+/// Requires: `ArtistWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite`"),
+        (status = 404, description = "Not Found the artist does not have that alias"),
+    ),
+    params(
+        ("id" = String, description = "The id of the artist"),
+        ("name" = String, description = "The alias to remove"),
+    ),
+    security(
+        ("permissions" = ["ArtistWrite"])
+    ),
+)]
+#[delete("/artist/<id>/alias/<name>")]
+async fn artist_alias_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+    name: String,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::ArtistWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            if tx.execute(
+                "DELETE FROM artist_aliases WHERE artist_id = ?1 AND name = ?2",
+                params![id, name],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            let event = log.record(&tx, Resource::Artist, Action::Update, id)?;
+
+            tx.commit()?;
+
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
+}
+
+/// Relate an artist to another, e.g. make it a member of a group.
+///
+/// Requires: `ArtistWrite` permission.
+#[utoipa::path(
+    request_body = ArtistRelation,
+    responses(
+        (status = 200, description = "Success", body = ArtistDetails),
+        (status = 400, description = "Bad Request an artist can't be related to itself"),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite`"),
+        (status = 404, description = "Not Found either artist does not exist"),
+        (status = 409, description = "Conflict the artists are already related that way"),
+    ),
+    params(
+        ("id" = String, description = "The id of the artist")
+    ),
+    security(
+        ("permissions" = ["ArtistWrite"])
+    ),
+)]
+#[post("/artist/<id>/relation", data = "<relation>")]
+async fn artist_relation_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+    relation: Json<ArtistRelation>,
+) -> Result<Json<ArtistDetails>> {
+    if !user.permissions.contains(&Permission::ArtistWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let ArtistRelation { kind, artist } = relation.into_inner();
+    if artist == id {
+        Err(Status::BadRequest)?
+    }
+
+    let log = events.log();
+    let (artist, recorded) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            exists(&tx, &id)?;
+            exists(&tx, &artist)?;
+
+            // being the same person goes both ways
+            let reversed: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM artist_relations WHERE artist_id = ?1 AND related_id = ?2 AND kind = ?3)",
+                params![artist, id, <&'static str>::from(kind)],
+                |row| row.get(0),
+            )?;
+            if reversed && kind == ArtistRelationKind::SamePersonAs {
+                Err(Status::Conflict)?
+            }
+
+            tx.execute(
+                "INSERT INTO artist_relations (artist_id, related_id, kind) VALUES (?1, ?2, ?3)",
+                params![id, artist, <&'static str>::from(kind)],
+            )?;
+            let details = artists::read(&tx, &id)?;
+            let recorded = log.record_all(&tx, Resource::Artist, Action::Update, &[id, artist])?;
+
+            tx.commit()?;
+
+            Ok((details, recorded))
+        })
+        .await?;
+
+    events.send(recorded);
+
+    Ok(Json(artist))
+}
+
+/// Remove an artist's relationships to another, only those of `kind` if given.
+///
+/// Requires: `ArtistWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite`"),
+        (status = 404, description = "Not Found the artists aren't related that way"),
+    ),
+    params(
+        ("id" = String, description = "The id of the artist"),
+        ("related" = String, description = "The id of the other artist"),
+        ("kind" = Option<ArtistRelationKind>, Query, description = "The kind of relationship to remove"),
+    ),
+    security(
+        ("permissions" = ["ArtistWrite"])
+    ),
+)]
+#[delete("/artist/<id>/relation/<related>?<kind>")]
+async fn artist_relation_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+    related: String,
+    kind: Option<&str>,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::ArtistWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let kind = kind
+        .map(|kind| kind.parse::<ArtistRelationKind>())
+        .transpose()
+        .map_err(|_| Status::BadRequest)?
+        .map(<&'static str>::from);
+
+    let log = events.log();
+    let recorded = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            if tx.execute(
+                "DELETE FROM artist_relations WHERE artist_id = ?1 AND related_id = ?2 AND (?3 IS NULL OR kind = ?3)",
+                params![id, related, kind],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            let recorded = log.record_all(&tx, Resource::Artist, Action::Update, &[id, related])?;
+
+            tx.commit()?;
+
+            Ok(recorded)
+        })
+        .await?;
+
+    events.send(recorded);
+
+    Ok(())
+}
+
+/// Merge an artist into another.
+///
+/// The other artist gets the artist's albums (& so the tracks credited to them), genres, aliases & relationships.
+/// The artist is deleted & its name becomes an alias of the other artist.
+///
+/// Requires: `ArtistWrite` & `ArtistDelete` permissions.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = ArtistDetails),
+        (status = 400, description = "Bad Request can't merge an artist into itself"),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite` & `ArtistDelete`"),
+        (status = 404, description = "Not Found either artist does not exist"),
+    ),
+    params(
+        ("id" = String, description = "The id of the artist to merge away"),
+        ("into" = String, description = "The id of the artist to keep"),
+    ),
+    security(
+        ("permissions" = ["ArtistWrite", "ArtistDelete"])
+    ),
+)]
+#[post("/artist/<id>/merge/<into>")]
+async fn artist_merge(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+    into: String,
+) -> Result<Json<ArtistDetails>> {
+    if ![Permission::ArtistWrite, Permission::ArtistDelete]
+        .iter()
+        .all(|permission| user.permissions.contains(permission))
+    {
+        Err(Status::Forbidden)?
+    }
+    if id == into {
+        Err(Status::BadRequest)?
+    }
+
+    let log = events.log();
+    let (into, recorded) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            exists(&tx, &id)?;
+            exists(&tx, &into)?;
+            artists::merge(&tx, &id, &into)?;
+            let details = artists::read(&tx, &into)?;
+            let recorded = vec![
+                log.record(&tx, Resource::Artist, Action::Delete, id)?,
+                log.record(&tx, Resource::Artist, Action::Update, into)?,
+            ];
+
+            tx.commit()?;
+
+            Ok((details, recorded))
+        })
+        .await?;
+
+    events.send(recorded);
+
+    Ok(Json(into))
+}
+
+/// A `404 Not Found` if the artist does not exist.
+fn exists(conn: &Connection, id: &str) -> Result<()> {
+    if let Err(QueryReturnedNoRows) = conn.query_row(
+        "SELECT 1 FROM artists WHERE id = ?",
+        params![id],
+        |_| Ok(()),
+    ) {
+        Err(Status::NotFound)?
+    }
+    Ok(())
 }
 
 pub fn fairing() -> AdHoc {
     AdHoc::on_ignite("API Artist EndPoints", |rocket| async {
-        rocket.mount("/", routes![artist_write, artist_get, artist_delete])
+        rocket.mount(
+            "/",
+            routes![
+                artist_write,
+                artist_get,
+                artist_details_get,
+                artist_delete,
+                artist_alias_write,
+                artist_alias_delete,
+                artist_relation_write,
+                artist_relation_delete,
+                artist_merge
+            ],
+        )
     })
 }
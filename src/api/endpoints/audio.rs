@@ -1,19 +1,31 @@
 use rocket::{
-    data::ToByteUnit, fairing::AdHoc, fs::NamedFile, http::Status, tokio::fs::remove_file, Data,
+    fairing::AdHoc,
+    http::{ContentType, Status},
+    serde::json::Json,
+    Data, Either, State,
 };
 
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use crate::{
-    api::data::{permissions::Permission, users::User},
-    database::MyDatabase,
+    api::data::{
+        audio::{AudioGarbage, AudioInfo, GainMode, Peaks, Verification},
+        permissions::Permission,
+        users::User,
+    },
+    audio::{self, peaks, Blobs},
+    database::{Connections, MyDatabase},
     error::ApiError,
+    events::Events,
+    storage::{Download, Storage},
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
 
 /// Upload the audio file for a track.
 ///
+/// The file is stored by its SHA-256, so tracks with the same recording share one copy.
+///
 /// Requires: `AudioWrite` permission.
 #[utoipa::path(
     request_body(
@@ -33,6 +45,10 @@ type Result<T> = std::result::Result<T, ApiError>;
     (
         status = 404,
         description = "The track does not exist",
+    ),
+    (
+        status = 413,
+        description = "Payload Too Large the file is larger than `audio.limit`, the previous audio is kept",
     )),
     params(
         ("track", description = "The id of track for which you are uploading audio"),
@@ -42,7 +58,15 @@ type Result<T> = std::result::Result<T, ApiError>;
     ),
 )]
 #[put("/audio/<track>", format = "audio/mpeg", data = "<data>")]
-async fn audio_upload(db: MyDatabase, user: User, track: &str, data: Data<'_>) -> Result<()> {
+async fn audio_upload(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    blobs: &State<Blobs>,
+    content_type: &ContentType,
+    track: &str,
+    data: Data<'_>,
+) -> Result<()> {
     if !user.permissions.contains(&Permission::AudioWrite) {
         Err(Status::Forbidden)?
     }
@@ -64,19 +88,26 @@ async fn audio_upload(db: MyDatabase, user: User, track: &str, data: Data<'_>) -
         Err(Status::NotFound)?
     }
 
-    // save the audio file
-    data.open(20.megabytes())
-        .into_file(
-            Path::new("./database/audio")
-                .join(track)
-                .with_extension("mp3"),
-        )
+    // hash the audio file, then store it unless an identical one already is
+    let limit = blobs.config().limit;
+    let staged = blobs.stage(Box::pin(data.open(limit + 1)), limit).await?;
+    let mime = format!("{}/{}", content_type.top(), content_type.sub());
+    let event = blobs
+        .upload(&db, events.log(), track, &staged, mime, &user.username)
         .await?;
+
+    events.send([event]);
+
     Ok(())
 }
 
 /// Get the audio file for a track.
 ///
+/// With the `s3` storage backend this redirects to a short-lived link to the file in the bucket, unless a gain is applied.
+///
+/// With `gain` the track's ReplayGain gain is applied to MP3 audio on the way out, in steps of 1.5dB without re-encoding it.
+/// It's lowered to keep the true peak under 0 dBTP & audio that hasn't been measured yet is sent as it is.
+///
 /// Requires: `AudioRead` permission.
 #[utoipa::path(
     responses(
@@ -86,6 +117,10 @@ async fn audio_upload(db: MyDatabase, user: User, track: &str, data: Data<'_>) -
         content_type = "audio/mpeg",
         body = String,
     ),
+    (
+        status = 307,
+        description = "Temporary Redirect to the file in the storage bucket",
+    ),
     (
         status = 403,
         description = "Forbidden requires permission `AudioRead`",
@@ -93,27 +128,143 @@ async fn audio_upload(db: MyDatabase, user: User, track: &str, data: Data<'_>) -
     (
         status = 404,
         description = "The requested audio does not exist",
+    ),
+    (
+        status = 415,
+        description = "Unsupported Media Type a gain was asked for & the audio is not MP3",
     )),
     params(
         ("track", description = "The id of the track who's audio you are downloading"),
+        ("gain" = Option<GainMode>, Query, description = "Apply the track's or its album's ReplayGain gain"),
     ),
     security(
         ("permissions" = ["AudioRead"])
     ),
 )]
-#[get("/audio/<track>")]
-async fn audio_get(user: User, track: PathBuf) -> Result<Option<NamedFile>> {
+#[get("/audio/<track>?<gain>")]
+async fn audio_get(
+    db: MyDatabase,
+    user: User,
+    blobs: &State<Blobs>,
+    storage: &State<Storage>,
+    track: PathBuf,
+    gain: Option<GainMode>,
+) -> Result<Option<Download>> {
     if !user.permissions.contains(&Permission::AudioRead) {
         Err(Status::Forbidden)?
     }
 
-    Ok(NamedFile::open(
-        Path::new("./database/audio")
-            .join(track)
-            .with_extension("mp3"),
-    )
-    .await
-    .ok())
+    let Some(info) = audio::info(&db, &track.to_string_lossy()).await? else {
+        return Ok(None);
+    };
+
+    if let Some(mode) = gain {
+        return blobs.download_with_gain(&db, &info, mode).await;
+    }
+
+    let content_type = ContentType::parse_flexible(&info.mime).unwrap_or(ContentType::Binary);
+    storage
+        .audio
+        .download(&Storage::blob_key(&info.hash), &content_type)
+        .await
+}
+
+/// Get the hash, size, MIME type & upload time of a track's audio file.
+///
+/// Requires: `AudioRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = AudioInfo),
+        (status = 403, description = "Forbidden requires permission `AudioRead`"),
+        (status = 404, description = "Not Found the track has no audio"),
+    ),
+    params(
+        ("track", description = "The id of the track"),
+    ),
+    security(
+        ("permissions" = ["AudioRead"])
+    ),
+)]
+#[get("/audio/<track>/info")]
+async fn audio_info_get(
+    db: MyDatabase,
+    user: User,
+    track: &str,
+) -> Result<Option<Json<AudioInfo>>> {
+    if !user.permissions.contains(&Permission::AudioRead) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(audio::info(&db, track).await?.map(Json))
+}
+
+/// Get the waveform of a track's audio, to draw it without downloading the file.
+///
+/// The peaks are found when the audio is analyzed after it's uploaded & cached until it's replaced.
+/// With `binary` they're sent as signed bytes instead, scaled so that ±127 is full scale & interleaved min, max, min, max...
+///
+/// Requires: `AudioRead` permission.
+#[utoipa::path(
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            content_type = ["application/json", "application/octet-stream"],
+            body = Peaks,
+        ),
+        (status = 400, description = "Bad Request `samples` is 0"),
+        (status = 403, description = "Forbidden requires permission `AudioRead`"),
+        (status = 404, description = "Not Found the track has no audio"),
+        (status = 422, description = "Unprocessable Entity the audio can't be decoded"),
+    ),
+    params(
+        ("track", description = "The id of the track"),
+        ("samples" = Option<usize>, Query, description = "How many slices to split the audio into, 1000 by default"),
+        ("binary" = Option<bool>, Query, description = "Send the peaks as bytes instead of JSON"),
+    ),
+    security(
+        ("permissions" = ["AudioRead"])
+    ),
+)]
+#[get("/audio/<track>/peaks?<samples>&<binary>")]
+async fn audio_peaks_get(
+    db: MyDatabase,
+    user: User,
+    blobs: &State<Blobs>,
+    track: &str,
+    samples: Option<usize>,
+    binary: Option<bool>,
+) -> Result<Option<Either<Json<Peaks>, (ContentType, Vec<u8>)>>> {
+    if !user.permissions.contains(&Permission::AudioRead) {
+        Err(Status::Forbidden)?
+    }
+
+    let samples = samples.unwrap_or(1000);
+    if samples == 0 {
+        Err(Status::BadRequest)?
+    }
+
+    let Some(info) = audio::info(&db, track).await? else {
+        return Ok(None);
+    };
+    let Some(cached) = blobs.peaks(&info).await? else {
+        return Ok(None);
+    };
+    let peaks = peaks::resample(&cached, samples);
+
+    if binary.unwrap_or(false) {
+        let bytes = peaks
+            .into_iter()
+            .flat_map(|(min, max)| [min as u8, max as u8])
+            .collect();
+        return Ok(Some(Either::Right((ContentType::Binary, bytes))));
+    }
+
+    Ok(Some(Either::Left(Json(Peaks {
+        samples: peaks.len(),
+        min: peaks.iter().map(|(min, _)| *min as f32 / 127.0).collect(),
+        max: peaks.iter().map(|(_, max)| *max as f32 / 127.0).collect(),
+    }))))
 }
 
 /// Delete the audio file for a track.
@@ -141,27 +292,140 @@ async fn audio_get(user: User, track: PathBuf) -> Result<Option<NamedFile>> {
     ),
 )]
 #[delete("/audio/<track>")]
-async fn audio_delete(user: User, track: PathBuf) -> Result<()> {
+async fn audio_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    blobs: &State<Blobs>,
+    track: PathBuf,
+) -> Result<()> {
     if !user.permissions.contains(&Permission::AudioDelete) {
         Err(Status::Forbidden)?
     }
 
-    let path = Path::new("./database/audio")
-        .join(track)
-        .with_extension("mp3");
+    // the file itself is only deleted if no other track uses it
+    let event = blobs
+        .remove(&db, events.log(), &track.to_string_lossy())
+        .await?
+        .ok_or(Status::NotFound)?;
 
-    if !path.exists() {
-        Err(Status::NotFound)?
+    events.send([event]);
+
+    Ok(())
+}
+
+/// Find & remove audio that's out of step with the database.
+///
+/// That's files in the storage no record points to, stored files no track uses anymore & tracks whose file is missing (they no longer have audio afterwards).
+/// Uploads wait for the collection to finish.
+///
+/// Requires: `AudioDelete` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = AudioGarbage),
+        (status = 403, description = "Forbidden requires permission `AudioDelete`"),
+    ),
+    params(
+        ("dry_run" = Option<bool>, Query, description = "Report what would be removed without removing it"),
+    ),
+    security(
+        ("permissions" = ["AudioDelete"])
+    ),
+)]
+#[post("/audio/gc?<dry_run>")]
+async fn audio_gc(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    blobs: &State<Blobs>,
+    dry_run: Option<bool>,
+) -> Result<Json<AudioGarbage>> {
+    if !user.permissions.contains(&Permission::AudioDelete) {
+        Err(Status::Forbidden)?
     }
 
-    // use tokio::fs::remove_file because it is async
-    remove_file(path).await?;
+    let (garbage, recorded) = blobs
+        .collect(&db, events.log(), dry_run.unwrap_or(false))
+        .await?;
 
-    Ok(())
+    events.send(recorded);
+
+    Ok(Json(garbage))
+}
+
+/// Re-hash every stored audio file in the background.
+///
+/// Files that are missing or no longer match their hash are flagged as `corrupt` in `GET /audio/<track>/info` until they're uploaded again.
+/// Poll `GET /verification/<id>` for the progress & the files that didn't match.
+///
+/// Requires: `AudioVerify` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success the verification has started", body = Verification),
+        (status = 403, description = "Forbidden requires permission `AudioVerify`"),
+    ),
+    security(
+        ("permissions" = ["AudioVerify"])
+    ),
+)]
+#[post("/verification")]
+async fn verification_write(
+    connections: Connections,
+    user: User,
+    blobs: &State<Blobs>,
+) -> Result<Json<Verification>> {
+    if !user.permissions.contains(&Permission::AudioVerify) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(Json(blobs.verify(connections)))
+}
+
+/// Get the progress & report of a verification.
+///
+/// Verifications are kept in memory, they're gone after a restart.
+///
+/// Requires: `AudioVerify` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Verification),
+        (status = 403, description = "Forbidden requires permission `AudioVerify`"),
+        (status = 404, description = "Not Found the verification does not exist"),
+    ),
+    params(
+        ("id", description = "The id of the verification"),
+    ),
+    security(
+        ("permissions" = ["AudioVerify"])
+    ),
+)]
+#[get("/verification/<id>")]
+async fn verification_get(
+    user: User,
+    blobs: &State<Blobs>,
+    id: &str,
+) -> Result<Option<Json<Verification>>> {
+    if !user.permissions.contains(&Permission::AudioVerify) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(blobs.verification(id).map(Json))
 }
 
 pub fn fairing() -> AdHoc {
     AdHoc::on_ignite("API Audio Endpoints", |rocket| async {
-        rocket.mount("/", routes![audio_upload, audio_get, audio_delete])
+        rocket.mount(
+            "/",
+            routes![
+                audio_upload,
+                audio_get,
+                audio_info_get,
+                audio_peaks_get,
+                audio_delete,
+                audio_gc,
+                verification_write,
+                verification_get
+            ],
+        )
     })
 }
@@ -0,0 +1,146 @@
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+
+use crate::{
+    api::data::{
+        backups::{Backup, BackupManifest},
+        permissions::Permission,
+        users::User,
+    },
+    backups::Backups,
+    database::MyDatabase,
+    error::ApiError,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Take a snapshot of the database.
+///
+/// The snapshot is consistent even while the server is being written to.
+/// Only the newest `backups.keep` snapshots are kept.
+///
+/// Requires: `BackupWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Backup),
+        (status = 403, description = "Forbidden requires permission `BackupWrite`"),
+    ),
+    params(
+        ("manifest" = Option<bool>, Query, description = "Also write a manifest of the audio files"),
+    ),
+    security(
+        ("permissions" = ["BackupWrite"])
+    ),
+)]
+#[post("/backup?<manifest>")]
+async fn backup_write(
+    db: MyDatabase,
+    user: User,
+    backups: &State<Backups>,
+    manifest: Option<bool>,
+) -> Result<Json<Backup>> {
+    if !user.permissions.contains(&Permission::BackupWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(Json(backups.create(&db, manifest.unwrap_or(false)).await?))
+}
+
+/// List the snapshots, oldest first.
+///
+/// Requires: `BackupRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<Backup>),
+        (status = 403, description = "Forbidden requires permission `BackupRead`"),
+    ),
+    security(
+        ("permissions" = ["BackupRead"])
+    ),
+)]
+#[get("/backup")]
+async fn backup_get(user: User, backups: &State<Backups>) -> Result<Json<Vec<Backup>>> {
+    if !user.permissions.contains(&Permission::BackupRead) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(Json(backups.list()?))
+}
+
+/// Retrieve the audio manifest of a snapshot.
+///
+/// Requires: `BackupRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = BackupManifest),
+        (status = 403, description = "Forbidden requires permission `BackupRead`"),
+        (status = 404, description = "Not Found snapshot does not exist or has no manifest"),
+    ),
+    params(
+        ("id" = String, description = "The id of the snapshot"),
+    ),
+    security(
+        ("permissions" = ["BackupRead"])
+    ),
+)]
+#[get("/backup/<id>/manifest")]
+async fn backup_manifest_get(
+    user: User,
+    backups: &State<Backups>,
+    id: &str,
+) -> Result<Json<BackupManifest>> {
+    if !user.permissions.contains(&Permission::BackupRead) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(Json(backups.manifest(id)?))
+}
+
+/// Replace the database with a snapshot.
+///
+/// The snapshot is checked with `PRAGMA integrity_check` before anything is replaced.
+/// The current database is snapshotted first, that snapshot is returned so the restore can be undone.
+/// Users, tokens & permissions are restored too, so the session used to restore may no longer exist.
+/// Audio files are left as they are, `POST /audio/gc` finds tracks whose file was deleted since the snapshot.
+///
+/// Requires: `BackupRestore` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success the snapshot of the database before the restore", body = Backup),
+        (status = 403, description = "Forbidden requires permission `BackupRestore`"),
+        (status = 404, description = "Not Found snapshot does not exist"),
+        (status = 422, description = "Unprocessable Entity the snapshot is corrupt or not a tuna database"),
+    ),
+    params(
+        ("id" = String, description = "The id of the snapshot to restore"),
+    ),
+    security(
+        ("permissions" = ["BackupRestore"])
+    ),
+)]
+#[post("/backup/<id>/restore")]
+async fn backup_restore(
+    db: MyDatabase,
+    user: User,
+    backups: &State<Backups>,
+    id: &str,
+) -> Result<Json<Backup>> {
+    if !user.permissions.contains(&Permission::BackupRestore) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(Json(backups.restore(&db, id).await?))
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Backup EndPoints", |rocket| async {
+        rocket.mount(
+            "/",
+            routes![
+                backup_write,
+                backup_get,
+                backup_manifest_get,
+                backup_restore
+            ],
+        )
+    })
+}
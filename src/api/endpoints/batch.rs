@@ -0,0 +1,324 @@
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::Connection;
+
+use crate::{
+    api::data::{
+        albums::Album,
+        artists::Artist,
+        batch::{Batch, BatchItem, BatchMode},
+        events::{Action, ChangeEvent, Resource},
+        permissions::Permission,
+        tracks::Track,
+        users::User,
+    },
+    database::MyDatabase,
+    error::ApiError,
+    events::{EventLog, Events},
+    genres,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+fn mode(mode: Option<String>) -> Result<BatchMode> {
+    Ok(mode
+        .map(|mode| mode.parse())
+        .transpose()
+        .map_err(|_| Status::BadRequest)?
+        .unwrap_or_default())
+}
+
+/// Insert the items in one transaction, each in a savepoint so a failure only undoes its own item.
+///
+/// The inserts cache their statements on the connection, so they're prepared once for the whole batch.
+fn write<T>(
+    conn: &mut Connection,
+    log: EventLog,
+    resource: Resource,
+    mode: BatchMode,
+    items: &mut [T],
+    id: fn(&T) -> &str,
+    insert: fn(&mut T, &Connection) -> Result<()>,
+) -> Result<(Vec<BatchItem>, Vec<ChangeEvent>)> {
+    let mut tx = conn.transaction()?;
+    let mut results = Vec::with_capacity(items.len());
+
+    for item in items.iter_mut() {
+        let savepoint = tx.savepoint()?;
+        // a savepoint is rolled back when it's dropped without a commit
+        let (status, error) = match insert(item, &savepoint) {
+            Ok(()) => {
+                savepoint.commit()?;
+                (Status::Ok, None)
+            }
+            Err(e) => (e.status(), Some(e.to_string())),
+        };
+
+        results.push(BatchItem {
+            id: id(item).to_string(),
+            status: status.code,
+            error,
+        });
+    }
+
+    if mode == BatchMode::AllOrNothing && results.iter().any(|result| result.error.is_some()) {
+        for result in results.iter_mut().filter(|result| result.error.is_none()) {
+            result.status = Status::FailedDependency.code;
+            result.error = Some("Another Item Failed".to_string());
+        }
+        return Ok((results, Vec::new()));
+    }
+
+    let written = results
+        .iter()
+        .filter(|result| result.error.is_none())
+        .map(|result| result.id.clone())
+        .collect::<Vec<_>>();
+    let recorded = log.record_all(&tx, resource, Action::Create, &written)?;
+
+    tx.commit()?;
+
+    Ok((results, recorded))
+}
+
+/// Send the events of the items that were written.
+fn respond(
+    events: &Events,
+    (results, recorded): (Vec<BatchItem>, Vec<ChangeEvent>),
+) -> Result<(Status, Json<Vec<BatchItem>>)> {
+    events.send(recorded);
+
+    // when nothing was written the batch failed like its first failure
+    let status = results
+        .iter()
+        .find(|result| result.status != Status::FailedDependency.code)
+        .filter(|_| results.iter().all(|result| result.error.is_some()))
+        .and_then(|result| Status::from_code(result.status))
+        .unwrap_or(Status::Ok);
+
+    Ok((status, Json(results)))
+}
+
+/// Create many genres at once.
+///
+/// Requires: `GenreWrite` permission.
+#[utoipa::path(
+    request_body(
+        content = Vec<String>,
+        description = "The genres to create",
+    ),
+    responses(
+        (status = 200, description = "What happened to each genre", body = Vec<BatchItem>),
+        (status = 400, description = "Bad Request the mode is invalid"),
+        (status = 403, description = "Forbidden requires permission `GenreWrite`"),
+        (status = 409, description = "Nothing was written, the status of the first genre that failed", body = Vec<BatchItem>),
+    ),
+    params(
+        ("mode" = Option<BatchMode>, Query, description = "`all_or_nothing` (the default) or `best_effort`"),
+    ),
+    security(
+        ("permissions" = ["GenreWrite"])
+    ),
+)]
+#[post("/batch/genre?<mode>", data = "<genres>")]
+async fn genre_batch(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    mode: Option<String>,
+    genres: Batch<String>,
+) -> Result<(Status, Json<Vec<BatchItem>>)> {
+    if !user.permissions.contains(&Permission::GenreWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let mode = self::mode(mode)?;
+    let Batch(mut genres) = genres;
+    let log = events.log();
+
+    let written = db
+        .run(move |conn| {
+            write(
+                conn,
+                log,
+                Resource::Genre,
+                mode,
+                &mut genres,
+                |genre| genre,
+                |genre, conn| genres::create(conn, genre),
+            )
+        })
+        .await?;
+
+    respond(events, written)
+}
+
+/// Create many artists at once.
+///
+/// Requires: `ArtistWrite` permission.
+#[utoipa::path(
+    request_body(
+        content = Vec<Artist>,
+        description = "The artists to create, like `POST /artist`",
+    ),
+    responses(
+        (status = 200, description = "What happened to each artist", body = Vec<BatchItem>),
+        (status = 400, description = "Bad Request the mode is invalid"),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite`"),
+        (status = 409, description = "Nothing was written, the status of the first artist that failed", body = Vec<BatchItem>),
+    ),
+    params(
+        ("mode" = Option<BatchMode>, Query, description = "`all_or_nothing` (the default) or `best_effort`"),
+    ),
+    security(
+        ("permissions" = ["ArtistWrite"])
+    ),
+)]
+#[post("/batch/artist?<mode>", data = "<artists>")]
+async fn artist_batch(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    mode: Option<String>,
+    artists: Batch<Artist>,
+) -> Result<(Status, Json<Vec<BatchItem>>)> {
+    if !user.permissions.contains(&Permission::ArtistWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let mode = self::mode(mode)?;
+    let Batch(mut artists) = artists;
+    let log = events.log();
+
+    let written = db
+        .run(move |conn| {
+            write(
+                conn,
+                log,
+                Resource::Artist,
+                mode,
+                &mut artists,
+                |artist| &artist.id,
+                Artist::insert,
+            )
+        })
+        .await?;
+
+    respond(events, written)
+}
+
+/// Create many albums at once.
+///
+/// Requires: `AlbumWrite` permission.
+#[utoipa::path(
+    request_body(
+        content = Vec<Object>,
+        description = "The albums to create, like `POST /album`",
+    ),
+    responses(
+        (status = 200, description = "What happened to each album", body = Vec<BatchItem>),
+        (status = 400, description = "Bad Request the mode is invalid"),
+        (status = 403, description = "Forbidden requires permission `AlbumWrite`"),
+        (status = 409, description = "Nothing was written, the status of the first album that failed", body = Vec<BatchItem>),
+    ),
+    params(
+        ("mode" = Option<BatchMode>, Query, description = "`all_or_nothing` (the default) or `best_effort`"),
+    ),
+    security(
+        ("permissions" = ["AlbumWrite"])
+    ),
+)]
+#[post("/batch/album?<mode>", data = "<albums>")]
+async fn album_batch(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    mode: Option<String>,
+    albums: Batch<Album>,
+) -> Result<(Status, Json<Vec<BatchItem>>)> {
+    if !user.permissions.contains(&Permission::AlbumWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let mode = self::mode(mode)?;
+    let Batch(mut albums) = albums;
+    let log = events.log();
+
+    let written = db
+        .run(move |conn| {
+            write(
+                conn,
+                log,
+                Resource::Album,
+                mode,
+                &mut albums,
+                |album| &album.id,
+                Album::insert,
+            )
+        })
+        .await?;
+
+    respond(events, written)
+}
+
+/// Create many tracks at once.
+///
+/// Requires: `TrackWrite` permission.
+#[utoipa::path(
+    request_body(
+        content = Vec<Object>,
+        description = "The tracks to create, like `POST /track`",
+    ),
+    responses(
+        (status = 200, description = "What happened to each track", body = Vec<BatchItem>),
+        (status = 400, description = "Bad Request the mode is invalid"),
+        (status = 403, description = "Forbidden requires permission `TrackWrite`"),
+        (status = 409, description = "Nothing was written, the status of the first track that failed", body = Vec<BatchItem>),
+    ),
+    params(
+        ("mode" = Option<BatchMode>, Query, description = "`all_or_nothing` (the default) or `best_effort`"),
+    ),
+    security(
+        ("permissions" = ["TrackWrite"])
+    ),
+)]
+#[post("/batch/track?<mode>", data = "<tracks>")]
+async fn track_batch(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    mode: Option<String>,
+    tracks: Batch<Track>,
+) -> Result<(Status, Json<Vec<BatchItem>>)> {
+    if !user.permissions.contains(&Permission::TrackWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let mode = self::mode(mode)?;
+    let Batch(mut tracks) = tracks;
+    let log = events.log();
+
+    let written = db
+        .run(move |conn| {
+            write(
+                conn,
+                log,
+                Resource::Track,
+                mode,
+                &mut tracks,
+                |track| &track.id,
+                Track::insert,
+            )
+        })
+        .await?;
+
+    respond(events, written)
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Batch EndPoints", |rocket| async {
+        rocket.mount(
+            "/",
+            routes![genre_batch, artist_batch, album_batch, track_batch],
+        )
+    })
+}
@@ -0,0 +1,225 @@
+use rocket::{
+    fairing::AdHoc,
+    http::{ContentType, Status},
+    response::stream::ByteStream,
+    serde::json::Json,
+    tokio::fs::File,
+    Data, Either, State,
+};
+use tempfile::NamedTempFile;
+
+use crate::{
+    api::{
+        data::{
+            catalog::{CatalogRecord, CatalogReport, Conflict},
+            permissions::Permission,
+            users::User,
+        },
+        endpoints::imports::can_import,
+    },
+    audio::Blobs,
+    catalog::{self, CatalogConfig, TABLES},
+    database::{Connections, MyDatabase},
+    error::ApiError,
+    events::Events,
+    imports::extract,
+    storage::Storage,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Export every genre, artist, album, track, link, lyrics & audio file's metadata as JSON Lines.
+///
+/// Each line is a record tagged with its `type`: `genre`, `genre_parent`, `genre_alias`, `artist`, `artist_alias`,
+/// `artist_relation`, `album`, `track`, `external_id`, `artist_genre`, `artist_album`, `album_genre`, `album_track`,
+/// `track_genre`, `lyrics` or `audio`.
+/// The last line is an `end` record with the number of records before it, an export without it was cut short by an error.
+/// With `audio=true` a tar is returned instead, holding `catalog.ndjson` & the audio files as `audio/<track>.mp3`.
+/// Either can be imported into another server with `POST /catalog/import`.
+///
+/// Requires: `GenreRead`, `ArtistRead`, `AlbumRead`, `TrackRead` & `AudioRead` permissions.
+#[utoipa::path(
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            content_type = ["application/x-ndjson", "application/x-tar"],
+            body = String,
+            example = json!({"type": "artist", "id": "0", "name": "Phoebe Bridgers", "genres": [], "bio": ""}),
+        ),
+        (status = 403, description = "Forbidden requires permissions"),
+    ),
+    params(
+        ("audio" = Option<bool>, Query, description = "Bundle the audio files into a tar"),
+    ),
+    security(
+        ("permissions" = ["GenreRead", "ArtistRead", "AlbumRead", "TrackRead", "AudioRead"])
+    ),
+)]
+#[get("/export?<audio>")]
+async fn catalog_export(
+    connections: Connections,
+    user: User,
+    storage: &State<Storage>,
+    audio: Option<bool>,
+) -> Result<(ContentType, Either<ByteStream![Vec<u8>], File>)> {
+    if ![
+        Permission::GenreRead,
+        Permission::ArtistRead,
+        Permission::AlbumRead,
+        Permission::TrackRead,
+        Permission::AudioRead,
+    ]
+    .iter()
+    .all(|permission| user.permissions.contains(permission))
+    {
+        Err(Status::Forbidden)?
+    }
+
+    if audio.unwrap_or(false) {
+        let tar = NamedTempFile::new()?;
+        let file = tar.reopen()?;
+        catalog::write_tar(&connections, storage, file).await?;
+
+        // the open file outlives the temporary path
+        return Ok((
+            ContentType::new("application", "x-tar"),
+            Either::Right(File::open(tar.path()).await?),
+        ));
+    }
+
+    Ok((
+        ContentType::new("application", "x-ndjson"),
+        Either::Left(ByteStream! {
+            let mut records = 0;
+            for table in TABLES {
+                let mut after = 0;
+                loop {
+                    let page = catalog::read_page(&connections, table, after, 500)
+                        .await
+                        .and_then(|(page, last)| {
+                            let mut lines = Vec::new();
+                            for record in page.iter() {
+                                lines.extend(catalog::serialize(record)?.into_bytes());
+                                lines.push(b'\n');
+                            }
+                            Ok((lines, page.len(), last))
+                        });
+
+                    match page {
+                        Ok((lines, count, Some(last))) => {
+                            after = last;
+                            records += count;
+                            yield lines;
+                        }
+                        Ok((_, _, None)) => break,
+                        // without the end record the import refuses what was sent
+                        Err(e) => {
+                            error!("Failed to export catalog: {:?}", e);
+                            return;
+                        }
+                    }
+                }
+            }
+
+            if let Ok(end) = catalog::serialize(&CatalogRecord::End { records }) {
+                yield format!("{end}\n").into_bytes();
+            }
+        }),
+    ))
+}
+
+/// Import a catalog exported with `GET /export`, as JSON Lines or a tar bundling the audio.
+///
+/// The catalog has to end with its `end` record, so one that was cut short isn't applied at all.
+/// Records are applied in batches (`catalog.batch` in `Rocket.toml`), each in its own transaction.
+/// If a batch fails the batches before it are kept.
+///
+/// Requires: `ImportWrite`, `GenreWrite`, `ArtistWrite`, `AlbumWrite`, `TrackWrite` & `AudioWrite` permissions.
+#[utoipa::path(
+    request_body(
+        description = "The catalog, or with `Content-Type: application/x-tar` a tar holding `catalog.ndjson` & `audio/<track>.mp3` files",
+        content_type = "application/x-ndjson",
+        content = String,
+    ),
+    responses(
+        (status = 200, description = "Success every record was applied", body = CatalogReport),
+        (status = 400, description = "Bad Request the catalog is incomplete, a record could not be parsed or links something that does not exist"),
+        (status = 403, description = "Forbidden requires permissions"),
+        (status = 409, description = "Conflict a record already exists & the conflict policy is `fail`"),
+        (status = 413, description = "Payload Too Large the catalog is larger than `catalog.limit`, before or after it's extracted"),
+        (status = 415, description = "Unsupported Media Type the catalog isn't `application/x-ndjson` or `application/x-tar`"),
+    ),
+    params(
+        ("conflict" = Option<Conflict>, Query, description = "What to do with records that already exist, `fail` by default"),
+    ),
+    security(
+        ("permissions" = ["ImportWrite", "GenreWrite", "ArtistWrite", "AlbumWrite", "TrackWrite", "AudioWrite"])
+    ),
+)]
+#[post("/catalog/import?<conflict>", data = "<data>")]
+async fn catalog_import(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    blobs: &State<Blobs>,
+    config: &State<CatalogConfig>,
+    content_type: Option<&ContentType>,
+    conflict: Option<Conflict>,
+    data: Data<'_>,
+) -> Result<Json<CatalogReport>> {
+    if !can_import(&user) {
+        Err(Status::Forbidden)?
+    }
+
+    let bundle = match content_type.map(|content_type| (content_type.top(), content_type.sub())) {
+        Some((top, sub)) if top == "application" && sub == "x-tar" => true,
+        Some((top, sub)) if top == "application" && sub == "x-ndjson" => false,
+        _ => Err(Status::UnsupportedMediaType)?,
+    };
+
+    // kept on disk, it's read twice
+    let upload = NamedTempFile::new()?;
+    if !data
+        .open(config.limit)
+        .into_file(upload.path())
+        .await?
+        .is_complete()
+    {
+        Err(Status::PayloadTooLarge)?
+    }
+
+    let (catalog, dir) = match bundle {
+        true => {
+            let dir = extract(upload.path().to_path_buf(), config.limit).await?;
+            let catalog = dir.path().join("catalog.ndjson");
+            if !catalog.is_file() {
+                Err(ApiError::IoError((
+                    Status::BadRequest,
+                    "Missing catalog.ndjson".to_string(),
+                )))?
+            }
+            (catalog, Some(dir))
+        }
+        false => (upload.path().to_path_buf(), None),
+    };
+
+    Ok(Json(
+        catalog::import(
+            &db,
+            events,
+            blobs,
+            &catalog,
+            conflict.unwrap_or_default(),
+            config.batch,
+            dir.as_ref().map(|dir| dir.path().to_path_buf()),
+        )
+        .await?,
+    ))
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Catalog EndPoints", |rocket| async {
+        rocket.mount("/", routes![catalog_export, catalog_import])
+    })
+}
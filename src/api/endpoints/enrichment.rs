@@ -0,0 +1,333 @@
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::{params, Connection, ToSql};
+
+use crate::{
+    api::data::{
+        enrichment::{EnrichmentReport, Proposal, Suggestion},
+        events::{Action, ChangeEvent, Resource},
+        permissions::Permission,
+        users::User,
+    },
+    database::MyDatabase,
+    enrichment::{self, Created, Enrichment},
+    error::ApiError,
+    events::{EventLog, Events},
+    external_ids,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Record an update of each resource that was changed & the creation of everything that was created.
+fn record(
+    log: EventLog,
+    conn: &Connection,
+    changed: &[Proposal],
+    created: Created,
+) -> Result<Vec<ChangeEvent>> {
+    let mut updated: Vec<(Resource, &str)> = Vec::new();
+    for proposal in changed {
+        if !updated.contains(&(proposal.resource, proposal.target.as_str())) {
+            updated.push((proposal.resource, proposal.target.as_str()));
+        }
+    }
+
+    let mut recorded = Vec::new();
+    for (resource, id) in created {
+        recorded.push(log.record(conn, resource, Action::Create, id)?);
+    }
+    for (resource, id) in updated {
+        recorded.push(log.record(conn, resource, Action::Update, id)?);
+    }
+    Ok(recorded)
+}
+
+/// Look up an artist, album or track in MusicBrainz & propose the release dates, tracks, relationships, genres & ids it's missing.
+///
+/// Artists, albums & tracks without an MBID are searched for by name, the MBID of the match is proposed with the rest.
+/// Only missing release dates are filled in & relationships are only proposed with artists that already have an MBID.
+///
+/// With `apply` the changes you have the permissions for are made, everything else is saved as a suggestion.
+/// Changes also need write permission for what they change, `GenreWrite` for genres (they're created if they don't exist) & `TrackWrite` for tracks.
+///
+/// Requests to the service are rate limited & cached.
+///
+/// Requires: `EnrichWrite` & `ArtistRead`, `AlbumRead` or `TrackRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = EnrichmentReport),
+        (status = 403, description = "Forbidden requires permission `EnrichWrite` & `ArtistRead`, `AlbumRead` or `TrackRead`"),
+        (status = 404, description = "Not Found the artist, album or track does not exist"),
+        (status = 502, description = "Bad Gateway the service could not be reached or sent something unexpected"),
+        (status = 503, description = "Service Unavailable the service is rate limiting requests"),
+    ),
+    params(
+        ("resource" = String, description = "`artist`, `album` or `track`"),
+        ("id" = String, description = "The id of the artist, album or track"),
+        ("apply", Query, description = "Apply the changes you have the permissions for instead of saving them as suggestions"),
+    ),
+    security(
+        ("permissions" = ["EnrichWrite", "ArtistRead", "AlbumRead", "TrackRead"])
+    ),
+)]
+#[post("/enrich/<resource>/<id>?<apply>")]
+async fn enrich(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    enrichment: &State<Enrichment>,
+    resource: &str,
+    id: String,
+    apply: Option<bool>,
+) -> Result<Option<Json<EnrichmentReport>>> {
+    let Some(resource) = resource
+        .parse::<Resource>()
+        .ok()
+        .filter(|resource| external_ids::column(*resource).is_some())
+    else {
+        return Ok(None);
+    };
+    if ![Permission::EnrichWrite, resource.read_permission()]
+        .iter()
+        .all(|permission| user.permissions.contains(permission))
+    {
+        Err(Status::Forbidden)?
+    }
+
+    let Some(enriched) = enrichment.enrich(&db, resource, id).await? else {
+        return Ok(None);
+    };
+
+    let log = events.log();
+    let (applied, suggested, recorded) = db
+        .run(move |conn| -> Result<(Vec<Proposal>, Vec<Suggestion>, Vec<ChangeEvent>)> {
+            let tx = conn.transaction()?;
+            let mut applied = Vec::new();
+            let mut suggested = Vec::new();
+            let mut created = Vec::new();
+
+            for proposal in enriched.proposals {
+                let allowed = enrichment::permissions(&proposal)
+                    .iter()
+                    .all(|permission| user.permissions.contains(permission));
+
+                if apply.unwrap_or(false) && allowed {
+                    // an earlier change may have made it, e.g. two genres that are aliases of one
+                    if !enrichment::holds(&tx, &proposal)? {
+                        created.extend(enrichment::apply(&tx, &proposal)?);
+                        applied.push(proposal);
+                    }
+                    continue;
+                }
+
+                let params: [&dyn ToSql; 3] = [
+                    &<&'static str>::from(proposal.resource),
+                    &proposal.target,
+                    &enrichment::encode(&proposal.change),
+                ];
+                tx.execute(
+                    "INSERT OR IGNORE INTO enrichment_suggestions (resource, target, change) VALUES (?1, ?2, ?3)",
+                    params,
+                )?;
+                suggested.push(tx.query_row(
+                    "SELECT * FROM enrichment_suggestions WHERE resource = ?1 AND target = ?2 AND change = ?3",
+                    params,
+                    Suggestion::try_from_row,
+                )?);
+            }
+
+            let recorded = record(log, &tx, &applied, created)?;
+
+            tx.commit()?;
+
+            Ok((applied, suggested, recorded))
+        })
+        .await?;
+
+    events.send(recorded);
+
+    Ok(Some(Json(EnrichmentReport {
+        mbid: enriched.mbid,
+        requests: enriched.requests,
+        applied,
+        suggested,
+    })))
+}
+
+/// Retrieve suggestions saved by enrichment, oldest first.
+///
+/// Requires: `EnrichWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<Suggestion>),
+        (status = 403, description = "Forbidden requires permission `EnrichWrite`"),
+    ),
+    params(
+        ("resource", Query, description = "Only suggestions for `artist`s, `album`s or `track`s"),
+        ("target", Query, description = "Only suggestions for the artist, album or track with this id"),
+        ("limit", Query, description = "The maximum number of results to return"),
+    ),
+    security(
+        ("permissions" = ["EnrichWrite"])
+    ),
+)]
+#[get("/enrich/suggestion?<resource>&<target>&<limit>")]
+async fn suggestion_get(
+    db: MyDatabase,
+    user: User,
+    resource: Option<String>,
+    target: Option<String>,
+    limit: Option<u16>,
+) -> Result<Json<Vec<Suggestion>>> {
+    if !user.permissions.contains(&Permission::EnrichWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<Json<Vec<Suggestion>>> {
+        let mut sql = "SELECT * FROM enrichment_suggestions WHERE 1=1".to_string();
+        let mut params_vec = Vec::new();
+
+        if let Some(resource_val) = resource {
+            sql += " AND resource = ?";
+            params_vec.push(resource_val);
+        }
+
+        if let Some(target_val) = target {
+            sql += " AND target = ?";
+            params_vec.push(target_val);
+        }
+
+        sql += &format!(" ORDER BY id LIMIT {}", limit.unwrap_or(50));
+
+        let params_sql: Vec<&dyn ToSql> =
+            params_vec.iter().map(|param| param as &dyn ToSql).collect();
+
+        Ok(Json(
+            conn.prepare(&sql)?
+                .query_map(&params_sql[..], Suggestion::try_from_row)?
+                .map(|v| v.map_err(ApiError::from))
+                .collect::<Result<Vec<Suggestion>>>()?,
+        ))
+    })
+    .await
+}
+
+/// Apply a suggestion saved by enrichment & remove it.
+///
+/// A suggestion that's already in place is just removed.
+///
+/// Requires: `EnrichWrite` & write permission for what it changes, `GenreWrite` for genres & `TrackWrite` for tracks.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `EnrichWrite` & write permission for what it changes"),
+        (status = 404, description = "Not Found the suggestion or what it changes does not exist"),
+        (status = 409, description = "Conflict the external id belongs to another artist, album or track"),
+    ),
+    params(
+        ("id" = u64, description = "The id of the suggestion"),
+    ),
+    security(
+        ("permissions" = ["EnrichWrite", "ArtistWrite", "AlbumWrite", "TrackWrite", "GenreWrite"])
+    ),
+)]
+#[post("/enrich/suggestion/<id>/apply")]
+async fn suggestion_apply(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: u64,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::EnrichWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let suggestion = db
+        .run(move |conn| {
+            conn.query_row(
+                "SELECT * FROM enrichment_suggestions WHERE id = ?",
+                [id],
+                Suggestion::try_from_row,
+            )
+        })
+        .await?;
+    let proposal = Proposal {
+        resource: suggestion.resource,
+        target: suggestion.target,
+        change: suggestion.change,
+    };
+    if !enrichment::permissions(&proposal)
+        .iter()
+        .all(|permission| user.permissions.contains(permission))
+    {
+        Err(Status::Forbidden)?
+    }
+
+    let log = events.log();
+    let recorded = db
+        .run(move |conn| -> Result<Vec<ChangeEvent>> {
+            let tx = conn.transaction()?;
+
+            let recorded = match enrichment::holds(&tx, &proposal)? {
+                true => {
+                    tx.execute("DELETE FROM enrichment_suggestions WHERE id = ?", [id])?;
+                    Vec::new()
+                }
+                false => {
+                    let created = enrichment::apply(&tx, &proposal)?;
+                    record(log, &tx, &[proposal], created)?
+                }
+            };
+
+            tx.commit()?;
+
+            Ok(recorded)
+        })
+        .await?;
+
+    events.send(recorded);
+
+    Ok(())
+}
+
+/// Discard a suggestion saved by enrichment.
+///
+/// Requires: `EnrichWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `EnrichWrite`"),
+        (status = 404, description = "Not Found the suggestion does not exist"),
+    ),
+    params(
+        ("id" = u64, description = "The id of the suggestion"),
+    ),
+    security(
+        ("permissions" = ["EnrichWrite"])
+    ),
+)]
+#[delete("/enrich/suggestion/<id>")]
+async fn suggestion_delete(db: MyDatabase, user: User, id: u64) -> Result<Option<()>> {
+    if !user.permissions.contains(&Permission::EnrichWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let deleted = db
+        .run(move |conn| {
+            conn.execute(
+                "DELETE FROM enrichment_suggestions WHERE id = ?",
+                params![id],
+            )
+        })
+        .await?;
+
+    Ok((deleted != 0).then_some(()))
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Enrichment Endpoints", |rocket| async {
+        rocket.mount(
+            "/",
+            routes![enrich, suggestion_get, suggestion_apply, suggestion_delete],
+        )
+    })
+}
@@ -0,0 +1,112 @@
+use rocket::{
+    fairing::AdHoc,
+    request::{self, FromRequest, Request},
+    response::stream::{Event, EventStream},
+    tokio::select,
+    Shutdown, State,
+};
+
+use std::time::Duration;
+
+use crate::{
+    api::data::{events::ChangeEvent, users::User},
+    database::Connections,
+    error::ApiError,
+    events::Events,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `Last-Event-ID` header sent by reconnecting `EventSource` clients.
+pub struct LastEventId(Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(LastEventId(
+            request
+                .headers()
+                .get_one("Last-Event-ID")
+                .and_then(|id| id.trim().parse().ok()),
+        ))
+    }
+}
+
+fn to_sse(event: &ChangeEvent) -> Event {
+    Event::json(event)
+        .id(event.id.to_string())
+        .event(event.event_type())
+}
+
+/// Stream changes to genres, artists, albums, tracks, audio & users as server-sent events.
+///
+/// Each event's type is `<resource>.<action>` e.g. `track.create`, the data is a `ChangeEvent`.
+/// You only receive events for resources you have the `*Read` permission for.
+/// Reconnect with the `Last-Event-ID` header to replay missed events (as long as they are still in the backlog).
+/// With `follow=false` the stream ends once they have been replayed, instead of waiting for new ones.
+#[utoipa::path(
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            content_type = "text/event-stream",
+            body = ChangeEvent,
+        ),
+    ),
+    params(
+        ("Last-Event-ID" = Option<u64>, Header, description = "The id of the last event you received"),
+        ("follow" = Option<bool>, Query, description = "Whether to wait for new events, `true` by default"),
+    ),
+)]
+#[get("/events?<follow>")]
+async fn event_get(
+    connections: Connections,
+    user: User,
+    events: &State<Events>,
+    last_event_id: LastEventId,
+    follow: Option<bool>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![]> {
+    let follow = follow.unwrap_or(true);
+    let (missed, subscription) = match (follow, last_event_id.0) {
+        (true, last_id) => (Vec::new(), Some(events.subscribe(connections, last_id).await?)),
+        (false, Some(last_id)) => (Events::since(&connections, last_id).await?, None),
+        (false, None) => (Vec::new(), None),
+    };
+
+    let visible =
+        move |event: &ChangeEvent| user.permissions.contains(&event.resource.read_permission());
+
+    let stream = EventStream! {
+        for event in missed.iter().filter(|event| visible(event)) {
+            yield to_sse(event);
+        }
+
+        if let Some(mut subscription) = subscription {
+            loop {
+                let received = select! {
+                    received = subscription.next() => match received {
+                        Ok(Some(received)) => received,
+                        Ok(None) | Err(_) => break,
+                    },
+                    _ = &mut shutdown => break,
+                };
+
+                for event in received.iter().filter(|event| visible(event)) {
+                    yield to_sse(event);
+                }
+            }
+        }
+    };
+
+    // keep-alive comments are only needed while waiting
+    Ok(stream.heartbeat(follow.then_some(Duration::from_secs(30))))
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Event EndPoints", |rocket| async {
+        rocket.mount("/", routes![event_get])
+    })
+}
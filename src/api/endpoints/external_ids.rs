@@ -0,0 +1,238 @@
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::params;
+
+use crate::{
+    api::data::{
+        events::{Action, Resource},
+        external_ids::{ExternalId, ExternalSource},
+        users::User,
+    },
+    database::MyDatabase,
+    error::ApiError,
+    events::Events,
+    external_ids,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// An artist, album or track & its column in `external_ids`, `None` for anything else.
+fn target(resource: &str) -> Option<(Resource, &'static str)> {
+    let resource = resource.parse::<Resource>().ok()?;
+    Some((resource, external_ids::column(resource)?))
+}
+
+/// Retrieve the ids an artist, album or track has in outside databases.
+///
+/// Requires: `ArtistRead`, `AlbumRead` or `TrackRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<ExternalId>),
+        (status = 403, description = "Forbidden requires permission `ArtistRead`, `AlbumRead` or `TrackRead`"),
+        (status = 404, description = "Not Found the artist, album or track does not exist"),
+    ),
+    params(
+        ("resource" = String, description = "`artist`, `album` or `track`"),
+        ("id" = String, description = "The id of the artist, album or track"),
+    ),
+    security(
+        ("permissions" = ["ArtistRead", "AlbumRead", "TrackRead"])
+    ),
+)]
+#[get("/<resource>/<id>/external", rank = 2)]
+async fn external_id_get(
+    db: MyDatabase,
+    user: User,
+    resource: &str,
+    id: String,
+) -> Result<Option<Json<Vec<ExternalId>>>> {
+    let Some((resource, column)) = target(resource) else {
+        return Ok(None);
+    };
+    if !user.permissions.contains(&resource.read_permission()) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<Option<Json<Vec<ExternalId>>>> {
+        let exists: bool = conn.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {resource}s WHERE id = ?)"),
+            [&id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Ok(None);
+        }
+
+        Ok(Some(Json(external_ids::read(conn, column, &id)?)))
+    })
+    .await
+}
+
+/// Store an id an artist, album or track has in an outside database.
+///
+/// ISRCs are only for tracks, UPC/EAN barcodes only for albums & Discogs ids only for artists & albums.
+/// ISRCs have to be well-formed & barcodes need a valid check digit.
+/// An id can only belong to one artist, one album & one track.
+///
+/// Requires: `ArtistWrite`, `AlbumWrite` or `TrackWrite` permission.
+#[utoipa::path(
+    request_body = ExternalId,
+    responses(
+        (status = 200, description = "Success", body = Vec<ExternalId>),
+        (status = 400, description = "Bad Request the id isn't valid for the source or the source has no ids for the resource"),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite`, `AlbumWrite` or `TrackWrite`"),
+        (status = 404, description = "Not Found the artist, album or track does not exist"),
+        (status = 409, description = "Conflict the id already belongs to an artist, album or track"),
+    ),
+    params(
+        ("resource" = String, description = "`artist`, `album` or `track`"),
+        ("id" = String, description = "The id of the artist, album or track"),
+    ),
+    security(
+        ("permissions" = ["ArtistWrite", "AlbumWrite", "TrackWrite"])
+    ),
+)]
+#[post("/<resource>/<id>/external", data = "<external>", rank = 2)]
+async fn external_id_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    resource: &str,
+    id: String,
+    external: Json<ExternalId>,
+) -> Result<Option<Json<Vec<ExternalId>>>> {
+    let Some((resource, column)) = target(resource) else {
+        return Ok(None);
+    };
+    if !user
+        .permissions
+        .contains(&external_ids::write_permission(resource))
+    {
+        Err(Status::Forbidden)?
+    }
+
+    let ExternalId { source, value } = external.into_inner();
+    if !external_ids::accepts(source, resource) {
+        Err(Status::BadRequest)?
+    }
+    let value = external_ids::normalize(source, &value).ok_or(Status::BadRequest)?;
+
+    let log = events.log();
+    let written = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            let exists: bool = tx.query_row(
+                &format!("SELECT EXISTS(SELECT 1 FROM {resource}s WHERE id = ?)"),
+                [&id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Ok(None);
+            }
+
+            tx.execute(
+                &format!("INSERT INTO external_ids (source, value, {column}) VALUES (?1, ?2, ?3)"),
+                params![<&'static str>::from(source), value, id],
+            )?;
+            let ids = external_ids::read(&tx, column, &id)?;
+            let event = log.record(&tx, resource, Action::Update, id)?;
+
+            tx.commit()?;
+
+            Ok(Some((ids, event)))
+        })
+        .await?;
+    let Some((ids, event)) = written else {
+        return Ok(None);
+    };
+
+    events.send([event]);
+
+    Ok(Some(Json(ids)))
+}
+
+/// Remove an id an artist, album or track has in an outside database.
+///
+/// Requires: `ArtistWrite`, `AlbumWrite` or `TrackWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `ArtistWrite`, `AlbumWrite` or `TrackWrite`"),
+        (status = 404, description = "Not Found the artist, album or track does not have that id"),
+    ),
+    params(
+        ("resource" = String, description = "`artist`, `album` or `track`"),
+        ("id" = String, description = "The id of the artist, album or track"),
+        ("source" = ExternalSource, description = "The outside database"),
+        ("value" = String, description = "The id in the outside database"),
+    ),
+    security(
+        ("permissions" = ["ArtistWrite", "AlbumWrite", "TrackWrite"])
+    ),
+)]
+#[delete("/<resource>/<id>/external/<source>/<value>", rank = 2)]
+async fn external_id_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    resource: &str,
+    id: String,
+    source: &str,
+    value: &str,
+) -> Result<Option<()>> {
+    let Some((resource, column)) = target(resource) else {
+        return Ok(None);
+    };
+    if !user
+        .permissions
+        .contains(&external_ids::write_permission(resource))
+    {
+        Err(Status::Forbidden)?
+    }
+
+    let Some((source, value)) = source
+        .parse::<ExternalSource>()
+        .ok()
+        .and_then(|source| Some((source, external_ids::normalize(source, value)?)))
+    else {
+        return Ok(None);
+    };
+
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            if tx.execute(
+                &format!(
+                    "DELETE FROM external_ids WHERE source = ?1 AND value = ?2 AND {column} = ?3"
+                ),
+                params![<&'static str>::from(source), value, id],
+            )? == 0
+            {
+                return Ok(None);
+            }
+            let event = log.record(&tx, resource, Action::Update, id)?;
+
+            tx.commit()?;
+
+            Ok(Some(event))
+        })
+        .await?;
+    let Some(event) = event else {
+        return Ok(None);
+    };
+
+    events.send([event]);
+
+    Ok(Some(()))
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API External Id Endpoints", |rocket| async {
+        rocket.mount(
+            "/",
+            routes![external_id_get, external_id_write, external_id_delete],
+        )
+    })
+}
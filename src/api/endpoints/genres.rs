@@ -1,10 +1,17 @@
 use crate::{
-    api::data::{permissions::Permission, users::User},
+    api::data::{
+        events::{Action, Resource},
+        genres::{Genre, GenreFilter},
+        permissions::Permission,
+        users::User,
+    },
     database::MyDatabase,
     error::ApiError,
+    events::Events,
+    genres,
 };
-use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
-use rocket_sync_db_pools::rusqlite::{params, Error::QueryReturnedNoRows, ToSql};
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::{params, Connection, Error::QueryReturnedNoRows};
 
 type Result<T> = std::result::Result<T, ApiError>;
 
@@ -19,7 +26,7 @@ type Result<T> = std::result::Result<T, ApiError>;
             content_type = "application/json",
             body = String,
         ),
-        (status = 409, description = "Conflict genre already exists"),
+        (status = 409, description = "Conflict genre or alias already exists"),
         (status = 403, description = "Forbidden requires permission `GenreWrite`"),
     ),
     params(
@@ -30,16 +37,32 @@ type Result<T> = std::result::Result<T, ApiError>;
     ),
 )]
 #[post("/genre/<genre>")]
-async fn genre_write(db: MyDatabase, user: User, genre: String) -> Result<Json<String>> {
+async fn genre_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    genre: String,
+) -> Result<Json<String>> {
     if !user.permissions.contains(&Permission::GenreWrite) {
         Err(Status::Forbidden)?
     }
-    db.run(move |conn| -> Result<Json<String>> {
-        conn.execute("INSERT INTO genres (id) VALUES (?1)", params![genre])?;
+    let log = events.log();
+    let (genre, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        Ok(Json(genre))
-    })
-    .await
+            genres::create(&tx, &genre)?;
+            let event = log.record(&tx, Resource::Genre, Action::Create, genre.clone())?;
+
+            tx.commit()?;
+
+            Ok((genre, event))
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(Json(genre))
 }
 
 /// Retrieve a list of genres from the database.
@@ -51,7 +74,7 @@ async fn genre_write(db: MyDatabase, user: User, genre: String) -> Result<Json<S
         (status = 403, description = "Forbidden requires permission `GenreRead`"),
     ),
     params(
-        ("genre", Query, description = "The the name/part of the name of a genre"),
+        ("genre", Query, description = "The the name/part of the name or of an alias of a genre"),
         ("limit", Query, description = "The maximum number of results to return")
     ),
     security(
@@ -70,25 +93,7 @@ async fn genre_get(
     }
 
     db.run(move |conn| -> Result<Json<Vec<String>>> {
-        let mut sql = "SELECT * FROM genres WHERE 1=1".to_string();
-        let mut params_vec = Vec::new();
-
-        if let Some(genre_val) = genre {
-            sql += " AND id LIKE ?";
-            params_vec.push(format!("%{}%", genre_val));
-        }
-
-        sql += &format!(" LIMIT {}", limit.unwrap_or(50));
-
-        let params_sql: Vec<&dyn ToSql> =
-            params_vec.iter().map(|param| param as &dyn ToSql).collect();
-
-        Ok(Json(
-            conn.prepare(&sql)?
-                .query_map(&params_sql[..], |row| row.get(0))?
-                .map(|v| v.map_err(ApiError::from))
-                .collect::<Result<Vec<String>>>()?,
-        ))
+        Ok(Json(GenreFilter { genre }.select(conn, limit, None)?))
     })
     .await
 }
@@ -110,33 +115,400 @@ async fn genre_get(
     ),
 )]
 #[delete("/genre/<genre>")]
-async fn genre_delete(db: MyDatabase, user: User, genre: String) -> Result<()> {
+async fn genre_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    genre: String,
+) -> Result<()> {
     if !user.permissions.contains(&Permission::GenreDelete) {
         Err(Status::Forbidden)?
     }
 
-    db.run(move |conn| -> Result<()> {
-        let tx = conn.transaction()?;
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            if let Err(QueryReturnedNoRows) =
+                tx.query_row("SELECT 1 FROM genres WHERE id = ?", params![genre], |_| {
+                    Ok(())
+                })
+            {
+                Err(Status::NotFound)?
+            }
+
+            tx.execute("DELETE FROM genres WHERE id = ?", params![genre])?;
+            let event = log.record(&tx, Resource::Genre, Action::Delete, genre)?;
 
-        if let Err(QueryReturnedNoRows) =
-            tx.query_row("SELECT 1 FROM genres WHERE id = ?", params![genre], |_| {
-                Ok(())
-            })
-        {
-            Err(Status::NotFound)?
-        }
+            tx.commit()?;
 
-        tx.execute("DELETE FROM genres WHERE id = ?", params![genre])?;
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
+}
+
+/// Resolve a genre's id, a `404 Not Found` if no genre or alias has that name.
+fn resolve(conn: &Connection, name: &str) -> Result<String> {
+    genres::resolve(conn, name)?.ok_or(ApiError::Status(Status::NotFound))
+}
 
-        tx.commit()?;
+/// Retrieve a genre with its parents, children & aliases.
+///
+/// The genre can be given by one of its aliases.
+///
+/// Requires: `GenreRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Genre),
+        (status = 403, description = "Forbidden requires permission `GenreRead`"),
+        (status = 404, description = "Not Found genre does not exist"),
+    ),
+    params(
+        ("genre" = String, description = "The genre or one of its aliases")
+    ),
+    security(
+        ("permissions" = ["GenreRead"])
+    ),
+)]
+#[get("/genre/<genre>")]
+async fn genre_info_get(db: MyDatabase, user: User, genre: String) -> Result<Json<Genre>> {
+    if !user.permissions.contains(&Permission::GenreRead) {
+        Err(Status::Forbidden)?
+    }
 
-        Ok(())
+    db.run(move |conn| -> Result<Json<Genre>> {
+        Ok(Json(genres::read(conn, &resolve(conn, &genre)?)?))
     })
     .await
 }
 
+/// Make a genre a kind of another genre, genres can have any number of parents.
+///
+/// Requires: `GenreWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Genre),
+        (status = 403, description = "Forbidden requires permission `GenreWrite`"),
+        (status = 404, description = "Not Found genre or parent does not exist"),
+        (status = 409, description = "Conflict already a parent or the parent is the genre or one of its descendants"),
+    ),
+    params(
+        ("genre" = String, description = "The genre or one of its aliases"),
+        ("parent" = String, description = "The parent genre or one of its aliases"),
+    ),
+    security(
+        ("permissions" = ["GenreWrite"])
+    ),
+)]
+#[post("/genre/<genre>/parent/<parent>")]
+async fn genre_parent_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    genre: String,
+    parent: String,
+) -> Result<Json<Genre>> {
+    if !user.permissions.contains(&Permission::GenreWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let log = events.log();
+    let (genre, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            let genre = resolve(&tx, &genre)?;
+            let parent = resolve(&tx, &parent)?;
+            if genres::cycles(&tx, &genre, &parent)? {
+                Err(ApiError::RusqliteError((
+                    Status::Conflict,
+                    "Genre Cycle".to_string(),
+                )))?
+            }
+
+            tx.execute(
+                "INSERT INTO genre_parents (genre_id, parent_id) VALUES (?1, ?2)",
+                params![genre, parent],
+            )?;
+            let event = log.record(&tx, Resource::Genre, Action::Update, genre.clone())?;
+            let genre = genres::read(&tx, &genre)?;
+
+            tx.commit()?;
+
+            Ok((genre, event))
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(Json(genre))
+}
+
+/// Stop a genre being a kind of another genre.
+///
+/// Requires: `GenreWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `GenreWrite`"),
+        (status = 404, description = "Not Found genre does not exist or does not have that parent"),
+    ),
+    params(
+        ("genre" = String, description = "The genre or one of its aliases"),
+        ("parent" = String, description = "The parent genre or one of its aliases"),
+    ),
+    security(
+        ("permissions" = ["GenreWrite"])
+    ),
+)]
+#[delete("/genre/<genre>/parent/<parent>")]
+async fn genre_parent_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    genre: String,
+    parent: String,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::GenreWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            let genre = resolve(&tx, &genre)?;
+            let parent = resolve(&tx, &parent)?;
+            if tx.execute(
+                "DELETE FROM genre_parents WHERE genre_id = ?1 AND parent_id = ?2",
+                params![genre, parent],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            let event = log.record(&tx, Resource::Genre, Action::Update, genre)?;
+
+            tx.commit()?;
+
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
+}
+
+/// Add another name for a genre.
+///
+/// Aliases are matched case insensitively, anywhere a genre is given by name: in genre filters & when tagging artists, albums & tracks.
+///
+/// Requires: `GenreWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Genre),
+        (status = 403, description = "Forbidden requires permission `GenreWrite`"),
+        (status = 404, description = "Not Found genre does not exist"),
+        (status = 409, description = "Conflict the alias is already a genre or an alias"),
+    ),
+    params(
+        ("genre" = String, description = "The genre or one of its aliases"),
+        ("alias" = String, description = "The new name"),
+    ),
+    security(
+        ("permissions" = ["GenreWrite"])
+    ),
+)]
+#[post("/genre/<genre>/alias/<alias>")]
+async fn genre_alias_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    genre: String,
+    alias: String,
+) -> Result<Json<Genre>> {
+    if !user.permissions.contains(&Permission::GenreWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let log = events.log();
+    let (genre, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            let genre = resolve(&tx, &genre)?;
+            if genres::taken(&tx, &alias)? {
+                Err(ApiError::RusqliteError((
+                    Status::Conflict,
+                    "Genre Name Taken".to_string(),
+                )))?
+            }
+
+            tx.execute(
+                "INSERT INTO genre_aliases (alias, genre_id) VALUES (?1, ?2)",
+                params![alias, genre],
+            )?;
+            let event = log.record(&tx, Resource::Genre, Action::Update, genre.clone())?;
+            let genre = genres::read(&tx, &genre)?;
+
+            tx.commit()?;
+
+            Ok((genre, event))
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(Json(genre))
+}
+
+/// Remove one of a genre's aliases.
+///
+/// Requires: `GenreWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `GenreWrite`"),
+        (status = 404, description = "Not Found genre does not exist or does not have that alias"),
+    ),
+    params(
+        ("genre" = String, description = "The genre or one of its aliases"),
+        ("alias" = String, description = "The alias to remove"),
+    ),
+    security(
+        ("permissions" = ["GenreWrite"])
+    ),
+)]
+#[delete("/genre/<genre>/alias/<alias>")]
+async fn genre_alias_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    genre: String,
+    alias: String,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::GenreWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            let genre = resolve(&tx, &genre)?;
+            if tx.execute(
+                "DELETE FROM genre_aliases WHERE alias = ?1 AND genre_id = ?2",
+                params![alias, genre],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            let event = log.record(&tx, Resource::Genre, Action::Update, genre)?;
+
+            tx.commit()?;
+
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
+}
+
+/// Merge a genre into another.
+///
+/// Every artist, album & track with the genre gets the other genre instead, the genre's parents, children & aliases move over too.
+/// The genre is deleted & its name becomes an alias of the other genre.
+///
+/// Requires: `GenreWrite` & `GenreDelete` permissions.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Genre),
+        (status = 400, description = "Bad Request can't merge a genre into itself"),
+        (status = 403, description = "Forbidden requires permission `GenreWrite` & `GenreDelete`"),
+        (status = 404, description = "Not Found either genre does not exist"),
+    ),
+    params(
+        ("genre" = String, description = "The genre to merge away"),
+        ("into" = String, description = "The genre to keep or one of its aliases"),
+    ),
+    security(
+        ("permissions" = ["GenreWrite", "GenreDelete"])
+    ),
+)]
+#[post("/genre/<genre>/merge/<into>")]
+async fn genre_merge(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    genre: String,
+    into: String,
+) -> Result<Json<Genre>> {
+    if ![Permission::GenreWrite, Permission::GenreDelete]
+        .iter()
+        .all(|permission| user.permissions.contains(permission))
+    {
+        Err(Status::Forbidden)?
+    }
+
+    let log = events.log();
+    let (into, recorded) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            if let Err(QueryReturnedNoRows) =
+                tx.query_row("SELECT 1 FROM genres WHERE id = ?", params![genre], |_| {
+                    Ok(())
+                })
+            {
+                Err(Status::NotFound)?
+            }
+            let into = resolve(&tx, &into)?;
+            if into == genre {
+                Err(Status::BadRequest)?
+            }
+
+            genres::merge(&tx, &genre, &into)?;
+            let recorded = vec![
+                log.record(&tx, Resource::Genre, Action::Delete, genre)?,
+                log.record(&tx, Resource::Genre, Action::Update, into.clone())?,
+            ];
+            let into = genres::read(&tx, &into)?;
+
+            tx.commit()?;
+
+            Ok((into, recorded))
+        })
+        .await?;
+
+    events.send(recorded);
+
+    Ok(Json(into))
+}
+
 pub fn fairing() -> AdHoc {
     AdHoc::on_ignite("API Genre EndPoints", |rocket| async {
-        rocket.mount("/", routes![genre_write, genre_get, genre_delete])
+        rocket.mount(
+            "/",
+            routes![
+                genre_write,
+                genre_get,
+                genre_info_get,
+                genre_delete,
+                genre_parent_write,
+                genre_parent_delete,
+                genre_alias_write,
+                genre_alias_delete,
+                genre_merge
+            ],
+        )
     })
 }
@@ -0,0 +1,53 @@
+use async_graphql::{Request, Response};
+use rocket::{fairing::AdHoc, serde::json::Json, State};
+
+use crate::{
+    api::data::users::User,
+    audio::Blobs,
+    database::MyDatabase,
+    events::Events,
+    graphql::{self, TunaSchema},
+};
+
+/// Query the music library & its users, or change them, with GraphQL.
+///
+/// Each field requires the permission of its REST endpoint, selecting one without it is an error.
+/// The schema is in the response to an introspection query.
+///
+/// Requires: the permissions of the fields selected.
+#[utoipa::path(
+    request_body(
+        content = Object,
+        description = "A GraphQL request with a `query` & optionally its `variables` & `operationName`",
+    ),
+    responses(
+        (status = 200, description = "The GraphQL response, with `data` & any `errors`", body = Object),
+    ),
+)]
+#[post("/graphql", data = "<request>")]
+async fn graphql_post(
+    db: MyDatabase,
+    user: User,
+    schema: &State<TunaSchema>,
+    events: &State<Events>,
+    blobs: &State<Blobs>,
+    request: Json<Request>,
+) -> Json<Response> {
+    Json(
+        graphql::execute(
+            schema,
+            db,
+            user,
+            events.inner().clone(),
+            blobs.inner().clone(),
+            request.into_inner(),
+        )
+        .await,
+    )
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API GraphQL EndPoints", |rocket| async {
+        rocket.mount("/", routes![graphql_post])
+    })
+}
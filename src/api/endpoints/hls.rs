@@ -0,0 +1,326 @@
+use rocket::{
+    fairing::AdHoc,
+    http::{ContentType, Status},
+    State,
+};
+
+use crate::{
+    api::data::{
+        audio::{AudioInfo, GainMode},
+        permissions::Permission,
+        users::User,
+    },
+    audio::{
+        self, encoder,
+        hls::{timestamp, Segment},
+        Blobs,
+    },
+    database::MyDatabase,
+    error::ApiError,
+    signing::Signer,
+    storage::Download,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+fn gain_name(gain: Option<GainMode>) -> &'static str {
+    match gain {
+        None => "",
+        Some(GainMode::Track) => "track",
+        Some(GainMode::Album) => "album",
+    }
+}
+
+/// What a stream's links are signed for, they stop working once the track's audio is replaced.
+///
+/// A `bitrate` of `0` is the stored audio, otherwise it's the variant transcoded to it.
+fn payload(info: &AudioInfo, bitrate: u32, gain: Option<GainMode>) -> String {
+    format!(
+        "hls\n{}\n{}\n{bitrate}\n{}",
+        info.track,
+        info.hash,
+        gain_name(gain)
+    )
+}
+
+/// A link relative to the playlist, signed for `audio.stream_expiry` seconds.
+fn signed(
+    signer: &Signer,
+    blobs: &Blobs,
+    info: &AudioInfo,
+    bitrate: u32,
+    gain: Option<GainMode>,
+    target: &str,
+) -> String {
+    let (expires, signature) =
+        signer.sign(&payload(info, bitrate, gain), blobs.config().stream_expiry);
+    let mut link = format!("{target}?expires={expires}&signature={signature}");
+    if bitrate != 0 {
+        link.push_str(&format!("&bitrate={bitrate}"));
+    }
+    if gain.is_some() {
+        link.push_str(&format!("&gain={}", gain_name(gain)));
+    }
+    link
+}
+
+fn playlist(playlist: String) -> (ContentType, String) {
+    (
+        ContentType::new("application", "vnd.apple.mpegurl"),
+        playlist,
+    )
+}
+
+/// A track's audio & the segments of one of its variants, if it can be streamed.
+async fn stream(
+    db: &MyDatabase,
+    blobs: &Blobs,
+    info: AudioInfo,
+    bitrate: u32,
+) -> Result<Option<(AudioInfo, Vec<Segment>)>> {
+    if info.mime != "audio/mpeg" {
+        Err(ApiError::IoError((
+            Status::UnsupportedMediaType,
+            "HLS Needs MP3 Audio".to_string(),
+        )))?
+    }
+
+    Ok(blobs
+        .segments(db, &info.hash, &info.mime, bitrate)
+        .await?
+        .map(|segments| (info, segments)))
+}
+
+/// Check a signed link, it has to be for the track's current audio.
+async fn verify(
+    db: &MyDatabase,
+    blobs: &Blobs,
+    signer: &Signer,
+    track: &str,
+    expires: Option<u64>,
+    signature: Option<&str>,
+    bitrate: u32,
+    gain: Option<GainMode>,
+) -> Result<Option<(AudioInfo, Vec<Segment>)>> {
+    let (Some(expires), Some(signature)) = (expires, signature) else {
+        Err(Status::Forbidden)?
+    };
+
+    // the signature is checked before a variant is transcoded for it
+    let Some(info) = audio::info(db, track).await? else {
+        Err(Status::Forbidden)?
+    };
+    if !signer.verify(&payload(&info, bitrate, gain), expires, signature) {
+        Err(Status::Forbidden)?
+    }
+    stream(db, blobs, info, bitrate).await
+}
+
+/// Stream a track's audio with HLS.
+///
+/// The playlists & segments this links to are signed, so players don't need the token cookie to fetch them.
+/// Their links work for `audio.stream_expiry` seconds & stop working once the audio is replaced.
+/// The audio is split into segments of about 10 seconds the first time it's streamed.
+///
+/// The first variant is the stored MP3 at its own bitrate, then one for each of `audio.stream_bitrates` below it.
+/// Those are transcoded from the stored MP3 the first time they're streamed, if its sample rate is 32, 44.1 or 48kHz.
+/// With `gain` the segments have the track's or its album's ReplayGain gain applied, like `GET /audio/<track>?gain=`.
+///
+/// Requires: `AudioRead` permission.
+#[utoipa::path(
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            content_type = "application/vnd.apple.mpegurl",
+            body = String,
+        ),
+        (status = 403, description = "Forbidden requires permission `AudioRead`"),
+        (status = 404, description = "Not Found the track has no audio"),
+        (status = 415, description = "Unsupported Media Type the audio is not MP3"),
+        (status = 422, description = "Unprocessable Entity the audio has no MP3 frames"),
+    ),
+    params(
+        ("track", description = "The id of the track"),
+        ("gain" = Option<GainMode>, Query, description = "Apply the track's or its album's ReplayGain gain"),
+    ),
+    security(
+        ("permissions" = ["AudioRead"])
+    ),
+)]
+#[get("/audio/<track>/hls/master.m3u8?<gain>")]
+async fn hls_master_get(
+    db: MyDatabase,
+    user: User,
+    blobs: &State<Blobs>,
+    signer: &State<Signer>,
+    track: &str,
+    gain: Option<GainMode>,
+) -> Result<Option<(ContentType, String)>> {
+    if !user.permissions.contains(&Permission::AudioRead) {
+        Err(Status::Forbidden)?
+    }
+
+    let Some(info) = audio::info(&db, track).await? else {
+        return Ok(None);
+    };
+    let Some((info, segments)) = stream(&db, blobs, info, 0).await? else {
+        return Ok(None);
+    };
+
+    let bandwidth = segments
+        .iter()
+        .map(|segment| (segment.size as f64 * 8.0 / segment.duration).ceil() as u64)
+        .max()
+        .unwrap_or_default();
+    let mut master = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-STREAM-INF:BANDWIDTH={bandwidth},CODECS=\"mp4a.40.34\"\n{}\n",
+        signed(signer, blobs, &info, 0, gain, "media.m3u8")
+    );
+
+    // the variants are constant bitrate, with the timestamp each of their segments starts with
+    let (size, duration) = segments.iter().fold((0, 0.0), |(size, duration), segment| {
+        (size + segment.size, duration + segment.duration)
+    });
+    let average = size as f64 * 8.0 / duration;
+    let overhead = segments
+        .iter()
+        .map(|segment| (timestamp(0.0).len() as f64 * 8.0 / segment.duration).ceil() as u64)
+        .max()
+        .unwrap_or_default();
+    if encoder::supported(segments[0].rate) {
+        for bitrate in &blobs.config().stream_bitrates {
+            if (*bitrate as f64 * 1000.0) < average {
+                master.push_str(&format!(
+                    "#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"mp4a.40.34\"\n{}\n",
+                    *bitrate as u64 * 1000 + overhead,
+                    signed(signer, blobs, &info, *bitrate, gain, "media.m3u8")
+                ));
+            }
+        }
+    }
+
+    Ok(Some(playlist(master)))
+}
+
+/// The media playlist of a track's HLS stream, linked to by its master playlist.
+#[utoipa::path(
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            content_type = "application/vnd.apple.mpegurl",
+            body = String,
+        ),
+        (status = 403, description = "Forbidden the link is not signed, has expired or the audio has been replaced"),
+        (status = 404, description = "Not Found the track has no audio"),
+    ),
+    params(
+        ("track", description = "The id of the track"),
+        ("expires" = u64, Query, description = "When the link stops working (unix seconds)"),
+        ("signature" = String, Query, description = "The signature of the link"),
+        ("bitrate" = Option<u32>, Query, description = "The bitrate (kbit/s) of the variant the link was signed for, the stored audio without it"),
+        ("gain" = Option<GainMode>, Query, description = "The gain the link was signed with"),
+    ),
+)]
+#[get("/audio/<track>/hls/media.m3u8?<expires>&<signature>&<bitrate>&<gain>")]
+async fn hls_media_get(
+    db: MyDatabase,
+    blobs: &State<Blobs>,
+    signer: &State<Signer>,
+    track: &str,
+    expires: Option<u64>,
+    signature: Option<&str>,
+    bitrate: Option<u32>,
+    gain: Option<GainMode>,
+) -> Result<Option<(ContentType, String)>> {
+    let bitrate = bitrate.unwrap_or_default();
+    let Some((info, segments)) =
+        verify(&db, blobs, signer, track, expires, signature, bitrate, gain).await?
+    else {
+        return Ok(None);
+    };
+
+    let target = segments
+        .iter()
+        .map(|segment| segment.duration.ceil() as u64)
+        .max()
+        .unwrap_or_default();
+    let mut media = format!(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{target}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n"
+    );
+    for (index, segment) in segments.iter().enumerate() {
+        media.push_str(&format!(
+            "#EXTINF:{:.3},\n{}\n",
+            segment.duration,
+            signed(signer, blobs, &info, bitrate, gain, &format!("{index}.mp3"))
+        ));
+    }
+    media.push_str("#EXT-X-ENDLIST\n");
+
+    Ok(Some(playlist(media)))
+}
+
+/// A segment of a track's HLS stream, linked to by its media playlist.
+///
+/// With the `s3` storage backend this redirects to a short-lived link to the segment in the bucket, unless a gain is applied.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", content_type = "audio/mpeg", body = String),
+        (status = 307, description = "Temporary Redirect to the segment in the storage bucket"),
+        (status = 403, description = "Forbidden the link is not signed, has expired or the audio has been replaced"),
+        (status = 404, description = "Not Found the track has no audio or the segment does not exist"),
+    ),
+    params(
+        ("track", description = "The id of the track"),
+        ("segment", description = "The segment, e.g. `0.mp3`"),
+        ("expires" = u64, Query, description = "When the link stops working (unix seconds)"),
+        ("signature" = String, Query, description = "The signature of the link"),
+        ("bitrate" = Option<u32>, Query, description = "The bitrate (kbit/s) of the variant the link was signed for, the stored audio without it"),
+        ("gain" = Option<GainMode>, Query, description = "The gain the link was signed with"),
+    ),
+)]
+#[get(
+    "/audio/<track>/hls/<segment>?<expires>&<signature>&<bitrate>&<gain>",
+    rank = 2
+)]
+async fn hls_segment_get(
+    db: MyDatabase,
+    blobs: &State<Blobs>,
+    signer: &State<Signer>,
+    track: &str,
+    segment: &str,
+    expires: Option<u64>,
+    signature: Option<&str>,
+    bitrate: Option<u32>,
+    gain: Option<GainMode>,
+) -> Result<Option<Download>> {
+    let bitrate = bitrate.unwrap_or_default();
+    let Some((info, segments)) =
+        verify(&db, blobs, signer, track, expires, signature, bitrate, gain).await?
+    else {
+        return Ok(None);
+    };
+
+    let Some(segment) = segment
+        .strip_suffix(".mp3")
+        .and_then(|segment| segment.parse::<u64>().ok())
+        .filter(|segment| *segment < segments.len() as u64)
+    else {
+        return Ok(None);
+    };
+
+    let steps = match gain {
+        Some(mode) => blobs.gain(&db, track, mode).await?,
+        None => 0,
+    };
+    blobs
+        .segment_with_gain(&info.hash, bitrate, segment, steps)
+        .await
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API HLS Endpoints", |rocket| async {
+        rocket.mount("/", routes![hls_master_get, hls_media_get, hls_segment_get])
+    })
+}
@@ -0,0 +1,175 @@
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, Data, State};
+use tempfile::NamedTempFile;
+
+use crate::{
+    api::data::{
+        imports::{ImportDirectory, ImportJob},
+        permissions::Permission,
+        users::User,
+    },
+    audio::Blobs,
+    database::Connections,
+    error::ApiError,
+    events::Events,
+    imports::{extract, ImportSource, Imports},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// An import writes genres, artists, albums, tracks & audio, so it needs all of their permissions.
+pub fn can_import(user: &User) -> bool {
+    [
+        Permission::ImportWrite,
+        Permission::GenreWrite,
+        Permission::ArtistWrite,
+        Permission::AlbumWrite,
+        Permission::TrackWrite,
+        Permission::AudioWrite,
+    ]
+    .iter()
+    .all(|permission| user.permissions.contains(permission))
+}
+
+/// Import every mp3 in a server-side directory.
+///
+/// Artists, albums, genres & tracks are read from each file's ID3 tags & matched by name (case insensitive), artists & genres by their aliases too, anything missing is created.
+/// Files are copied, the directory is left untouched.
+/// The import runs in the background, poll `GET /import/<id>` for its progress & report.
+///
+/// Requires: `ImportWrite`, `GenreWrite`, `ArtistWrite`, `AlbumWrite`, `TrackWrite` & `AudioWrite` permissions.
+#[utoipa::path(
+    request_body(content = ImportDirectory, description = "The directory to import"),
+    responses(
+        (status = 200, description = "Success the import has started", body = ImportJob),
+        (status = 403, description = "Forbidden requires permissions or the directory is not inside an import root"),
+        (status = 404, description = "Not Found the directory does not exist"),
+    ),
+    security(
+        ("permissions" = ["ImportWrite", "GenreWrite", "ArtistWrite", "AlbumWrite", "TrackWrite", "AudioWrite"])
+    ),
+)]
+#[post("/import", data = "<directory>")]
+async fn import_directory(
+    connections: Connections,
+    user: User,
+    events: &State<Events>,
+    imports: &State<Imports>,
+    blobs: &State<Blobs>,
+    directory: Json<ImportDirectory>,
+) -> Result<Json<ImportJob>> {
+    if !can_import(&user) {
+        Err(Status::Forbidden)?
+    }
+
+    let directory = directory.into_inner();
+    let path = imports.directory(&directory.path)?;
+
+    Ok(Json(imports.start(
+        connections,
+        events.inner().clone(),
+        blobs.inner().clone(),
+        user.username,
+        path.display().to_string(),
+        ImportSource::Directory(path),
+        directory.dry_run,
+    )))
+}
+
+/// Import every mp3 in an uploaded tar, tar.gz or zip archive.
+///
+/// The same as `POST /import` except the files come from the archive.
+///
+/// Requires: `ImportWrite`, `GenreWrite`, `ArtistWrite`, `AlbumWrite`, `TrackWrite` & `AudioWrite` permissions.
+#[utoipa::path(
+    request_body(
+        description = "The archive to import",
+        content_type = "application/octet-stream",
+        content = String,
+    ),
+    responses(
+        (status = 200, description = "Success the import has started", body = ImportJob),
+        (status = 400, description = "Bad Request the archive could not be extracted"),
+        (status = 403, description = "Forbidden requires permissions"),
+        (status = 413, description = "Payload Too Large the archive is larger than `import.archive_limit`, before or after it's extracted"),
+    ),
+    params(
+        ("dry_run" = Option<bool>, Query, description = "Report what would happen without writing anything"),
+    ),
+    security(
+        ("permissions" = ["ImportWrite", "GenreWrite", "ArtistWrite", "AlbumWrite", "TrackWrite", "AudioWrite"])
+    ),
+)]
+#[post("/import/archive?<dry_run>", data = "<data>")]
+async fn import_archive(
+    connections: Connections,
+    user: User,
+    events: &State<Events>,
+    imports: &State<Imports>,
+    blobs: &State<Blobs>,
+    dry_run: Option<bool>,
+    data: Data<'_>,
+) -> Result<Json<ImportJob>> {
+    if !can_import(&user) {
+        Err(Status::Forbidden)?
+    }
+
+    let archive = NamedTempFile::new()?;
+    if !data
+        .open(imports.config().archive_limit)
+        .into_file(archive.path())
+        .await?
+        .is_complete()
+    {
+        Err(Status::PayloadTooLarge)?
+    }
+
+    let dir = extract(
+        archive.path().to_path_buf(),
+        imports.config().archive_limit,
+    )
+    .await?;
+
+    Ok(Json(imports.start(
+        connections,
+        events.inner().clone(),
+        blobs.inner().clone(),
+        user.username,
+        "archive".to_string(),
+        ImportSource::Archive(dir),
+        dry_run.unwrap_or(false),
+    )))
+}
+
+/// Retrieve the progress & report of an import.
+///
+/// Imports are kept in memory, so they are forgotten when the server restarts,
+/// & only the last `keep` finished ones (`import` table in `Rocket.toml`) are kept.
+///
+/// Requires: `ImportRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = ImportJob),
+        (status = 403, description = "Forbidden requires permission `ImportRead`"),
+        (status = 404, description = "Not Found import does not exist"),
+    ),
+    params(
+        ("id" = String, description = "The id of the import"),
+    ),
+    security(
+        ("permissions" = ["ImportRead"])
+    ),
+)]
+#[get("/import/<id>")]
+async fn import_get(user: User, imports: &State<Imports>, id: &str) -> Result<Json<ImportJob>> {
+    if !user.permissions.contains(&Permission::ImportRead) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(Json(imports.get(id).ok_or(Status::NotFound)?))
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Import EndPoints", |rocket| async {
+        rocket.mount("/", routes![import_directory, import_archive, import_get])
+    })
+}
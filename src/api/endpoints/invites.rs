@@ -1,14 +1,17 @@
-use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
 use rocket_sync_db_pools::rusqlite::{params, params_from_iter, ToSql};
 
 use crate::{
     api::data::{
+        events::{Action, Resource},
         invites::Invite,
         permissions::{permissions_from_row, Permission},
-        users::{DangerousLogin, User},
+        users::{within_quota, DangerousLogin, User},
     },
     database::MyDatabase,
     error::ApiError,
+    events::Events,
+    rate_limit::RateLimit,
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
@@ -22,30 +25,42 @@ type Result<T> = std::result::Result<T, ApiError>;
     responses(
         (status = 200, description = "Successfully created account"),
         (status = 404, description = "Invite code not found"),
+        (status = 429, description = "Too Many Requests retry after the number of seconds in the `Retry-After` header"),
     ),
     params(
         ("code", description = "The invite code to use"),
     ),
 )]
 #[post("/invite/<code>", data = "<login>")]
-async fn invite_use(db: MyDatabase, code: String, login: Json<DangerousLogin>) -> Result<()> {
+async fn invite_use(
+    db: MyDatabase,
+    _limit: RateLimit<'_>,
+    events: &State<Events>,
+    code: String,
+    login: Json<DangerousLogin>,
+) -> Result<()> {
     let login = login.into_inner();
+    let log = events.log();
 
-    db.run(move |conn| -> Result<()> {
+    let event = db.run(move |conn| -> Result<_> {
         let tx = conn.transaction()?;
 
-        let (remaining, permissions): (u16, Vec<Permission>) = tx
+        let (remaining, quota, permissions): (u16, Option<u64>, Vec<Permission>) = tx
             .query_row(
-                "SELECT invites.remaining AS remaining, GROUP_CONCAT(DISTINCT invite_permissions.id) AS permissions FROM invites
+                "SELECT invites.remaining AS remaining, invites.quota AS quota, GROUP_CONCAT(DISTINCT invite_permissions.id) AS permissions FROM invites
                 LEFT JOIN invite_permissions ON invites.code = invite_permissions.code
                 WHERE invites.code = ?
                 GROUP BY invites.code",
                 params![code],
-                |row| Ok((row.get("remaining")?, permissions_from_row(row)?)),
+                |row| Ok((row.get("remaining")?, row.get("quota")?, permissions_from_row(row)?)),
             )
             .map_err(ApiError::from)?;
 
         login.insert_user_into_transaction(permissions, &tx)?;
+        tx.execute(
+            "UPDATE users SET quota = ? WHERE username = ?",
+            params![quota, login.username],
+        )?;
 
         if remaining > 1 {
             tx.execute(
@@ -55,10 +70,15 @@ async fn invite_use(db: MyDatabase, code: String, login: Json<DangerousLogin>) -
         } else {
             tx.execute("DELETE FROM invites WHERE code = ?", params![code])?;
         }
+        let event = log.record(&tx, Resource::User, Action::Create, login.username)?;
         tx.commit()?;
-        Ok(())
+        Ok(event)
     })
-    .await
+    .await?;
+
+    events.send([event]);
+
+    Ok(())
 }
 
 /// Creates a new invite code.
@@ -71,7 +91,7 @@ async fn invite_use(db: MyDatabase, code: String, login: Json<DangerousLogin>) -
     ),
     responses(
         (status = 200, description = "Successfully created invite"),
-        (status = 403, description = "You do not have the required permissions to create the invite"),
+        (status = 403, description = "You do not have the required permissions to create the invite, or its quota is higher than your own"),
         (status = 409, description = "Invite code already exists"),
     ),
     security(
@@ -95,6 +115,16 @@ async fn invite_write(db: MyDatabase, user: User, invite: Json<Invite>) -> Resul
     db.run(move |conn| -> Result<Json<Invite>> {
         let tx = conn.transaction()?;
 
+        // the users it creates can't get more storage than you have
+        let own: Option<u64> = tx.query_row(
+            "SELECT quota FROM users WHERE username = ?",
+            params![invite.creator],
+            |row| row.get(0),
+        )?;
+        if !within_quota(invite.quota, own) {
+            Err(Status::Forbidden)?
+        }
+
         if tx.query_row(
             "SELECT EXISTS(SELECT 1 FROM invites WHERE code = ?)",
             params![invite.code],
@@ -104,8 +134,8 @@ async fn invite_write(db: MyDatabase, user: User, invite: Json<Invite>) -> Resul
         }
 
         tx.execute(
-            "INSERT INTO invites (code, remaining, creator) VALUES (?1, ?2, ?3)",
-            params![invite.code, invite.remaining, invite.creator],
+            "INSERT INTO invites (code, remaining, quota, creator) VALUES (?1, ?2, ?3, ?4)",
+            params![invite.code, invite.remaining, invite.quota, invite.creator],
         )?;
 
         // idk why you would want a invite with no permissions...
@@ -176,7 +206,7 @@ async fn invite_get(
     }
 
     db.run(move |conn| -> Result<Json<Vec<Invite>>> {
-        let mut sql = "SELECT invites.code, GROUP_CONCAT(DISTINCT invite_permissions.id) AS permissions, invites.remaining, invites.creator FROM invites
+        let mut sql = "SELECT invites.code, GROUP_CONCAT(DISTINCT invite_permissions.id) AS permissions, invites.remaining, invites.quota, invites.creator FROM invites
         LEFT JOIN invite_permissions ON invites.code = invite_permissions.code
         WHERE 1=1".to_string();
         let mut params_vec = vec![];
@@ -3,12 +3,25 @@ use rocket::fairing::AdHoc;
 pub mod albums;
 pub mod artists;
 pub mod audio;
+pub mod backups;
+pub mod batch;
+pub mod catalog;
+pub mod enrichment;
+pub mod events;
+pub mod external_ids;
 pub mod genres;
+pub mod graphql;
+pub mod hls;
+pub mod imports;
 pub mod invites;
 pub mod permissions;
+pub mod shares;
 pub mod tokens;
+pub mod totp;
 pub mod tracks;
+pub mod uploads;
 pub mod users;
+pub mod webhooks;
 
 pub fn fairing() -> AdHoc {
     AdHoc::on_ignite("API Music Systems", |rocket| async {
@@ -17,10 +30,23 @@ pub fn fairing() -> AdHoc {
             .attach(artists::fairing())
             .attach(albums::fairing())
             .attach(tracks::fairing())
+            .attach(batch::fairing())
+            .attach(external_ids::fairing())
+            .attach(enrichment::fairing())
+            .attach(graphql::fairing())
             .attach(invites::fairing())
             .attach(permissions::fairing())
             .attach(users::fairing())
             .attach(tokens::fairing())
+            .attach(totp::fairing())
             .attach(audio::fairing())
+            .attach(hls::fairing())
+            .attach(uploads::fairing())
+            .attach(imports::fairing())
+            .attach(catalog::fairing())
+            .attach(events::fairing())
+            .attach(webhooks::fairing())
+            .attach(backups::fairing())
+            .attach(shares::fairing())
     })
 }
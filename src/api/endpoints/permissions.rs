@@ -1,13 +1,14 @@
-use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
-use rocket_sync_db_pools::rusqlite::{params, params_from_iter};
+use rocket::{fairing::AdHoc, serde::json::Json, State};
 
 use crate::{
     api::data::{
-        permissions::{permissions_from_row, Permission},
+        events::{Action, Resource},
+        permissions::Permission,
         users::User,
     },
     database::MyDatabase,
     error::ApiError,
+    events::Events,
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
@@ -41,43 +42,26 @@ type Result<T> = std::result::Result<T, ApiError>;
 async fn permission_add(
     db: MyDatabase,
     user: User,
+    events: &State<Events>,
     username: String,
     permissions_to_add: Json<Vec<Permission>>,
 ) -> Result<()> {
     let permissions_to_add = permissions_to_add.into_inner();
 
-    let mut required_permissions = permissions_to_add.clone();
-    required_permissions.push(Permission::PermissionAdd);
-    if !required_permissions
-        .iter()
-        .all(|permission| user.permissions.contains(permission))
-    {
-        Err(Status::Forbidden)?
-    }
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-    db.run(move |conn| -> Result<()> {
-        let tx = conn.transaction()?;
+            user.grant(&tx, &username, &permissions_to_add)?;
+            let event = log.record(&tx, Resource::User, Action::Update, username)?;
+            tx.commit()?;
+            Ok(event)
+        })
+        .await?;
 
-        let sql = format!(
-            "INSERT OR IGNORE INTO user_permissions (id, username) VALUES {};",
-            permissions_to_add
-                .iter()
-                .map(|_| "\n (?, ?)".to_string())
-                .collect::<Vec<String>>()
-                .join(",")
-        );
+    events.send([event]);
 
-        let params = params_from_iter(
-            permissions_to_add
-                .into_iter()
-                .flat_map(|p| [<&'static str>::from(p), &username]),
-        );
-
-        tx.execute(&sql, params)?;
-        tx.commit()?;
-        Ok(())
-    })
-    .await?;
     Ok(())
 }
 
@@ -110,59 +94,29 @@ async fn permission_add(
 async fn permission_delete(
     db: MyDatabase,
     user: User,
+    events: &State<Events>,
     username: String,
     permissions_to_delete: Json<Vec<Permission>>,
 ) -> Result<()> {
     let permissions_to_delete = permissions_to_delete.into_inner();
 
-    db.run(move |conn| {
-        let tx = conn.transaction()?;
-
-        let mut required_permissions = tx.query_row(
-            "SELECT GROUP_CONCAT(DISTINCT id) AS permissions FROM user_permissions
-            WHERE username = ?",
-            params![&username],
-            permissions_from_row,
-        )?;
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        required_permissions.push(Permission::PermissionDelete);
-        if !required_permissions
-            .iter()
-            .all(|permission| user.permissions.contains(permission))
-        {
-            Err(Status::Forbidden)?
-        }
+            user.revoke(&tx, &username, &permissions_to_delete)?;
+            let event = log.record(&tx, Resource::User, Action::Update, username)?;
 
-        let permission_placeholders = permissions_to_delete
-            .iter()
-            .map(|_| "?")
-            .collect::<Vec<_>>()
-            .join(", ");
+            tx.commit()?;
 
-        // You can't fix the redundant closure here or you get a lifetime error
-        // It feels like it should work the same but IDK
-        #[allow(clippy::redundant_closure)]
-        let params = params_from_iter(
-            std::iter::once(username.as_str()).chain(
-                permissions_to_delete
-                    .into_iter()
-                    .map(|p| <&'static str>::from(p)),
-            ),
-        );
+            Ok(event)
+        })
+        .await?;
 
-        tx.execute(
-            &format!(
-                "DELETE FROM user_permissions WHERE username = ? AND id IN ({})",
-                permission_placeholders
-            ),
-            params,
-        )?;
+    events.send([event]);
 
-        tx.commit()?;
-
-        Ok(())
-    })
-    .await
+    Ok(())
 }
 
 pub fn fairing() -> AdHoc {
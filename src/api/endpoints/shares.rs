@@ -0,0 +1,364 @@
+use rocket::{fairing::AdHoc, http::ContentType, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::{params, OptionalExtension};
+use uuid::Uuid;
+
+use crate::{
+    api::data::{
+        albums::Album,
+        permissions::Permission,
+        shares::{Share, ShareCreate, Shared},
+        tracks::Track,
+        users::User,
+    },
+    audio::{self, loudness},
+    database::MyDatabase,
+    error::ApiError,
+    signing::Signer,
+    storage::{Download, Storage},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// What a share's link is signed for.
+fn payload(id: &str) -> String {
+    format!("share\n{id}")
+}
+
+fn url(signer: &Signer, share: &Share) -> String {
+    format!(
+        "/share/{}?expires={}&signature={}",
+        share.id,
+        share.expires,
+        signer.signature(&payload(&share.id), share.expires)
+    )
+}
+
+/// Check a share link, a `404 Not Found` if it has been revoked.
+async fn verify(
+    db: &MyDatabase,
+    signer: &Signer,
+    id: String,
+    expires: Option<u64>,
+    signature: Option<&str>,
+) -> Result<Share> {
+    let (Some(expires), Some(signature)) = (expires, signature) else {
+        Err(Status::Forbidden)?
+    };
+    if !signer.verify(&payload(&id), expires, signature) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<Share> {
+        conn.query_row(
+            "SELECT * FROM shares WHERE id = ?",
+            [id],
+            Share::try_from_row,
+        )
+        .optional()?
+        .ok_or(ApiError::Status(Status::NotFound))
+    })
+    .await
+}
+
+/// Create a link that lets anyone with it read a track or an album & download its audio, without logging in.
+///
+/// The link stops working once it expires, once its audio has been downloaded `plays` times or once it's revoked.
+/// You can only share what you can read: sharing needs `AudioRead` & `TrackRead` or `AlbumRead`.
+///
+/// Requires: `ShareWrite` permission.
+#[utoipa::path(
+    request_body = ShareCreate,
+    responses(
+        (status = 200, description = "Success", body = Share),
+        (status = 400, description = "Bad Request not exactly one of `track` & `album` or `expires` is too far off"),
+        (status = 403, description = "Forbidden requires permission `ShareWrite`, `AudioRead` & `TrackRead` or `AlbumRead`"),
+        (status = 404, description = "Not Found the track or album does not exist"),
+    ),
+    security(
+        ("permissions" = ["ShareWrite", "AudioRead", "TrackRead", "AlbumRead"])
+    ),
+)]
+#[post("/share", data = "<share>")]
+async fn share_write(
+    db: MyDatabase,
+    user: User,
+    signer: &State<Signer>,
+    share: Json<ShareCreate>,
+) -> Result<Json<Share>> {
+    let ShareCreate {
+        track,
+        album,
+        expires,
+        plays,
+    } = share.into_inner();
+
+    let read = match (&track, &album) {
+        (Some(_), None) => Permission::TrackRead,
+        (None, Some(_)) => Permission::AlbumRead,
+        _ => Err(Status::BadRequest)?,
+    };
+    if ![Permission::ShareWrite, Permission::AudioRead, read]
+        .iter()
+        .all(|permission| user.permissions.contains(permission))
+    {
+        Err(Status::Forbidden)?
+    }
+
+    let id = Uuid::new_v4().to_string();
+    // stored as a signed 64 bit integer, later than that isn't a lifetime
+    let (expires, _) = signer.sign(&payload(&id), expires);
+    if i64::try_from(expires).is_err() {
+        Err(Status::BadRequest)?
+    }
+
+    let mut share = db
+        .run(move |conn| -> Result<Share> {
+            let tx = conn.transaction()?;
+
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?1) OR EXISTS(SELECT 1 FROM albums WHERE id = ?2)",
+                params![track, album],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                Err(Status::NotFound)?
+            }
+
+            let share = tx.query_row(
+                "INSERT INTO shares (id, creator, track_id, album_id, expires, max_plays) VALUES (?1, ?2, ?3, ?4, ?5, ?6) RETURNING *",
+                params![id, user.username, track, album, expires, plays],
+                Share::try_from_row,
+            )?;
+
+            tx.commit()?;
+
+            Ok(share)
+        })
+        .await?;
+
+    share.url = url(signer, &share);
+    Ok(Json(share))
+}
+
+/// Retrieve a list of your share links, including expired ones.
+///
+/// Requires: `ShareWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<Share>),
+        (status = 403, description = "Forbidden requires permission `ShareWrite`"),
+    ),
+    params(
+        ("limit", Query, description = "The maximum number of results to return"),
+    ),
+    security(
+        ("permissions" = ["ShareWrite"])
+    ),
+)]
+#[get("/share?<limit>")]
+async fn share_get(
+    db: MyDatabase,
+    user: User,
+    signer: &State<Signer>,
+    limit: Option<u16>,
+) -> Result<Json<Vec<Share>>> {
+    if !user.permissions.contains(&Permission::ShareWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let mut shares = db
+        .run(move |conn| -> Result<Vec<Share>> {
+            Ok(conn
+                .prepare(
+                    "SELECT * FROM shares WHERE creator = ?1 ORDER BY created DESC, id LIMIT ?2",
+                )?
+                .query_map(
+                    params![user.username, limit.unwrap_or(50)],
+                    Share::try_from_row,
+                )?
+                .collect::<std::result::Result<Vec<Share>, _>>()?)
+        })
+        .await?;
+
+    for share in shares.iter_mut() {
+        share.url = url(signer, share);
+    }
+    Ok(Json(shares))
+}
+
+/// Revoke one of your share links.
+///
+/// Requires: `ShareWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `ShareWrite`"),
+        (status = 404, description = "Not Found you have no share link with that id"),
+    ),
+    params(
+        ("id" = String, description = "The id of the share link to revoke")
+    ),
+    security(
+        ("permissions" = ["ShareWrite"])
+    ),
+)]
+#[delete("/share/<id>")]
+async fn share_delete(db: MyDatabase, user: User, id: String) -> Result<()> {
+    if !user.permissions.contains(&Permission::ShareWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<()> {
+        if conn.execute(
+            "DELETE FROM shares WHERE id = ?1 AND creator = ?2",
+            params![id, user.username],
+        )? == 0
+        {
+            Err(Status::NotFound)?
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+/// Read what a share link gives access to, no login needed.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Shared),
+        (status = 403, description = "Forbidden the link is not signed or has expired"),
+        (status = 404, description = "Not Found the link has been revoked"),
+    ),
+    params(
+        ("id" = String, description = "The id of the share link"),
+        ("expires" = u64, Query, description = "When the link stops working (unix seconds)"),
+        ("signature" = String, Query, description = "The signature of the link"),
+    ),
+)]
+#[get("/share/<id>?<expires>&<signature>")]
+async fn shared_get(
+    db: MyDatabase,
+    signer: &State<Signer>,
+    id: String,
+    expires: Option<u64>,
+    signature: Option<&str>,
+) -> Result<Json<Shared>> {
+    let share = verify(&db, signer, id, expires, signature).await?;
+
+    db.run(move |conn| -> Result<Json<Shared>> {
+        let album = share
+            .album
+            .as_ref()
+            .map(|album| -> Result<Album> {
+                let mut album = conn.query_row(
+                    &format!("{} AND albums.id = ? GROUP BY albums.id", Album::SELECT),
+                    [album],
+                    Album::try_from_row,
+                )?;
+                album.loudness = loudness::album(conn, &album.id)?;
+                Ok(album)
+            })
+            .transpose()?;
+
+        let tracks = conn
+            .prepare(&format!(
+                "{} AND (id = ?1 OR id IN (SELECT track_id FROM album_tracks WHERE album_id = ?2)) GROUP BY id",
+                Track::SELECT
+            ))?
+            .query_map(params![share.track, share.album], Track::try_from_row)?
+            .collect::<std::result::Result<Vec<Track>, _>>()?;
+
+        Ok(Json(Shared {
+            album,
+            tracks,
+            expires: share.expires,
+            plays_left: share
+                .max_plays
+                .map(|max_plays| max_plays.saturating_sub(share.plays)),
+        }))
+    })
+    .await
+}
+
+/// Download the audio of a shared track, or of one of a shared album's tracks, no login needed.
+///
+/// Every download counts as a play.
+/// With the `s3` storage backend this redirects to a short-lived link to the file in the bucket.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", content_type = "audio/mpeg", body = String),
+        (status = 307, description = "Temporary Redirect to the file in the storage bucket"),
+        (status = 403, description = "Forbidden the link is not signed or has expired"),
+        (status = 404, description = "Not Found the link has been revoked, the track is not shared by it or has no audio"),
+        (status = 410, description = "Gone the link has no plays left"),
+    ),
+    params(
+        ("id" = String, description = "The id of the share link"),
+        ("track", description = "The id of the track"),
+        ("expires" = u64, Query, description = "When the link stops working (unix seconds)"),
+        ("signature" = String, Query, description = "The signature of the link"),
+    ),
+)]
+#[get("/share/<id>/audio/<track>?<expires>&<signature>")]
+async fn shared_audio_get(
+    db: MyDatabase,
+    signer: &State<Signer>,
+    storage: &State<Storage>,
+    id: String,
+    track: String,
+    expires: Option<u64>,
+    signature: Option<&str>,
+) -> Result<Option<Download>> {
+    let share = verify(&db, signer, id, expires, signature).await?;
+
+    let shared = track.clone();
+    let included = db
+        .run(move |conn| -> Result<bool> {
+            Ok(conn.query_row(
+                "SELECT ?1 IS ?2 OR EXISTS(SELECT 1 FROM album_tracks WHERE album_id = ?3 AND track_id = ?1)",
+                params![shared, share.track, share.album],
+                |row| row.get(0),
+            )?)
+        })
+        .await?;
+    if !included {
+        return Ok(None);
+    }
+
+    let Some(info) = audio::info(&db, &track).await? else {
+        return Ok(None);
+    };
+
+    let played = db
+        .run(move |conn| {
+            conn.execute(
+                "UPDATE shares SET plays = plays + 1 WHERE id = ? AND (max_plays IS NULL OR plays < max_plays)",
+                [share.id],
+            )
+        })
+        .await?;
+    if played == 0 {
+        Err(Status::Gone)?
+    }
+
+    let content_type = ContentType::parse_flexible(&info.mime).unwrap_or(ContentType::Binary);
+    storage
+        .audio
+        .download(&Storage::blob_key(&info.hash), &content_type)
+        .await
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Share Endpoints", |rocket| async {
+        rocket.mount(
+            "/",
+            routes![
+                share_write,
+                share_get,
+                share_delete,
+                shared_get,
+                shared_audio_get
+            ],
+        )
+    })
+}
@@ -10,15 +10,19 @@ use uuid::Uuid;
 use crate::{
     api::data::{
         permissions::Permission,
+        totp::verify_second_factor,
         users::{DangerousLogin, User},
     },
     database::MyDatabase,
     error::ApiError,
+    rate_limit::RateLimit,
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
 
 /// Creates a login token which can be used to access other endpoints
+///
+/// Once a user has confirmed a TOTP second factor the `code` field is required.
 #[utoipa::path(
     request_body(content = DangerousLogin, description = "Your username & password"),
     responses(
@@ -29,19 +33,33 @@ type Result<T> = std::result::Result<T, ApiError>;
         body = String,
         example = json!(String::from("479f879a-db6d-47e9-a094-124cd0ad648f")),
     ),
+    (
+        status = 401,
+        description = "Unauthorized the user has a second factor & no `code` was given",
+    ),
     (
         status = 403,
-        description = "Forbidden invalid username and/or password",
+        description = "Forbidden invalid username, password and/or code",
+    ),
+    (
+        status = 429,
+        description = "Too Many Requests retry after the number of seconds in the `Retry-After` header",
     )),
 )]
 #[post("/token", data = "<login>")]
 async fn token_write(
     db: MyDatabase,
     jar: &CookieJar<'_>,
+    limit: RateLimit<'_>,
     login: Json<DangerousLogin>,
 ) -> Result<Json<String>> {
     let login = login.into_inner();
-    let token: String = db
+    let username = login.username.clone();
+
+    limit.username(&username)?;
+    limit.login_allowed(&username)?;
+
+    let token: Result<String> = db
         .run(move |conn| -> Result<String> {
             let tx = conn.transaction()?;
 
@@ -55,6 +73,8 @@ async fn token_write(
                 Err(Status::Forbidden)?
             }
 
+            verify_second_factor(&tx, &login.username, login.code.as_deref())?;
+
             let token: String = Uuid::new_v4().to_string();
 
             tx.execute(
@@ -66,7 +86,21 @@ async fn token_write(
 
             Ok(token)
         })
-        .await?;
+        .await;
+
+    let token = match token {
+        Ok(token) => {
+            limit.login_succeeded(&username);
+            token
+        }
+        // only a wrong password or code counts, not a missing code or a database error
+        Err(e) if e.status() == Status::Forbidden => {
+            limit.login_failed(&username);
+            Err(e)?
+        }
+        Err(e) => Err(e)?,
+    };
+
     jar.add(("token", token.clone()));
     Ok(Json(token))
 }
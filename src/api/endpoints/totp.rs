@@ -0,0 +1,198 @@
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
+use rocket_sync_db_pools::rusqlite::params;
+
+use crate::{
+    api::data::{
+        permissions::{permissions_from_row, Permission},
+        totp::{self, verify_second_factor, TotpCode, TotpEnrollment},
+        users::User,
+    },
+    database::MyDatabase,
+    error::ApiError,
+    rate_limit::RateLimit,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Start enrolling a TOTP second factor.
+///
+/// The secret is not required when logging in until it has been confirmed with a code.
+/// Enrolling again before confirming replaces the previous secret.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = TotpEnrollment),
+        (status = 409, description = "Conflict a second factor is already confirmed"),
+    ),
+)]
+#[post("/totp")]
+async fn totp_write(db: MyDatabase, user: User) -> Result<Json<TotpEnrollment>> {
+    db.run(move |conn| -> Result<Json<TotpEnrollment>> {
+        let tx = conn.transaction()?;
+
+        if tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM totp WHERE username = ? AND confirmed = 1)",
+            params![user.username],
+            |row| Ok(row.get::<usize, u8>(0)? == 1),
+        )? {
+            Err(Status::Conflict)?
+        }
+
+        let enrollment = TotpEnrollment::generate(&user.username);
+
+        tx.execute(
+            "INSERT OR REPLACE INTO totp (username, secret) VALUES (?1, ?2)",
+            params![user.username, enrollment.secret],
+        )?;
+
+        tx.commit()?;
+
+        Ok(Json(enrollment))
+    })
+    .await
+}
+
+/// Confirm a TOTP second factor with a code from your authenticator.
+///
+/// Returns one-time recovery codes, they can be used in place of a TOTP code when logging in.
+#[utoipa::path(
+    request_body(content = TotpCode, description = "A code generated from the enrolled secret"),
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            body = Vec<String>,
+            example = json!(["x7kq2m9fa0pe", "c3ud8wz1rn5h"]),
+        ),
+        (status = 403, description = "Forbidden invalid code"),
+        (status = 404, description = "Not Found no pending enrollment"),
+    ),
+)]
+#[post("/totp/confirm", data = "<code>")]
+async fn totp_confirm(
+    db: MyDatabase,
+    user: User,
+    code: Json<TotpCode>,
+) -> Result<Json<Vec<String>>> {
+    let code = code.into_inner().code;
+
+    db.run(move |conn| -> Result<Json<Vec<String>>> {
+        let tx = conn.transaction()?;
+
+        let secret: String = tx.query_row(
+            "SELECT secret FROM totp WHERE username = ? AND confirmed = 0",
+            params![user.username],
+            |row| row.get(0),
+        )?;
+
+        let Some(step) = totp::verify(&secret, &code, None) else {
+            Err(Status::Forbidden)?
+        };
+
+        tx.execute(
+            "UPDATE totp SET confirmed = 1, last_step = ?1 WHERE username = ?2",
+            params![step, user.username],
+        )?;
+
+        let recovery_codes = totp::recovery_codes();
+        for recovery_code in recovery_codes.iter() {
+            tx.execute(
+                "INSERT INTO recovery_codes (hash, username) VALUES (?1, ?2)",
+                params![totp::hash_recovery_code(recovery_code), user.username],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(Json(recovery_codes))
+    })
+    .await
+}
+
+/// Remove the second factor of a user.
+///
+/// Requires: `TotpDelete` & all permissions of the user to reset another users second factor, but you are free to remove your own.
+/// Removing your own confirmed second factor takes a current TOTP code or one of your recovery codes.
+#[utoipa::path(
+    request_body(content = Option<TotpCode>, description = "A TOTP or recovery code, when removing your own second factor"),
+    responses(
+        (status = 200, description = "Success"),
+        (status = 401, description = "Unauthorized a code is required to remove your own second factor"),
+        (status = 403, description = "Forbidden you do not have the required permissions or an invalid code"),
+        (status = 404, description = "Not Found the user has no second factor"),
+        (
+            status = 429,
+            description = "Too Many Requests retry after the number of seconds in the `Retry-After` header",
+        ),
+    ),
+    params(
+        ("username", description = "The username of the user who's second factor you would like to remove"),
+    ),
+    security(
+        ("permissions" = ["TotpDelete"])
+    ),
+)]
+#[delete("/totp/<username>", data = "<code>")]
+async fn totp_delete(
+    db: MyDatabase,
+    user: User,
+    limit: RateLimit<'_>,
+    username: String,
+    code: Option<Json<TotpCode>>,
+) -> Result<()> {
+    let own = username == user.username;
+    if own {
+        limit.login_allowed(&username)?;
+    }
+
+    let name = username.clone();
+    let result = db
+        .run(move |conn| -> Result<()> {
+            let tx = conn.transaction()?;
+
+            if own {
+                // a session cookie alone isn't enough to drop the second factor
+                let code = code.map(|code| code.into_inner().code);
+                verify_second_factor(&tx, &username, code.as_deref())?;
+            } else {
+                let mut required_permissions = tx.query_row(
+                    "SELECT GROUP_CONCAT(DISTINCT user_permissions.id) AS permissions FROM users
+                    LEFT JOIN user_permissions ON users.username = user_permissions.username
+                    WHERE users.username = ? GROUP BY users.username",
+                    params![username],
+                    permissions_from_row,
+                )?;
+
+                required_permissions.push(Permission::TotpDelete);
+
+                if !required_permissions
+                    .iter()
+                    .all(|permission| user.permissions.contains(permission))
+                {
+                    Err(Status::Forbidden)?
+                }
+            }
+
+            if tx.execute("DELETE FROM totp WHERE username = ?", params![username])? == 0 {
+                Err(Status::NotFound)?
+            }
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await;
+
+    match result {
+        // wrong codes count towards the login backoff, so they can't be guessed here instead
+        Err(e) if own && e.status() == Status::Forbidden => {
+            limit.login_failed(&name);
+            Err(e)
+        }
+        result => result,
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API TOTP EndPoints", |rocket| async {
+        rocket.mount("/", routes![totp_write, totp_confirm, totp_delete])
+    })
+}
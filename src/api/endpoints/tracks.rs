@@ -1,68 +1,72 @@
-use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
-use rocket_sync_db_pools::rusqlite::{params, Error::QueryReturnedNoRows, ToSql};
+use rocket::{
+    data::ToByteUnit,
+    fairing::AdHoc,
+    http::{ContentType, Status},
+    serde::json::Json,
+    Data, Either, State,
+};
 
 use crate::{
-    api::data::{permissions::Permission, tracks::Track, users::User},
+    api::data::{
+        events::{Action, Resource},
+        lyrics::{LyricLine, Lyrics, LyricsFormat},
+        permissions::Permission,
+        tracks::{Duplicate, DuplicateGroup, Track, TrackFilter},
+        users::User,
+    },
+    audio::{fingerprint, Blobs},
     database::MyDatabase,
     error::ApiError,
+    events::Events,
+    lyrics,
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
 
 #[post("/track", data = "<track>")]
-async fn track_write(db: MyDatabase, user: User, track: Json<Track>) -> Result<Json<Track>> {
+async fn track_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    track: Json<Track>,
+) -> Result<Json<Track>> {
     if !user.permissions.contains(&Permission::TrackWrite) {
         Err(Status::Forbidden)?
     }
 
     let mut track = track.into_inner();
+    let log = events.log();
 
-    db.run(move |conn| -> Result<Json<Track>> {
-        let tx = conn.transaction()?;
-
-        tx.execute(
-            "INSERT INTO tracks (id, name, release, lyrics) VALUES (?1, ?2, ?3, ?4)",
-            params![track.id, track.name, track.release, track.lyrics,],
-        )?;
-
-        for album in track.albums.iter() {
-            tx.execute(
-                "INSERT INTO album_tracks (album_id, track_id) VALUES (?1, ?2)",
-                params![album, track.id],
-            )?;
-
-            // return track should contain the track's artists
-            track.artists.extend(
-                tx.prepare("SELECT artist_id FROM artist_albums WHERE album_id = ?")?
-                    .query_map(params![album], |row| row.get::<usize, String>(0))?
-                    .map(|v| v.map_err(ApiError::from))
-                    .collect::<Result<Vec<String>>>()?,
-            );
-        }
+    let (track, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        for genre in track.genres.iter() {
-            tx.execute(
-                "INSERT INTO track_genres (track_id, genre_id) VALUES (?1, ?2)",
-                params![track.id, genre],
-            )?;
-        }
+            track.insert(&tx)?;
+            let event = log.record(&tx, Resource::Track, Action::Create, track.id.clone())?;
 
-        tx.commit()?;
+            tx.commit()?;
 
-        Ok(Json(track))
-    })
-    .await
+            Ok((track, event))
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(Json(track))
 }
 
-#[get("/track?<id>&<name>&<maxrelease>&<minrelease>&<genres>&<albums>&<artists>&<lyrics>&<limit>")]
+#[get("/track?<id>&<name>&<maxrelease>&<minrelease>&<genres>&<descendants>&<isrc>&<mbid>&<albums>&<artists>&<lyrics>&<limit>")]
 async fn track_get(
     db: MyDatabase,
     user: User,
     id: Option<String>,
     name: Option<String>,
-    maxrelease: Option<u16>,
-    minrelease: Option<u16>,
+    maxrelease: Option<String>,
+    minrelease: Option<String>,
     genres: Option<Json<Vec<String>>>,
+    descendants: Option<bool>,
+    isrc: Option<String>,
+    mbid: Option<String>,
     albums: Option<Json<Vec<String>>>,
     artists: Option<Json<Vec<String>>>,
     lyrics: Option<String>,
@@ -71,125 +75,328 @@ async fn track_get(
     if !user.permissions.contains(&Permission::TrackRead) {
         Err(Status::Forbidden)?
     }
-    db.run(move |conn| -> Result<Json<Vec<Track>>> {
-        let mut sql = "SELECT id, name, release, duration, COALESCE(GROUP_CONCAT(DISTINCT album_tracks.album_id), '') AS albums, COALESCE(GROUP_CONCAT(DISTINCT artist_albums.artist_id), '') AS artists, lyrics, COALESCE(GROUP_CONCAT(DISTINCT track_genres.genre_id), '') AS genres FROM tracks
-            LEFT JOIN track_genres ON tracks.id = track_genres.track_id
-            LEFT JOIN album_tracks ON tracks.id = album_tracks.track_id
-            LEFT JOIN artist_albums ON album_tracks.album_id = artist_albums.album_id WHERE 1=1".to_string();
-        let mut params_vec = Vec::new();
-
-        if let Some(id_val) = id {
-            sql += " AND id = ?";
-            params_vec.push(id_val);
-        }
-
-        if let Some(name_val) = name {
-            sql += " AND name LIKE ?";
-            params_vec.push(format!("%{}%", name_val));
-        }
 
-        if let Some(maxrelease_val) = maxrelease {
-            sql += " AND release <= ?";
-            params_vec.push(maxrelease_val.to_string());
-        }
+    let filter = TrackFilter {
+        id,
+        name,
+        maxrelease,
+        minrelease,
+        genres: genres.map(Json::into_inner),
+        descendants,
+        isrc,
+        mbid,
+        albums: albums.map(Json::into_inner),
+        artists: artists.map(Json::into_inner),
+        lyrics,
+    };
+
+    db.run(move |conn| -> Result<Json<Vec<Track>>> { Ok(Json(filter.select(conn, limit, None)?)) })
+        .await
+}
 
-        if let Some(minrelease_val) = minrelease {
-            sql += " AND release >= ?";
-            params_vec.push(minrelease_val.to_string());
-        }
+#[delete("/track/<id>")]
+async fn track_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    blobs: &State<Blobs>,
+    id: String,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::TrackDelete) {
+        Err(Status::Forbidden)?
+    }
 
-        if let Some(artists_val) = artists {
-            let artists_val = artists_val.into_inner();
-            let artist_placeholders = artists_val.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
-            sql += &format!(" AND album_tracks.album_id IN ({})", artist_placeholders);
-            params_vec.extend(artists_val);
-        }
+    let recorded = blobs.delete_track(&db, events.log(), id).await?;
+    events.send(recorded);
 
-        if let Some(albums_val) = albums {
-            let albums_val = albums_val.into_inner();
-            let album_placeholders = albums_val.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
-            sql += &format!(" AND artist_albums.album_id IN ({})", album_placeholders);
-            params_vec.extend(albums_val);
-        }
+    Ok(())
+}
 
-        if let Some(genres_val) = genres {
-            let genres_val = genres_val.into_inner();
-            let genre_placeholders = genres_val.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
-            sql += &format!(" AND track_genres.genre_id IN ({})", genre_placeholders);
-            params_vec.extend(genres_val);
-        }
+/// The similarity above which tracks are reported as duplicates when no threshold is given.
+const THRESHOLD: f64 = 0.75;
+
+/// Get the tracks that are likely the same recording as a track, most similar first.
+///
+/// Tracks with the same audio file are always duplicates, others are found by their acoustic fingerprints once their audio has been analyzed.
+///
+/// Requires: `TrackRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<Duplicate>),
+        (status = 400, description = "Bad Request `threshold` is not between 0 & 1"),
+        (status = 403, description = "Forbidden requires permission `TrackRead`"),
+        (status = 404, description = "Not Found the track does not exist"),
+    ),
+    params(
+        ("id", description = "The id of the track"),
+        ("threshold" = Option<f64>, Query, description = "The lowest similarity to return, 0.75 by default"),
+    ),
+    security(
+        ("permissions" = ["TrackRead"])
+    ),
+)]
+#[get("/track/<id>/duplicates?<threshold>")]
+async fn track_duplicates_get(
+    db: MyDatabase,
+    user: User,
+    id: String,
+    threshold: Option<f64>,
+) -> Result<Option<Json<Vec<Duplicate>>>> {
+    if !user.permissions.contains(&Permission::TrackRead) {
+        Err(Status::Forbidden)?
+    }
 
-        if let Some(lyrics_val) = lyrics {
-            sql += " AND lyrics LIKE ?";
-            params_vec.push(format!("%{}%", lyrics_val));
-        }
+    let threshold = threshold.unwrap_or(THRESHOLD);
+    if !(0.0..=1.0).contains(&threshold) {
+        Err(Status::BadRequest)?
+    }
 
-        sql += &format!(" GROUP BY id LIMIT {}", limit.unwrap_or(50));
-
-        let params_sql: Vec<&dyn ToSql> =
-            params_vec.iter().map(|param| param as &dyn ToSql).collect();
-
-        Ok(Json(
-                conn.prepare(&sql)?.query_map(&params_sql[..], |row| {
-                    let artists_str: String = row.get("artists")?;
-                    let artists: Vec<String> = artists_str.split(',')
-                        .filter_map(|s| if s.trim().is_empty() { None } else { Some(s.to_string()) })
-                        .collect();
-
-                    let albums_str: String = row.get("albums")?;
-                    let albums: Vec<String> = albums_str.split(',')
-                        .filter_map(|s| if s.trim().is_empty() { None } else { Some(s.to_string()) })
-                        .collect();
-
-                    let genres_str: String = row.get("genres")?;
-                    let genres: Vec<String> = genres_str.split(',')
-                        .filter_map(|s| if s.trim().is_empty() { None } else { Some(s.to_string()) })
-                        .collect();
-
-                    Ok(Track {
-                        id: row.get("id")?,
-                        name: row.get("name")?,
-                        release: row.get("release")?,
-                        duration: row.get("duration")?,
-                        albums,
-                        artists,
-                        lyrics: row.get("lyrics")?,
-                        genres,
-                    })
-                }
-                    )?
-                .map(|v| v.map_err(ApiError::from))
-                .collect::<Result<Vec<Track>>>()?,
-               ))
-    }).await
+    Ok(db
+        .run(move |conn| fingerprint::duplicates(conn, &id, threshold))
+        .await?
+        .map(Json))
 }
 
-#[delete("/track/<id>")]
-async fn track_delete(db: MyDatabase, user: User, id: String) -> Result<()> {
-    if !user.permissions.contains(&Permission::TrackDelete) {
+/// Get every group of tracks that are likely the same recording, for review.
+///
+/// Requires: `TrackRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<DuplicateGroup>),
+        (status = 400, description = "Bad Request `threshold` is not between 0 & 1"),
+        (status = 403, description = "Forbidden requires permission `TrackRead`"),
+    ),
+    params(
+        ("threshold" = Option<f64>, Query, description = "The lowest similarity to group tracks by, 0.75 by default"),
+    ),
+    security(
+        ("permissions" = ["TrackRead"])
+    ),
+)]
+#[get("/duplicates?<threshold>")]
+async fn duplicates_get(
+    db: MyDatabase,
+    user: User,
+    threshold: Option<f64>,
+) -> Result<Json<Vec<DuplicateGroup>>> {
+    if !user.permissions.contains(&Permission::TrackRead) {
         Err(Status::Forbidden)?
     }
 
-    db.run(move |conn| -> Result<()> {
-        let tx = conn.transaction()?;
+    let threshold = threshold.unwrap_or(THRESHOLD);
+    if !(0.0..=1.0).contains(&threshold) {
+        Err(Status::BadRequest)?
+    }
+
+    Ok(Json(
+        db.run(move |conn| fingerprint::report(conn, threshold))
+            .await?,
+    ))
+}
 
-        if let Err(QueryReturnedNoRows) =
-            tx.query_row("SELECT 1 FROM tracks WHERE id = ?", params![id], |_| Ok(()))
-        {
-            Err(Status::NotFound)?
+/// Get a track's lyrics.
+///
+/// `json` returns the lyrics in every language, or in `lang`; `lrc` & `text` return one language, the first if `lang` isn't given.
+/// The lyrics set on the track itself are `und`, unless lyrics in `und` were stored.
+///
+/// Requires: `TrackRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<Lyrics>),
+        (status = 200, description = "Success with `format` `lrc` or `text`", content_type = "text/plain", body = String),
+        (status = 403, description = "Forbidden requires permission `TrackRead`"),
+        (status = 404, description = "Not Found the track does not exist or has no lyrics (in `lang`)"),
+    ),
+    params(
+        ("id", description = "The id of the track"),
+        ("format" = Option<LyricsFormat>, Query, description = "`json` by default"),
+        ("lang", Query, description = "The language of the lyrics e.g. `eng`"),
+    ),
+    security(
+        ("permissions" = ["TrackRead"])
+    ),
+)]
+#[get("/track/<id>/lyrics?<format>&<lang>")]
+async fn track_lyrics_get(
+    db: MyDatabase,
+    user: User,
+    id: String,
+    format: Option<LyricsFormat>,
+    lang: Option<String>,
+) -> Result<Option<Either<Json<Vec<Lyrics>>, (ContentType, String)>>> {
+    if !user.permissions.contains(&Permission::TrackRead) {
+        Err(Status::Forbidden)?
+    }
+
+    let lang = lang
+        .map(|lang| lyrics::language(&lang).ok_or(Status::BadRequest))
+        .transpose()?;
+    let versions = db
+        .run(move |conn| -> Result<Option<Vec<Lyrics>>> {
+            if !conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?)",
+                [&id],
+                |row| row.get(0),
+            )? {
+                return Ok(None);
+            }
+            Ok(Some(lyrics::read(conn, &id, lang.as_deref())?))
+        })
+        .await?;
+    let Some(mut versions) = versions.filter(|versions| !versions.is_empty()) else {
+        return Ok(None);
+    };
+
+    Ok(Some(match format.unwrap_or_default() {
+        LyricsFormat::Json => Either::Left(Json(versions)),
+        LyricsFormat::Lrc => {
+            Either::Right((ContentType::Plain, lyrics::to_lrc(&versions.remove(0))))
+        }
+        LyricsFormat::Text => {
+            Either::Right((ContentType::Plain, lyrics::to_text(&versions.remove(0))))
         }
+    }))
+}
 
-        tx.execute("DELETE FROM tracks WHERE id = ?", params![id])?;
+/// Set a track's lyrics in one language, replacing any it had in that language.
+///
+/// The body is a list of `LyricLine` with `format` `json`, LRC with `lrc` or plain text with `text`.
+/// Lyrics are also read from the SYLT & USLT frames of uploaded MP3 files, in languages the track has no lyrics in yet.
+///
+/// Requires: `TrackWrite` permission.
+#[utoipa::path(
+    request_body(content = String, description = "The lyrics in `format`", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Success", body = Lyrics),
+        (status = 400, description = "Bad Request the language or lyrics can't be read"),
+        (status = 403, description = "Forbidden requires permission `TrackWrite`"),
+        (status = 404, description = "Not Found the track does not exist"),
+        (status = 413, description = "Payload Too Large the lyrics are over 1MiB"),
+    ),
+    params(
+        ("id", description = "The id of the track"),
+        ("lang", description = "The language of the lyrics e.g. `eng`"),
+        ("format" = Option<LyricsFormat>, Query, description = "`json` by default"),
+    ),
+    security(
+        ("permissions" = ["TrackWrite"])
+    ),
+)]
+#[put("/track/<id>/lyrics/<lang>?<format>", data = "<data>")]
+async fn track_lyrics_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+    lang: &str,
+    format: Option<LyricsFormat>,
+    data: Data<'_>,
+) -> Result<Option<Json<Lyrics>>> {
+    if !user.permissions.contains(&Permission::TrackWrite) {
+        Err(Status::Forbidden)?
+    }
 
-        tx.commit()?;
+    let lang = lyrics::language(lang).ok_or(Status::BadRequest)?;
+    let body = data.open(1.mebibytes()).into_string().await?;
+    if !body.is_complete() {
+        Err(Status::PayloadTooLarge)?
+    }
+    let lines = match format.unwrap_or_default() {
+        LyricsFormat::Json => rocket::serde::json::from_str::<Vec<LyricLine>>(&body)
+            .map_err(|_| Status::BadRequest)?,
+        LyricsFormat::Lrc => lyrics::parse_lrc(&body),
+        LyricsFormat::Text => lyrics::parse_text(&body),
+    };
+
+    let log = events.log();
+    let written = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+            if !tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?)",
+                [&id],
+                |row| row.get(0),
+            )? {
+                return Ok(None);
+            }
+
+            lyrics::write(&tx, &id, &lang, &lines)?;
+            let written = lyrics::read(&tx, &id, Some(&lang))?.pop();
+            let event = log.record(&tx, Resource::Track, Action::Update, id)?;
+            tx.commit()?;
+            Ok(written.map(|written| (written, event)))
+        })
+        .await?;
+    let Some((written, event)) = written else {
+        return Ok(None);
+    };
+
+    events.send([event]);
+    Ok(Some(Json(written)))
+}
 
-        Ok(())
-    })
-    .await
+/// Remove a track's lyrics in one language.
+///
+/// Requires: `TrackWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `TrackWrite`"),
+        (status = 404, description = "Not Found the track has no lyrics stored in that language"),
+    ),
+    params(
+        ("id", description = "The id of the track"),
+        ("lang", description = "The language of the lyrics e.g. `eng`"),
+    ),
+    security(
+        ("permissions" = ["TrackWrite"])
+    ),
+)]
+#[delete("/track/<id>/lyrics/<lang>")]
+async fn track_lyrics_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    id: String,
+    lang: &str,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::TrackWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let lang = lyrics::language(lang).ok_or(Status::NotFound)?;
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+            if !lyrics::delete(&tx, &id, &lang)? {
+                Err(Status::NotFound)?
+            }
+            let event = log.record(&tx, Resource::Track, Action::Update, id)?;
+            tx.commit()?;
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
 }
 
 pub fn fairing() -> AdHoc {
     AdHoc::on_ignite("API Track EndPoints", |rocket| async {
-        rocket.mount("/", routes![track_write, track_get, track_delete])
+        rocket.mount(
+            "/",
+            routes![
+                track_write,
+                track_get,
+                track_delete,
+                track_duplicates_get,
+                duplicates_get,
+                track_lyrics_get,
+                track_lyrics_write,
+                track_lyrics_delete
+            ],
+        )
     })
 }
@@ -0,0 +1,248 @@
+use rocket::{
+    fairing::AdHoc,
+    http::{Header, Status},
+    request::{self, FromRequest, Request},
+    serde::json::Json,
+    Data, State,
+};
+
+use crate::{
+    api::data::{
+        audio::{UploadCreate, UploadSession},
+        permissions::Permission,
+        users::User,
+    },
+    audio::Uploads,
+    database::MyDatabase,
+    error::ApiError,
+    events::Events,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `Upload-Offset` header, where a chunk starts in the whole file.
+pub struct UploadOffset(u64);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UploadOffset {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match request
+            .headers()
+            .get_one("Upload-Offset")
+            .and_then(|offset| offset.trim().parse().ok())
+        {
+            Some(offset) => request::Outcome::Success(UploadOffset(offset)),
+            None => {
+                request::Outcome::Error((Status::BadRequest, ApiError::Status(Status::BadRequest)))
+            }
+        }
+    }
+}
+
+/// A session, with its progress in the `Upload-Offset` & `Upload-Length` headers for `HEAD` requests.
+#[derive(Responder)]
+struct Progress {
+    session: Json<UploadSession>,
+    offset: Header<'static>,
+    length: Header<'static>,
+}
+
+impl From<UploadSession> for Progress {
+    fn from(session: UploadSession) -> Self {
+        Self {
+            offset: Header::new("Upload-Offset", session.offset.to_string()),
+            length: Header::new("Upload-Length", session.size.to_string()),
+            session: Json(session),
+        }
+    }
+}
+
+/// Start a resumable upload of a track's audio.
+///
+/// Send the file in chunks with `PATCH /upload/<id>`, once all of it has arrived it's checked against `sha256` & becomes the track's audio.
+/// Sessions that receive nothing for `audio.session_expiry` seconds are deleted.
+///
+/// Requires: `AudioWrite` permission.
+#[utoipa::path(
+    request_body = UploadCreate,
+    responses(
+        (status = 200, description = "Success", body = UploadSession),
+        (status = 400, description = "Bad Request `sha256` is not a SHA-256"),
+        (status = 403, description = "Forbidden requires permission `AudioWrite`"),
+        (status = 404, description = "Not Found the track does not exist"),
+        (status = 413, description = "Payload Too Large `size` is larger than `audio.limit`"),
+        (status = 415, description = "Unsupported Media Type `mime` is not an audio type"),
+    ),
+    security(
+        ("permissions" = ["AudioWrite"])
+    ),
+)]
+#[post("/upload", data = "<upload>", format = "json")]
+async fn upload_write(
+    db: MyDatabase,
+    user: User,
+    uploads: &State<Uploads>,
+    upload: Json<UploadCreate>,
+) -> Result<Json<UploadSession>> {
+    if !user.permissions.contains(&Permission::AudioWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(Json(
+        uploads
+            .create(&db, &user.username, upload.into_inner())
+            .await?,
+    ))
+}
+
+/// Get the progress of one of your resumable uploads, `HEAD` only sends the headers.
+///
+/// Requires: `AudioWrite` permission.
+#[utoipa::path(
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            body = UploadSession,
+            headers(
+                ("Upload-Offset" = u64, description = "The number of bytes received"),
+                ("Upload-Length" = u64, description = "The size of the whole file"),
+            ),
+        ),
+        (status = 403, description = "Forbidden requires permission `AudioWrite`"),
+        (status = 404, description = "Not Found the upload does not exist or has expired"),
+    ),
+    params(
+        ("id", description = "The id of the upload"),
+    ),
+    security(
+        ("permissions" = ["AudioWrite"])
+    ),
+)]
+#[get("/upload/<id>")]
+async fn upload_get(
+    db: MyDatabase,
+    user: User,
+    uploads: &State<Uploads>,
+    id: &str,
+) -> Result<Progress> {
+    if !user.permissions.contains(&Permission::AudioWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    Ok(uploads.get(&db, &user.username, id).await?.into())
+}
+
+/// Send the next chunk of a resumable upload.
+///
+/// `Upload-Offset` has to match the session's offset, whatever arrives is kept even if the connection drops.
+/// The chunk that completes the file stores it as the track's audio, if storing fails an empty chunk retries it.
+///
+/// Requires: `AudioWrite` permission.
+#[utoipa::path(
+    request_body(
+        description = "The next bytes of the file",
+        content_type = "application/offset+octet-stream",
+        content = String,
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Success",
+            body = UploadSession,
+            headers(
+                ("Upload-Offset" = u64, description = "The number of bytes received"),
+                ("Upload-Length" = u64, description = "The size of the whole file"),
+            ),
+        ),
+        (status = 400, description = "Bad Request `Upload-Offset` is missing"),
+        (status = 403, description = "Forbidden requires permission `AudioWrite`"),
+        (status = 404, description = "Not Found the upload does not exist or has expired"),
+        (status = 409, description = "Conflict `Upload-Offset` is not the session's offset, or another chunk is being received"),
+        (status = 413, description = "Payload Too Large the chunk goes past the end of the file"),
+        (status = 422, description = "Unprocessable Entity the file doesn't match its SHA-256, the upload is deleted"),
+    ),
+    params(
+        ("id", description = "The id of the upload"),
+        ("Upload-Offset" = u64, Header, description = "Where the chunk starts in the file"),
+    ),
+    security(
+        ("permissions" = ["AudioWrite"])
+    ),
+)]
+#[patch(
+    "/upload/<id>",
+    data = "<data>",
+    format = "application/offset+octet-stream"
+)]
+async fn upload_append(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    uploads: &State<Uploads>,
+    offset: UploadOffset,
+    id: &str,
+    data: Data<'_>,
+) -> Result<Progress> {
+    if !user.permissions.contains(&Permission::AudioWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let limit = uploads.config().limit;
+    let (session, event) = uploads
+        .append(
+            &db,
+            events.log(),
+            &user.username,
+            id,
+            offset.0,
+            Box::pin(data.open(limit + 1)),
+        )
+        .await?;
+
+    events.send(event);
+
+    Ok(session.into())
+}
+
+/// Cancel one of your resumable uploads, deleting what it has received.
+///
+/// Requires: `AudioWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `AudioWrite`"),
+        (status = 404, description = "Not Found the upload does not exist or has expired"),
+        (status = 409, description = "Conflict a chunk is being received"),
+    ),
+    params(
+        ("id", description = "The id of the upload"),
+    ),
+    security(
+        ("permissions" = ["AudioWrite"])
+    ),
+)]
+#[delete("/upload/<id>")]
+async fn upload_delete(
+    db: MyDatabase,
+    user: User,
+    uploads: &State<Uploads>,
+    id: &str,
+) -> Result<()> {
+    if !user.permissions.contains(&Permission::AudioWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    uploads.cancel(&db, &user.username, id).await
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Upload Endpoints", |rocket| async {
+        rocket.mount(
+            "/",
+            routes![upload_write, upload_get, upload_append, upload_delete],
+        )
+    })
+}
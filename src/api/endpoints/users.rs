@@ -1,14 +1,16 @@
-use rocket::{fairing::AdHoc, http::Status, serde::json::Json};
-use rocket_sync_db_pools::rusqlite::{params, params_from_iter};
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
 use strum::IntoEnumIterator;
 
 use crate::{
     api::data::{
-        permissions::{permissions_from_row, Permission},
-        users::{DangerousLogin, User},
+        events::{Action, Resource},
+        permissions::Permission,
+        users::{DangerousLogin, Quota, Usage, User, UserFilter},
     },
+    audio,
     database::MyDatabase,
     error::ApiError,
+    events::Events,
 };
 
 type Result<T> = std::result::Result<T, ApiError>;
@@ -29,28 +31,39 @@ type Result<T> = std::result::Result<T, ApiError>;
     ),
 )]
 #[post("/init", data = "<login>")]
-async fn user_init(db: MyDatabase, login: Json<DangerousLogin>) -> Result<()> {
+async fn user_init(
+    db: MyDatabase,
+    events: &State<Events>,
+    login: Json<DangerousLogin>,
+) -> Result<()> {
     let login = login.into_inner();
+    let log = events.log();
 
-    db.run(move |conn| -> Result<()> {
-        let tx = conn.transaction()?;
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
 
-        // if they are any rows in the db
-        if tx.query_row(
-            "SELECT EXISTS(SELECT 1 FROM users) OR EXISTS(SELECT 1 FROM invites)",
-            [],
-            |row| Ok(row.get::<usize, u8>(0)? == 1),
-        )? {
-            Err(Status::Conflict)?
-        };
+            // if they are any rows in the db
+            if tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM users) OR EXISTS(SELECT 1 FROM invites)",
+                [],
+                |row| Ok(row.get::<usize, u8>(0)? == 1),
+            )? {
+                Err(Status::Conflict)?
+            };
 
-        login.insert_user_into_transaction(Permission::iter(), &tx)?;
+            login.insert_user_into_transaction(Permission::iter(), &tx)?;
+            let event = log.record(&tx, Resource::User, Action::Create, login.username)?;
 
-        tx.commit()?;
+            tx.commit()?;
 
-        Ok(())
-    })
-    .await
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
 }
 
 /// Retrieve a list of users.
@@ -89,36 +102,13 @@ async fn user_get(
         Err(Status::Forbidden)?
     }
 
-    db.run(move |conn| -> Result<Json<Vec<User>>> {
-        let mut sql = "SELECT user_permissions.username AS username, COALESCE(GROUP_CONCAT(DISTINCT user_permissions.id), '') AS permissions
-        FROM users
-        LEFT JOIN user_permissions ON users.username = user_permissions.username
-        WHERE 1=1".to_string();
-        let mut params = Vec::new();
-
-        if let Some(username_val) = username {
-            sql += " AND users.username LIKE ?";
-            params.push(format!("%{}%", username_val));
-        }
-        if let Some(permissions_val) = permissions {
-            let permissions_val = permissions_val.into_inner();
-            sql += &format!(" AND user_permissions.id CONTAINS ({})", permissions_val.iter().map(|_| "?".to_string()).collect::<Vec<String>>().join(", "));
-            params.extend(permissions_val.into_iter().map(|p| p.to_string()));
-        }
-
-        sql += " GROUP BY user_permissions.username";
-        if let Some(limit_val) = limit {
-            sql += &format!(" LIMIT {}", limit_val)
-        }
+    let filter = UserFilter {
+        username,
+        permissions: permissions.map(Json::into_inner),
+    };
 
-        Ok(Json(
-            conn.prepare(&sql)?
-                .query_map(params_from_iter(params), User::try_from_row)?
-                .map(|v| v.map_err(ApiError::from))
-                .collect::<Result<Vec<User>>>()?,
-        ))
-    })
-    .await
+    db.run(move |conn| -> Result<Json<Vec<User>>> { Ok(Json(filter.select(conn, limit, None)?)) })
+        .await
 }
 
 /// Deletes a user from the database.
@@ -137,41 +127,158 @@ async fn user_get(
     ),
 )]
 #[delete("/user/<username>")]
-async fn user_delete(db: MyDatabase, user: User, username: &str) -> Result<()> {
+async fn user_delete(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    username: &str,
+) -> Result<()> {
     let username = username.to_string(); // Fix Message: Using `String` as a parameter type is inefficient. Use `&str` instead.
-    db.run(move |conn| -> Result<()> {
-        let tx = conn.transaction()?;
-
-        if username != user.username {
-            // we cant select directly from the user_permissions table because the user might not have any permissions
-            let mut required_permissions = tx.query_row(
-                "SELECT GROUP_CONCAT(DISTINCT user_permissions.id) AS permissions FROM users
-                    LEFT JOIN user_permissions ON users.username = user_permissions.username
-                    WHERE users.username = ? GROUP BY users.username",
-                params![username],
-                permissions_from_row,
-            )?;
-
-            required_permissions.push(Permission::UserDelete);
-
-            if !required_permissions
-                .iter()
-                .all(|permission| user.permissions.contains(permission))
-            {
-                Err(Status::Forbidden)?
-            }
-        }
-
-        tx.execute("DELETE FROM users WHERE username = ?", params![username])?;
-
-        tx.commit()?;
-        Ok(())
+    let log = events.log();
+    let event = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            user.delete_user(&tx, &username)?;
+            let event = log.record(&tx, Resource::User, Action::Delete, username)?;
+
+            tx.commit()?;
+            Ok(event)
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(())
+}
+
+/// Get how much audio a user has uploaded & their quota.
+///
+/// Requires: `UserRead` permission to see another user's usage, but you are free to see your own.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Usage),
+        (status = 403, description = "Forbidden requires permission `UserRead`"),
+        (status = 404, description = "Not Found the user does not exist"),
+    ),
+    params(
+        ("username", description = "The username of the user"),
+    ),
+    security(
+        ("permissions" = ["UserRead"])
+    ),
+)]
+#[get("/user/<username>/usage")]
+async fn user_usage_get(db: MyDatabase, user: User, username: &str) -> Result<Option<Json<Usage>>> {
+    if username != user.username && !user.permissions.contains(&Permission::UserRead) {
+        Err(Status::Forbidden)?
+    }
+
+    let username = username.to_string();
+    Ok(db
+        .run(move |conn| audio::usage(conn, &username, None))
+        .await?
+        .map(Json))
+}
+
+/// List the users who uploaded the most audio.
+///
+/// Requires: `UserRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<Usage>),
+        (status = 403, description = "Forbidden requires permission `UserRead`"),
+    ),
+    params(
+        ("limit", Query, description = "The maximum number of users to return, 10 by default"),
+    ),
+    security(
+        ("permissions" = ["UserRead"])
+    ),
+)]
+#[get("/usage?<limit>")]
+async fn usage_get(db: MyDatabase, user: User, limit: Option<u16>) -> Result<Json<Vec<Usage>>> {
+    if !user.permissions.contains(&Permission::UserRead) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<Json<Vec<Usage>>> {
+        Ok(Json(
+            conn.prepare(
+                "SELECT username, COALESCE(SUM(size), 0) AS used, COUNT(track_audio.track_id), quota FROM users
+                LEFT JOIN track_audio ON track_audio.uploader = users.username
+                LEFT JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+                GROUP BY username
+                ORDER BY used DESC, username
+                LIMIT ?",
+            )?
+            .query_map([limit.unwrap_or(10)], Usage::try_from_row)?
+            .map(|v| v.map_err(ApiError::from))
+            .collect::<Result<Vec<Usage>>>()?,
+        ))
     })
     .await
 }
 
+/// Set a user's storage quota, uploads that would take them over it are refused.
+///
+/// Audio they already uploaded is kept when the quota is lowered below it.
+///
+/// Requires: `QuotaWrite` permission & the permissions of the user, the quota can't be higher than your own.
+#[utoipa::path(
+    request_body = Quota,
+    responses(
+        (status = 200, description = "Success", body = Usage),
+        (status = 403, description = "Forbidden you do not have the required permissions or the quota is higher than your own"),
+        (status = 404, description = "Not Found the user does not exist"),
+    ),
+    params(
+        ("username", description = "The username of the user"),
+    ),
+    security(
+        ("permissions" = ["QuotaWrite"])
+    ),
+)]
+#[put("/user/<username>/quota", data = "<quota>")]
+async fn user_quota_write(
+    db: MyDatabase,
+    user: User,
+    events: &State<Events>,
+    username: &str,
+    quota: Json<Quota>,
+) -> Result<Json<Usage>> {
+    let quota = quota.into_inner().quota;
+    let username = username.to_string();
+    let log = events.log();
+    let (usage, event) = db
+        .run(move |conn| -> Result<_> {
+            let tx = conn.transaction()?;
+
+            user.set_quota(&tx, &username, quota)?;
+            let usage = audio::usage(&tx, &username, None)?.ok_or(Status::NotFound)?;
+            let event = log.record(&tx, Resource::User, Action::Update, username)?;
+
+            tx.commit()?;
+            Ok((usage, event))
+        })
+        .await?;
+
+    events.send([event]);
+    Ok(Json(usage))
+}
+
 pub fn fairing() -> AdHoc {
     AdHoc::on_ignite("API User EndPoints", |rocket| async {
-        rocket.mount("/", routes![user_init, user_get, user_delete,])
+        rocket.mount(
+            "/",
+            routes![
+                user_init,
+                user_get,
+                user_delete,
+                user_usage_get,
+                usage_get,
+                user_quota_write,
+            ],
+        )
     })
 }
@@ -0,0 +1,310 @@
+use data_encoding::HEXLOWER;
+use rand::RngCore;
+use rocket::{fairing::AdHoc, http::Status, serde::json::Json, State};
+use rocket_sync_db_pools::rusqlite::{params, Error::QueryReturnedNoRows, ToSql};
+use uuid::Uuid;
+
+use crate::{
+    api::data::{
+        permissions::Permission,
+        users::User,
+        webhooks::{Webhook, WebhookDelivery},
+    },
+    database::MyDatabase,
+    error::ApiError,
+    webhooks::{self, Webhooks},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Register a url to receive change events.
+///
+/// Every matching event is POSTed as a `ChangeEvent` with the headers `X-Tuna-Event`, `X-Tuna-Delivery` & `X-Tuna-Signature`.
+/// The signature is `sha256=` followed by the hex HMAC-SHA256 of the body keyed with the webhook's secret.
+/// Failed deliveries are retried with exponential backoff.
+/// The webhook only receives events for resources its creator has the `*Read` permission for.
+///
+/// Requires: `WebhookWrite` permission.
+#[utoipa::path(
+    request_body(content = Webhook, description = "The webhook to register, the id & creator are ignored"),
+    responses(
+        (
+            status = 200,
+            description = "Success, the only time the secret is returned",
+            content_type = "application/json",
+            body = Webhook,
+        ),
+        (status = 400, description = "Bad Request the url is not http(s) or an event type is unknown"),
+        (status = 403, description = "Forbidden requires permission `WebhookWrite`"),
+    ),
+    security(
+        ("permissions" = ["WebhookWrite"])
+    ),
+)]
+#[post("/webhook", data = "<webhook>")]
+async fn webhook_write(
+    db: MyDatabase,
+    user: User,
+    webhook: Json<Webhook>,
+) -> Result<Json<Webhook>> {
+    if !user.permissions.contains(&Permission::WebhookWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let mut webhook = webhook.into_inner();
+    if !reqwest::Url::parse(&webhook.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+        || !webhook
+            .events
+            .iter()
+            .all(|event| webhooks::subscribable(event))
+    {
+        Err(Status::BadRequest)?
+    }
+
+    webhook.id = Uuid::new_v4().to_string();
+    webhook.creator = user.username;
+    let secret = webhook.secret.get_or_insert_with(|| {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        HEXLOWER.encode(&secret)
+    });
+    let secret = secret.clone();
+
+    db.run(move |conn| -> Result<Json<Webhook>> {
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO webhooks (id, url, secret, creator) VALUES (?1, ?2, ?3, ?4)",
+            params![webhook.id, webhook.url, secret, webhook.creator],
+        )?;
+
+        for event in webhook.events.iter() {
+            tx.execute(
+                "INSERT OR IGNORE INTO webhook_events (event, webhook_id) VALUES (?1, ?2)",
+                params![event, webhook.id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(Json(webhook))
+    })
+    .await
+}
+
+/// Retrieve a list of webhooks, secrets are never included.
+///
+/// Requires: `WebhookRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<Webhook>),
+        (status = 403, description = "Forbidden requires permission `WebhookRead`"),
+    ),
+    params(
+        ("id", Query, description = "The id of the webhook"),
+        ("url", Query, description = "The url/part of the url of the webhook"),
+        ("limit", Query, description = "The maximum number of results to return"),
+    ),
+    security(
+        ("permissions" = ["WebhookRead"])
+    ),
+)]
+#[get("/webhook?<id>&<url>&<limit>")]
+async fn webhook_get(
+    db: MyDatabase,
+    user: User,
+    id: Option<String>,
+    url: Option<String>,
+    limit: Option<u16>,
+) -> Result<Json<Vec<Webhook>>> {
+    if !user.permissions.contains(&Permission::WebhookRead) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<Json<Vec<Webhook>>> {
+        let mut sql = "SELECT webhooks.id, webhooks.url, webhooks.creator, GROUP_CONCAT(webhook_events.event) AS events
+            FROM webhooks
+            LEFT JOIN webhook_events ON webhooks.id = webhook_events.webhook_id
+            WHERE 1=1".to_string();
+        let mut params_vec = Vec::new();
+
+        if let Some(id_val) = id {
+            sql += " AND webhooks.id = ?";
+            params_vec.push(id_val);
+        }
+
+        if let Some(url_val) = url {
+            sql += " AND webhooks.url LIKE ?";
+            params_vec.push(format!("%{}%", url_val));
+        }
+
+        sql += &format!(" GROUP BY webhooks.id LIMIT {}", limit.unwrap_or(50));
+
+        let params_sql: Vec<&dyn ToSql> =
+            params_vec.iter().map(|param| param as &dyn ToSql).collect();
+
+        Ok(Json(
+            conn.prepare(&sql)?
+                .query_map(&params_sql[..], Webhook::try_from_row)?
+                .map(|v| v.map_err(ApiError::from))
+                .collect::<Result<Vec<Webhook>>>()?,
+        ))
+    })
+    .await
+}
+
+/// Delete a webhook along with its delivery log.
+///
+/// Requires: `WebhookDelete` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success"),
+        (status = 403, description = "Forbidden requires permission `WebhookDelete`"),
+        (status = 404, description = "Not Found webhook does not exist"),
+    ),
+    params(
+        ("id" = String, description = "The id of the webhook to be deleted")
+    ),
+    security(
+        ("permissions" = ["WebhookDelete"])
+    ),
+)]
+#[delete("/webhook/<id>")]
+async fn webhook_delete(db: MyDatabase, user: User, id: String) -> Result<()> {
+    if !user.permissions.contains(&Permission::WebhookDelete) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<()> {
+        if conn.execute("DELETE FROM webhooks WHERE id = ?", params![id])? == 0 {
+            Err(Status::NotFound)?
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+/// Retrieve the delivery log of a webhook, newest first.
+///
+/// Requires: `WebhookRead` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = Vec<WebhookDelivery>),
+        (status = 403, description = "Forbidden requires permission `WebhookRead`"),
+        (status = 404, description = "Not Found webhook does not exist"),
+    ),
+    params(
+        ("id" = String, description = "The id of the webhook"),
+        ("limit", Query, description = "The maximum number of results to return"),
+    ),
+    security(
+        ("permissions" = ["WebhookRead"])
+    ),
+)]
+#[get("/webhook/<id>/delivery?<limit>")]
+async fn webhook_delivery_get(
+    db: MyDatabase,
+    user: User,
+    id: String,
+    limit: Option<u16>,
+) -> Result<Json<Vec<WebhookDelivery>>> {
+    if !user.permissions.contains(&Permission::WebhookRead) {
+        Err(Status::Forbidden)?
+    }
+
+    db.run(move |conn| -> Result<Json<Vec<WebhookDelivery>>> {
+        if let Err(QueryReturnedNoRows) =
+            conn.query_row("SELECT 1 FROM webhooks WHERE id = ?", params![id], |_| {
+                Ok(())
+            })
+        {
+            Err(Status::NotFound)?
+        }
+
+        Ok(Json(
+            conn.prepare(
+                "SELECT * FROM webhook_deliveries WHERE webhook_id = ?1 ORDER BY id DESC LIMIT ?2",
+            )?
+            .query_map(
+                params![id, limit.unwrap_or(50)],
+                WebhookDelivery::try_from_row,
+            )?
+            .map(|v| v.map_err(ApiError::from))
+            .collect::<Result<Vec<WebhookDelivery>>>()?,
+        ))
+    })
+    .await
+}
+
+/// Send a previous delivery again, it is logged as a new delivery.
+///
+/// Requires: `WebhookWrite` permission.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Success", body = WebhookDelivery),
+        (status = 403, description = "Forbidden requires permission `WebhookWrite`"),
+        (status = 404, description = "Not Found delivery does not exist"),
+    ),
+    params(
+        ("id" = u64, description = "The id of the delivery to redeliver")
+    ),
+    security(
+        ("permissions" = ["WebhookWrite"])
+    ),
+)]
+#[post("/webhook/delivery/<id>")]
+async fn webhook_redeliver(
+    db: MyDatabase,
+    user: User,
+    webhooks: &State<Webhooks>,
+    id: u64,
+) -> Result<Json<WebhookDelivery>> {
+    if !user.permissions.contains(&Permission::WebhookWrite) {
+        Err(Status::Forbidden)?
+    }
+
+    let delivery = db
+        .run(move |conn| -> Result<WebhookDelivery> {
+            let tx = conn.transaction()?;
+
+            if tx.execute(
+                "INSERT INTO webhook_deliveries (webhook_id, event, payload)
+                SELECT webhook_id, event, payload FROM webhook_deliveries WHERE id = ?",
+                params![id],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+
+            let delivery = tx.query_row(
+                "SELECT * FROM webhook_deliveries WHERE id = ?",
+                params![tx.last_insert_rowid()],
+                WebhookDelivery::try_from_row,
+            )?;
+
+            tx.commit()?;
+
+            Ok(delivery)
+        })
+        .await?;
+
+    webhooks.wake();
+
+    Ok(Json(delivery))
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("API Webhook EndPoints", |rocket| async {
+        rocket.mount(
+            "/",
+            routes![
+                webhook_write,
+                webhook_get,
+                webhook_delete,
+                webhook_delivery_get,
+                webhook_redeliver
+            ],
+        )
+    })
+}
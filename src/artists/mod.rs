@@ -0,0 +1,52 @@
+use rocket_sync_db_pools::rusqlite::{self, params, Connection};
+
+use crate::api::data::artists::{Artist, ArtistAlias, ArtistDetails, ArtistRelation};
+
+/// An artist with its aliases & relationships.
+pub fn read(conn: &Connection, id: &str) -> rusqlite::Result<ArtistDetails> {
+    Ok(ArtistDetails {
+        artist: conn.query_row(
+            &format!("{} AND artists.id = ? GROUP BY artists.id", Artist::SELECT),
+            [id],
+            Artist::try_from_row,
+        )?,
+        aliases: conn
+            .prepare("SELECT name, kind, locale FROM artist_aliases WHERE artist_id = ? ORDER BY name")?
+            .query_map([id], ArtistAlias::try_from_row)?
+            .collect::<rusqlite::Result<_>>()?,
+        relations: conn
+            .prepare("SELECT kind, related_id AS artist FROM artist_relations WHERE artist_id = ? ORDER BY kind, related_id")?
+            .query_map([id], ArtistRelation::try_from_row)?
+            .collect::<rusqlite::Result<_>>()?,
+        related: conn
+            .prepare("SELECT kind, artist_id AS artist FROM artist_relations WHERE related_id = ? ORDER BY kind, artist_id")?
+            .query_map([id], ArtistRelation::try_from_row)?
+            .collect::<rusqlite::Result<_>>()?,
+    })
+}
+
+/// Fold `from` into `into`, then delete `from`.
+///
+/// `into` gets `from`'s albums (& with them the tracks they credit), external ids, genres, aliases & relationships.
+/// `from`'s name is kept as an alias of `into`, unless they have the same name.
+pub fn merge(conn: &Connection, from: &str, into: &str) -> rusqlite::Result<()> {
+    for sql in [
+        "INSERT OR IGNORE INTO artist_albums (album_id, artist_id) SELECT album_id, ?2 FROM artist_albums WHERE artist_id = ?1",
+        // ids that `into` already has are left to go with `from`
+        "UPDATE OR IGNORE external_ids SET artist_id = ?2 WHERE artist_id = ?1",
+        "INSERT OR IGNORE INTO artist_genres (artist_id, genre_id) SELECT ?2, genre_id FROM artist_genres WHERE artist_id = ?1",
+        "INSERT OR IGNORE INTO artist_aliases (artist_id, name, kind, locale) SELECT ?2, name, kind, locale FROM artist_aliases WHERE artist_id = ?1",
+        "INSERT OR IGNORE INTO artist_aliases (artist_id, name) SELECT ?2, name FROM artists
+            WHERE id = ?1 AND name != (SELECT name FROM artists WHERE id = ?2) COLLATE NOCASE",
+        // relationships between the two would be with itself
+        "INSERT OR IGNORE INTO artist_relations (artist_id, related_id, kind) SELECT ?2, related_id, kind FROM artist_relations WHERE artist_id = ?1 AND related_id != ?2",
+        "INSERT OR IGNORE INTO artist_relations (artist_id, related_id, kind) SELECT artist_id, ?2, kind FROM artist_relations WHERE related_id = ?1 AND artist_id != ?2",
+    ] {
+        conn.execute(sql, params![from, into])?;
+    }
+
+    // the links to `from` go with it
+    conn.execute("DELETE FROM artists WHERE id = ?", [from])?;
+
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use rocket::{
+    data::ByteUnit,
+    http::Status,
+    tokio::{
+        fs::File,
+        io::{self, AsyncReadExt, AsyncWriteExt},
+        select,
+        task::spawn_blocking,
+        time::sleep,
+    },
+    Shutdown,
+};
+use rocket_sync_db_pools::rusqlite::{params, OptionalExtension};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as DecodeError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+use tempfile::NamedTempFile;
+
+use std::time::Duration;
+
+use crate::{
+    api::data::audio::AudioInfo,
+    audio::{
+        fingerprint::{self, Fingerprinter},
+        loudness::{Measurement, Meter},
+        peaks::PeakMeter,
+        Blobs,
+    },
+    database::{Connections, MyDatabase},
+    error::ApiError,
+    storage::Storage,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Decode a file, handing every packet's interleaved samples to `sink` with the sample rate & channel count.
+///
+/// The error is why it couldn't be decoded.
+pub(super) fn decode(
+    file: std::fs::File,
+    mime: &str,
+    mut sink: impl FnMut(u32, usize, &[f32]),
+) -> std::result::Result<(), String> {
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(
+            Hint::new().mime_type(mime),
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| format!("Unreadable Audio: {e}"))?
+        .format;
+
+    let track = format.default_track().ok_or("No Audio Track")?;
+    let id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| format!("Unsupported Codec: {e}"))?;
+
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => Err(format!("Unreadable Audio: {e}"))?,
+        };
+        if packet.track_id() != id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // a damaged frame is skipped, like a player would
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(e) => Err(format!("Undecodable Audio: {e}"))?,
+        };
+
+        let spec = *decoded.spec();
+        let buffer = match &mut samples {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() => buffer,
+            _ => samples.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        sink(spec.rate, spec.channels.count(), buffer.samples());
+    }
+
+    match samples {
+        Some(_) => Ok(()),
+        None => Err("No Audio Decoded".to_string()),
+    }
+}
+
+/// What the analysis finds in a file.
+struct Analysis {
+    measurement: Measurement,
+    peaks: Vec<u8>,
+    fingerprint: Vec<u32>,
+}
+
+/// Measure a file's loudness, find its peaks & fingerprint it in one decode.
+fn measure(file: std::fs::File, mime: &str) -> std::result::Result<Analysis, String> {
+    let mut meters: Option<(Meter, PeakMeter, Fingerprinter)> = None;
+    decode(file, mime, |rate, channels, samples| {
+        let (meter, peaks, fingerprinter) = meters.get_or_insert_with(|| {
+            (
+                Meter::new(rate, channels),
+                PeakMeter::new(rate, channels),
+                Fingerprinter::new(rate, channels),
+            )
+        });
+        meter.add(samples);
+        peaks.add(samples);
+        fingerprinter.add(samples);
+    })?;
+
+    let (meter, peaks, fingerprinter) = meters.ok_or("No Audio Decoded")?;
+    Ok(Analysis {
+        measurement: meter.finish(),
+        peaks: peaks.finish(),
+        fingerprint: fingerprinter.finish(),
+    })
+}
+
+/// Find a file's peaks.
+fn find_peaks(file: std::fs::File, mime: &str) -> std::result::Result<Vec<u8>, String> {
+    let mut meter: Option<PeakMeter> = None;
+    decode(file, mime, |rate, channels, samples| {
+        meter
+            .get_or_insert_with(|| PeakMeter::new(rate, channels))
+            .add(samples)
+    })?;
+
+    Ok(meter.ok_or("No Audio Decoded")?.finish())
+}
+
+impl Blobs {
+    /// Wake the analysis, after blobs have been stored.
+    pub fn analyze(&self) {
+        self.analyzing.notify_one();
+    }
+
+    /// Measure the loudness of a blob that hasn't been yet, returning whether there was one.
+    ///
+    /// Files that can't be decoded are marked analyzed without any measurements, they're only retried once they're uploaded again.
+    async fn analyze_next(&self, db: &MyDatabase) -> Result<bool> {
+        let Some((hash, mime)) = db
+            .run(|conn| {
+                conn.query_row(
+                    "SELECT hash, mime FROM audio_blobs WHERE analyzed IS NULL AND NOT corrupt LIMIT 1",
+                    [],
+                    |row| Ok((row.get::<usize, String>(0)?, row.get::<usize, String>(1)?)),
+                )
+                .optional()
+            })
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        let analyzed = match self.copy_out(&hash).await? {
+            Some(copy) => spawn_blocking(move || measure(copy.into_file(), &mime))
+                .await
+                .map_err(std::io::Error::other)?,
+            None => Err("Missing From Storage".to_string()),
+        };
+
+        let (measurement, peaks, fingerprint) = analyzed
+            .map(|analysis| {
+                (
+                    analysis.measurement,
+                    Some(analysis.peaks),
+                    Some(fingerprint::to_bytes(&analysis.fingerprint)),
+                )
+            })
+            .unwrap_or_else(|e| {
+                warn!("Failed to analyze audio {hash}: {e}");
+                let nothing = Measurement {
+                    integrated: None,
+                    true_peak: None,
+                    blocks: 0,
+                };
+                (nothing, None, None)
+            });
+
+        // the blob may have been released while it was being decoded
+        let _lock = self.lock().await;
+        let hash_clone = hash.clone();
+        let updated = db
+            .run(move |conn| {
+                conn.execute(
+                    "UPDATE audio_blobs SET analyzed = strftime('%s', 'now'), loudness = ?2, true_peak = ?3, loudness_blocks = ?4,
+                    fingerprint = ?5
+                    WHERE hash = ?1",
+                    params![
+                        hash_clone,
+                        measurement.integrated,
+                        measurement.true_peak,
+                        measurement.blocks,
+                        fingerprint
+                    ],
+                )
+            })
+            .await?;
+
+        if let (1, Some(peaks)) = (updated, peaks) {
+            self.cache_peaks(&hash, peaks).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Copy a blob out of the storage into the staging directory, decoding needs to seek.
+    pub(super) async fn copy_out(&self, hash: &str) -> Result<Option<NamedTempFile>> {
+        let Some((_, mut reader)) = self.storage.audio.open(&Storage::blob_key(hash)).await? else {
+            return Ok(None);
+        };
+
+        let copy = NamedTempFile::new_in(&self.config.staging)?;
+        let mut file = File::from_std(copy.reopen()?);
+        io::copy(&mut reader, &mut file).await?;
+        file.flush().await?;
+        Ok(Some(copy))
+    }
+
+    /// Call with the lock held.
+    async fn cache_peaks(&self, hash: &str, peaks: Vec<u8>) -> Result<()> {
+        self.storage
+            .audio
+            .put(
+                &Storage::peaks_key(hash),
+                Box::pin(std::io::Cursor::new(peaks)),
+                ByteUnit::max_value(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The peaks of a track's audio at full resolution, see [`super::peaks::resample`].
+    ///
+    /// They're cached by the analysis, audio that hasn't been analyzed yet is decoded here instead.
+    /// `None` if the file is missing from the storage.
+    pub async fn peaks(&self, info: &AudioInfo) -> Result<Option<Vec<u8>>> {
+        if let Some((_, mut reader)) = self
+            .storage
+            .audio
+            .open(&Storage::peaks_key(&info.hash))
+            .await?
+        {
+            let mut peaks = Vec::new();
+            reader.read_to_end(&mut peaks).await?;
+            return Ok(Some(peaks));
+        }
+
+        let Some(copy) = self.copy_out(&info.hash).await? else {
+            return Ok(None);
+        };
+        let mime = info.mime.clone();
+        let peaks = spawn_blocking(move || find_peaks(copy.into_file(), &mime))
+            .await
+            .map_err(std::io::Error::other)?
+            .map_err(|e| ApiError::IoError((Status::UnprocessableEntity, e)))?;
+
+        // a corrupt file isn't what the hash says, its peaks wouldn't be either
+        if !info.corrupt {
+            let _lock = self.lock().await;
+            if self
+                .storage
+                .audio
+                .exists(&Storage::blob_key(&info.hash))
+                .await?
+            {
+                self.cache_peaks(&info.hash, peaks.clone()).await?;
+            }
+        }
+
+        Ok(Some(peaks))
+    }
+
+    /// Analyze blobs one at a time until the server shuts down, waiting to be woken when there are none left.
+    pub(super) async fn run_analysis(self, connections: Connections, mut shutdown: Shutdown) {
+        loop {
+            let analyzed = match connections.get().await {
+                Some(db) => self.analyze_next(&db).await,
+                None => Err(ApiError::Status(Status::ServiceUnavailable)),
+            };
+            let wait = match analyzed {
+                Ok(true) => continue,
+                Ok(false) => None,
+                Err(e) => {
+                    error!("Failed to analyze audio: {:?}", e);
+                    Some(Duration::from_secs(60))
+                }
+            };
+
+            select! {
+                _ = self.analyzing.notified() => {},
+                _ = sleep(wait.unwrap_or_default()), if wait.is_some() => {},
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,682 @@
+use std::f64::consts::PI;
+
+use tables::{Table, BANDS, QUADS, TABLES, WINDOW};
+
+mod tables;
+
+/// Samples of each channel in a granule, a frame is two.
+const GRANULE: usize = 576;
+/// Samples of each channel the filters delay the audio by.
+const DELAY: usize = 528;
+/// The largest value the tables with linbits can code.
+const LARGEST: u32 = 15 + (1 << 13) - 1;
+/// Layer III bitrates (kbit/s), by their index in the header from 1.
+const BITRATES: [u32; 14] = [
+    32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+/// Sample rates, by their index in the header.
+const RATES: [u32; 3] = [44100, 48000, 32000];
+/// The scalefactor bands regions 0 & 1 span, by the bands the big values span.
+const REGIONS: [(usize, usize); 23] = [
+    (0, 0),
+    (0, 0),
+    (0, 0),
+    (0, 0),
+    (0, 0),
+    (0, 1),
+    (1, 1),
+    (1, 1),
+    (1, 2),
+    (2, 2),
+    (2, 3),
+    (2, 3),
+    (3, 4),
+    (3, 4),
+    (3, 4),
+    (4, 5),
+    (4, 5),
+    (4, 6),
+    (5, 6),
+    (5, 6),
+    (5, 7),
+    (6, 7),
+    (6, 7),
+];
+/// The alias reduction coefficients.
+const ALIASING: [f64; 8] = [
+    -0.6, -0.535, -0.33, -0.185, -0.095, -0.041, -0.0142, -0.0037,
+];
+
+/// Whether audio at a sample rate can be encoded, only MPEG 1's are.
+pub fn supported(rate: u32) -> bool {
+    RATES.contains(&rate)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        for bit in (0..count).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if value >> bit & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+/// How a granule of one channel is coded, its part of the side information.
+#[derive(Debug, Default, Clone, Copy)]
+struct Granule {
+    /// Bits of Huffman code, there are no scalefactors.
+    part2_3_length: u32,
+    /// Pairs of values coded with the region tables, the rest are quadruples of at most 1 & then zeros.
+    big_values: usize,
+    global_gain: u32,
+    tables: [usize; 3],
+    region0_count: usize,
+    region1_count: usize,
+    count1_table: usize,
+    /// Quadruples coded with the count1 table.
+    count1: usize,
+}
+
+impl Granule {
+    /// Where each region ends.
+    fn regions(&self, bands: &[usize; 23]) -> [usize; 3] {
+        let end = self.big_values * 2;
+        [
+            bands[self.region0_count + 1].min(end),
+            bands[self.region0_count + self.region1_count + 2].min(end),
+            end,
+        ]
+    }
+}
+
+fn pair_length(table: &Table, x: u32, y: u32) -> u32 {
+    let signs = (x != 0) as u32 + (y != 0) as u32;
+    match table.linbits {
+        0 => table.lengths[x as usize * table.size + y as usize] as u32 + signs,
+        linbits => {
+            let escapes = (x >= 15) as u32 + (y >= 15) as u32;
+            table.lengths[x.min(15) as usize * 16 + y.min(15) as usize] as u32
+                + escapes * linbits
+                + signs
+        }
+    }
+}
+
+fn region_length(table: usize, values: &[u32]) -> u32 {
+    match table {
+        0 => 0,
+        _ => values
+            .chunks_exact(2)
+            .map(|pair| pair_length(&TABLES[table], pair[0], pair[1]))
+            .sum(),
+    }
+}
+
+/// The table that codes a region in the fewest bits, with the bits.
+///
+/// Only the smallest tables the values fit in are tried, bigger ones are rarely shorter.
+fn choose(values: &[u32]) -> (usize, u32) {
+    let largest = values.iter().copied().max().unwrap_or_default();
+    let candidates: Vec<usize> = match largest {
+        0 => return (0, 0),
+        1..=15 => {
+            let fits = |table: &usize| TABLES[*table].size > largest as usize;
+            let size = (1..16).filter(fits).map(|table| TABLES[table].size).min();
+            (1..16)
+                .filter(|table| Some(TABLES[*table].size) == size)
+                .collect()
+        }
+        _ => [16, 24]
+            .into_iter()
+            .filter_map(|first| {
+                (first..first + 8).find(|table| largest - 15 < 1 << TABLES[*table].linbits)
+            })
+            .collect(),
+    };
+
+    candidates
+        .into_iter()
+        .map(|table| (table, region_length(table, values)))
+        .min_by_key(|(_, length)| *length)
+        .unwrap_or((0, 0))
+}
+
+fn quad_index(quad: &[u32]) -> usize {
+    (quad[0] * 8 + quad[1] * 4 + quad[2] * 2 + quad[3]) as usize
+}
+
+/// Split quantized values into regions & pick their tables.
+fn layout(values: &[u32; GRANULE], bands: &[usize; 23]) -> Granule {
+    let mut end = GRANULE;
+    while end >= 2 && values[end - 1] == 0 && values[end - 2] == 0 {
+        end -= 2;
+    }
+    let mut big = end;
+    while big >= 4 && values[big - 4..big].iter().all(|value| *value <= 1) {
+        big -= 4;
+    }
+
+    let mut granule = Granule {
+        big_values: big / 2,
+        count1: (end - big) / 4,
+        ..Default::default()
+    };
+
+    let quads = values[big..end].chunks_exact(4);
+    let signs: u32 = values[big..end].iter().sum();
+    let lengths = QUADS.map(|(_, lengths)| {
+        quads
+            .clone()
+            .map(|quad| lengths[quad_index(quad)] as u32)
+            .sum::<u32>()
+    });
+    granule.count1_table = (lengths[1] < lengths[0]) as usize;
+    granule.part2_3_length = lengths[granule.count1_table] + signs;
+
+    let spanned = (0..22).find(|band| bands[band + 1] >= big).unwrap_or(22);
+    let (mut region0, mut region1) = REGIONS[spanned];
+    while region0 > 0 && bands[region0 + 1] > big {
+        region0 -= 1;
+    }
+    while region1 > 0 && bands[region0 + region1 + 2] > big {
+        region1 -= 1;
+    }
+    granule.region0_count = region0;
+    granule.region1_count = region1;
+
+    let mut start = 0;
+    for (region, end) in granule.regions(bands).into_iter().enumerate() {
+        let (table, length) = choose(&values[start..end.max(start)]);
+        granule.tables[region] = table;
+        granule.part2_3_length += length;
+        start = end.max(start);
+    }
+
+    granule
+}
+
+/// Write the Huffman code of a granule.
+fn code(
+    writer: &mut BitWriter,
+    granule: &Granule,
+    values: &[u32; GRANULE],
+    negative: &[bool; GRANULE],
+    bands: &[usize; 23],
+) {
+    let mut start = 0;
+    for (region, end) in granule.regions(bands).into_iter().enumerate() {
+        let table = &TABLES[granule.tables[region]];
+        if granule.tables[region] == 0 {
+            start = end.max(start);
+            continue;
+        }
+
+        for index in (start..end).step_by(2) {
+            let (x, y) = (values[index], values[index + 1]);
+            let code = match table.linbits {
+                0 => x as usize * table.size + y as usize,
+                _ => x.min(15) as usize * 16 + y.min(15) as usize,
+            };
+            writer.write(table.codes[code], table.lengths[code] as u32);
+            for (value, negative) in [(x, negative[index]), (y, negative[index + 1])] {
+                if table.linbits > 0 && value >= 15 {
+                    writer.write(value - 15, table.linbits);
+                }
+                if value != 0 {
+                    writer.write(negative as u32, 1);
+                }
+            }
+        }
+        start = end.max(start);
+    }
+
+    let (codes, lengths) = &QUADS[granule.count1_table];
+    let end = start + granule.count1 * 4;
+    for index in (start..end).step_by(4) {
+        let quad = &values[index..index + 4];
+        writer.write(codes[quad_index(quad)], lengths[quad_index(quad)] as u32);
+        for offset in 0..4 {
+            if quad[offset] != 0 {
+                writer.write(negative[index + offset] as u32, 1);
+            }
+        }
+    }
+}
+
+/// Quantize a granule with the smallest global gain whose code fits in `bits`.
+///
+/// There's no psychoacoustic model, the noise is spread evenly over the spectrum.
+fn quantize(
+    spectrum: &[f64; GRANULE],
+    bits: u32,
+    bands: &[usize; 23],
+) -> (Granule, [u32; GRANULE]) {
+    let powered = spectrum.map(|x| x.abs().powf(0.75));
+    let mut values = [0; GRANULE];
+    let fits = |gain: u32, values: &mut [u32; GRANULE]| -> Option<Granule> {
+        let scale = 2f64.powf(-0.1875 * (gain as f64 - 210.0));
+        for (value, x) in values.iter_mut().zip(powered.iter()) {
+            *value = (x * scale + 0.4054) as u32;
+        }
+        if values.iter().any(|value| *value > LARGEST) {
+            return None;
+        }
+        let granule = layout(values, bands);
+        (granule.part2_3_length <= bits).then_some(Granule {
+            global_gain: gain,
+            ..granule
+        })
+    };
+
+    let (mut low, mut high) = (0, 255);
+    while low < high {
+        let middle = (low + high) / 2;
+        match fits(middle, &mut values) {
+            Some(_) => high = middle,
+            None => low = middle + 1,
+        }
+    }
+
+    let granule = fits(low, &mut values).unwrap_or_else(|| {
+        // even the quietest gain doesn't fit, drop what doesn't
+        let mut granule = layout(&values, bands);
+        while granule.part2_3_length > bits {
+            let last = values
+                .iter()
+                .rposition(|value| *value != 0)
+                .unwrap_or_default();
+            values[last] = 0;
+            granule = layout(&values, bands);
+        }
+        Granule {
+            global_gain: 255,
+            ..granule
+        }
+    });
+    (granule, values)
+}
+
+/// What's kept of a channel between granules.
+struct Channel {
+    /// The latest 512 samples, newest first.
+    history: [f64; 512],
+    /// The subband samples of the previous granule.
+    previous: [[f64; 18]; 32],
+}
+
+/// A constant bitrate MPEG 1 layer III encoder, for interleaved samples.
+///
+/// Every granule is coded with long blocks & without scalefactors or a bit reservoir.
+/// Audio with more than 2 channels is encoded from the first 2.
+pub struct Encoder {
+    /// The channels of the samples, & the ones encoded.
+    channels: (usize, usize),
+    rate: usize,
+    bitrate: usize,
+    /// Frames are padded by a byte whenever the remainders add up to a whole one, at 44.1kHz they aren't a whole number of bytes.
+    remainder: u32,
+    /// Coefficients from this one up are dropped, there aren't enough bits for the highest frequencies.
+    cutoff: usize,
+    states: Vec<Channel>,
+    /// Samples waiting for a whole frame.
+    pending: Vec<f32>,
+    /// Samples still to be dropped from the start, to make up for the delay.
+    skip: usize,
+    /// The analysis window.
+    window: [f64; 512],
+    matrix: Box<[[f64; 64]; 32]>,
+    mdct: Box<[[f64; 36]; 18]>,
+}
+
+impl Encoder {
+    /// `None` if the sample rate isn't supported, see [`supported`], or there's no layer III bitrate of `kilobits`.
+    pub fn new(rate: u32, channels: usize, kilobits: u32) -> Option<Self> {
+        let rate = RATES.iter().position(|supported| *supported == rate)?;
+        let bitrate = BITRATES.iter().position(|bitrate| *bitrate == kilobits)? + 1;
+        if channels == 0 {
+            return None;
+        }
+        let encoded = channels.min(2);
+
+        let cutoff = match kilobits / encoded as u32 {
+            0..=32 => 11_000,
+            33..=48 => 14_000,
+            49..=64 => 15_500,
+            65..=96 => 17_500,
+            _ => 19_500,
+        };
+        let nyquist = RATES[rate] as usize / 2;
+
+        let mut matrix = Box::new([[0.0; 64]; 32]);
+        for (subband, row) in matrix.iter_mut().enumerate() {
+            for (k, value) in row.iter_mut().enumerate() {
+                *value = (PI * (2 * subband + 1) as f64 * (k as f64 - 16.0) / 64.0).cos();
+            }
+        }
+        let mut mdct = Box::new([[0.0; 36]; 18]);
+        for (k, row) in mdct.iter_mut().enumerate() {
+            for (n, value) in row.iter_mut().enumerate() {
+                // scaled by 1 / 9 to undo the gain of the decoder's inverse transform
+                let window = (PI / 36.0 * (n as f64 + 0.5)).sin() / 9.0;
+                *value = window * (PI / 72.0 * (2 * n + 19) as f64 * (2 * k + 1) as f64).cos();
+            }
+        }
+
+        Some(Self {
+            channels: (channels, encoded),
+            rate,
+            bitrate,
+            remainder: 0,
+            cutoff: (cutoff.min(nyquist) * GRANULE / nyquist).min(GRANULE),
+            states: (0..encoded)
+                .map(|_| Channel {
+                    history: [0.0; 512],
+                    previous: [[0.0; 18]; 32],
+                })
+                .collect(),
+            pending: Vec::new(),
+            skip: DELAY * channels,
+            window: WINDOW.map(|value| value as f64 / 65536.0 / 32.0),
+            matrix,
+            mdct,
+        })
+    }
+
+    /// Encode interleaved samples, appending every whole frame to `out`.
+    pub fn add(&mut self, samples: &[f32], out: &mut Vec<u8>) {
+        let skipped = self.skip.min(samples.len());
+        self.skip -= skipped;
+        self.pending.extend_from_slice(&samples[skipped..]);
+        let frame = GRANULE * 2 * self.channels.0;
+        let mut start = 0;
+        while self.pending.len() - start >= frame {
+            let samples = self.pending[start..start + frame].to_vec();
+            self.frame(&samples, out);
+            start += frame;
+        }
+        self.pending.drain(..start);
+    }
+
+    /// Encode what's left, followed by a frame of silence for the delay of the filters.
+    pub fn finish(mut self, out: &mut Vec<u8>) {
+        let frame = GRANULE * 2 * self.channels.0;
+        let silence = frame - self.pending.len() % frame + frame;
+        self.add(&vec![0.0; silence], out);
+    }
+
+    /// The coefficients of a channel's next granule.
+    fn spectrum(&mut self, channel: usize, samples: &[f32]) -> [f64; GRANULE] {
+        let channels = self.channels.0;
+        let state = &mut self.states[channel];
+
+        let mut subbands = [[0.0; 18]; 32];
+        for time in 0..18 {
+            state.history.copy_within(0..480, 32);
+            for i in 0..32 {
+                state.history[31 - i] = samples[(time * 32 + i) * channels + channel] as f64;
+            }
+
+            let mut partial = [0.0; 64];
+            for (i, partial) in partial.iter_mut().enumerate() {
+                *partial = (0..8)
+                    .map(|j| self.window[i + 64 * j] * state.history[i + 64 * j])
+                    .sum();
+            }
+            for (subband, row) in self.matrix.iter().enumerate() {
+                let sample: f64 = row.iter().zip(partial.iter()).map(|(m, y)| m * y).sum();
+                // the decoder inverts every other sample of the odd subbands
+                subbands[subband][time] = match subband % 2 == 1 && time % 2 == 1 {
+                    true => -sample,
+                    false => sample,
+                };
+            }
+        }
+
+        let mut spectrum = [0.0; GRANULE];
+        for (subband, current) in subbands.iter().enumerate() {
+            let previous = &state.previous[subband];
+            for (k, row) in self.mdct.iter().enumerate() {
+                spectrum[subband * 18 + k] = row[..18]
+                    .iter()
+                    .zip(previous.iter())
+                    .chain(row[18..].iter().zip(current.iter()))
+                    .map(|(m, x)| m * x)
+                    .sum();
+            }
+        }
+        state.previous = subbands;
+
+        for subband in 0..31 {
+            for (i, c) in ALIASING.iter().enumerate() {
+                let (cs, ca) = (1.0 / (1.0 + c * c).sqrt(), c / (1.0 + c * c).sqrt());
+                let (upper, lower) = (subband * 18 + 17 - i, (subband + 1) * 18 + i);
+                let (bu, bd) = (spectrum[upper], spectrum[lower]);
+                spectrum[upper] = bu * cs + bd * ca;
+                spectrum[lower] = bd * cs - bu * ca;
+            }
+        }
+
+        spectrum[self.cutoff..].fill(0.0);
+        spectrum
+    }
+
+    fn frame(&mut self, samples: &[f32], out: &mut Vec<u8>) {
+        let (channels, encoded) = self.channels;
+        let rate = RATES[self.rate];
+        let bytes = 144_000 * BITRATES[self.bitrate - 1];
+        self.remainder += bytes % rate;
+        let padding = self.remainder >= rate;
+        if padding {
+            self.remainder -= rate;
+        }
+        let length = (bytes / rate + padding as u32) as usize;
+
+        let side = match encoded {
+            1 => 17,
+            _ => 32,
+        };
+        let bands = &BANDS[self.rate];
+        let mut budget = (length - 4 - side) as u32 * 8;
+        let mut granules = [[Granule::default(); 2]; 2];
+        let mut main = BitWriter::default();
+        for (index, granule) in samples.chunks_exact(GRANULE * channels).enumerate() {
+            for (channel, entry) in granules[index].iter_mut().enumerate().take(encoded) {
+                let spectrum = self.spectrum(channel, granule);
+                let remaining = ((2 - index) * encoded - channel) as u32;
+                let bits = (budget / remaining).min(4095);
+
+                let (coded, values) = quantize(&spectrum, bits, bands);
+                code(
+                    &mut main,
+                    &coded,
+                    &values,
+                    &spectrum.map(|x| x < 0.0),
+                    bands,
+                );
+                budget -= coded.part2_3_length;
+                *entry = coded;
+            }
+        }
+
+        let mut frame = BitWriter::default();
+        frame.write(0xFFFB, 16);
+        frame.write(self.bitrate as u32, 4);
+        frame.write(self.rate as u32, 2);
+        frame.write(padding as u32, 1);
+        frame.write(0, 1);
+        // stereo or mono, no mode extension, not copyrighted, a copy & no emphasis
+        frame.write(if encoded == 1 { 3 } else { 0 }, 2);
+        frame.write(0, 6);
+
+        frame.write(0, 9);
+        frame.write(0, if encoded == 1 { 5 } else { 3 });
+        frame.write(0, 4 * encoded as u32);
+        for granule in granules.iter() {
+            for coded in granule.iter().take(encoded) {
+                frame.write(coded.part2_3_length, 12);
+                frame.write(coded.big_values as u32, 9);
+                frame.write(coded.global_gain, 8);
+                // no scalefactors & long blocks
+                frame.write(0, 5);
+                for table in coded.tables {
+                    frame.write(table as u32, 5);
+                }
+                frame.write(coded.region0_count as u32, 4);
+                frame.write(coded.region1_count as u32, 3);
+                frame.write(0, 2);
+                frame.write(coded.count1_table as u32, 1);
+            }
+        }
+
+        out.extend_from_slice(&frame.bytes);
+        out.extend_from_slice(&main.bytes);
+        out.resize(out.len() + length - frame.bytes.len() - main.bytes.len(), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use symphonia::core::{
+        audio::SampleBuffer, codecs::DecoderOptions, formats::FormatOptions, io::MediaSourceStream,
+        meta::MetadataOptions, probe::Hint,
+    };
+
+    /// A second of a 440Hz sine, an octave up in the second channel.
+    fn sine(rate: u32, channels: usize) -> Vec<f32> {
+        (0..rate as usize)
+            .flat_map(|i| {
+                (0..channels).map(move |channel| {
+                    let frequency = 440.0 * (channel + 1) as f64;
+                    (0.5 * (2.0 * PI * frequency * i as f64 / rate as f64).sin()) as f32
+                })
+            })
+            .collect()
+    }
+
+    /// The bitrate (kbit/s) of every frame, checking each header is where the previous frame ends.
+    fn frames(mp3: &[u8], rate: u32) -> Vec<u32> {
+        let mut bitrates = Vec::new();
+        let mut offset = 0;
+        while offset < mp3.len() {
+            let header = &mp3[offset..offset + 4];
+            assert_eq!(header[0..2], [0xFF, 0xFB], "no frame at {offset}");
+            let bitrate = BITRATES[(header[2] >> 4) as usize - 1];
+            assert_eq!(RATES[(header[2] >> 2 & 3) as usize], rate);
+            let padding = (header[2] >> 1 & 1) as u32;
+            offset += (144_000 * bitrate / rate + padding) as usize;
+            bitrates.push(bitrate);
+        }
+        assert_eq!(offset, mp3.len());
+        bitrates
+    }
+
+    /// The interleaved samples symphonia decodes, with their rate & channels.
+    fn decode(mp3: Vec<u8>) -> (Vec<f32>, u32, usize) {
+        let source = MediaSourceStream::new(Box::new(Cursor::new(mp3)), Default::default());
+        let mut format = symphonia::default::get_probe()
+            .format(
+                Hint::new().with_extension("mp3"),
+                source,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .unwrap()
+            .format;
+        let track = format.default_track().unwrap();
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .unwrap();
+
+        let (mut samples, mut rate, mut channels) = (Vec::new(), 0, 0);
+        while let Ok(packet) = format.next_packet() {
+            let decoded = decoder.decode(&packet).unwrap();
+            let spec = *decoded.spec();
+            (rate, channels) = (spec.rate, spec.channels.count());
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend_from_slice(buffer.samples());
+        }
+        (samples, rate, channels)
+    }
+
+    /// The signal to noise ratio (dB) of a channel of the decoded audio.
+    fn snr(original: &[f32], decoded: &[f32], channels: usize, channel: usize) -> f64 {
+        let channel = |samples: &[f32]| -> Vec<f64> {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .map(|x| *x as f64)
+                .collect()
+        };
+        let (original, decoded) = (channel(original), channel(decoded));
+
+        // the middle half, the decoder's own delay of 529 samples isn't trimmed without a LAME header
+        let (start, length) = (original.len() / 4, original.len() / 2);
+        let pairs = || {
+            original[start..start + length]
+                .iter()
+                .zip(&decoded[start + DELAY + 1..])
+        };
+        let signal: f64 = pairs().map(|(x, _)| x * x).sum();
+        let noise: f64 = pairs().map(|(x, y)| (x - y) * (x - y)).sum();
+        10.0 * (signal / noise).log10()
+    }
+
+    fn check(rate: u32, channels: usize, kilobits: u32) {
+        let samples = sine(rate, channels);
+        let mut encoder = Encoder::new(rate, channels, kilobits).unwrap();
+        let mut mp3 = Vec::new();
+        // in pieces that aren't whole frames
+        for chunk in samples.chunks(1000 * channels) {
+            encoder.add(chunk, &mut mp3);
+        }
+        encoder.finish(&mut mp3);
+
+        // the delay is dropped, the rest is padded to whole frames & followed by one of silence
+        let bitrates = frames(&mp3, rate);
+        assert_eq!(bitrates.len(), (rate as usize - DELAY) / (2 * GRANULE) + 2);
+        assert!(bitrates.iter().all(|bitrate| *bitrate == kilobits));
+
+        let (decoded, decoded_rate, decoded_channels) = decode(mp3);
+        assert_eq!((decoded_rate, decoded_channels), (rate, channels));
+        assert_eq!(decoded.len(), bitrates.len() * 2 * GRANULE * channels);
+        for channel in 0..channels {
+            let snr = snr(&samples, &decoded, channels, channel);
+            assert!(snr > 40.0, "{rate}Hz channel {channel}: {snr:.1}dB");
+        }
+    }
+
+    #[test]
+    fn encodes_32khz() {
+        check(32000, 1, 64);
+        check(32000, 2, 128);
+    }
+
+    #[test]
+    fn encodes_44khz() {
+        check(44100, 1, 64);
+        check(44100, 2, 128);
+    }
+
+    #[test]
+    fn encodes_48khz() {
+        check(48000, 1, 64);
+        check(48000, 2, 128);
+    }
+}
@@ -0,0 +1,446 @@
+use rocket_sync_db_pools::rusqlite::{self, Connection, OptionalExtension};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    f64::consts::{PI, SQRT_2},
+    sync::Arc,
+};
+
+use crate::{
+    api::data::tracks::{Duplicate, DuplicateGroup},
+    audio::loudness::Biquad,
+};
+
+/// Audio is resampled to this before it's fingerprinted, the chroma doesn't need any more.
+const RATE: f64 = 11025.0;
+const FRAME: usize = 4096;
+const HOP: usize = FRAME / 3;
+/// Only the start of the audio is fingerprinted, like Chromaprint (seconds).
+const LENGTH: f64 = 120.0;
+/// The range of the notes in the chroma (Hz).
+const LOWEST: f64 = 28.0;
+const HIGHEST: f64 = 3520.0;
+/// Chromaprint's smoothing of the chroma over time.
+const SMOOTHING: [f64; 5] = [0.25, 0.75, 1.0, 0.75, 0.25];
+/// Frames quieter than about -60dBFS are silence.
+const SILENCE: f64 = 1.0;
+/// The bits of a sub-fingerprint that have to match exactly to align two fingerprints, the shape of its chroma.
+const KEY: u32 = 0x00FF_FFFF;
+/// Keys this common don't say much about where fingerprints line up.
+const MAX_POSTINGS: usize = 256;
+/// Fingerprints are only compared where enough keys line up.
+const MIN_MATCHES: usize = 4;
+/// Fingerprints that overlap by fewer frames (about 4 seconds) aren't compared.
+const MIN_OVERLAP: usize = 32;
+
+fn low_pass(rate: f64, cutoff: f64) -> Biquad {
+    let w = 2.0 * PI * cutoff / rate;
+    let (cos, alpha) = (w.cos(), w.sin() / SQRT_2);
+    let a0 = 1.0 + alpha;
+    Biquad::new(
+        [
+            (1.0 - cos) / 2.0 / a0,
+            (1.0 - cos) / a0,
+            (1.0 - cos) / 2.0 / a0,
+        ],
+        [-2.0 * cos / a0, (1.0 - alpha) / a0],
+    )
+}
+
+/// Turns interleaved samples into a Chromaprint-style fingerprint, a 32-bit sub-fingerprint for every ~124ms.
+///
+/// The audio is mixed down, resampled & split into overlapping frames, each frame's spectrum is folded into the 12 notes of an octave.
+/// The bits of a sub-fingerprint are the shape of that chroma & how it's changing, which survive re-encoding & changes in volume.
+pub struct Fingerprinter {
+    channels: usize,
+    filters: [Biquad; 2],
+    /// Input samples per resampled sample.
+    step: f64,
+    /// When the next resampled sample is, in input samples after the previous one.
+    position: f64,
+    previous: f64,
+    /// Resampled samples left before [`LENGTH`].
+    remaining: usize,
+    samples: Vec<f32>,
+    window: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    /// The note of every bin of the spectrum, if it's in the range.
+    notes: Vec<Option<usize>>,
+    chroma: Vec<[f64; 12]>,
+}
+
+impl Fingerprinter {
+    pub fn new(rate: u32, channels: usize) -> Self {
+        let rate = rate as f64;
+        let cutoff = 0.45 * rate.min(RATE);
+
+        let window = (0..FRAME)
+            .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / (FRAME - 1) as f64).cos()) as f32)
+            .collect();
+        let notes = (0..FRAME / 2)
+            .map(|bin| {
+                let frequency = bin as f64 * RATE / FRAME as f64;
+                (LOWEST..=HIGHEST).contains(&frequency).then(|| {
+                    let note = 12.0 * (frequency / 27.5).log2();
+                    note.floor().rem_euclid(12.0) as usize
+                })
+            })
+            .collect();
+
+        Self {
+            channels,
+            filters: [low_pass(rate, cutoff); 2],
+            step: rate / RATE,
+            position: 0.0,
+            previous: 0.0,
+            remaining: (LENGTH * RATE) as usize,
+            samples: Vec::with_capacity(FRAME),
+            window,
+            fft: FftPlanner::new().plan_fft_forward(FRAME),
+            notes,
+            chroma: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            if self.remaining == 0 {
+                return;
+            }
+
+            let mixed =
+                frame.iter().map(|sample| *sample as f64).sum::<f64>() / self.channels as f64;
+            let [first, second] = &mut self.filters;
+            let x = second.process(first.process(mixed));
+
+            while self.position <= 1.0 && self.remaining > 0 {
+                let y = self.previous + (x - self.previous) * self.position;
+                self.samples.push(y as f32);
+                self.position += self.step;
+                self.remaining -= 1;
+
+                if self.samples.len() == FRAME {
+                    self.transform();
+                    self.samples.drain(..HOP);
+                }
+            }
+            self.position -= 1.0;
+            self.previous = x;
+        }
+    }
+
+    fn transform(&mut self) {
+        let mut spectrum = self
+            .samples
+            .iter()
+            .zip(self.window.iter())
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect::<Vec<Complex<f32>>>();
+        self.fft.process(&mut spectrum);
+
+        let mut chroma = [0.0; 12];
+        for (bin, note) in self.notes.iter().enumerate() {
+            if let Some(note) = note {
+                chroma[*note] += spectrum[bin].norm_sqr() as f64;
+            }
+        }
+        self.chroma.push(chroma);
+    }
+
+    pub fn finish(self) -> Vec<u32> {
+        let smoothed = self
+            .chroma
+            .windows(SMOOTHING.len())
+            .map(|frames| {
+                let mut chroma = [0.0; 12];
+                for (frame, weight) in frames.iter().zip(SMOOTHING) {
+                    for (note, energy) in chroma.iter_mut().zip(frame) {
+                        *note += weight * energy;
+                    }
+                }
+
+                let norm = chroma
+                    .iter()
+                    .map(|energy| energy * energy)
+                    .sum::<f64>()
+                    .sqrt();
+                match norm < SILENCE {
+                    true => [0.0; 12],
+                    false => chroma.map(|energy| energy / norm),
+                }
+            })
+            .collect::<Vec<[f64; 12]>>();
+
+        smoothed
+            .iter()
+            .skip(2)
+            .zip(smoothed.iter())
+            .map(|(chroma, before)| {
+                let mean = chroma.iter().sum::<f64>() / 12.0;
+                let mut bits = 0;
+                for note in 0..12 {
+                    bits |= ((chroma[note] > chroma[(note + 1) % 12]) as u32) << note;
+                    bits |= ((chroma[note] > mean) as u32) << (12 + note);
+                }
+                for note in 0..8 {
+                    let now = chroma[note] - chroma[note + 1];
+                    let then = before[note] - before[note + 1];
+                    bits |= ((now > then) as u32) << (24 + note);
+                }
+                bits
+            })
+            .collect()
+    }
+}
+
+pub fn to_bytes(fingerprint: &[u32]) -> Vec<u8> {
+    fingerprint
+        .iter()
+        .flat_map(|bits| bits.to_le_bytes())
+        .collect()
+}
+
+fn from_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|bits| u32::from_le_bytes([bits[0], bits[1], bits[2], bits[3]]))
+        .collect()
+}
+
+/// How alike two fingerprints are when `b` starts `offset` frames into `a`, from 0 for unrelated audio to 1.
+fn similarity(a: &[u32], b: &[u32], offset: isize) -> f64 {
+    let start = offset.max(0) as usize;
+    let end = a.len().min((b.len() as isize + offset).max(0) as usize);
+    if end < start + MIN_OVERLAP {
+        return 0.0;
+    }
+
+    let errors = (start..end)
+        .map(|i| (a[i] ^ b[(i as isize - offset) as usize]).count_ones())
+        .sum::<u32>();
+    let error_rate = errors as f64 / (32 * (end - start)) as f64;
+    (1.0 - 2.0 * error_rate).max(0.0)
+}
+
+/// Where the keys of some fingerprints are.
+struct Index(HashMap<u32, Vec<(usize, usize)>>);
+
+impl Index {
+    fn new(fingerprints: &[&[u32]]) -> Self {
+        let mut postings: HashMap<u32, Vec<(usize, usize)>> = HashMap::new();
+        for (fingerprint, bits) in fingerprints.iter().enumerate() {
+            for (frame, bits) in bits.iter().enumerate() {
+                postings
+                    .entry(bits & KEY)
+                    .or_default()
+                    .push((fingerprint, frame));
+            }
+        }
+        // silence & anything else that's everywhere
+        postings.retain(|key, postings| *key != 0 && postings.len() <= MAX_POSTINGS);
+        Self(postings)
+    }
+
+    /// The fingerprints `query` lines up with & how many frames into them it starts.
+    fn align(&self, query: &[u32]) -> HashMap<usize, isize> {
+        let mut matches: HashMap<(usize, isize), usize> = HashMap::new();
+        for (frame, bits) in query.iter().enumerate() {
+            for (fingerprint, position) in self.0.get(&(bits & KEY)).into_iter().flatten() {
+                *matches
+                    .entry((*fingerprint, *position as isize - frame as isize))
+                    .or_default() += 1;
+            }
+        }
+
+        let mut best: HashMap<usize, (isize, usize)> = HashMap::new();
+        for ((fingerprint, offset), count) in matches {
+            let entry = best.entry(fingerprint).or_insert((offset, 0));
+            if count > entry.1 {
+                *entry = (offset, count);
+            }
+        }
+        best.into_iter()
+            .filter(|(_, (_, count))| *count >= MIN_MATCHES)
+            .map(|(fingerprint, (offset, _))| (fingerprint, offset))
+            .collect()
+    }
+}
+
+/// The similarity of two fingerprints around where their keys line up, frames may be split differently.
+fn compare(a: &[u32], b: &[u32], offset: isize) -> f64 {
+    (offset - 1..=offset + 1)
+        .map(|offset| similarity(a, b, offset))
+        .fold(0.0, f64::max)
+}
+
+/// A stored file, with the tracks that use it & its fingerprint, which is empty until it's been analyzed.
+struct File {
+    hash: String,
+    tracks: Vec<String>,
+    fingerprint: Vec<u32>,
+}
+
+fn files(conn: &Connection) -> rusqlite::Result<Vec<File>> {
+    let mut files: BTreeMap<String, File> = BTreeMap::new();
+    let mut statement = conn.prepare(
+        "SELECT audio_blobs.hash, track_audio.track_id, fingerprint FROM track_audio
+        JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+        ORDER BY track_audio.track_id",
+    )?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let file = match files.entry(row.get(0)?) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let fingerprint = row.get::<usize, Option<Vec<u8>>>(2)?;
+                let hash = entry.key().clone();
+                entry.insert(File {
+                    hash,
+                    tracks: Vec::new(),
+                    fingerprint: from_bytes(&fingerprint.unwrap_or_default()),
+                })
+            }
+        };
+        file.tracks.push(row.get(1)?);
+    }
+
+    Ok(files.into_values().collect())
+}
+
+/// Follow a file's group to the file at its root, flattening the way there.
+fn root(parents: &mut [usize], mut file: usize) -> usize {
+    while parents[file] != file {
+        parents[file] = parents[parents[file]];
+        file = parents[file];
+    }
+    file
+}
+
+/// The tracks that are likely the same recording as `track`, most similar first.
+///
+/// Tracks with the same file are always duplicates, others once the audio has been analyzed.
+/// `None` if the track doesn't exist.
+pub fn duplicates(
+    conn: &Connection,
+    track: &str,
+    threshold: f64,
+) -> rusqlite::Result<Option<Vec<Duplicate>>> {
+    if !conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?)",
+        [track],
+        |row| row.get::<usize, bool>(0),
+    )? {
+        return Ok(None);
+    }
+
+    let hash = conn
+        .query_row(
+            "SELECT hash FROM track_audio WHERE track_id = ?",
+            [track],
+            |row| row.get::<usize, String>(0),
+        )
+        .optional()?;
+    let Some(hash) = hash else {
+        return Ok(Some(Vec::new()));
+    };
+
+    let mut duplicates = conn
+        .prepare(
+            "SELECT track_id FROM track_audio WHERE hash = ? AND track_id != ? ORDER BY track_id",
+        )?
+        .query_map([&hash, track], |row| {
+            Ok(Duplicate {
+                track: row.get(0)?,
+                similarity: 1.0,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<Duplicate>>>()?;
+
+    let files = files(conn)?;
+    if let Some(query) = files.iter().find(|file| file.hash == hash) {
+        let index = Index::new(&[&query.fingerprint]);
+        for file in files.iter().filter(|file| file.hash != hash) {
+            let Some(offset) = index.align(&file.fingerprint).get(&0).copied() else {
+                continue;
+            };
+            let similarity = compare(&query.fingerprint, &file.fingerprint, offset);
+            if similarity >= threshold {
+                duplicates.extend(file.tracks.iter().map(|track| Duplicate {
+                    track: track.clone(),
+                    similarity,
+                }));
+            }
+        }
+    }
+
+    duplicates.sort_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then_with(|| a.track.cmp(&b.track))
+    });
+    Ok(Some(duplicates))
+}
+
+/// Every group of tracks that are likely the same recording.
+///
+/// Tracks are grouped when they're alike enough to at least one other track in the group,
+/// its similarity is the lowest of those matches.
+pub fn report(conn: &Connection, threshold: f64) -> rusqlite::Result<Vec<DuplicateGroup>> {
+    let files = files(conn)?;
+    let index = Index::new(
+        &files
+            .iter()
+            .map(|file| file.fingerprint.as_slice())
+            .collect::<Vec<&[u32]>>(),
+    );
+
+    // union-find over the files, with the lowest similarity that joined each group
+    let mut parents = (0..files.len()).collect::<Vec<usize>>();
+    let mut lowest = files
+        .iter()
+        .map(|file| (file.tracks.len() > 1).then_some(1.0))
+        .collect::<Vec<Option<f64>>>();
+
+    for (query, file) in files.iter().enumerate() {
+        for (other, offset) in index.align(&file.fingerprint) {
+            if other >= query {
+                continue;
+            }
+
+            let similarity = compare(&files[other].fingerprint, &file.fingerprint, offset);
+            if similarity < threshold {
+                continue;
+            }
+
+            let (a, b) = (root(&mut parents, other), root(&mut parents, query));
+            let joined = [lowest[a], lowest[b], Some(similarity)]
+                .into_iter()
+                .flatten()
+                .fold(1.0, f64::min);
+            parents[b] = a;
+            lowest[a] = Some(joined);
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for (index, file) in files.iter().enumerate() {
+        groups
+            .entry(root(&mut parents, index))
+            .or_default()
+            .extend(file.tracks.iter().cloned());
+    }
+
+    let mut groups = groups
+        .into_iter()
+        .filter_map(|(root, mut tracks)| {
+            tracks.sort();
+            Some(DuplicateGroup {
+                similarity: lowest[root]?,
+                tracks,
+            })
+        })
+        .collect::<Vec<DuplicateGroup>>();
+    groups.sort_by(|a, b| a.tracks.cmp(&b.tracks));
+    Ok(groups)
+}
@@ -0,0 +1,237 @@
+use rocket::{
+    fs::NamedFile,
+    http::{ContentType, Status},
+    tokio::{
+        fs::File,
+        io::{self, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    },
+};
+use tempfile::NamedTempFile;
+
+use crate::{
+    api::data::audio::{AudioInfo, GainMode, Loudness},
+    audio::{
+        hls::{fill, Frame},
+        loudness, Blobs,
+    },
+    database::MyDatabase,
+    error::ApiError,
+    storage::{Download, Reader, Storage},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// One step of a layer III granule's global gain scales it by 2^(1/4) (dB).
+const STEP: f64 = 1.505149978319906;
+
+/// The steps of global gain that bring audio to the ReplayGain reference.
+///
+/// It's lowered when it would push the true peak over 0 dBTP.
+pub fn steps(loudness: &Loudness) -> i32 {
+    let headroom = -loudness.true_peak;
+    let mut steps = (loudness.gain.min(headroom) / STEP).round();
+    if steps * STEP > headroom {
+        steps -= 1.0;
+    }
+    steps as i32
+}
+
+fn read_bits(bytes: &[u8], offset: usize, count: usize) -> u32 {
+    (offset..offset + count).fold(0, |value, bit| {
+        value << 1 | (bytes[bit / 8] >> (7 - bit % 8) & 1) as u32
+    })
+}
+
+fn write_bits(bytes: &mut [u8], offset: usize, count: usize, value: u32) {
+    for (index, bit) in (offset..offset + count).enumerate() {
+        let mask = 1 << (7 - bit % 8);
+        match value >> (count - 1 - index) & 1 {
+            0 => bytes[bit / 8] &= !mask,
+            _ => bytes[bit / 8] |= mask,
+        }
+    }
+}
+
+/// The CRC-16 protected frames carry, over the end of the header & the side information.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ (*byte as u16) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => crc << 1 ^ 0x8005,
+        })
+    })
+}
+
+/// Shift the global gain of every granule of a layer III frame, other frames are left as they are.
+fn adjust(frame: &mut [u8], steps: i32) {
+    // 3 is MPEG 1 & 1 is layer III
+    let (version, layer) = ((frame[1] >> 3) & 3, (frame[1] >> 1) & 3);
+    if layer != 1 {
+        return;
+    }
+
+    let protected = frame[1] & 1 == 0;
+    let channels = match frame[3] >> 6 {
+        3 => 1,
+        _ => 2,
+    };
+    // the granules, the bits before the first granule, the bits of each granule & channel & the bytes of side information
+    let (granules, start, bits, side) = match (version, channels) {
+        (3, 1) => (2, 18, 59, 17),
+        (3, _) => (2, 20, 59, 32),
+        (_, 1) => (1, 9, 63, 9),
+        (_, _) => (1, 10, 63, 17),
+    };
+    let info = match protected {
+        true => 6,
+        false => 4,
+    };
+    if frame.len() < info + side {
+        return;
+    }
+
+    // the global gain follows the 12 bit part2_3_length & the 9 bit big_values
+    for block in 0..granules * channels {
+        let offset = info * 8 + start + block * bits + 21;
+        let gain = read_bits(frame, offset, 8) as i32;
+        write_bits(frame, offset, 8, (gain + steps).clamp(0, 255) as u32);
+    }
+
+    if protected {
+        let mut covered = frame[2..4].to_vec();
+        covered.extend_from_slice(&frame[info..info + side]);
+        frame[4..6].copy_from_slice(&crc16(&covered).to_be_bytes());
+    }
+}
+
+/// Copy MPEG audio with the global gain of its layer III frames shifted by `steps`, without decoding it.
+///
+/// Tags & anything between frames are copied as they are, a frame cut short at the end is dropped.
+pub async fn apply(
+    reader: Reader<'static>,
+    writer: &mut (impl AsyncWrite + Unpin),
+    steps: i32,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+
+    let mut header = [0; 4];
+    if !fill(&mut reader, &mut header).await? {
+        return writer.flush().await;
+    }
+
+    // an ID3v2 tag at the start, or a segment's timestamp
+    if header[..3] == *b"ID3" {
+        let mut rest = [0; 6];
+        if !fill(&mut reader, &mut rest).await? {
+            return writer.flush().await;
+        }
+        let footer = if rest[1] & 0x10 != 0 { 10 } else { 0 };
+        let size = rest[2..]
+            .iter()
+            .fold(0, |size, byte| size << 7 | (*byte & 0x7F) as u64);
+        writer.write_all(&header).await?;
+        writer.write_all(&rest).await?;
+        io::copy(&mut (&mut reader).take(size + footer), writer).await?;
+
+        if !fill(&mut reader, &mut header).await? {
+            return writer.flush().await;
+        }
+    }
+
+    let mut frame = Vec::new();
+    loop {
+        let Some(parsed) = Frame::parse(header) else {
+            writer.write_all(&header[..1]).await?;
+            header.copy_within(1.., 0);
+            if !fill(&mut reader, &mut header[3..]).await? {
+                writer.write_all(&header[..3]).await?;
+                break;
+            }
+            continue;
+        };
+
+        frame.clear();
+        frame.extend_from_slice(&header);
+        frame.resize(parsed.length, 0);
+        if !fill(&mut reader, &mut frame[4..]).await? {
+            break;
+        }
+        adjust(&mut frame, steps);
+        writer.write_all(&frame).await?;
+
+        if !fill(&mut reader, &mut header).await? {
+            break;
+        }
+    }
+
+    writer.flush().await
+}
+
+impl Blobs {
+    /// The steps of global gain a track's audio is played back with, `0` until it's been measured.
+    pub async fn gain(&self, db: &MyDatabase, track: &str, mode: GainMode) -> Result<i32> {
+        let track = track.to_string();
+        let loudness = db
+            .run(move |conn| loudness::playback(conn, &track, mode == GainMode::Album))
+            .await?;
+        Ok(loudness.as_ref().map(steps).unwrap_or_default())
+    }
+
+    /// A track's audio with its ReplayGain gain applied, see [`apply`].
+    ///
+    /// Audio that hasn't been measured yet is downloaded as it is.
+    /// A `415 Unsupported Media Type` if it isn't MP3.
+    pub async fn download_with_gain(
+        &self,
+        db: &MyDatabase,
+        info: &AudioInfo,
+        mode: GainMode,
+    ) -> Result<Option<Download>> {
+        if info.mime != "audio/mpeg" {
+            Err(ApiError::IoError((
+                Status::UnsupportedMediaType,
+                "Gain Needs MP3 Audio".to_string(),
+            )))?
+        }
+
+        let content_type = ContentType::new("audio", "mpeg");
+        let key = Storage::blob_key(&info.hash);
+        let steps = self.gain(db, &info.track, mode).await?;
+        if steps == 0 {
+            return self.storage.audio.download(&key, &content_type).await;
+        }
+
+        let Some((_, reader)) = self.storage.audio.open(&key).await? else {
+            return Ok(None);
+        };
+        let copy = NamedTempFile::new_in(&self.config.staging)?;
+        let mut file = BufWriter::new(File::from_std(copy.reopen()?));
+        apply(reader, &mut file, steps).await?;
+
+        // it's deleted once it's been opened, the open file is what's sent
+        let file = NamedFile::open(copy.path()).await?;
+        Ok(Some(Download::File((content_type, file))))
+    }
+
+    /// An HLS segment with a gain applied, see [`apply`].
+    pub async fn segment_with_gain(
+        &self,
+        hash: &str,
+        bitrate: u32,
+        segment: u64,
+        steps: i32,
+    ) -> Result<Option<Download>> {
+        let content_type = ContentType::new("audio", "mpeg");
+        let key = Storage::segment_key(hash, bitrate, segment);
+        if steps == 0 {
+            return self.storage.audio.download(&key, &content_type).await;
+        }
+
+        let Some((size, reader)) = self.storage.audio.open(&key).await? else {
+            return Ok(None);
+        };
+        let mut bytes = Vec::with_capacity(size as usize);
+        apply(reader, &mut bytes, steps).await?;
+        Ok(Some(Download::Bytes((content_type, bytes))))
+    }
+}
@@ -0,0 +1,423 @@
+use rocket::{
+    data::ByteUnit,
+    http::Status,
+    tokio::{
+        fs::File,
+        io::{self, AsyncReadExt, BufReader},
+        task::spawn_blocking,
+    },
+};
+use rocket_sync_db_pools::rusqlite::{self, params};
+use tempfile::NamedTempFile;
+
+use std::{
+    io::{BufWriter, Cursor, Write},
+    sync::Arc,
+};
+
+use crate::{
+    audio::{analysis::decode, encoder::Encoder, Blobs},
+    database::MyDatabase,
+    error::ApiError,
+    storage::{Reader, Storage},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Segments are cut at the first frame that ends this many seconds after they start.
+const SEGMENT: f64 = 10.0;
+
+/// One of the pieces an MP3 file is split into for HLS.
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    /// Seconds
+    pub duration: f64,
+    /// Bytes, including the timestamp
+    pub size: u64,
+    /// The sample rate of its frames
+    pub rate: u32,
+}
+
+/// The header of an MPEG audio frame.
+pub(super) struct Frame {
+    /// Bytes, including the header
+    pub(super) length: usize,
+    samples: u32,
+    rate: u32,
+}
+
+impl Frame {
+    pub(super) fn parse(header: [u8; 4]) -> Option<Self> {
+        if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        // 0 is MPEG 2.5, 2 is MPEG 2 & 3 is MPEG 1
+        let version = (header[1] >> 3) & 3;
+        // 1 is layer III, 2 is layer II & 3 is layer I
+        let layer = (header[1] >> 1) & 3;
+        let bitrate = (header[2] >> 4) as usize;
+        let rate = ((header[2] >> 2) & 3) as usize;
+        let padding = ((header[2] >> 1) & 1) as usize;
+        // free format bitrates aren't supported
+        if version == 1 || layer == 0 || bitrate == 0 || bitrate == 15 || rate == 3 {
+            return None;
+        }
+
+        let kilobits: [u32; 14] = match (version, layer) {
+            (3, 3) => [
+                32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+            ],
+            (3, 2) => [
+                32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+            ],
+            (3, _) => [
+                32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+            ],
+            (_, 3) => [
+                32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+            ],
+            (_, _) => [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        };
+        let bitrate = kilobits[bitrate - 1] as usize * 1000;
+        let rate = [44100, 48000, 32000][rate]
+            >> match version {
+                3 => 0,
+                2 => 1,
+                _ => 2,
+            };
+        let samples = match (version, layer) {
+            (_, 3) => 384,
+            (3, _) | (_, 2) => 1152,
+            (_, _) => 576,
+        };
+
+        let length = match layer {
+            3 => (12 * bitrate / rate as usize + padding) * 4,
+            _ => samples / 8 * bitrate / rate as usize + padding,
+        };
+        (length > 4).then_some(Self {
+            length,
+            samples: samples as u32,
+            rate,
+        })
+    }
+}
+
+/// The ID3 tag HLS packed audio starts with, saying when the segment starts in the stream.
+pub fn timestamp(seconds: f64) -> Vec<u8> {
+    fn syncsafe(size: usize) -> [u8; 4] {
+        [21, 14, 7, 0].map(|shift| ((size >> shift) & 0x7F) as u8)
+    }
+
+    let mut private = b"com.apple.streaming.transportStreamTimestamp\0".to_vec();
+    // 33 bits of a 90kHz clock
+    let ticks = (seconds * 90_000.0).round() as u64 & 0x1_FFFF_FFFF;
+    private.extend_from_slice(&ticks.to_be_bytes());
+
+    let mut frame = b"PRIV".to_vec();
+    frame.extend_from_slice(&syncsafe(private.len()));
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(&private);
+
+    let mut tag = b"ID3\x04\x00\x00".to_vec();
+    tag.extend_from_slice(&syncsafe(frame.len()));
+    tag.extend_from_slice(&frame);
+    tag
+}
+
+/// Fill `buffer`, returning `false` if the file ended first.
+pub(super) async fn fill(
+    reader: &mut BufReader<Reader<'static>>,
+    buffer: &mut [u8],
+) -> io::Result<bool> {
+    match reader.read_exact(buffer).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Transcode a file to constant bitrate MP3, see [`Encoder`].
+///
+/// The error is why it couldn't be.
+fn transcode(
+    file: std::fs::File,
+    mime: &str,
+    kilobits: u32,
+    out: std::fs::File,
+) -> std::result::Result<(), String> {
+    let mut out = BufWriter::new(out);
+    let mut encoder: Option<Encoder> = None;
+    let mut frames = Vec::new();
+    let mut failed = None;
+    decode(file, mime, |rate, channels, samples| {
+        if encoder.is_none() && failed.is_none() {
+            encoder = Encoder::new(rate, channels, kilobits);
+            if encoder.is_none() {
+                failed = Some(format!("Can't Encode {rate}Hz Audio"));
+            }
+        }
+        let Some(encoder) = &mut encoder else {
+            return;
+        };
+
+        encoder.add(samples, &mut frames);
+        if let (None, Err(e)) = (&failed, out.write_all(&frames)) {
+            failed = Some(e.to_string());
+        }
+        frames.clear();
+    })?;
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    encoder.ok_or("No Audio Decoded")?.finish(&mut frames);
+    out.write_all(&frames).map_err(|e| e.to_string())?;
+    out.flush().map_err(|e| e.to_string())
+}
+
+/// The segments of a blob's variant that were already split.
+async fn cached(db: &MyDatabase, hash: &str, bitrate: u32) -> Result<Vec<Segment>> {
+    let hash = hash.to_string();
+    Ok(db
+        .run(move |conn| {
+            conn.prepare(
+                "SELECT duration, size, rate FROM hls_segments WHERE hash = ? AND bitrate = ? ORDER BY segment",
+            )?
+            .query_map(params![hash, bitrate], |row| {
+                Ok(Segment {
+                    duration: row.get(0)?,
+                    size: row.get(1)?,
+                    rate: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<Segment>>>()
+        })
+        .await?)
+}
+
+impl Blobs {
+    /// A blob's HLS segments, it's split the first time they're asked for.
+    ///
+    /// A `bitrate` (kbit/s) is a variant transcoded from the blob, it's transcoded before it's split, `0` is the blob itself.
+    /// `None` if the file is missing from the storage, a `422 Unprocessable Entity` if it isn't MP3 or can't be transcoded.
+    pub async fn segments(
+        &self,
+        db: &MyDatabase,
+        hash: &str,
+        mime: &str,
+        bitrate: u32,
+    ) -> Result<Option<Vec<Segment>>> {
+        let segments = cached(db, hash, bitrate).await?;
+        if !segments.is_empty() {
+            return Ok(Some(segments));
+        }
+
+        // one request splits a variant, the others wait for it & read what it stored
+        let key = (hash.to_string(), bitrate);
+        let flight = self
+            .splitting
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let guard = flight.lock().await;
+        let result = match cached(db, hash, bitrate).await {
+            Ok(segments) if !segments.is_empty() => Ok(Some(segments)),
+            Ok(_) => self.split_variant(db, hash, mime, bitrate).await,
+            Err(e) => Err(e),
+        };
+        drop(guard);
+
+        // the map's & this one's, nobody else is waiting for it
+        let mut splitting = self.splitting.lock().unwrap();
+        if Arc::strong_count(&flight) == 2 {
+            splitting.remove(&key);
+        }
+
+        result
+    }
+
+    /// Split a blob's variant into the storage & store its segments, see [`Blobs::segments`].
+    async fn split_variant(
+        &self,
+        db: &MyDatabase,
+        hash: &str,
+        mime: &str,
+        bitrate: u32,
+    ) -> Result<Option<Vec<Segment>>> {
+        let segments = match bitrate {
+            0 => {
+                let Some((_, reader)) = self.storage.audio.open(&Storage::blob_key(hash)).await?
+                else {
+                    return Ok(None);
+                };
+                self.split(hash, bitrate, reader).await?
+            }
+            _ => {
+                let Some(copy) = self.copy_out(hash).await? else {
+                    return Ok(None);
+                };
+                let variant = NamedTempFile::new_in(&self.config.staging)?;
+                let (out, mime) = (variant.reopen()?, mime.to_string());
+                spawn_blocking(move || transcode(copy.into_file(), &mime, bitrate, out))
+                    .await
+                    .map_err(std::io::Error::other)?
+                    .map_err(|e| ApiError::IoError((Status::UnprocessableEntity, e)))?;
+
+                let reader = Box::pin(File::from_std(variant.reopen()?));
+                self.split(hash, bitrate, reader).await?
+            }
+        };
+        if segments.is_empty() {
+            Err(ApiError::IoError((
+                Status::UnprocessableEntity,
+                "No MP3 Frames".to_string(),
+            )))?
+        }
+
+        // the blob may have been released while it was being split
+        let _lock = self.lock().await;
+        let (hash_clone, rows) = (hash.to_string(), segments.clone());
+        let kept = db
+            .run(move |conn| -> rusqlite::Result<bool> {
+                let tx = conn.transaction()?;
+                if !tx.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM audio_blobs WHERE hash = ?)",
+                    [&hash_clone],
+                    |row| row.get(0),
+                )? {
+                    return Ok(false);
+                }
+
+                for (segment, Segment { duration, size, rate }) in rows.into_iter().enumerate() {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO hls_segments (hash, bitrate, segment, duration, size, rate) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![hash_clone, bitrate, segment, duration, size, rate],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(true)
+            })
+            .await?;
+
+        if !kept {
+            for segment in 0..segments.len() as u64 {
+                self.storage
+                    .audio
+                    .delete(&Storage::segment_key(hash, bitrate, segment))
+                    .await?;
+            }
+        }
+
+        Ok(Some(segments))
+    }
+
+    /// Split an MP3 file at frame boundaries into the storage, each segment starting with its timestamp.
+    async fn split(
+        &self,
+        hash: &str,
+        bitrate: u32,
+        reader: Reader<'static>,
+    ) -> Result<Vec<Segment>> {
+        let mut reader = BufReader::new(reader);
+        let mut segments = Vec::new();
+
+        let mut header = [0; 4];
+        if !fill(&mut reader, &mut header).await? {
+            return Ok(segments);
+        }
+
+        // an ID3v2 tag at the start is metadata
+        if header[..3] == *b"ID3" {
+            let mut rest = [0; 6];
+            if !fill(&mut reader, &mut rest).await? {
+                return Ok(segments);
+            }
+            let footer = if rest[1] & 0x10 != 0 { 10 } else { 0 };
+            let size = rest[2..]
+                .iter()
+                .fold(0, |size, byte| size << 7 | (*byte & 0x7F) as u64);
+            io::copy(&mut (&mut reader).take(size + footer), &mut io::sink()).await?;
+
+            if !fill(&mut reader, &mut header).await? {
+                return Ok(segments);
+            }
+        }
+
+        let mut start = 0.0;
+        let mut duration = 0.0;
+        let mut rate = 0;
+        let mut current = timestamp(start);
+        loop {
+            let Some(frame) = Frame::parse(header) else {
+                // not a frame, look for one a byte further on
+                header.copy_within(1.., 0);
+                if !fill(&mut reader, &mut header[3..]).await? {
+                    break;
+                }
+                continue;
+            };
+
+            let end = current.len();
+            current.extend_from_slice(&header);
+            current.resize(end + frame.length, 0);
+            if !fill(&mut reader, &mut current[end + 4..]).await? {
+                // a frame cut short isn't playable
+                current.truncate(end);
+                break;
+            }
+            duration += frame.samples as f64 / frame.rate as f64;
+            rate = frame.rate;
+
+            if duration >= SEGMENT {
+                segments.push(
+                    self.put_segment(hash, bitrate, segments.len(), current, duration, rate)
+                        .await?,
+                );
+                start += duration;
+                duration = 0.0;
+                current = timestamp(start);
+            }
+
+            if !fill(&mut reader, &mut header).await? {
+                break;
+            }
+        }
+
+        if duration > 0.0 {
+            segments.push(
+                self.put_segment(hash, bitrate, segments.len(), current, duration, rate)
+                    .await?,
+            );
+        }
+        Ok(segments)
+    }
+
+    async fn put_segment(
+        &self,
+        hash: &str,
+        bitrate: u32,
+        segment: usize,
+        bytes: Vec<u8>,
+        duration: f64,
+        rate: u32,
+    ) -> Result<Segment> {
+        let size = bytes.len() as u64;
+        self.storage
+            .audio
+            .put(
+                &Storage::segment_key(hash, bitrate, segment as u64),
+                Box::pin(Cursor::new(bytes)),
+                ByteUnit::max_value(),
+            )
+            .await?;
+        Ok(Segment {
+            duration,
+            size,
+            rate,
+        })
+    }
+}
@@ -0,0 +1,304 @@
+use rocket_sync_db_pools::rusqlite::{self, Connection, OptionalExtension};
+
+use std::{collections::VecDeque, f64::consts::PI};
+
+use crate::api::data::audio::Loudness;
+
+/// The loudness ReplayGain 2.0 brings everything to (LUFS).
+const REFERENCE: f64 = -18.0;
+/// Blocks quieter than this are silence, they're left out of the integrated loudness (LUFS).
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks this much quieter than the rest are left out too (LU).
+const RELATIVE_GATE: f64 = -10.0;
+/// True peaks are found by oversampling 4 times.
+const PHASES: usize = 4;
+const TAPS: usize = 12;
+
+/// A biquad filter, in transposed direct form II.
+#[derive(Clone, Copy)]
+pub(super) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    pub(super) fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    pub(super) fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The K-weighting of ITU-R BS.1770, a high shelf for the head followed by a high-pass.
+///
+/// The coefficients are derived for the sample rate, the ones in the standard are only for 48kHz.
+fn k_weighting(rate: f64) -> [Biquad; 2] {
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// The polyphase interpolation filter for true peaks, a Hann windowed sinc.
+fn oversampling() -> [[f64; TAPS]; PHASES] {
+    let length = (TAPS * PHASES) as f64;
+    let mut fir = [[0.0; TAPS]; PHASES];
+    for (phase, taps) in fir.iter_mut().enumerate() {
+        for (tap, value) in taps.iter_mut().enumerate() {
+            let n = (tap * PHASES + phase) as f64;
+            let x = (n - (length - 1.0) / 2.0) / PHASES as f64;
+            let sinc = match x == 0.0 {
+                true => 1.0,
+                false => (PI * x).sin() / (PI * x),
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n + 0.5) / length).cos();
+            *value = sinc * window;
+        }
+
+        let sum: f64 = taps.iter().sum();
+        taps.iter_mut().for_each(|value| *value /= sum);
+    }
+    fir
+}
+
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+fn power(loudness: f64) -> f64 {
+    10f64.powf((loudness + 0.691) / 10.0)
+}
+
+/// What a [`Meter`] measured.
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    /// The integrated loudness (LUFS), `None` for silence or anything shorter than a block.
+    pub integrated: Option<f64>,
+    /// The true peak (dBTP), `None` for silence.
+    pub true_peak: Option<f64>,
+    /// The number of blocks the integrated loudness is from, its weight in an album.
+    pub blocks: u64,
+}
+
+/// Measures EBU R128 loudness (ITU-R BS.1770-4) as interleaved samples are fed to it.
+pub struct Meter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    fir: [[f64; TAPS]; PHASES],
+    /// The most the oversampling can amplify a sample by.
+    reach: f64,
+    /// The latest samples of each channel, newest first, for the oversampling.
+    history: Vec<[f64; TAPS]>,
+    /// Frames in 100ms, blocks are 400ms & overlap by 75%.
+    step: usize,
+    energy: f64,
+    frames: usize,
+    steps: VecDeque<f64>,
+    /// The mean square of every block.
+    blocks: Vec<f64>,
+    peak: f64,
+}
+
+impl Meter {
+    pub fn new(rate: u32, channels: usize) -> Self {
+        // 5.1 is L, R, C, LFE, Ls, Rs, the LFE isn't heard as loudness & the surrounds count more
+        let weights = match channels {
+            6 => vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41],
+            _ => vec![1.0; channels],
+        };
+
+        let fir = oversampling();
+        let reach = fir
+            .iter()
+            .map(|taps| taps.iter().map(|tap| tap.abs()).sum::<f64>())
+            .fold(0.0, f64::max);
+
+        Self {
+            channels,
+            weights,
+            filters: vec![k_weighting(rate as f64); channels],
+            fir,
+            reach,
+            history: vec![[0.0; TAPS]; channels],
+            step: (rate as usize / 10).max(1),
+            energy: 0.0,
+            frames: 0,
+            steps: VecDeque::with_capacity(4),
+            blocks: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let x = *sample as f64;
+
+                let history = &mut self.history[channel];
+                history.rotate_right(1);
+                history[0] = x;
+                // only interpolate when it could find a new peak, most samples are well below it
+                let loudest = history.iter().fold(0.0, |max: f64, x| max.max(x.abs()));
+                if loudest * self.reach > self.peak {
+                    for taps in self.fir.iter() {
+                        let y: f64 = taps.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+                        self.peak = self.peak.max(y.abs());
+                    }
+                    self.peak = self.peak.max(x.abs());
+                }
+
+                let [shelf, high_pass] = &mut self.filters[channel];
+                let y = high_pass.process(shelf.process(x));
+                self.energy += self.weights[channel] * y * y;
+            }
+
+            self.frames += 1;
+            if self.frames == self.step {
+                if self.steps.len() == 4 {
+                    self.steps.pop_front();
+                }
+                self.steps.push_back(self.energy);
+                if self.steps.len() == 4 {
+                    let energy: f64 = self.steps.iter().sum();
+                    self.blocks.push(energy / (4 * self.step) as f64);
+                }
+                self.energy = 0.0;
+                self.frames = 0;
+            }
+        }
+    }
+
+    pub fn finish(&self) -> Measurement {
+        let audible = self
+            .blocks
+            .iter()
+            .copied()
+            .filter(|power| loudness(*power) > ABSOLUTE_GATE)
+            .collect::<Vec<f64>>();
+
+        let (integrated, blocks) = match audible.is_empty() {
+            true => (None, 0),
+            false => {
+                let threshold =
+                    loudness(audible.iter().sum::<f64>() / audible.len() as f64) + RELATIVE_GATE;
+                let gated = audible
+                    .into_iter()
+                    .filter(|power| loudness(*power) > threshold)
+                    .collect::<Vec<f64>>();
+                let mean = gated.iter().sum::<f64>() / gated.len() as f64;
+                (Some(loudness(mean)), gated.len() as u64)
+            }
+        };
+
+        Measurement {
+            integrated,
+            true_peak: (self.peak > 0.0).then(|| 20.0 * self.peak.log10()),
+            blocks,
+        }
+    }
+}
+
+/// The ReplayGain 2.0 gain for audio of `integrated` loudness (dB).
+pub fn replay_gain(integrated: f64) -> f64 {
+    REFERENCE - integrated
+}
+
+impl Loudness {
+    pub fn new(integrated: Option<f64>, true_peak: Option<f64>) -> Option<Self> {
+        Some(Self {
+            integrated: integrated?,
+            true_peak: true_peak?,
+            gain: replay_gain(integrated?),
+        })
+    }
+}
+
+/// The loudness of an album's tracks played back to back.
+///
+/// Every track is weighted by the blocks its loudness was gated to, approximating gating the whole album at once.
+/// `None` until at least one track has been measured.
+pub fn album(conn: &Connection, album: &str) -> rusqlite::Result<Option<Loudness>> {
+    let mut energy = 0.0;
+    let mut blocks = 0;
+    let mut true_peak = f64::NEG_INFINITY;
+
+    let mut statement = conn.prepare(
+        "SELECT loudness, true_peak, loudness_blocks FROM album_tracks
+        JOIN track_audio ON album_tracks.track_id = track_audio.track_id
+        JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+        WHERE album_tracks.album_id = ? AND loudness IS NOT NULL AND true_peak IS NOT NULL",
+    )?;
+    let mut rows = statement.query([album])?;
+    while let Some(row) = rows.next()? {
+        let (integrated, peak, count): (f64, f64, u64) = (row.get(0)?, row.get(1)?, row.get(2)?);
+        energy += count as f64 * power(integrated);
+        blocks += count;
+        true_peak = true_peak.max(peak);
+    }
+
+    if blocks == 0 {
+        return Ok(None);
+    }
+
+    Ok(Loudness::new(
+        Some(loudness(energy / blocks as f64)),
+        Some(true_peak),
+    ))
+}
+
+/// The loudness a track's audio is played back at, its album's if `album` is set.
+///
+/// The album is the first of the track's, the track's own loudness is used if it isn't on one or none of it has been measured.
+/// `None` until the track's audio has been measured.
+pub fn playback(conn: &Connection, track: &str, album: bool) -> rusqlite::Result<Option<Loudness>> {
+    let first: Option<String> = match album {
+        true => conn
+            .query_row(
+                "SELECT album_id FROM album_tracks WHERE track_id = ? ORDER BY album_id LIMIT 1",
+                [track],
+                |row| row.get(0),
+            )
+            .optional()?,
+        false => None,
+    };
+    if let Some(loudness) = first.map(|album| self::album(conn, &album)).transpose()?.flatten() {
+        return Ok(Some(loudness));
+    }
+
+    Ok(conn
+        .query_row(
+            "SELECT loudness, true_peak FROM track_audio
+            JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+            WHERE track_id = ?",
+            [track],
+            |row| Ok(Loudness::new(row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .flatten())
+}
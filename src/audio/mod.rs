@@ -0,0 +1,855 @@
+use rocket::{
+    data::{ByteUnit, ToByteUnit},
+    fairing::AdHoc,
+    http::Status,
+    serde::Deserialize,
+    tokio::{
+        fs::File,
+        io::{AsyncReadExt, AsyncWriteExt},
+        select,
+        sync::{Mutex, Notify, OwnedMutexGuard},
+        time::sleep,
+    },
+};
+use rocket_sync_db_pools::rusqlite::{self, params, Connection, OptionalExtension};
+use tempfile::{NamedTempFile, TempPath};
+use uuid::Uuid;
+
+use data_encoding::HEXLOWER;
+use sha2::{Digest, Sha256};
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::PathBuf,
+    sync::{self, Arc},
+    time::Duration,
+};
+
+use crate::{
+    api::data::{
+        audio::{AudioGarbage, AudioInfo, AudioMismatch, Verification, VerificationStatus},
+        events::{Action, ChangeEvent, Resource},
+        users::Usage,
+    },
+    database::{Connections, MyDatabase},
+    error::ApiError,
+    events::EventLog,
+    lyrics,
+    storage::{digest, Reader, Storage},
+};
+
+mod analysis;
+pub mod encoder;
+pub mod fingerprint;
+pub mod gain;
+pub mod hls;
+pub mod loudness;
+pub mod peaks;
+mod uploads;
+
+pub use uploads::Uploads;
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `audio` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct AudioConfig {
+    /// Where uploads are written & hashed before they're stored.
+    pub staging: PathBuf,
+    /// The maximum size of an audio file, uploaded at once or resumably.
+    pub limit: ByteUnit,
+    /// Seconds a resumable upload is kept without receiving anything.
+    pub session_expiry: u64,
+    /// Seconds between scheduled verifications, `0` disables them.
+    pub verify_interval: u64,
+    /// The number of finished verifications kept to be looked up, older ones are forgotten when a new one starts.
+    pub verify_keep: usize,
+    /// Seconds the signed links of an HLS stream work for.
+    pub stream_expiry: u64,
+    /// The bitrates (kbit/s) of the HLS variants transcoded from MP3 audio, the ones below its own are offered.
+    pub stream_bitrates: Vec<u32>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            staging: PathBuf::from("database/staging"),
+            limit: 1.gibibytes(),
+            session_expiry: 86400,
+            verify_interval: 0,
+            verify_keep: 100,
+            stream_expiry: 21600,
+            stream_bitrates: vec![64, 128],
+        }
+    }
+}
+
+/// An upload that has been hashed, but not stored yet.
+///
+/// The file is deleted when this is dropped.
+pub struct Staged {
+    file: TempPath,
+    pub size: u64,
+    pub hash: String,
+}
+
+/// A lock for each HLS variant being split, by the blob's hash & the bitrate.
+type Splitting = HashMap<(String, u32), Arc<Mutex<()>>>;
+
+/// Content-addressed audio, every distinct file is stored once under its SHA-256.
+///
+/// `track_audio` maps tracks to blobs, a blob is deleted once no track references it.
+#[derive(Clone)]
+pub struct Blobs {
+    config: AudioConfig,
+    storage: Storage,
+    /// Held while blobs are stored, linked or released, so a blob isn't deleted as it's being reused.
+    lock: Arc<Mutex<()>>,
+    /// Only one verification runs at a time.
+    verifying: Arc<Mutex<()>>,
+    verifications: Arc<sync::Mutex<Verifications>>,
+    /// Wakes the analysis when there are new blobs to measure.
+    analyzing: Arc<Notify>,
+    /// Held while a blob's HLS variant is split, so it's only split once.
+    splitting: Arc<sync::Mutex<Splitting>>,
+}
+
+#[derive(Default)]
+struct Verifications {
+    by_id: HashMap<String, Arc<sync::Mutex<Verification>>>,
+    /// The ids, oldest first.
+    started: VecDeque<String>,
+}
+
+impl Verifications {
+    fn insert(&mut self, job: Arc<sync::Mutex<Verification>>) {
+        let id = job.lock().unwrap().id.clone();
+        self.started.push_back(id.clone());
+        self.by_id.insert(id, job);
+    }
+
+    /// Forget the oldest finished verifications, until only `keep` are left.
+    fn evict(&mut self, keep: usize) {
+        let finished = |job: &Arc<sync::Mutex<Verification>>| {
+            job.lock().unwrap().status != VerificationStatus::Running
+        };
+
+        let mut excess = self
+            .by_id
+            .values()
+            .filter(|job| finished(job))
+            .count()
+            .saturating_sub(keep);
+
+        let by_id = &mut self.by_id;
+        self.started.retain(|id| {
+            if excess == 0 || !by_id.get(id).is_some_and(finished) {
+                return true;
+            }
+            by_id.remove(id);
+            excess -= 1;
+            false
+        });
+    }
+}
+
+impl Blobs {
+    pub fn config(&self) -> &AudioConfig {
+        &self.config
+    }
+
+    pub async fn lock(&self) -> OwnedMutexGuard<()> {
+        self.lock.clone().lock_owned().await
+    }
+
+    /// Write a reader to the staging directory, hashing it on the way.
+    ///
+    /// More than `limit` bytes is a `413 Payload Too Large`.
+    pub async fn stage(&self, mut reader: Reader<'_>, limit: ByteUnit) -> Result<Staged> {
+        fs::create_dir_all(&self.config.staging)?;
+        let staged = NamedTempFile::new_in(&self.config.staging)?;
+        let mut file = File::from_std(staged.reopen()?);
+
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+
+            size += read as u64;
+            if size > limit.as_u64() {
+                Err(Status::PayloadTooLarge)?
+            }
+            hasher.update(&buffer[..read]);
+            file.write_all(&buffer[..read]).await?;
+        }
+        file.flush().await?;
+
+        Ok(Staged {
+            file: staged.into_temp_path(),
+            size,
+            hash: HEXLOWER.encode(&hasher.finalize()),
+        })
+    }
+
+    /// Put a blob in the storage unless it's already there.
+    ///
+    /// A blob flagged `corrupt` by a verification is replaced anyway.
+    /// Call with the lock held.
+    pub async fn store(&self, hash: &str, reader: Reader<'_>, corrupt: bool) -> Result<()> {
+        let key = Storage::blob_key(hash);
+        if corrupt || !self.storage.audio.exists(&key).await? {
+            self.storage
+                .audio
+                .put(&key, reader, ByteUnit::max_value())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Store a staged upload as a track's audio, returning the event to send, a create or an update if the track already had audio.
+    ///
+    /// The file counts towards `uploader`'s quota, it's refused if it doesn't fit.
+    pub async fn upload(
+        &self,
+        db: &MyDatabase,
+        log: EventLog,
+        track: &str,
+        staged: &Staged,
+        mime: String,
+        uploader: &str,
+    ) -> Result<ChangeEvent> {
+        let _lock = self.lock().await;
+        let (track_clone, uploader_clone) = (track.to_string(), uploader.to_string());
+        let (hash, size) = (staged.hash.clone(), staged.size);
+        let corrupt = db
+            .run(move |conn| -> Result<bool> {
+                check_quota(conn, &uploader_clone, &track_clone, size)?;
+                is_corrupt(conn, &hash)
+            })
+            .await?;
+        let file = File::open(&staged.file).await?;
+        self.store(&staged.hash, Box::pin(file), corrupt).await?;
+
+        let (track, uploader) = (track.to_string(), uploader.to_string());
+        let (hash, file) = (staged.hash.clone(), staged.file.to_path_buf());
+        let (previous, event) = db
+            .run(move |conn| -> Result<_> {
+                let tx = conn.transaction()?;
+                let previous = link(&tx, &track, &hash, size, &mime, Some(&uploader))?;
+                if let Some(tag) = lyrics::read_tag(&file) {
+                    lyrics::write_tagged(&tx, &track, &tag)?;
+                }
+                let action = match previous {
+                    Some(_) => Action::Update,
+                    None => Action::Create,
+                };
+                let event = log.record(&tx, Resource::Audio, action, track)?;
+                tx.commit()?;
+                Ok((previous, event))
+            })
+            .await?;
+
+        self.analyze();
+        if let Some(previous) = previous {
+            self.release(db, &previous).await?;
+        }
+        Ok(event)
+    }
+
+    /// Remove a track's audio, returning the event to send if it had any.
+    pub async fn remove(
+        &self,
+        db: &MyDatabase,
+        log: EventLog,
+        track: &str,
+    ) -> Result<Option<ChangeEvent>> {
+        let _lock = self.lock().await;
+        let track = track.to_string();
+        let removed = db
+            .run(move |conn| -> Result<_> {
+                let tx = conn.transaction()?;
+                let Some(hash) = tx
+                    .query_row(
+                        "DELETE FROM track_audio WHERE track_id = ? RETURNING hash",
+                        [&track],
+                        |row| row.get::<usize, String>(0),
+                    )
+                    .optional()?
+                else {
+                    return Ok(None);
+                };
+                let event = log.record(&tx, Resource::Audio, Action::Delete, track)?;
+                tx.commit()?;
+                Ok(Some((hash, event)))
+            })
+            .await?;
+
+        match removed {
+            Some((hash, event)) => {
+                self.release(db, &hash).await?;
+                Ok(Some(event))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a track, returning the events to send, its audio's too if it had any.
+    pub async fn delete_track(
+        &self,
+        db: &MyDatabase,
+        log: EventLog,
+        track: String,
+    ) -> Result<Vec<ChangeEvent>> {
+        let (audio, recorded) = db
+            .run(move |conn| -> Result<_> {
+                let tx = conn.transaction()?;
+
+                if tx
+                    .query_row("SELECT 1 FROM tracks WHERE id = ?", [&track], |_| Ok(()))
+                    .optional()?
+                    .is_none()
+                {
+                    Err(Status::NotFound)?
+                }
+
+                // the audio reference goes with the track, the file only once no other track uses it
+                let audio = tx
+                    .query_row(
+                        "SELECT hash FROM track_audio WHERE track_id = ?",
+                        [&track],
+                        |row| row.get::<usize, String>(0),
+                    )
+                    .optional()?;
+
+                tx.execute("DELETE FROM tracks WHERE id = ?", [&track])?;
+
+                let mut recorded = Vec::new();
+                if audio.is_some() {
+                    recorded.push(log.record(
+                        &tx,
+                        Resource::Audio,
+                        Action::Delete,
+                        track.clone(),
+                    )?);
+                }
+                recorded.push(log.record(&tx, Resource::Track, Action::Delete, track)?);
+
+                tx.commit()?;
+
+                Ok((audio, recorded))
+            })
+            .await?;
+
+        if let Some(hash) = audio {
+            let _lock = self.lock().await;
+            // the track is gone either way, a file left behind is found by `POST /audio/gc`
+            if let Err(e) = self.release(db, &hash).await {
+                error!("Failed to delete the audio of a deleted track: {:?}", e);
+            }
+        }
+
+        Ok(recorded)
+    }
+
+    /// Delete a blob if no track references it anymore, returning whether it was deleted.
+    ///
+    /// Call with the lock held.
+    pub async fn release(&self, db: &MyDatabase, hash: &str) -> Result<bool> {
+        let hash_clone = hash.to_string();
+        let (deleted, segments) = db
+            .run(move |conn| -> rusqlite::Result<(usize, Vec<(u32, u64)>)> {
+                let segments = conn
+                    .prepare("SELECT bitrate, COUNT(*) FROM hls_segments WHERE hash = ? GROUP BY bitrate")?
+                    .query_map([&hash_clone], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<rusqlite::Result<Vec<(u32, u64)>>>()?;
+                let deleted = conn.execute(
+                    "DELETE FROM audio_blobs WHERE hash = ?1
+                    AND NOT EXISTS(SELECT 1 FROM track_audio WHERE hash = ?1)",
+                    [&hash_clone],
+                )?;
+                Ok((deleted, segments))
+            })
+            .await?;
+
+        if deleted == 0 {
+            return Ok(false);
+        }
+
+        self.storage.audio.delete(&Storage::blob_key(hash)).await?;
+        self.storage.audio.delete(&Storage::peaks_key(hash)).await?;
+        for (bitrate, count) in segments {
+            for segment in 0..count {
+                self.storage
+                    .audio
+                    .delete(&Storage::segment_key(hash, bitrate, segment))
+                    .await?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Find files no record points to, blobs no track uses & tracks whose file is missing, removing them unless `dry_run`.
+    ///
+    /// Also returns the events to send for the tracks whose audio was removed.
+    pub async fn collect(
+        &self,
+        db: &MyDatabase,
+        log: EventLog,
+        dry_run: bool,
+    ) -> Result<(AudioGarbage, Vec<ChangeEvent>)> {
+        let _lock = self.lock().await;
+
+        let (blobs, unreferenced) = db
+            .run(|conn| -> Result<(Vec<String>, Vec<String>)> {
+                let blobs = conn
+                    .prepare("SELECT hash FROM audio_blobs ORDER BY hash")?
+                    .query_map([], |row| row.get(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()?;
+                let unreferenced = conn
+                    .prepare(
+                        "SELECT hash FROM audio_blobs
+                        WHERE NOT EXISTS(SELECT 1 FROM track_audio WHERE hash = audio_blobs.hash)
+                        ORDER BY hash",
+                    )?
+                    .query_map([], |row| row.get(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()?;
+                Ok((blobs, unreferenced))
+            })
+            .await?;
+
+        let stored = self
+            .storage
+            .audio
+            .list()
+            .await?
+            .into_iter()
+            .collect::<HashSet<String>>();
+        let keys = blobs
+            .iter()
+            .map(|hash| Storage::blob_key(hash))
+            .collect::<HashSet<String>>();
+
+        // what's cached next to a blob goes with it
+        let mut orphaned = stored
+            .iter()
+            .filter(|key| !keys.contains(Storage::blob_of(key)))
+            .cloned()
+            .collect::<Vec<String>>();
+        orphaned.sort();
+
+        // an unreferenced blob is removed whether or not its file is there
+        let missing = blobs
+            .into_iter()
+            .filter(|hash| !stored.contains(&Storage::blob_key(hash)))
+            .filter(|hash| !unreferenced.contains(hash))
+            .collect::<Vec<String>>();
+        let (dangling, recorded) = db
+            .run(move |conn| -> Result<_> {
+                let tx = conn.transaction()?;
+                let mut dangling = Vec::new();
+                for hash in missing {
+                    dangling.extend(
+                        tx.prepare("SELECT track_id FROM track_audio WHERE hash = ?")?
+                            .query_map([&hash], |row| row.get::<usize, String>(0))?
+                            .collect::<rusqlite::Result<Vec<String>>>()?,
+                    );
+                    if !dry_run {
+                        tx.execute("DELETE FROM track_audio WHERE hash = ?", [&hash])?;
+                        tx.execute("DELETE FROM audio_blobs WHERE hash = ?", [&hash])?;
+                    }
+                }
+                dangling.sort();
+                let recorded = match dry_run {
+                    true => Vec::new(),
+                    false => log.record_all(&tx, Resource::Audio, Action::Delete, &dangling)?,
+                };
+                tx.commit()?;
+                Ok((dangling, recorded))
+            })
+            .await?;
+
+        if !dry_run {
+            for key in orphaned.iter() {
+                self.storage.audio.delete(key).await?;
+            }
+            for hash in unreferenced.iter() {
+                self.release(db, hash).await?;
+            }
+        }
+
+        Ok((
+            AudioGarbage {
+                dry_run,
+                orphaned,
+                unreferenced,
+                dangling,
+            },
+            recorded,
+        ))
+    }
+
+    pub fn verification(&self, id: &str) -> Option<Verification> {
+        self.verifications
+            .lock()
+            .unwrap()
+            .by_id
+            .get(id)
+            .map(|job| job.lock().unwrap().clone())
+    }
+
+    fn register(&self) -> (Verification, Arc<sync::Mutex<Verification>>) {
+        let job = Verification::new(Uuid::new_v4().to_string());
+        let shared = Arc::new(sync::Mutex::new(job.clone()));
+
+        {
+            let mut verifications = self.verifications.lock().unwrap();
+            verifications.evict(self.config.verify_keep);
+            verifications.insert(shared.clone());
+        }
+
+        (job, shared)
+    }
+
+    /// Start re-hashing every blob in the background, the returned job is a snapshot of its initial state.
+    pub fn verify(&self, connections: Connections) -> Verification {
+        let (job, shared) = self.register();
+        let blobs = self.clone();
+        rocket::tokio::spawn(async move { blobs.run_verification(&connections, shared).await });
+
+        job
+    }
+
+    /// Re-hash every blob, flagging the ones that are missing or changed.
+    ///
+    /// A connection is only taken to list the blobs & to flag each one, not while reading the storage.
+    async fn run_verification(
+        &self,
+        connections: &Connections,
+        job: Arc<sync::Mutex<Verification>>,
+    ) {
+        let _verifying = self.verifying.lock().await;
+        let connection = || async {
+            connections
+                .get()
+                .await
+                .ok_or(ApiError::Status(Status::ServiceUnavailable))
+        };
+
+        let result = async {
+            let blobs = connection()
+                .await?
+                .run(|conn| -> Result<Vec<(String, u64)>> {
+                    conn.prepare("SELECT hash, size FROM audio_blobs ORDER BY hash")?
+                        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                        .map(|v| v.map_err(ApiError::from))
+                        .collect()
+                })
+                .await?;
+            job.lock().unwrap().total = blobs.len();
+
+            for (hash, size) in blobs {
+                let reason = match digest(self.storage.audio.as_ref(), &Storage::blob_key(&hash))
+                    .await?
+                {
+                    None => Some("missing".to_string()),
+                    Some((found, _)) if found != size => {
+                        Some(format!("size is {found}, expected {size}"))
+                    }
+                    Some((_, found)) if found != hash => Some(format!("hash is {found}")),
+                    Some(_) => None,
+                };
+
+                let hash_clone = hash.clone();
+                let corrupt = reason.is_some();
+                let tracks = connection()
+                    .await?
+                    .run(move |conn| -> Result<Vec<String>> {
+                        conn.execute(
+                            "UPDATE audio_blobs SET verified = strftime('%s', 'now'), corrupt = ?2 WHERE hash = ?1",
+                            params![hash_clone, corrupt],
+                        )?;
+                        conn.prepare("SELECT track_id FROM track_audio WHERE hash = ? ORDER BY track_id")?
+                            .query_map([hash_clone], |row| row.get(0))?
+                            .map(|v| v.map_err(ApiError::from))
+                            .collect()
+                    })
+                    .await?;
+
+                let mut job = job.lock().unwrap();
+                job.checked += 1;
+                if let Some(reason) = reason {
+                    job.mismatched.push(AudioMismatch {
+                        hash,
+                        tracks,
+                        reason,
+                    });
+                }
+            }
+
+            Ok::<(), ApiError>(())
+        }
+        .await;
+
+        let mut job = job.lock().unwrap();
+        match result {
+            Ok(()) => job.status = VerificationStatus::Finished,
+            Err(e) => {
+                job.status = VerificationStatus::Failed;
+                job.error = Some(format!("{e:?}"));
+            }
+        }
+    }
+
+    /// Move audio stored as `<track>.mp3` (from before it was content-addressed) into blobs.
+    async fn migrate_legacy(&self, db: &MyDatabase) -> Result<usize> {
+        let tracks = db
+            .run(|conn| -> Result<Vec<String>> {
+                conn.prepare(
+                    "SELECT id FROM tracks
+                    WHERE NOT EXISTS(SELECT 1 FROM track_audio WHERE track_id = tracks.id)",
+                )?
+                .query_map([], |row| row.get(0))?
+                .map(|v| v.map_err(ApiError::from))
+                .collect()
+            })
+            .await?;
+
+        let _lock = self.lock().await;
+        let mut migrated = 0;
+        for track in tracks {
+            let legacy = format!("{track}.mp3");
+            let Some((size, hash)) = digest(self.storage.audio.as_ref(), &legacy).await? else {
+                continue;
+            };
+
+            let hash_clone = hash.clone();
+            let corrupt = db.run(move |conn| is_corrupt(conn, &hash_clone)).await?;
+            if let Some((_, reader)) = self.storage.audio.open(&legacy).await? {
+                self.store(&hash, reader, corrupt).await?;
+            }
+
+            db.run(move |conn| -> Result<Option<String>> {
+                let tx = conn.transaction()?;
+                let previous = link(&tx, &track, &hash, size, "audio/mpeg", None)?;
+                tx.commit()?;
+                Ok(previous)
+            })
+            .await?;
+
+            self.storage.audio.delete(&legacy).await?;
+            migrated += 1;
+        }
+
+        Ok(migrated)
+    }
+}
+
+/// Whether a verification found the blob missing or changed.
+pub fn is_corrupt(conn: &Connection, hash: &str) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM audio_blobs WHERE hash = ? AND corrupt)",
+        [hash],
+        |row| row.get(0),
+    )?)
+}
+
+/// Point a track at a blob, recording the blob if it's new.
+///
+/// Returns the blob the track pointed at before, release it once the transaction is committed.
+pub fn link(
+    conn: &Connection,
+    track: &str,
+    hash: &str,
+    size: u64,
+    mime: &str,
+    uploader: Option<&str>,
+) -> Result<Option<String>> {
+    conn.execute(
+        "INSERT INTO audio_blobs (hash, size, mime) VALUES (?1, ?2, ?3)
+        ON CONFLICT (hash) DO UPDATE SET corrupt = 0, verified = NULL,
+            analyzed = CASE WHEN loudness IS NULL THEN NULL ELSE analyzed END
+        WHERE corrupt",
+        params![hash, size, mime],
+    )?;
+
+    let previous = conn
+        .query_row(
+            "SELECT hash FROM track_audio WHERE track_id = ?",
+            [track],
+            |row| row.get::<usize, String>(0),
+        )
+        .optional()?;
+
+    conn.execute(
+        "INSERT INTO track_audio (track_id, hash, uploader) VALUES (?1, ?2, ?3)
+        ON CONFLICT (track_id) DO UPDATE SET hash = ?2, uploader = ?3, uploaded = strftime('%s', 'now')",
+        params![track, hash, uploader],
+    )?;
+
+    Ok(previous)
+}
+
+/// Refuse a file that would take `uploader` over their quota.
+///
+/// Audio they uploaded for the same track doesn't count, it's being replaced.
+pub fn check_quota(conn: &Connection, uploader: &str, track: &str, size: u64) -> Result<()> {
+    let Some(usage) = usage(conn, uploader, Some(track))? else {
+        return Ok(());
+    };
+
+    if let Some(quota) = usage.quota {
+        if usage.used + size > quota {
+            Err(ApiError::IoError((
+                Status::PayloadTooLarge,
+                format!(
+                    "Quota Exceeded: {} of {} bytes are used, the file is {} bytes",
+                    usage.used, quota, size
+                ),
+            )))?
+        }
+    }
+
+    Ok(())
+}
+
+/// The audio a user has uploaded, leaving out `except`'s, `None` if they don't exist.
+pub fn usage(conn: &Connection, username: &str, except: Option<&str>) -> Result<Option<Usage>> {
+    Ok(conn
+        .query_row(
+            "SELECT username, COALESCE(SUM(size), 0), COUNT(track_audio.track_id), quota FROM users
+            LEFT JOIN track_audio ON track_audio.uploader = users.username AND track_audio.track_id IS NOT ?2
+            LEFT JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+            WHERE username = ?1
+            GROUP BY username",
+            params![username, except],
+            Usage::try_from_row,
+        )
+        .optional()?)
+}
+
+pub async fn info(db: &MyDatabase, track: &str) -> Result<Option<AudioInfo>> {
+    let track = track.to_string();
+    Ok(db
+        .run(move |conn| {
+            conn.query_row(
+                "SELECT track_audio.track_id, audio_blobs.hash, size, mime, uploaded, uploader, verified, corrupt
+                FROM track_audio JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+                WHERE track_audio.track_id = ?",
+                [track],
+                |row| {
+                    Ok(AudioInfo {
+                        track: row.get(0)?,
+                        hash: row.get(1)?,
+                        size: row.get(2)?,
+                        mime: row.get(3)?,
+                        uploaded: row.get(4)?,
+                        uploader: row.get(5)?,
+                        verified: row.get(6)?,
+                        corrupt: row.get(7)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await?)
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Audio Blobs", |rocket| async {
+        let config: AudioConfig = rocket.figment().extract_inner("audio").unwrap_or_default();
+
+        fs::create_dir_all(&config.staging).expect("Failed to create staging directory");
+        let storage = rocket.state::<Storage>().expect("Manage Storage").clone();
+
+        let blobs = Blobs {
+            config,
+            storage,
+            lock: Arc::new(Mutex::new(())),
+            verifying: Arc::new(Mutex::new(())),
+            verifications: Arc::new(sync::Mutex::new(Verifications::default())),
+            analyzing: Arc::new(Notify::new()),
+            splitting: Arc::new(sync::Mutex::new(HashMap::new())),
+        };
+
+        rocket
+            .manage(Uploads::new(blobs.clone()))
+            .manage(blobs)
+            .attach(AdHoc::on_liftoff("Upload Expiry", |rocket| {
+                Box::pin(async move {
+                    let connections = Connections::new(rocket);
+                    let uploads = rocket.state::<Uploads>().expect("Manage Uploads").clone();
+                    // often enough that nothing outlives its expiry by more than an hour
+                    let interval = uploads.config().session_expiry.clamp(1, 3600);
+
+                    let mut shutdown = rocket.shutdown();
+                    rocket::tokio::spawn(async move {
+                        loop {
+                            match connections.get().await {
+                                Some(db) => match uploads.expire(&db).await {
+                                    Ok(0) => {}
+                                    Ok(expired) => info!("Expired {expired} resumable uploads"),
+                                    Err(e) => error!("Failed to expire resumable uploads: {:?}", e),
+                                },
+                                None => error!(
+                                    "Failed to expire resumable uploads: no database connection"
+                                ),
+                            }
+
+                            select! {
+                                _ = sleep(Duration::from_secs(interval)) => {},
+                                _ = &mut shutdown => break,
+                            }
+                        }
+                    });
+                })
+            }))
+            .attach(AdHoc::on_liftoff("Audio Analysis", |rocket| {
+                Box::pin(async move {
+                    let blobs = rocket.state::<Blobs>().expect("Manage Blobs").clone();
+                    rocket::tokio::spawn(
+                        blobs.run_analysis(Connections::new(rocket), rocket.shutdown()),
+                    );
+                })
+            }))
+            .attach(AdHoc::on_liftoff("Audio Verification", |rocket| {
+                Box::pin(async move {
+                    let connections = Connections::new(rocket);
+                    let blobs = rocket.state::<Blobs>().expect("Manage Blobs").clone();
+
+                    let db = connections.get().await.expect("Mount Database");
+                    match blobs.migrate_legacy(&db).await {
+                        Ok(0) => {}
+                        Ok(migrated) => info!("Moved {migrated} audio files into blobs"),
+                        Err(e) => error!("Failed to move audio files into blobs: {:?}", e),
+                    }
+
+                    drop(db);
+
+                    let interval = blobs.config.verify_interval;
+                    if interval == 0 {
+                        return;
+                    }
+
+                    let mut shutdown = rocket.shutdown();
+                    rocket::tokio::spawn(async move {
+                        loop {
+                            select! {
+                                _ = sleep(Duration::from_secs(interval)) => {},
+                                _ = &mut shutdown => break,
+                            }
+
+                            let (_, job) = blobs.register();
+                            blobs.run_verification(&connections, job).await;
+                        }
+                    });
+                })
+            }))
+    })
+}
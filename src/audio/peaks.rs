@@ -0,0 +1,82 @@
+/// Peaks are cached for every 10ms of audio, requests for fewer are merged from them.
+pub const PER_SECOND: u32 = 100;
+
+/// Turns interleaved samples into the lowest & highest sample of every bucket, across all channels.
+///
+/// The peaks are 8-bit, scaled so that full scale is ±127, & interleaved as min, max, min, max...
+pub struct PeakMeter {
+    channels: usize,
+    /// Frames in a bucket.
+    bucket: usize,
+    frames: usize,
+    min: f32,
+    max: f32,
+    peaks: Vec<i8>,
+}
+
+fn quantize(sample: f32) -> i8 {
+    (sample.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+impl PeakMeter {
+    pub fn new(rate: u32, channels: usize) -> Self {
+        Self {
+            channels,
+            bucket: (rate / PER_SECOND).max(1) as usize,
+            frames: 0,
+            min: 0.0,
+            max: 0.0,
+            peaks: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for sample in frame {
+                self.min = self.min.min(*sample);
+                self.max = self.max.max(*sample);
+            }
+
+            self.frames += 1;
+            if self.frames == self.bucket {
+                self.push();
+            }
+        }
+    }
+
+    fn push(&mut self) {
+        self.peaks.push(quantize(self.min));
+        self.peaks.push(quantize(self.max));
+        self.frames = 0;
+        self.min = 0.0;
+        self.max = 0.0;
+    }
+
+    /// The peaks, as bytes for the cache.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.frames > 0 {
+            self.push();
+        }
+        self.peaks.into_iter().map(|peak| peak as u8).collect()
+    }
+}
+
+/// Merge cached peaks into `samples` (min, max) pairs, or all of them if there are fewer.
+pub fn resample(cached: &[u8], samples: usize) -> Vec<(i8, i8)> {
+    let pairs = cached
+        .chunks_exact(2)
+        .map(|pair| (pair[0] as i8, pair[1] as i8))
+        .collect::<Vec<(i8, i8)>>();
+    let count = samples.min(pairs.len());
+
+    (0..count)
+        .map(|i| {
+            // every sample covers at least one pair as there are at least as many pairs
+            pairs[i * pairs.len() / count..(i + 1) * pairs.len() / count]
+                .iter()
+                .fold((i8::MAX, i8::MIN), |(min, max), pair| {
+                    (min.min(pair.0), max.max(pair.1))
+                })
+        })
+        .collect()
+}
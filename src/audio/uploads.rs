@@ -0,0 +1,360 @@
+use rocket::{
+    http::Status,
+    tokio::{
+        fs::{self, File, OpenOptions},
+        io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom},
+    },
+};
+use rocket_sync_db_pools::rusqlite::{params, OptionalExtension, Row};
+use tempfile::TempPath;
+use uuid::Uuid;
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    api::data::{
+        audio::{UploadCreate, UploadSession},
+        events::ChangeEvent,
+    },
+    audio::{check_quota, AudioConfig, Blobs, Staged},
+    database::MyDatabase,
+    error::ApiError,
+    events::EventLog,
+    storage::{hash, Reader},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Resumable uploads, the bytes of each session are appended to `<staging>/<id>.partial`.
+///
+/// Sessions are kept in the database, so an upload can be resumed after a restart.
+#[derive(Clone)]
+pub struct Uploads {
+    blobs: Blobs,
+    /// Sessions being written to, a session takes one chunk at a time.
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Marks a session busy until it's dropped.
+struct Busy {
+    busy: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.busy.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn session_from_row(
+    row: &Row,
+    expiry: u64,
+) -> rocket_sync_db_pools::rusqlite::Result<UploadSession> {
+    let size: u64 = row.get("size")?;
+    let offset: u64 = row.get("received")?;
+    Ok(UploadSession {
+        id: row.get("id")?,
+        track: row.get("track_id")?,
+        size,
+        offset,
+        sha256: row.get("sha256")?,
+        mime: row.get("mime")?,
+        complete: offset == size,
+        expires: row.get::<&str, u64>("updated")? + expiry,
+    })
+}
+
+impl Uploads {
+    pub fn new(blobs: Blobs) -> Self {
+        Self {
+            blobs,
+            busy: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub fn config(&self) -> &AudioConfig {
+        &self.blobs.config
+    }
+
+    fn partial(&self, id: &str) -> PathBuf {
+        self.blobs.config.staging.join(id).with_extension("partial")
+    }
+
+    /// Start a session for a track's audio.
+    pub async fn create(
+        &self,
+        db: &MyDatabase,
+        username: &str,
+        upload: UploadCreate,
+    ) -> Result<UploadSession> {
+        if upload.size > self.blobs.config.limit.as_u64() {
+            Err(Status::PayloadTooLarge)?
+        }
+
+        let sha256 = upload.sha256.to_lowercase();
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            Err(ApiError::IoError((
+                Status::BadRequest,
+                "Invalid SHA-256".to_string(),
+            )))?
+        }
+
+        if !upload.mime.starts_with("audio/") {
+            Err(Status::UnsupportedMediaType)?
+        }
+
+        let id = Uuid::new_v4().to_string();
+        let username = username.to_string();
+        let expiry = self.blobs.config.session_expiry;
+        db.run(move |conn| -> Result<UploadSession> {
+                let exists: bool = conn.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?)",
+                    [&upload.track],
+                    |row| row.get(0),
+                )?;
+                if !exists {
+                    Err(Status::NotFound)?
+                }
+                // refused now rather than once it has all been sent
+                check_quota(conn, &username, &upload.track, upload.size)?;
+
+                Ok(conn.query_row(
+                    "INSERT INTO upload_sessions (id, username, track_id, size, sha256, mime) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    RETURNING *",
+                    params![id, username, upload.track, upload.size, sha256, upload.mime],
+                    |row| session_from_row(row, expiry),
+                )?)
+            })
+            .await
+    }
+
+    /// A session of the user's, unless it has expired.
+    pub async fn get(&self, db: &MyDatabase, username: &str, id: &str) -> Result<UploadSession> {
+        let username = username.to_string();
+        let id = id.to_string();
+        let expiry = self.blobs.config.session_expiry;
+        db.run(move |conn| {
+            conn.query_row(
+                "SELECT * FROM upload_sessions
+                WHERE id = ?1 AND username = ?2 AND updated + ?3 >= CAST(strftime('%s', 'now') AS INTEGER)",
+                params![id, username, expiry],
+                |row| session_from_row(row, expiry),
+            )
+            .optional()
+        })
+        .await?
+        .ok_or(Status::NotFound.into())
+    }
+
+    /// Append a chunk starting at `offset`, which has to be the session's current offset.
+    ///
+    /// What arrives before the connection drops is kept.
+    /// Once the whole file has arrived it's checked against the session's SHA-256 & stored as the track's audio, the returned event says how.
+    pub async fn append(
+        &self,
+        db: &MyDatabase,
+        log: EventLog,
+        username: &str,
+        id: &str,
+        offset: u64,
+        mut reader: Reader<'_>,
+    ) -> Result<(UploadSession, Option<ChangeEvent>)> {
+        // marked busy before the offset is read, so two chunks can't both pass the check
+        if !self.busy.lock().unwrap().insert(id.to_string()) {
+            Err(ApiError::IoError((
+                Status::Conflict,
+                "Upload Busy: a chunk is already being received".to_string(),
+            )))?
+        }
+        let _busy = Busy {
+            busy: self.busy.clone(),
+            id: id.to_string(),
+        };
+
+        let session = self.get(db, username, id).await?;
+        if offset != session.offset {
+            Err(ApiError::IoError((
+                Status::Conflict,
+                format!("Offset Mismatch: the upload is at {}", session.offset),
+            )))?
+        }
+
+        // the file is created by the first chunk
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.partial(id))
+            .await?;
+        // anything past the recorded offset is from a write that was never recorded
+        file.set_len(offset).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+
+        let remaining = session.size - offset;
+        let mut reader = reader.as_mut().take(remaining + 1);
+        let mut written = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        let received = loop {
+            match reader.read(&mut buffer).await {
+                Ok(0) => break Ok(()),
+                Ok(read) if written + read as u64 > remaining => {
+                    break Err(ApiError::Status(Status::PayloadTooLarge))
+                }
+                Ok(read) => {
+                    if let Err(e) = file.write_all(&buffer[..read]).await {
+                        break Err(e.into());
+                    }
+                    written += read as u64;
+                }
+                Err(e) => break Err(e.into()),
+            }
+        };
+        file.flush().await?;
+        file.set_len(offset + written).await?;
+        drop(file);
+
+        let id_clone = id.to_string();
+        let expiry = self.blobs.config.session_expiry;
+        let session = db
+            .run(move |conn| {
+                conn.query_row(
+                    "UPDATE upload_sessions SET received = ?2, updated = strftime('%s', 'now') WHERE id = ?1
+                    RETURNING *",
+                    params![id_clone, offset + written],
+                    |row| session_from_row(row, expiry),
+                )
+            })
+            .await?;
+        received?;
+
+        if !session.complete {
+            return Ok((session, None));
+        }
+
+        let event = self.finish(db, log, username, &session).await?;
+        Ok((session, Some(event)))
+    }
+
+    /// Check the whole file against its hash & store it, the session is over either way unless storing fails.
+    async fn finish(
+        &self,
+        db: &MyDatabase,
+        log: EventLog,
+        username: &str,
+        session: &UploadSession,
+    ) -> Result<ChangeEvent> {
+        let path = self.partial(&session.id);
+        let (size, sha256) = hash(Box::pin(File::open(&path).await?)).await?;
+        let staged = Staged {
+            file: TempPath::try_from_path(path)?,
+            size,
+            hash: sha256,
+        };
+
+        if staged.size != session.size || staged.hash != session.sha256 {
+            self.delete(db, &session.id).await?;
+            Err(ApiError::IoError((
+                Status::UnprocessableEntity,
+                format!(
+                    "Hash Mismatch: the file received has SHA-256 {}",
+                    staged.hash
+                ),
+            )))?
+        }
+
+        match self
+            .blobs
+            .upload(
+                db,
+                log,
+                &session.track,
+                &staged,
+                session.mime.clone(),
+                username,
+            )
+            .await
+        {
+            Ok(event) => {
+                self.delete(db, &session.id).await?;
+                Ok(event)
+            }
+            Err(e) => {
+                // an empty chunk at the end retries it
+                staged.file.keep().map_err(|e| e.error)?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn delete(&self, db: &MyDatabase, id: &str) -> Result<()> {
+        let id_clone = id.to_string();
+        db.run(move |conn| conn.execute("DELETE FROM upload_sessions WHERE id = ?", [id_clone]))
+            .await?;
+
+        match fs::remove_file(self.partial(id)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)?,
+            _ => Ok(()),
+        }
+    }
+
+    /// Abandon a session & what it has received.
+    pub async fn cancel(&self, db: &MyDatabase, username: &str, id: &str) -> Result<()> {
+        self.get(db, username, id).await?;
+        if self.busy.lock().unwrap().contains(id) {
+            Err(ApiError::IoError((
+                Status::Conflict,
+                "Upload Busy: a chunk is being received".to_string(),
+            )))?
+        }
+
+        self.delete(db, id).await
+    }
+
+    /// Delete expired sessions, along with staged files nothing has touched for as long.
+    pub async fn expire(&self, db: &MyDatabase) -> Result<usize> {
+        let expiry = self.blobs.config.session_expiry;
+        let (expired, live) = db
+            .run(move |conn| -> Result<(Vec<String>, HashSet<String>)> {
+                let expired = conn
+                    .prepare(
+                        "DELETE FROM upload_sessions WHERE updated + ? < CAST(strftime('%s', 'now') AS INTEGER) RETURNING id",
+                    )?
+                    .query_map([expiry], |row| row.get(0))?
+                    .map(|v| v.map_err(ApiError::from))
+                    .collect::<Result<Vec<String>>>()?;
+                let live = conn
+                    .prepare("SELECT id FROM upload_sessions")?
+                    .query_map([], |row| row.get(0))?
+                    .map(|v| v.map_err(ApiError::from))
+                    .collect::<Result<HashSet<String>>>()?;
+                Ok((expired, live))
+            })
+            .await?;
+
+        for id in expired.iter() {
+            self.delete(db, id).await?;
+        }
+
+        // left behind by a crash or a session whose track was deleted
+        let stale = SystemTime::now() - Duration::from_secs(expiry);
+        let mut entries = fs::read_dir(&self.blobs.config.staging).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_live = path
+                .file_stem()
+                .is_some_and(|stem| live.contains(stem.to_string_lossy().as_ref()));
+            let modified = entry.metadata().await?.modified()?;
+            if !is_live && modified < stale {
+                fs::remove_file(path).await?;
+            }
+        }
+
+        Ok(expired.len())
+    }
+}
@@ -0,0 +1,322 @@
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    serde::{json, Deserialize},
+    tokio::{select, sync::Mutex, time::sleep},
+};
+use rocket_sync_db_pools::rusqlite::{
+    self,
+    backup::{self, Progress},
+    params, Connection, DatabaseName, OpenFlags,
+};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    api::data::backups::{Backup, BackupAudio, BackupManifest},
+    database::{Connections, MyDatabase},
+    error::ApiError,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `backups` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct BackupsConfig {
+    /// Where snapshots & their manifests are written.
+    pub dir: PathBuf,
+    /// Seconds between scheduled snapshots, `0` disables them.
+    pub interval: u64,
+    /// Take a snapshot when the server starts.
+    pub on_startup: bool,
+    /// The number of snapshots kept, older ones are deleted after every new snapshot.
+    pub keep: usize,
+    /// Write a manifest of the audio files with scheduled snapshots.
+    pub manifest: bool,
+}
+
+impl Default for BackupsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("./database/backups"),
+            interval: 0,
+            on_startup: false,
+            keep: 7,
+            manifest: false,
+        }
+    }
+}
+
+/// Takes, lists & restores snapshots, one at a time.
+#[derive(Clone)]
+pub struct Backups {
+    config: BackupsConfig,
+    lock: Arc<Mutex<()>>,
+}
+
+impl Backups {
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        self.config.dir.join(id).with_extension("sqlite")
+    }
+
+    fn manifest_path(&self, id: &str) -> PathBuf {
+        self.config.dir.join(id).with_extension("json")
+    }
+
+    /// Every snapshot, oldest first.
+    pub fn list(&self) -> Result<Vec<Backup>> {
+        let mut backups = fs::read_dir(&self.config.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|v| v == "sqlite"))
+            .filter_map(|entry| {
+                let id = entry.path().file_stem()?.to_str()?.to_string();
+                Some(Backup {
+                    size: entry.metadata().ok()?.len(),
+                    manifest: self.manifest_path(&id).exists(),
+                    id,
+                })
+            })
+            .collect::<Vec<Backup>>();
+
+        // ids are timestamps, so they sort by age
+        backups.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(backups)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Backup> {
+        self.list()?
+            .into_iter()
+            .find(|backup| backup.id == id)
+            .ok_or(Status::NotFound.into())
+    }
+
+    pub fn manifest(&self, id: &str) -> Result<BackupManifest> {
+        if !self.get(id)?.manifest {
+            Err(Status::NotFound)?
+        }
+
+        json::from_str(&fs::read_to_string(self.manifest_path(id))?)
+            .map_err(|_| ApiError::Status(Status::InternalServerError))
+    }
+
+    /// Take a snapshot, then delete any beyond `keep`.
+    pub async fn create(&self, db: &MyDatabase, manifest: bool) -> Result<Backup> {
+        let _lock = self.lock.lock().await;
+        let backup = self.snapshot(db, manifest).await?;
+        self.prune()?;
+        Ok(backup)
+    }
+
+    /// Take a snapshot while the server keeps running.
+    ///
+    /// The snapshot is written to a `.partial` file first, so a failed backup never looks like a snapshot.
+    async fn snapshot(&self, db: &MyDatabase, manifest: bool) -> Result<Backup> {
+        let dir = self.config.dir.clone();
+        let id = db
+            .run(move |conn| -> Result<String> {
+                let id: String = conn.query_row(
+                    "SELECT strftime('%Y%m%dT%H%M%S', 'now') || substr(strftime('%f', 'now'), 4)",
+                    [],
+                    |row| row.get(0),
+                )?;
+
+                fs::create_dir_all(&dir)?;
+                let partial = dir.join(&id).with_extension("partial");
+                let mut snapshot = Connection::open(&partial)?;
+                // copy a few pages at a time so writers aren't blocked for the whole backup
+                backup::Backup::new(&conn.0, &mut snapshot)?.run_to_completion(
+                    100,
+                    Duration::from_millis(10),
+                    None,
+                )?;
+                drop(snapshot);
+                fs::rename(&partial, dir.join(&id).with_extension("sqlite"))?;
+
+                Ok(id)
+            })
+            .await?;
+
+        if manifest {
+            let audio = db
+                .run(|conn| -> Result<Vec<BackupAudio>> {
+                    conn.prepare(
+                        "SELECT track_id, size, audio_blobs.hash FROM track_audio
+                        JOIN audio_blobs ON track_audio.hash = audio_blobs.hash
+                        ORDER BY track_id",
+                    )?
+                    .query_map([], |row| {
+                        Ok(BackupAudio {
+                            track: row.get(0)?,
+                            size: row.get(1)?,
+                            sha256: row.get(2)?,
+                        })
+                    })?
+                    .map(|v| v.map_err(ApiError::from))
+                    .collect()
+                })
+                .await?;
+
+            let manifest = json::to_pretty_string(&BackupManifest {
+                backup: id.clone(),
+                audio,
+            })
+            .map_err(|_| ApiError::Status(Status::InternalServerError))?;
+            fs::write(self.manifest_path(&id), manifest)?;
+        }
+
+        self.get(&id)
+    }
+
+    /// Delete the oldest snapshots (& their manifests) beyond `keep`.
+    fn prune(&self) -> Result<()> {
+        let backups = self.list()?;
+        for backup in backups
+            .iter()
+            .take(backups.len().saturating_sub(self.config.keep))
+        {
+            fs::remove_file(self.snapshot_path(&backup.id))?;
+            if backup.manifest {
+                fs::remove_file(self.manifest_path(&backup.id))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the database with a snapshot.
+    ///
+    /// The snapshot has to pass `PRAGMA integrity_check` first, & the current database is backed up before it's replaced.
+    /// Snapshots from older versions are migrated afterwards.
+    pub async fn restore(&self, db: &MyDatabase, id: &str) -> Result<Backup> {
+        let _lock = self.lock.lock().await;
+        let path = self.snapshot_path(&self.get(id)?.id);
+        validate(&path)?;
+
+        // a way back if the snapshot wasn't the one they wanted
+        let previous = self.snapshot(db, false).await?;
+
+        db.run(move |conn| -> Result<()> {
+            // event ids must keep increasing, or subscribers would skip new events
+            let last_event: u64 =
+                conn.query_row("SELECT COALESCE(MAX(id), 0) FROM events", [], |row| {
+                    row.get(0)
+                })?;
+
+            conn.0
+                .restore(DatabaseName::Main, &path, None::<fn(Progress)>)?;
+
+            // a snapshot taken before any event has no row to update
+            conn.execute(
+                "INSERT INTO sqlite_sequence (name, seq) SELECT 'events', ?1
+                WHERE NOT EXISTS(SELECT 1 FROM sqlite_sequence WHERE name = 'events')",
+                params![last_event],
+            )?;
+            conn.execute(
+                "UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = 'events'",
+                params![last_event],
+            )?;
+            Ok(())
+        })
+        .await?;
+
+        db.migrations().await?;
+        // only now, pruning could have removed the snapshot being restored
+        self.prune()?;
+        Ok(previous)
+    }
+}
+
+/// Check a snapshot is an intact tuna database.
+fn validate(path: &Path) -> Result<()> {
+    let invalid = |message: String| {
+        ApiError::RusqliteError((
+            Status::UnprocessableEntity,
+            format!("Invalid Backup: {message}"),
+        ))
+    };
+
+    // a file that isn't a database at all fails to be read rather than the check
+    let check = || -> rusqlite::Result<(Vec<String>, bool)> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let problems = conn
+            .prepare("PRAGMA integrity_check")?
+            .query_map([], |row| row.get::<usize, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        let migrated = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'refinery_schema_history')",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok((problems, migrated))
+    };
+
+    let (problems, migrated) = check().map_err(|e| invalid(e.to_string()))?;
+    if problems != ["ok"] {
+        Err(invalid(problems.join(", ")))?
+    }
+
+    if !migrated {
+        Err(invalid("not a tuna database".to_string()))?
+    }
+
+    Ok(())
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Backups", |rocket| async {
+        let config: BackupsConfig = rocket
+            .figment()
+            .extract_inner("backups")
+            .unwrap_or_default();
+
+        fs::create_dir_all(&config.dir).expect("Failed to create backup directory");
+
+        rocket
+            .manage(Backups {
+                config,
+                lock: Arc::new(Mutex::new(())),
+            })
+            .attach(AdHoc::on_liftoff("Scheduled Backups", |rocket| {
+                Box::pin(async move {
+                    let connections = Connections::new(rocket);
+                    let backups = rocket.state::<Backups>().expect("Manage Backups").clone();
+                    let config = backups.config.clone();
+
+                    if config.on_startup {
+                        let db = connections.get().await.expect("Mount Database");
+                        if let Err(e) = backups.create(&db, config.manifest).await {
+                            error!("Failed to backup the database: {:?}", e);
+                        }
+                    }
+
+                    if config.interval == 0 {
+                        return;
+                    }
+
+                    let mut shutdown = rocket.shutdown();
+                    rocket::tokio::spawn(async move {
+                        loop {
+                            select! {
+                                _ = sleep(Duration::from_secs(config.interval)) => {},
+                                _ = &mut shutdown => break,
+                            }
+
+                            let Some(db) = connections.get().await else {
+                                error!("Failed to backup the database: no database connection");
+                                continue;
+                            };
+                            if let Err(e) = backups.create(&db, config.manifest).await {
+                                error!("Failed to backup the database: {:?}", e);
+                            }
+                        }
+                    });
+                })
+            }))
+    })
+}
@@ -0,0 +1,923 @@
+use rocket::{
+    data::{ByteUnit, ToByteUnit},
+    fairing::AdHoc,
+    http::Status,
+    serde::{json, Deserialize},
+    tokio::{
+        fs,
+        io::{AsyncBufReadExt, BufReader},
+        runtime::Handle,
+        task::spawn_blocking,
+    },
+};
+use rocket_sync_db_pools::rusqlite::{self, params, types::Type, Row, Transaction};
+
+use tokio_util::io::SyncIoBridge;
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    api::data::{
+        albums::Album,
+        artists::Artist,
+        catalog::{CatalogRecord, CatalogReport, Conflict},
+        events::{Action, Resource},
+        tracks::Track,
+    },
+    audio::{self, Blobs},
+    database::{Connections, MyDatabase},
+    error::ApiError,
+    events::Events,
+    external_ids, genres, lyrics,
+    storage::{hash, Storage},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `catalog` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct CatalogConfig {
+    /// The number of records applied in each transaction when importing.
+    pub batch: usize,
+    /// The maximum size of an imported catalog, a bundle's before & after it's extracted.
+    pub limit: ByteUnit,
+}
+
+impl Default for CatalogConfig {
+    fn default() -> Self {
+        Self {
+            batch: 500,
+            limit: 1.gibibytes(),
+        }
+    }
+}
+
+/// The tables of a catalog, in the order they are exported so every link follows what it links.
+#[derive(Debug, Clone, Copy)]
+pub enum Table {
+    Genres,
+    GenreParents,
+    GenreAliases,
+    Artists,
+    ArtistAliases,
+    ArtistRelations,
+    Albums,
+    Tracks,
+    ExternalIds,
+    ArtistGenres,
+    ArtistAlbums,
+    AlbumGenres,
+    AlbumTracks,
+    TrackGenres,
+    Lyrics,
+    Audio,
+}
+
+pub const TABLES: [Table; 16] = [
+    Table::Genres,
+    Table::GenreParents,
+    Table::GenreAliases,
+    Table::Artists,
+    Table::ArtistAliases,
+    Table::ArtistRelations,
+    Table::Albums,
+    Table::Tracks,
+    Table::ExternalIds,
+    Table::ArtistGenres,
+    Table::ArtistAlbums,
+    Table::AlbumGenres,
+    Table::AlbumTracks,
+    Table::TrackGenres,
+    Table::Lyrics,
+    Table::Audio,
+];
+
+impl Table {
+    fn sql(&self) -> &'static str {
+        match self {
+            Table::Genres => "SELECT rowid, id FROM genres WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::GenreParents => "SELECT rowid, genre_id, parent_id FROM genre_parents WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::GenreAliases => "SELECT rowid, alias, genre_id FROM genre_aliases WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::Artists => "SELECT rowid, id, name, bio, sort_name FROM artists WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::ArtistAliases => "SELECT rowid, artist_id, name, kind, locale FROM artist_aliases WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::ArtistRelations => "SELECT rowid, artist_id, related_id, kind FROM artist_relations WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::Albums => "SELECT rowid, id, name, release, kind, label, catalog_number, edition_of FROM albums WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::Tracks => "SELECT rowid, id, name, release, duration, lyrics, EXISTS(SELECT 1 FROM track_audio WHERE track_id = tracks.id) FROM tracks WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::ExternalIds => "SELECT rowid, source, value, artist_id, album_id, track_id FROM external_ids WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::ArtistGenres => "SELECT rowid, artist_id, genre_id FROM artist_genres WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::ArtistAlbums => "SELECT rowid, artist_id, album_id FROM artist_albums WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::AlbumGenres => "SELECT rowid, album_id, genre_id FROM album_genres WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::AlbumTracks => "SELECT rowid, album_id, track_id FROM album_tracks WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::TrackGenres => "SELECT rowid, track_id, genre_id FROM track_genres WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+            // a record per language, paged by its first line
+            Table::Lyrics => "SELECT rowid, track_id, language, (SELECT json_group_array(json_object('time', time, 'text', text)) FROM (SELECT time, text FROM track_lyrics AS lines WHERE lines.track_id = track_lyrics.track_id AND lines.language = track_lyrics.language ORDER BY line)) FROM track_lyrics WHERE line = 0 AND rowid > ?1 ORDER BY rowid LIMIT ?2",
+            Table::Audio => "SELECT track_audio.rowid, track_id, size, audio_blobs.hash FROM track_audio JOIN audio_blobs ON track_audio.hash = audio_blobs.hash WHERE track_audio.rowid > ?1 ORDER BY track_audio.rowid LIMIT ?2",
+        }
+    }
+
+    fn record(&self, row: &Row) -> rocket_sync_db_pools::rusqlite::Result<CatalogRecord> {
+        Ok(match self {
+            Table::Genres => CatalogRecord::Genre { id: row.get(1)? },
+            Table::GenreParents => CatalogRecord::GenreParent {
+                genre: row.get(1)?,
+                parent: row.get(2)?,
+            },
+            Table::GenreAliases => CatalogRecord::GenreAlias {
+                alias: row.get(1)?,
+                genre: row.get(2)?,
+            },
+            Table::Artists => CatalogRecord::Artist(Artist {
+                id: row.get(1)?,
+                name: row.get(2)?,
+                sort_name: row.get(4)?,
+                genres: Vec::new(),
+                bio: row.get(3)?,
+            }),
+            Table::ArtistAliases => CatalogRecord::ArtistAlias {
+                artist: row.get(1)?,
+                name: row.get(2)?,
+                kind: row.get::<_, String>(3)?.parse().unwrap_or_default(),
+                locale: row.get(4)?,
+            },
+            Table::ArtistRelations => CatalogRecord::ArtistRelation {
+                artist: row.get(1)?,
+                related: row.get(2)?,
+                kind: row.get::<_, String>(3)?.parse().map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(3, "kind".to_string(), Type::Text)
+                })?,
+            },
+            Table::Albums => CatalogRecord::Album(Album {
+                id: row.get(1)?,
+                name: row.get(2)?,
+                release: row.get(3)?,
+                kind: row.get::<_, String>(4)?.parse().unwrap_or_default(),
+                label: row.get(5)?,
+                catalog_number: row.get(6)?,
+                edition_of: row.get(7)?,
+                editions: Vec::new(),
+                artists: Vec::new(),
+                tracks: Vec::new(),
+                genres: Vec::new(),
+                loudness: None,
+            }),
+            Table::Tracks => CatalogRecord::Track(Track {
+                id: row.get(1)?,
+                name: row.get(2)?,
+                release: row.get(3)?,
+                duration: row.get(4)?,
+                albums: Vec::new(),
+                artists: Vec::new(),
+                lyrics: row.get(5)?,
+                genres: Vec::new(),
+                has_audio: row.get(6)?,
+                loudness: None,
+            }),
+            Table::ExternalIds => CatalogRecord::ExternalId {
+                source: row.get::<_, String>(1)?.parse().map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(1, "source".to_string(), Type::Text)
+                })?,
+                value: row.get(2)?,
+                artist: row.get(3)?,
+                album: row.get(4)?,
+                track: row.get(5)?,
+            },
+            Table::ArtistGenres => CatalogRecord::ArtistGenre {
+                artist: row.get(1)?,
+                genre: row.get(2)?,
+            },
+            Table::ArtistAlbums => CatalogRecord::ArtistAlbum {
+                artist: row.get(1)?,
+                album: row.get(2)?,
+            },
+            Table::AlbumGenres => CatalogRecord::AlbumGenre {
+                album: row.get(1)?,
+                genre: row.get(2)?,
+            },
+            Table::AlbumTracks => CatalogRecord::AlbumTrack {
+                album: row.get(1)?,
+                track: row.get(2)?,
+            },
+            Table::TrackGenres => CatalogRecord::TrackGenre {
+                track: row.get(1)?,
+                genre: row.get(2)?,
+            },
+            Table::Lyrics => CatalogRecord::Lyrics {
+                track: row.get(1)?,
+                language: row.get(2)?,
+                lines: json::from_str(&row.get::<usize, String>(3)?).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e))
+                })?,
+            },
+            Table::Audio => CatalogRecord::Audio {
+                track: row.get(1)?,
+                size: row.get(2)?,
+                sha256: row.get(3)?,
+            },
+        })
+    }
+}
+
+/// Read up to `limit` records of a table after `after`, along with the rowid to continue from.
+///
+/// The rowid is `None` once the table has been read.
+/// A connection is only taken for the page, so a slow reader doesn't hold one.
+pub async fn read_page(
+    connections: &Connections,
+    table: Table,
+    after: i64,
+    limit: u32,
+) -> Result<(Vec<CatalogRecord>, Option<i64>)> {
+    let db = connections
+        .get()
+        .await
+        .ok_or(ApiError::Status(Status::ServiceUnavailable))?;
+    db.run(move |conn| -> Result<(Vec<CatalogRecord>, Option<i64>)> {
+        let mut last = None;
+        let records = conn
+            .prepare(table.sql())?
+            .query_map(params![after, limit], |row| {
+                last = Some(row.get::<usize, i64>(0)?);
+                table.record(row)
+            })?
+            .map(|v| v.map_err(ApiError::from))
+            .collect::<Result<Vec<CatalogRecord>>>()?;
+        Ok((records, last))
+    })
+    .await
+}
+
+/// Write the whole catalog to a tar, as `catalog.ndjson` followed by `audio/<track>.mp3` for each audio record.
+///
+/// Nothing is returned if reading the catalog fails, so a tar is always complete.
+pub async fn write_tar(connections: &Connections, storage: &Storage, file: File) -> Result<()> {
+    let mut catalog = tempfile::NamedTempFile::new()?;
+    let mut tracks = Vec::new();
+    let mut records = 0;
+
+    for table in TABLES {
+        let mut after = 0;
+        while let (page, Some(last)) = read_page(connections, table, after, 500).await? {
+            after = last;
+            for record in page {
+                if let CatalogRecord::Audio { track, sha256, .. } = &record {
+                    tracks.push((track.clone(), sha256.clone()));
+                }
+                writeln!(catalog, "{}", serialize(&record)?)?;
+                records += 1;
+            }
+        }
+    }
+    writeln!(catalog, "{}", serialize(&CatalogRecord::End { records })?)?;
+
+    // the tar crate is blocking, the audio is streamed from the storage into it
+    let audio = storage.audio.clone();
+    let handle = Handle::current();
+    spawn_blocking(move || -> Result<()> {
+        let mut builder = tar::Builder::new(file);
+        builder.append_path_with_name(catalog.path(), "catalog.ndjson")?;
+        for (track, hash) in tracks {
+            let Some((size, reader)) = handle.block_on(audio.open(&Storage::blob_key(&hash)))?
+            else {
+                // deleted since it was exported
+                continue;
+            };
+            let mut header = tar::Header::new_gnu();
+            header.set_size(size);
+            header.set_mode(0o644);
+            builder.append_data(
+                &mut header,
+                format!("audio/{track}.mp3"),
+                SyncIoBridge::new_with_handle(reader, handle.clone()),
+            )?;
+        }
+        builder.finish()?;
+        Ok(())
+    })
+    .await
+    .map_err(|_| ApiError::Status(Status::InternalServerError))?
+}
+
+pub fn serialize(record: &CatalogRecord) -> Result<String> {
+    json::to_string(record).map_err(|_| ApiError::Status(Status::InternalServerError))
+}
+
+/// Apply a change to an existing row according to the conflict policy.
+fn conflict(policy: Conflict, what: &str) -> Result<bool> {
+    match policy {
+        Conflict::Skip => Ok(false),
+        Conflict::Overwrite => Ok(true),
+        Conflict::Fail => Err(ApiError::RusqliteError((
+            Status::Conflict,
+            format!("{what} already exists"),
+        ))),
+    }
+}
+
+fn exists(
+    tx: &Transaction,
+    sql: &str,
+    params: impl rocket_sync_db_pools::rusqlite::Params,
+) -> Result<bool> {
+    Ok(tx.query_row(sql, params, |row| row.get::<usize, bool>(0))?)
+}
+
+/// What a batch did, the audio it replaced is released once it has been committed.
+#[derive(Default)]
+struct Applied {
+    report: CatalogReport,
+    changes: Vec<(Resource, Action, String)>,
+    replaced: Vec<String>,
+}
+
+impl Applied {
+    fn count(&mut self, action: Option<Action>) {
+        match action {
+            Some(Action::Create) => self.report.created += 1,
+            Some(_) => self.report.updated += 1,
+            None => self.report.skipped += 1,
+        }
+    }
+
+    fn entity(&mut self, resource: Resource, id: String, action: Option<Action>) {
+        self.count(action);
+        if let Some(action) = action {
+            self.changes.push((resource, action, id));
+        }
+    }
+
+    fn link(&mut self, created: bool) {
+        match created {
+            true => self.report.created += 1,
+            false => self.report.skipped += 1,
+        }
+    }
+}
+
+/// Insert a link row, links have nothing to overwrite.
+fn link(
+    tx: &Transaction,
+    policy: Conflict,
+    table: &str,
+    columns: (&str, &str),
+    values: (&str, &str),
+) -> Result<bool> {
+    let exists_sql = format!(
+        "SELECT EXISTS(SELECT 1 FROM {table} WHERE {} = ?1 AND {} = ?2)",
+        columns.0, columns.1
+    );
+    if exists(tx, &exists_sql, params![values.0, values.1])? {
+        conflict(policy, &format!("{table} {} {}", values.0, values.1))?;
+        return Ok(false);
+    }
+
+    tx.execute(
+        &format!(
+            "INSERT INTO {table} ({}, {}) VALUES (?1, ?2)",
+            columns.0, columns.1
+        ),
+        params![values.0, values.1],
+    )?;
+    Ok(true)
+}
+
+/// The genre a link is to, a catalog can tag with an alias that came before it.
+fn genre_id(tx: &Transaction, genre: String) -> Result<String> {
+    Ok(genres::resolve(tx, &genre)?.unwrap_or(genre))
+}
+
+/// `stored` holds the hashes of the bundled audio of the batch that was found intact & stored.
+fn apply(
+    tx: &Transaction,
+    record: CatalogRecord,
+    policy: Conflict,
+    bundle: bool,
+    stored: &HashSet<String>,
+    applied: &mut Applied,
+) -> Result<()> {
+    match record {
+        // checked before anything is applied
+        CatalogRecord::End { .. } => {}
+        CatalogRecord::Genre { id } => {
+            let action = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM genres WHERE id = ?)",
+                [&id],
+            )? {
+                // a genre is only its id
+                true => conflict(policy, &format!("genre {id}"))?.then_some(Action::Update),
+                false => {
+                    tx.execute("INSERT INTO genres (id) VALUES (?)", [&id])?;
+                    Some(Action::Create)
+                }
+            };
+            applied.entity(Resource::Genre, id, action);
+        }
+        CatalogRecord::GenreParent { genre, parent } => {
+            let created = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM genre_parents WHERE genre_id = ?1 AND parent_id = ?2)",
+                params![genre, parent],
+            )? {
+                true => {
+                    conflict(policy, &format!("genre_parents {genre} {parent}"))?;
+                    false
+                }
+                false => {
+                    if genres::cycles(tx, &genre, &parent)? {
+                        Err(ApiError::RusqliteError((
+                            Status::Conflict,
+                            format!("Genre Cycle: {genre} {parent}"),
+                        )))?
+                    }
+                    tx.execute(
+                        "INSERT INTO genre_parents (genre_id, parent_id) VALUES (?1, ?2)",
+                        params![genre, parent],
+                    )?;
+                    true
+                }
+            };
+            applied.link(created);
+        }
+        CatalogRecord::GenreAlias { alias, genre } => {
+            // an alias of another genre or a genre's own name is left as it is, like a link
+            let created = match genres::taken(tx, &alias)? {
+                true => {
+                    conflict(policy, &format!("genre_aliases {alias}"))?;
+                    false
+                }
+                false => {
+                    tx.execute(
+                        "INSERT INTO genre_aliases (alias, genre_id) VALUES (?1, ?2)",
+                        params![alias, genre],
+                    )?;
+                    true
+                }
+            };
+            applied.link(created);
+        }
+        CatalogRecord::Artist(artist) => {
+            let action = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM artists WHERE id = ?)",
+                [&artist.id],
+            )? {
+                true => match conflict(policy, &format!("artist {}", artist.id))? {
+                    true => {
+                        tx.execute(
+                            "UPDATE artists SET name = ?2, sort_name = ?3, bio = ?4 WHERE id = ?1",
+                            params![artist.id, artist.name, artist.sort_name, artist.bio],
+                        )?;
+                        Some(Action::Update)
+                    }
+                    false => None,
+                },
+                false => {
+                    tx.execute(
+                        "INSERT INTO artists (id, name, sort_name, bio) VALUES (?1, ?2, ?3, ?4)",
+                        params![artist.id, artist.name, artist.sort_name, artist.bio],
+                    )?;
+                    Some(Action::Create)
+                }
+            };
+            applied.entity(Resource::Artist, artist.id, action);
+        }
+        CatalogRecord::ArtistAlias {
+            artist,
+            name,
+            kind,
+            locale,
+        } => {
+            let created = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM artist_aliases WHERE artist_id = ?1 AND name = ?2)",
+                params![artist, name],
+            )? {
+                true => {
+                    conflict(policy, &format!("artist_aliases {artist} {name}"))?;
+                    false
+                }
+                false => {
+                    tx.execute(
+                        "INSERT INTO artist_aliases (artist_id, name, kind, locale) VALUES (?1, ?2, ?3, ?4)",
+                        params![artist, name, <&'static str>::from(kind), locale],
+                    )?;
+                    true
+                }
+            };
+            applied.link(created);
+        }
+        CatalogRecord::ArtistRelation {
+            artist,
+            related,
+            kind,
+        } => {
+            if artist == related {
+                Err(ApiError::IoError((
+                    Status::BadRequest,
+                    format!("Artist Related To Itself: {artist}"),
+                )))?
+            }
+
+            // being the same person goes both ways
+            let created = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM artist_relations WHERE artist_id = ?1 AND related_id = ?2 AND kind = ?3)
+                OR (?3 = 'same_person_as' AND EXISTS(SELECT 1 FROM artist_relations WHERE artist_id = ?2 AND related_id = ?1 AND kind = ?3))",
+                params![artist, related, <&'static str>::from(kind)],
+            )? {
+                true => {
+                    conflict(policy, &format!("artist_relations {artist} {related} {kind}"))?;
+                    false
+                }
+                false => {
+                    tx.execute(
+                        "INSERT INTO artist_relations (artist_id, related_id, kind) VALUES (?1, ?2, ?3)",
+                        params![artist, related, <&'static str>::from(kind)],
+                    )?;
+                    true
+                }
+            };
+            applied.link(created);
+        }
+        CatalogRecord::Album(album) => {
+            let action = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM albums WHERE id = ?)",
+                [&album.id],
+            )? {
+                true => match conflict(policy, &format!("album {}", album.id))? {
+                    true => {
+                        tx.execute(
+                            "UPDATE albums SET name = ?2, release = ?3, kind = ?4, label = ?5, catalog_number = ?6, edition_of = ?7 WHERE id = ?1",
+                            params![
+                                album.id,
+                                album.name,
+                                album.release,
+                                <&'static str>::from(album.kind),
+                                album.label,
+                                album.catalog_number,
+                                album.edition_of,
+                            ],
+                        )?;
+                        Some(Action::Update)
+                    }
+                    false => None,
+                },
+                false => {
+                    tx.execute(
+                        "INSERT INTO albums (id, name, release, kind, label, catalog_number, edition_of) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            album.id,
+                            album.name,
+                            album.release,
+                            <&'static str>::from(album.kind),
+                            album.label,
+                            album.catalog_number,
+                            album.edition_of,
+                        ],
+                    )?;
+                    Some(Action::Create)
+                }
+            };
+            applied.entity(Resource::Album, album.id, action);
+        }
+        CatalogRecord::Track(track) => {
+            let action = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?)",
+                [&track.id],
+            )? {
+                true => match conflict(policy, &format!("track {}", track.id))? {
+                    true => {
+                        tx.execute(
+                            "UPDATE tracks SET name = ?2, release = ?3, lyrics = ?4 WHERE id = ?1",
+                            params![track.id, track.name, track.release, track.lyrics],
+                        )?;
+                        Some(Action::Update)
+                    }
+                    false => None,
+                },
+                false => {
+                    tx.execute(
+                        "INSERT INTO tracks (id, name, release, lyrics) VALUES (?1, ?2, ?3, ?4)",
+                        params![track.id, track.name, track.release, track.lyrics],
+                    )?;
+                    Some(Action::Create)
+                }
+            };
+            applied.entity(Resource::Track, track.id, action);
+        }
+        CatalogRecord::ExternalId {
+            source,
+            value,
+            artist,
+            album,
+            track,
+        } => {
+            let (resource, column, id) = match (artist, album, track) {
+                (Some(id), None, None) => (Resource::Artist, "artist_id", id),
+                (None, Some(id), None) => (Resource::Album, "album_id", id),
+                (None, None, Some(id)) => (Resource::Track, "track_id", id),
+                _ => Err(ApiError::IoError((
+                    Status::BadRequest,
+                    format!("External Id Needs One Artist, Album Or Track: {source} {value}"),
+                )))?,
+            };
+            let Some(value) = external_ids::accepts(source, resource)
+                .then(|| external_ids::normalize(source, &value))
+                .flatten()
+            else {
+                Err(ApiError::IoError((
+                    Status::BadRequest,
+                    format!("Invalid External Id: {source} {value}"),
+                )))?
+            };
+
+            // an id can only belong to one artist, one album & one track, one that's taken is left as it is
+            let created = match exists(
+                tx,
+                &format!("SELECT EXISTS(SELECT 1 FROM external_ids WHERE source = ?1 AND value = ?2 AND {column} IS NOT NULL)"),
+                params![<&'static str>::from(source), value],
+            )? {
+                true => {
+                    conflict(policy, &format!("external_ids {source} {value}"))?;
+                    false
+                }
+                false => {
+                    tx.execute(
+                        &format!("INSERT INTO external_ids (source, value, {column}) VALUES (?1, ?2, ?3)"),
+                        params![<&'static str>::from(source), value, id],
+                    )?;
+                    true
+                }
+            };
+            applied.link(created);
+        }
+        CatalogRecord::ArtistGenre { artist, genre } => applied.link(link(
+            tx,
+            policy,
+            "artist_genres",
+            ("artist_id", "genre_id"),
+            (&artist, &genre_id(tx, genre)?),
+        )?),
+        CatalogRecord::ArtistAlbum { artist, album } => applied.link(link(
+            tx,
+            policy,
+            "artist_albums",
+            ("artist_id", "album_id"),
+            (&artist, &album),
+        )?),
+        CatalogRecord::AlbumGenre { album, genre } => applied.link(link(
+            tx,
+            policy,
+            "album_genres",
+            ("album_id", "genre_id"),
+            (&album, &genre_id(tx, genre)?),
+        )?),
+        CatalogRecord::AlbumTrack { album, track } => applied.link(link(
+            tx,
+            policy,
+            "album_tracks",
+            ("album_id", "track_id"),
+            (&album, &track),
+        )?),
+        CatalogRecord::TrackGenre { track, genre } => applied.link(link(
+            tx,
+            policy,
+            "track_genres",
+            ("track_id", "genre_id"),
+            (&track, &genre_id(tx, genre)?),
+        )?),
+        CatalogRecord::Lyrics {
+            track,
+            language,
+            lines,
+        } => {
+            let Some(language) = lyrics::language(&language) else {
+                Err(ApiError::IoError((
+                    Status::BadRequest,
+                    format!("Invalid Language: {language}"),
+                )))?
+            };
+            if !exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?)",
+                [&track],
+            )? {
+                Err(ApiError::RusqliteError((
+                    Status::BadRequest,
+                    format!("Track Not Found: {track}"),
+                )))?
+            }
+
+            let action = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM track_lyrics WHERE track_id = ?1 AND language = ?2)",
+                params![track, language],
+            )? {
+                true => conflict(policy, &format!("lyrics {track} {language}"))?
+                    .then_some(Action::Update),
+                false => Some(Action::Create),
+            };
+            applied.count(action);
+            // lyrics are part of their track, like `PUT /track/<id>/lyrics/<lang>`
+            if action.is_some() {
+                lyrics::write(tx, &track, &language, &lines)?;
+                applied
+                    .changes
+                    .push((Resource::Track, Action::Update, track));
+            }
+        }
+        CatalogRecord::Audio {
+            track,
+            size,
+            sha256: hash,
+        } => {
+            // without a bundle the record is only metadata
+            if !bundle {
+                applied.entity(Resource::Audio, track, None);
+                return Ok(());
+            }
+
+            if !exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM tracks WHERE id = ?)",
+                [&track],
+            )? {
+                Err(ApiError::RusqliteError((
+                    Status::BadRequest,
+                    format!("Track Not Found: {track}"),
+                )))?
+            }
+
+            if !stored.contains(&hash) {
+                Err(ApiError::IoError((
+                    Status::BadRequest,
+                    format!("Audio Missing Or Corrupt: {track}"),
+                )))?
+            }
+
+            let action = match exists(
+                tx,
+                "SELECT EXISTS(SELECT 1 FROM track_audio WHERE track_id = ?)",
+                [&track],
+            )? {
+                true => conflict(policy, &format!("audio {track}"))?.then_some(Action::Update),
+                false => Some(Action::Create),
+            };
+            if action.is_some() {
+                if let Some(previous) = audio::link(tx, &track, &hash, size, "audio/mpeg", None)? {
+                    applied.replaced.push(previous);
+                }
+            }
+            applied.entity(Resource::Audio, track, action);
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a catalog ends with its `end` record & has as many records as it says, so one that was cut short isn't applied at all.
+async fn verify(catalog: &Path) -> Result<()> {
+    let mut lines = BufReader::new(fs::File::open(catalog).await?).lines();
+    let (mut count, mut last) = (0, None);
+    while let Some(line) = lines.next_line().await? {
+        if !line.trim().is_empty() {
+            count += 1;
+            last = Some(line);
+        }
+    }
+
+    match last.map(|line| json::from_str::<CatalogRecord>(&line)) {
+        Some(Ok(CatalogRecord::End { records })) if records + 1 == count => Ok(()),
+        _ => Err(ApiError::IoError((
+            Status::BadRequest,
+            "Incomplete catalog, the last line must be an `end` record with the number of records before it".to_string(),
+        ))),
+    }
+}
+
+/// Read a catalog's records line by line & apply them `batch` at a time, each batch in its own transaction.
+///
+/// `audio` is the directory of an extracted bundle, with the audio files under `audio/`.
+pub async fn import(
+    db: &MyDatabase,
+    events: &Events,
+    blobs: &Blobs,
+    catalog: &Path,
+    policy: Conflict,
+    batch: usize,
+    audio: Option<PathBuf>,
+) -> Result<CatalogReport> {
+    verify(catalog).await?;
+
+    let mut lines = BufReader::new(fs::File::open(catalog).await?).lines();
+    let mut line_number = 0;
+    let mut report = CatalogReport::default();
+
+    loop {
+        let mut records = Vec::new();
+        while records.len() < batch.max(1) {
+            let Some(line) = lines.next_line().await? else {
+                break;
+            };
+            line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match json::from_str::<CatalogRecord>(&line).map_err(|e| {
+                ApiError::IoError((Status::BadRequest, format!("Line {line_number}: {e}")))
+            })? {
+                CatalogRecord::End { .. } => {}
+                record => records.push(record),
+            }
+        }
+
+        if records.is_empty() {
+            break;
+        }
+
+        // the bundled audio is stored before the batch, a failed batch leaves it unreferenced
+        let _lock = blobs.lock().await;
+        let mut stored = HashSet::new();
+        if let Some(dir) = &audio {
+            for record in records.iter() {
+                let CatalogRecord::Audio { track, sha256, .. } = record else {
+                    continue;
+                };
+                let source = dir.join("audio").join(track).with_extension("mp3");
+                let Ok(file) = fs::File::open(&source).await else {
+                    continue;
+                };
+                if hash(Box::pin(file)).await?.1 != *sha256 {
+                    continue;
+                }
+
+                let sha256_clone = sha256.clone();
+                let corrupt = db
+                    .run(move |conn| audio::is_corrupt(conn, &sha256_clone))
+                    .await?;
+                blobs
+                    .store(sha256, Box::pin(fs::File::open(&source).await?), corrupt)
+                    .await?;
+                stored.insert(sha256.clone());
+            }
+        }
+
+        let bundle = audio.is_some();
+        let log = events.log();
+        let (applied, recorded) = db
+            .run(move |conn| -> Result<_> {
+                let tx = conn.transaction()?;
+                let mut applied = Applied::default();
+                applied.report.records = records.len();
+
+                for record in records {
+                    apply(&tx, record, policy, bundle, &stored, &mut applied)?;
+                }
+                let recorded = applied
+                    .changes
+                    .drain(..)
+                    .map(|(resource, action, target)| log.record(&tx, resource, action, target))
+                    .collect::<Result<Vec<_>>>()?;
+
+                tx.commit()?;
+
+                Ok((applied, recorded))
+            })
+            .await?;
+
+        for previous in applied.replaced {
+            blobs.release(db, &previous).await?;
+        }
+        blobs.analyze();
+
+        events.send(recorded);
+
+        report.records += applied.report.records;
+        report.created += applied.report.created;
+        report.updated += applied.report.updated;
+        report.skipped += applied.report.skipped;
+        report.batches += 1;
+    }
+
+    Ok(report)
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Catalog", |rocket| async {
+        let config: CatalogConfig = rocket
+            .figment()
+            .extract_inner("catalog")
+            .unwrap_or_default();
+
+        rocket.manage(config)
+    })
+}
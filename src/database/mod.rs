@@ -1,5 +1,17 @@
-use rocket::fairing::AdHoc;
-use rocket_sync_db_pools::{database, rusqlite::Error};
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    request::{self, FromRequest, Request},
+    Phase, Rocket,
+};
+use rocket_sync_db_pools::{
+    database,
+    rusqlite::{params_from_iter, Error},
+    ConnectionPool,
+};
+use strum::IntoEnumIterator;
+
+use crate::api::data::permissions::Permission;
 
 mod connection;
 mod pool_manager;
@@ -20,12 +32,52 @@ impl MyDatabase {
         self.run(|conn| -> Result<(), Error> {
             embedded::migrations::runner().run(&mut conn.0).unwrap();
 
+            // new permissions can't go in a migration, they would change its checksum
+            let permissions = Permission::iter().collect::<Vec<_>>();
+            conn.execute(
+                &format!(
+                    "INSERT OR IGNORE INTO permissions (id) VALUES {}",
+                    permissions
+                        .iter()
+                        .map(|_| "(?)")
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                params_from_iter(permissions.into_iter().map(<&'static str>::from)),
+            )?;
+
             Ok(())
         })
         .await
     }
 }
 
+/// Connections for background tasks & long-lived responses, taken as they're needed so an idle one doesn't hold one of the pool's.
+#[derive(Clone)]
+pub struct Connections(ConnectionPool<MyDatabase, MyConnection>);
+
+impl Connections {
+    pub fn new<P: Phase>(rocket: &Rocket<P>) -> Self {
+        Self(MyDatabase::pool(rocket).expect("Mount Database").clone())
+    }
+
+    pub async fn get(&self) -> Option<MyDatabase> {
+        self.0.get().await.map(MyDatabase)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Connections {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match MyDatabase::pool(request.rocket()) {
+            Some(pool) => request::Outcome::Success(Self(pool.clone())),
+            None => request::Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
+
 pub fn fairing() -> AdHoc {
     AdHoc::on_ignite("Database Systems", |rocket| async {
         rocket
@@ -17,6 +17,7 @@ pub struct MyPoolManager {
     on_init: Option<Box<OnInitFn>>,
 }
 
+#[allow(clippy::useless_conversion)]
 impl r2d2::ManageConnection for MyPoolManager {
     type Connection = MyConnection;
     type Error = rusqlite::Error;
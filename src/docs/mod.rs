@@ -18,10 +18,33 @@ use utoipa::{
 
 use crate::api::{
     data::{
+        artists::{
+            Artist, ArtistAlias, ArtistAliasKind, ArtistDetails, ArtistRelation, ArtistRelationKind,
+        },
+        audio::{
+            AudioGarbage, AudioInfo, AudioMismatch, GainMode, Loudness, Peaks, UploadCreate,
+            UploadSession, Verification, VerificationStatus,
+        },
+        backups::{Backup, BackupAudio, BackupManifest},
+        batch::{BatchItem, BatchMode},
+        catalog::{CatalogReport, Conflict},
+        enrichment::{Change, EnrichmentReport, Proposal, Suggestion},
+        events::{Action, ChangeEvent, Resource},
+        external_ids::{ExternalId, ExternalSource},
+        genres::Genre,
+        imports::{ImportDirectory, ImportEntry, ImportJob, ImportSkip, ImportStatus},
+        lyrics::{LyricLine, Lyrics, LyricsFormat},
         permissions::Permission,
-        users::{DangerousLogin, User},
+        shares::{Share, ShareCreate, Shared},
+        totp::{TotpCode, TotpEnrollment},
+        tracks::{Duplicate, DuplicateGroup},
+        users::{DangerousLogin, Quota, Usage, User},
+        webhooks::{Webhook, WebhookDelivery},
+    },
+    endpoints::{
+        artists, audio, backups, batch, catalog, enrichment, events, external_ids, genres, graphql,
+        hls, imports, invites, permissions, shares, tokens, totp, tracks, uploads, users, webhooks,
     },
-    endpoints::{audio, genres, invites, permissions, tokens, users},
 };
 
 #[derive(OpenApi)]
@@ -30,22 +53,89 @@ use crate::api::{
         docs_json,
         tokens::token_write,
         tokens::token_delete,
+        totp::totp_write,
+        totp::totp_confirm,
+        totp::totp_delete,
         permissions::permission_add,
         permissions::permission_delete,
         invites::invite_use,
         invites::invite_write,
         invites::invite_get,
         invites::invite_delete,
+        tracks::track_duplicates_get,
+        tracks::duplicates_get,
+        tracks::track_lyrics_get,
+        tracks::track_lyrics_write,
+        tracks::track_lyrics_delete,
         users::user_init,
         users::user_get,
         users::user_delete,
+        users::user_usage_get,
+        users::usage_get,
+        users::user_quota_write,
+        artists::artist_details_get,
+        artists::artist_alias_write,
+        artists::artist_alias_delete,
+        artists::artist_relation_write,
+        artists::artist_relation_delete,
+        artists::artist_merge,
+        external_ids::external_id_get,
+        external_ids::external_id_write,
+        external_ids::external_id_delete,
+        enrichment::enrich,
+        enrichment::suggestion_get,
+        enrichment::suggestion_apply,
+        enrichment::suggestion_delete,
+        batch::genre_batch,
+        batch::artist_batch,
+        batch::album_batch,
+        batch::track_batch,
+        graphql::graphql_post,
         genres::genre_write,
         genres::genre_get,
+        genres::genre_info_get,
         genres::genre_delete,
+        genres::genre_parent_write,
+        genres::genre_parent_delete,
+        genres::genre_alias_write,
+        genres::genre_alias_delete,
+        genres::genre_merge,
         audio::audio_upload,
         audio::audio_get,
+        audio::audio_info_get,
+        audio::audio_peaks_get,
+        hls::hls_master_get,
+        hls::hls_media_get,
+        hls::hls_segment_get,
         audio::audio_delete,
-    ), components(schemas(Permission, DangerousLogin, User)), modifiers(&SecurityAddon))]
+        audio::audio_gc,
+        audio::verification_write,
+        audio::verification_get,
+        uploads::upload_write,
+        uploads::upload_get,
+        uploads::upload_append,
+        uploads::upload_delete,
+        imports::import_directory,
+        imports::import_archive,
+        imports::import_get,
+        catalog::catalog_export,
+        catalog::catalog_import,
+        events::event_get,
+        webhooks::webhook_write,
+        webhooks::webhook_get,
+        webhooks::webhook_delete,
+        webhooks::webhook_delivery_get,
+        webhooks::webhook_redeliver,
+        backups::backup_write,
+        backups::backup_get,
+        backups::backup_manifest_get,
+        backups::backup_restore,
+        shares::share_write,
+        shares::share_get,
+        shares::share_delete,
+        shares::shared_get,
+        shares::shared_audio_get,
+    ), components(schemas(Permission, DangerousLogin, User, TotpEnrollment, TotpCode, ChangeEvent, Resource, Action, Genre, Artist, ArtistDetails, ArtistAlias, ArtistAliasKind, ArtistRelation, ArtistRelationKind, ExternalId, ExternalSource, Change, Proposal, Suggestion, EnrichmentReport, Webhook, WebhookDelivery, ImportDirectory, ImportJob, ImportStatus, ImportEntry, ImportSkip, CatalogReport, Conflict, BatchMode, BatchItem, Backup, BackupManifest, BackupAudio, AudioInfo, AudioGarbage, AudioMismatch, Verification, VerificationStatus, UploadCreate, UploadSession, Usage, Quota, Loudness, GainMode, Peaks, Duplicate, DuplicateGroup, Lyrics, LyricLine, LyricsFormat, ShareCreate, Share, Shared)), modifiers(&SecurityAddon))]
 struct ApiDoc;
 
 struct SecurityAddon;
@@ -0,0 +1,832 @@
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    serde::{de::DeserializeOwned, json, Deserialize},
+    tokio::{
+        sync::Mutex,
+        time::{sleep_until, Instant},
+    },
+};
+use rocket_sync_db_pools::rusqlite::{self, params, Connection, OptionalExtension};
+use uuid::Uuid;
+
+use std::time::Duration;
+
+use crate::{
+    api::data::{
+        albums::ReleaseDate,
+        artists::ArtistRelationKind,
+        enrichment::{Change, Proposal},
+        events::Resource,
+        external_ids::ExternalSource,
+        permissions::Permission,
+    },
+    database::MyDatabase,
+    error::ApiError,
+    external_ids, genres,
+};
+
+pub mod musicbrainz;
+
+use musicbrainz as mb;
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `enrichment` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct EnrichmentConfig {
+    /// The root of a MusicBrainz-compatible web service.
+    pub endpoint: String,
+    /// Sent with every request, MusicBrainz blocks clients without a meaningful one.
+    pub user_agent: String,
+    /// Milliseconds between requests to the service.
+    pub interval: u64,
+    /// Seconds a response is cached for.
+    pub cache_ttl: u64,
+    /// Seconds to wait for the service to respond.
+    pub timeout: u64,
+    /// The lowest search score (0-100) that counts as a match.
+    pub min_score: u8,
+}
+
+impl Default for EnrichmentConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://musicbrainz.org/ws/2".to_string(),
+            user_agent: format!(
+                "tuna/{} ( https://github.com/5-pebbles/tuna )",
+                env!("CARGO_PKG_VERSION")
+            ),
+            interval: 1000,
+            cache_ttl: 604800,
+            timeout: 10,
+            min_score: 90,
+        }
+    }
+}
+
+/// The result of looking up an artist, album or track.
+pub struct Enriched {
+    /// The MBID it was matched to.
+    pub mbid: Option<String>,
+    /// The number of requests that weren't answered by the cache.
+    pub requests: u32,
+    /// The changes that aren't in place yet.
+    pub proposals: Vec<Proposal>,
+}
+
+/// A client of the metadata service, shared so requests are rate limited across the server.
+pub struct Enrichment {
+    config: EnrichmentConfig,
+    client: reqwest::Client,
+    /// The earliest the next request can be sent.
+    next: Mutex<Instant>,
+}
+
+/// The requests made for one lookup.
+struct Lookup<'a> {
+    enrichment: &'a Enrichment,
+    db: &'a MyDatabase,
+    requests: u32,
+}
+
+impl Lookup<'_> {
+    /// `GET` a path of the service, `None` if it doesn't exist.
+    ///
+    /// Responses come from the cache while they're fresh, 404s included.
+    async fn get<T: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Option<T>> {
+        let config = &self.enrichment.config;
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/{path}", config.endpoint.trim_end_matches('/')),
+            query.iter().chain(&[("fmt", "json")]),
+        )
+        .map_err(|e| {
+            ApiError::IoError((Status::InternalServerError, format!("Enrichment Url: {e}")))
+        })?
+        .to_string();
+
+        let key = url.clone();
+        let ttl = config.cache_ttl;
+        let cached: Option<(u16, String)> = self
+            .db
+            .run(move |conn| {
+                conn.query_row(
+                    "SELECT status, body FROM enrichment_cache WHERE url = ?1 AND fetched > strftime('%s', 'now') - ?2",
+                    params![key, ttl],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()
+            })
+            .await?;
+
+        let (status, body) = match cached {
+            Some(cached) => cached,
+            None => {
+                let (status, body) = self.fetch(&url).await?;
+                let cached = body.clone();
+                self.db
+                    .run(move |conn| {
+                        // the cache only grows by what's fetched, so that's when it sheds what expired
+                        conn.execute(
+                            "DELETE FROM enrichment_cache WHERE fetched <= strftime('%s', 'now') - ?1",
+                            params![ttl],
+                        )?;
+                        conn.execute(
+                            "INSERT OR REPLACE INTO enrichment_cache (url, status, body) VALUES (?1, ?2, ?3)",
+                            params![url, status, cached],
+                        )
+                    })
+                    .await?;
+                (status, body)
+            }
+        };
+
+        if status == 404 {
+            return Ok(None);
+        }
+        json::from_str(&body).map(Some).map_err(|e| {
+            ApiError::IoError((Status::BadGateway, format!("Enrichment Response: {e}")))
+        })
+    }
+
+    /// Send a request once the rate limit allows it, only successes & 404s are returned.
+    async fn fetch(&mut self, url: &str) -> Result<(u16, String)> {
+        let bad_gateway = |e: reqwest::Error| {
+            ApiError::IoError((Status::BadGateway, format!("Enrichment Error: {e}")))
+        };
+
+        let response = {
+            // held until the request is answered so requests never overlap
+            let mut next = self.enrichment.next.lock().await;
+            sleep_until(*next).await;
+            let response = self
+                .enrichment
+                .client
+                .get(url)
+                .header("Accept", "application/json")
+                .send()
+                .await;
+            *next = Instant::now() + Duration::from_millis(self.enrichment.config.interval);
+            response.map_err(bad_gateway)?
+        };
+        self.requests += 1;
+
+        match response.status().as_u16() {
+            200 => Ok((200, response.text().await.map_err(bad_gateway)?)),
+            404 => Ok((404, String::new())),
+            // the service's rate limit, someone else is probably using the same address
+            503 => Err(ApiError::IoError((
+                Status::ServiceUnavailable,
+                "Enrichment Service Unavailable".to_string(),
+            ))),
+            status => Err(ApiError::IoError((
+                Status::BadGateway,
+                format!("Enrichment Error: {status}"),
+            ))),
+        }
+    }
+}
+
+/// What enrichment needs to know about an artist, album or track.
+struct Local {
+    name: String,
+    mbid: Option<String>,
+    /// An ISRC for tracks & the first credited artist for albums, to narrow searches.
+    hint: Option<String>,
+}
+
+fn local(conn: &Connection, resource: Resource, id: &str) -> rusqlite::Result<Option<Local>> {
+    let hint = match resource {
+        Resource::Album => "(SELECT artists.name FROM artist_albums JOIN artists ON artists.id = artist_albums.artist_id WHERE album_id = ?1 ORDER BY artists.name LIMIT 1)",
+        Resource::Track => "(SELECT value FROM external_ids WHERE source = 'isrc' AND track_id = ?1 ORDER BY value LIMIT 1)",
+        _ => "NULL",
+    };
+    let column = external_ids::column(resource).unwrap_or("artist_id");
+    conn.query_row(
+        &format!(
+            "SELECT name, (SELECT value FROM external_ids WHERE source = 'musicbrainz' AND {column} = ?1), {hint} FROM {resource}s WHERE id = ?1"
+        ),
+        [id],
+        |row| {
+            Ok(Local {
+                name: row.get(0)?,
+                mbid: row.get(1)?,
+                hint: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
+
+impl Enrichment {
+    /// Match an artist, album or track to MusicBrainz & propose what it's missing, `None` if it doesn't exist.
+    ///
+    /// Artists, albums & tracks without an MBID are searched for by name.
+    pub async fn enrich(
+        &self,
+        db: &MyDatabase,
+        resource: Resource,
+        id: String,
+    ) -> Result<Option<Enriched>> {
+        let target = id.clone();
+        let Some(local) = db.run(move |conn| local(conn, resource, &target)).await? else {
+            return Ok(None);
+        };
+
+        let mut lookup = Lookup {
+            enrichment: self,
+            db,
+            requests: 0,
+        };
+        let min_score = self.config.min_score;
+        let matches =
+            |name: &str, score: u8| score >= min_score && name.eq_ignore_ascii_case(&local.name);
+
+        let mbid = match local.mbid.clone() {
+            Some(mbid) => Some(mbid),
+            None => match resource {
+                Resource::Artist => lookup
+                    .get::<mb::ArtistSearch>(
+                        "artist",
+                        &[
+                            ("query", &format!("artist:{}", mb::quote(&local.name))),
+                            ("limit", "10"),
+                        ],
+                    )
+                    .await?
+                    .and_then(|search| {
+                        search
+                            .artists
+                            .into_iter()
+                            .find(|artist| matches(&artist.name, artist.score))
+                    })
+                    .map(|artist| artist.id),
+                Resource::Album => {
+                    let mut query = format!("release:{}", mb::quote(&local.name));
+                    if let Some(artist) = &local.hint {
+                        query += &format!(" AND artist:{}", mb::quote(artist));
+                    }
+                    lookup
+                        .get::<mb::ReleaseSearch>("release", &[("query", &query), ("limit", "10")])
+                        .await?
+                        .and_then(|search| {
+                            search
+                                .releases
+                                .into_iter()
+                                .find(|release| matches(&release.title, release.score))
+                        })
+                        .map(|release| release.id)
+                }
+                _ => {
+                    // an ISRC is more specific than a title
+                    let query = match &local.hint {
+                        Some(isrc) => format!("isrc:{isrc}"),
+                        None => format!("recording:{}", mb::quote(&local.name)),
+                    };
+                    lookup
+                        .get::<mb::RecordingSearch>(
+                            "recording",
+                            &[("query", &query), ("limit", "10")],
+                        )
+                        .await?
+                        .and_then(|search| {
+                            search
+                                .recordings
+                                .into_iter()
+                                .find(|recording| matches(&recording.title, recording.score))
+                        })
+                        .map(|recording| recording.id)
+                }
+            },
+        }
+        .and_then(|mbid| external_ids::normalize(ExternalSource::Musicbrainz, &mbid));
+
+        let Some(mbid) = mbid else {
+            return Ok(Some(Enriched {
+                mbid: None,
+                requests: lookup.requests,
+                proposals: Vec::new(),
+            }));
+        };
+
+        let path = format!(
+            "{}/{mbid}",
+            match resource {
+                Resource::Artist => "artist",
+                Resource::Album => "release",
+                _ => "recording",
+            }
+        );
+        let proposals = match resource {
+            Resource::Artist => match lookup
+                .get::<mb::Artist>(&path, &[("inc", "aliases genres artist-rels")])
+                .await?
+            {
+                Some(artist) => Some(
+                    db.run(move |conn| artist_proposals(conn, &id, artist))
+                        .await?,
+                ),
+                None => None,
+            },
+            Resource::Album => match lookup
+                .get::<mb::Release>(&path, &[("inc", "recordings genres isrcs")])
+                .await?
+            {
+                Some(release) => Some(
+                    db.run(move |conn| album_proposals(conn, &id, release))
+                        .await?,
+                ),
+                None => None,
+            },
+            _ => match lookup
+                .get::<mb::Recording>(&path, &[("inc", "isrcs genres")])
+                .await?
+            {
+                Some(recording) => Some(
+                    db.run(move |conn| track_proposals(conn, &id, recording))
+                        .await?,
+                ),
+                None => None,
+            },
+        };
+
+        Ok(Some(Enriched {
+            // a stored MBID the service doesn't know isn't a match
+            mbid: proposals.is_some().then_some(mbid),
+            requests: lookup.requests,
+            proposals: proposals.unwrap_or_default(),
+        }))
+    }
+}
+
+/// Collects the proposals that aren't in place yet, once each.
+struct Proposals<'a> {
+    conn: &'a Connection,
+    proposals: Vec<Proposal>,
+}
+
+impl Proposals<'_> {
+    fn push(&mut self, resource: Resource, target: &str, change: Change) -> rusqlite::Result<()> {
+        let proposal = Proposal {
+            resource,
+            target: target.to_string(),
+            change,
+        };
+        if !self.proposals.contains(&proposal) && !redundant(self.conn, &proposal)? {
+            self.proposals.push(proposal);
+        }
+        Ok(())
+    }
+
+    fn external_id(
+        &mut self,
+        resource: Resource,
+        target: &str,
+        source: ExternalSource,
+        value: &str,
+    ) -> rusqlite::Result<()> {
+        match external_ids::normalize(source, value) {
+            Some(value) => self.push(resource, target, Change::ExternalId { source, value }),
+            None => Ok(()),
+        }
+    }
+
+    fn genres(
+        &mut self,
+        resource: Resource,
+        target: &str,
+        genres: Vec<mb::Genre>,
+    ) -> rusqlite::Result<()> {
+        for genre in genres {
+            self.push(resource, target, Change::Genre { genre: genre.name })?;
+        }
+        Ok(())
+    }
+
+    fn release(
+        &mut self,
+        resource: Resource,
+        target: &str,
+        date: Option<&str>,
+    ) -> rusqlite::Result<()> {
+        let current: Option<ReleaseDate> = self.conn.query_row(
+            &format!("SELECT release FROM {resource}s WHERE id = ?"),
+            [target],
+            |row| row.get(0),
+        )?;
+        // dates are only filled in, missing ones or ones that are less precise
+        match (
+            current,
+            date.and_then(|date| date.parse::<ReleaseDate>().ok()),
+        ) {
+            (current, Some(date))
+                if current.is_none_or(|current| current != date && current.contains(&date)) =>
+            {
+                self.push(resource, target, Change::Release { date })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The local artist with an MBID.
+fn artist_by_mbid(conn: &Connection, mbid: &str) -> rusqlite::Result<Option<String>> {
+    let Some(mbid) = external_ids::normalize(ExternalSource::Musicbrainz, mbid) else {
+        return Ok(None);
+    };
+    conn.query_row(
+        "SELECT artist_id FROM external_ids WHERE source = 'musicbrainz' AND value = ? AND artist_id IS NOT NULL",
+        [mbid],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// A MusicBrainz artist-artist relationship type as `(kind, reversed)`.
+///
+/// `reversed` when it's the relationship's target that's `kind` of its subject.
+fn relation_kind(kind: &str) -> Option<(ArtistRelationKind, bool)> {
+    match kind {
+        "member of band" => Some((ArtistRelationKind::MemberOf, false)),
+        "is person" => Some((ArtistRelationKind::SamePersonAs, false)),
+        "subgroup" => Some((ArtistRelationKind::SideProjectOf, true)),
+        _ => None,
+    }
+}
+
+fn artist_proposals(
+    conn: &Connection,
+    id: &str,
+    artist: mb::Artist,
+) -> rusqlite::Result<Vec<Proposal>> {
+    let mut proposals = Proposals {
+        conn,
+        proposals: Vec::new(),
+    };
+    let resource = Resource::Artist;
+
+    proposals.external_id(resource, id, ExternalSource::Musicbrainz, &artist.id)?;
+    if let Some(name) = artist.sort_name.filter(|name| *name != artist.name) {
+        proposals.push(resource, id, Change::SortName { name })?;
+    }
+    for alias in artist.aliases {
+        proposals.push(
+            resource,
+            id,
+            Change::Alias {
+                name: alias.name,
+                locale: alias.locale,
+            },
+        )?;
+    }
+    proposals.genres(resource, id, artist.genres)?;
+
+    // only artists that are already here can be related
+    for relation in artist.relations {
+        let (Some((kind, reversed)), Some(related)) =
+            (relation_kind(&relation.kind), relation.artist)
+        else {
+            continue;
+        };
+        let Some(related) = artist_by_mbid(conn, &related.id)? else {
+            continue;
+        };
+        let (subject, object) = match (relation.direction == "backward") != reversed {
+            false => (id.to_string(), related),
+            true => (related, id.to_string()),
+        };
+        proposals.push(
+            resource,
+            &subject,
+            Change::Relation {
+                relation: kind,
+                artist: object,
+            },
+        )?;
+    }
+
+    Ok(proposals.proposals)
+}
+
+fn album_proposals(
+    conn: &Connection,
+    id: &str,
+    release: mb::Release,
+) -> rusqlite::Result<Vec<Proposal>> {
+    let mut proposals = Proposals {
+        conn,
+        proposals: Vec::new(),
+    };
+    let resource = Resource::Album;
+
+    proposals.external_id(resource, id, ExternalSource::Musicbrainz, &release.id)?;
+    proposals.release(resource, id, release.date.as_deref())?;
+    if let Some(barcode) = &release.barcode {
+        proposals.external_id(resource, id, ExternalSource::Upc, barcode)?;
+    }
+    proposals.genres(resource, id, release.genres)?;
+
+    // the album's tracks as `(id, name, mbid)`
+    let tracks: Vec<(String, String, Option<String>)> = conn
+        .prepare(
+            "SELECT tracks.id, tracks.name, (SELECT value FROM external_ids WHERE source = 'musicbrainz' AND track_id = tracks.id)
+            FROM album_tracks JOIN tracks ON tracks.id = album_tracks.track_id WHERE album_tracks.album_id = ?",
+        )?
+        .query_map([id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    for track in release.media.into_iter().flat_map(|medium| medium.tracks) {
+        let recording = track.recording;
+        let Some(mbid) = external_ids::normalize(ExternalSource::Musicbrainz, &recording.id) else {
+            continue;
+        };
+        let matched = tracks
+            .iter()
+            .find(|(_, _, local)| local.as_deref() == Some(mbid.as_str()))
+            .or_else(|| {
+                tracks.iter().find(|(_, name, local)| {
+                    local.is_none() && name.eq_ignore_ascii_case(&track.title)
+                })
+            });
+
+        match matched {
+            Some((track, _, _)) => {
+                proposals.external_id(
+                    Resource::Track,
+                    track,
+                    ExternalSource::Musicbrainz,
+                    &mbid,
+                )?;
+                proposals.release(Resource::Track, track, release.date.as_deref())?;
+                for isrc in recording.isrcs {
+                    proposals.external_id(Resource::Track, track, ExternalSource::Isrc, &isrc)?;
+                }
+            }
+            None => proposals.push(
+                resource,
+                id,
+                Change::Track {
+                    name: track.title,
+                    mbid,
+                },
+            )?,
+        }
+    }
+
+    Ok(proposals.proposals)
+}
+
+fn track_proposals(
+    conn: &Connection,
+    id: &str,
+    recording: mb::Recording,
+) -> rusqlite::Result<Vec<Proposal>> {
+    let mut proposals = Proposals {
+        conn,
+        proposals: Vec::new(),
+    };
+    let resource = Resource::Track;
+
+    proposals.external_id(resource, id, ExternalSource::Musicbrainz, &recording.id)?;
+    proposals.release(resource, id, recording.first_release_date.as_deref())?;
+    for isrc in &recording.isrcs {
+        proposals.external_id(resource, id, ExternalSource::Isrc, isrc)?;
+    }
+    proposals.genres(resource, id, recording.genres)?;
+
+    Ok(proposals.proposals)
+}
+
+/// The genre a name refers to, by id, alias or case insensitively.
+fn genre(conn: &Connection, name: &str) -> rusqlite::Result<Option<String>> {
+    match genres::resolve(conn, name)? {
+        Some(id) => Ok(Some(id)),
+        None => conn
+            .query_row(
+                "SELECT id FROM genres WHERE id = ? COLLATE NOCASE LIMIT 1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional(),
+    }
+}
+
+/// Whether a change is already in place.
+pub fn holds(conn: &Connection, proposal: &Proposal) -> rusqlite::Result<bool> {
+    let Proposal {
+        resource,
+        target,
+        change,
+    } = proposal;
+    let column = external_ids::column(*resource).unwrap_or("artist_id");
+
+    match change {
+        Change::Release { date } => conn.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {resource}s WHERE id = ?1 AND release = ?2)"),
+            params![target, date],
+            |row| row.get(0),
+        ),
+        Change::SortName { name } => conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM artists WHERE id = ?1 AND sort_name = ?2)",
+            params![target, name],
+            |row| row.get(0),
+        ),
+        Change::Alias { name, .. } => conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM artists WHERE id = ?1 AND name = ?2 COLLATE NOCASE)
+            OR EXISTS(SELECT 1 FROM artist_aliases WHERE artist_id = ?1 AND name = ?2 COLLATE NOCASE)",
+            params![target, name],
+            |row| row.get(0),
+        ),
+        Change::Genre { genre: name } => match genre(conn, name)? {
+            Some(genre) => conn.query_row(
+                &format!("SELECT EXISTS(SELECT 1 FROM {resource}_genres WHERE {column} = ?1 AND genre_id = ?2)"),
+                params![target, genre],
+                |row| row.get(0),
+            ),
+            None => Ok(false),
+        },
+        Change::ExternalId { source, value } => conn.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM external_ids WHERE source = ?1 AND value = ?2 AND {column} = ?3)"),
+            params![<&'static str>::from(*source), value, target],
+            |row| row.get(0),
+        ),
+        Change::Relation { relation, artist } => conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM artist_relations WHERE artist_id = ?1 AND related_id = ?2 AND kind = ?3)
+            OR (?3 = 'same_person_as' AND EXISTS(SELECT 1 FROM artist_relations WHERE artist_id = ?2 AND related_id = ?1 AND kind = ?3))",
+            params![target, artist, <&'static str>::from(*relation)],
+            |row| row.get(0),
+        ),
+        Change::Track { name, mbid } => conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM album_tracks JOIN tracks ON tracks.id = album_tracks.track_id
+                WHERE album_tracks.album_id = ?1 AND (tracks.name = ?2 COLLATE NOCASE
+                    OR EXISTS(SELECT 1 FROM external_ids WHERE source = 'musicbrainz' AND value = ?3 AND track_id = tracks.id)))",
+            params![target, name, mbid],
+            |row| row.get(0),
+        ),
+    }
+}
+
+/// Whether a change is in place or can't be made, because the external id belongs to something else.
+fn redundant(conn: &Connection, proposal: &Proposal) -> rusqlite::Result<bool> {
+    if holds(conn, proposal)? {
+        return Ok(true);
+    }
+    match &proposal.change {
+        Change::ExternalId { source, value } => conn.query_row(
+            &format!(
+                "SELECT EXISTS(SELECT 1 FROM external_ids WHERE source = ?1 AND value = ?2 AND {} IS NOT NULL)",
+                external_ids::column(proposal.resource).unwrap_or("artist_id")
+            ),
+            params![<&'static str>::from(*source), value],
+            |row| row.get(0),
+        ),
+        _ => Ok(false),
+    }
+}
+
+/// The permissions needed to apply a change, besides `EnrichWrite`.
+pub fn permissions(proposal: &Proposal) -> Vec<Permission> {
+    let mut permissions = vec![match proposal.resource {
+        Resource::Artist => Permission::ArtistWrite,
+        Resource::Album => Permission::AlbumWrite,
+        _ => Permission::TrackWrite,
+    }];
+    match proposal.change {
+        // the genre is created if it doesn't exist
+        Change::Genre { .. } => permissions.push(Permission::GenreWrite),
+        Change::Track { .. } => permissions.push(Permission::TrackWrite),
+        _ => (),
+    }
+    permissions
+}
+
+/// The resources a change created besides the change itself, e.g. a genre it was the first to use.
+pub type Created = Vec<(Resource, String)>;
+
+/// Make a change, returns what it created.
+///
+/// Fails with `QueryReturnedNoRows` if the target doesn't exist.
+pub fn apply(conn: &Connection, proposal: &Proposal) -> rusqlite::Result<Created> {
+    let Proposal {
+        resource,
+        target,
+        change,
+    } = proposal;
+    let column = external_ids::column(*resource).unwrap_or("artist_id");
+    let mut created = Vec::new();
+
+    let release: Option<ReleaseDate> = conn.query_row(
+        &format!(
+            "SELECT {} FROM {resource}s WHERE id = ?",
+            if *resource == Resource::Artist {
+                "NULL"
+            } else {
+                "release"
+            }
+        ),
+        [target],
+        |row| row.get(0),
+    )?;
+
+    match change {
+        Change::Release { date } => {
+            conn.execute(
+                &format!("UPDATE {resource}s SET release = ?1 WHERE id = ?2"),
+                params![date, target],
+            )?;
+        }
+        Change::SortName { name } => {
+            conn.execute(
+                "UPDATE artists SET sort_name = ?1 WHERE id = ?2",
+                params![name, target],
+            )?;
+        }
+        Change::Alias { name, locale } => {
+            conn.execute(
+                "INSERT INTO artist_aliases (artist_id, name, locale) VALUES (?1, ?2, ?3)",
+                params![target, name, locale],
+            )?;
+        }
+        Change::Genre { genre: name } => {
+            let genre = match genre(conn, name)? {
+                Some(genre) => genre,
+                None => {
+                    conn.execute("INSERT INTO genres (id) VALUES (?)", [name])?;
+                    created.push((Resource::Genre, name.clone()));
+                    name.clone()
+                }
+            };
+            conn.execute(
+                &format!("INSERT INTO {resource}_genres ({column}, genre_id) VALUES (?1, ?2)"),
+                params![target, genre],
+            )?;
+        }
+        Change::ExternalId { source, value } => {
+            conn.execute(
+                &format!("INSERT INTO external_ids (source, value, {column}) VALUES (?1, ?2, ?3)"),
+                params![<&'static str>::from(*source), value, target],
+            )?;
+        }
+        Change::Relation { relation, artist } => {
+            conn.execute(
+                "INSERT INTO artist_relations (artist_id, related_id, kind) VALUES (?1, ?2, ?3)",
+                params![target, artist, <&'static str>::from(*relation)],
+            )?;
+        }
+        Change::Track { name, mbid } => {
+            let id = Uuid::new_v4().to_string();
+            conn.execute(
+                "INSERT INTO tracks (id, name, release) VALUES (?1, ?2, ?3)",
+                params![id, name, release],
+            )?;
+            conn.execute(
+                "INSERT INTO album_tracks (album_id, track_id) VALUES (?1, ?2)",
+                params![target, id],
+            )?;
+            // the recording may already belong to a track on another album
+            conn.execute(
+                "INSERT OR IGNORE INTO external_ids (source, value, track_id) VALUES ('musicbrainz', ?1, ?2)",
+                params![mbid, id],
+            )?;
+            created.push((Resource::Track, id));
+        }
+    }
+
+    // a suggestion for it has been taken care of
+    conn.execute(
+        "DELETE FROM enrichment_suggestions WHERE resource = ?1 AND target = ?2 AND change = ?3",
+        params![<&'static str>::from(*resource), target, encode(change)],
+    )?;
+
+    Ok(created)
+}
+
+/// A change as it's stored in `enrichment_suggestions`.
+pub fn encode(change: &Change) -> String {
+    json::to_string(change).expect("Changes serialize")
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Enrichment", |rocket| async {
+        let config: EnrichmentConfig = rocket
+            .figment()
+            .extract_inner("enrichment")
+            .unwrap_or_default();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .user_agent(config.user_agent.clone())
+            .build()
+            .expect("Failed to build enrichment client");
+
+        rocket.manage(Enrichment {
+            config,
+            client,
+            next: Mutex::new(Instant::now()),
+        })
+    })
+}
@@ -0,0 +1,118 @@
+//! The parts of the MusicBrainz web service's JSON that enrichment reads.
+
+use rocket::serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Genre {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Alias {
+    pub name: String,
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RelatedArtist {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Relation {
+    /// e.g. `member of band`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// `forward` if the artist looked up is the subject, `backward` if the related artist is
+    pub direction: String,
+    pub artist: Option<RelatedArtist>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Artist {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "sort-name")]
+    pub sort_name: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<Alias>,
+    #[serde(default)]
+    pub genres: Vec<Genre>,
+    #[serde(default)]
+    pub relations: Vec<Relation>,
+    /// How well a search result matches (0-100)
+    #[serde(default)]
+    pub score: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Recording {
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "first-release-date")]
+    pub first_release_date: Option<String>,
+    #[serde(default)]
+    pub isrcs: Vec<String>,
+    #[serde(default)]
+    pub genres: Vec<Genre>,
+    #[serde(default)]
+    pub score: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Track {
+    pub title: String,
+    pub recording: Recording,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Medium {
+    #[serde(default)]
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Release {
+    pub id: String,
+    pub title: String,
+    pub date: Option<String>,
+    pub barcode: Option<String>,
+    #[serde(default)]
+    pub media: Vec<Medium>,
+    #[serde(default)]
+    pub genres: Vec<Genre>,
+    #[serde(default)]
+    pub score: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ArtistSearch {
+    pub artists: Vec<Artist>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReleaseSearch {
+    pub releases: Vec<Release>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RecordingSearch {
+    pub recordings: Vec<Recording>,
+}
+
+/// A value for a Lucene query, quoted.
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -1,6 +1,9 @@
 use bcrypt::BcryptError;
 use rocket::http::Status;
 use rocket_sync_db_pools::rusqlite::{Error as RusqliteError, ErrorCode as RusqliteErrorCode};
+use std::fmt;
+
+use crate::error::RetryAfter;
 
 #[derive(Debug, Responder)]
 pub enum ApiError {
@@ -8,9 +11,35 @@ pub enum ApiError {
     #[response(status = 500)]
     HashError(String),
     IoError((Status, String)),
+    RateLimited(RetryAfter),
     Status(Status),
 }
 
+impl ApiError {
+    /// The status of the response.
+    pub fn status(&self) -> Status {
+        match self {
+            Self::RusqliteError((status, _)) | Self::IoError((status, _)) => *status,
+            Self::HashError(_) => Status::InternalServerError,
+            Self::RateLimited(_) => Status::TooManyRequests,
+            Self::Status(status) => *status,
+        }
+    }
+}
+
+/// The message of the response, used where an error isn't one, e.g. in a GraphQL response.
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RusqliteError((_, message))
+            | Self::HashError(message)
+            | Self::IoError((_, message)) => f.write_str(message),
+            Self::RateLimited(_) => write!(f, "{}", Status::TooManyRequests),
+            Self::Status(status) => write!(f, "{status}"),
+        }
+    }
+}
+
 impl From<Status> for ApiError {
     fn from(e: Status) -> Self {
         Self::Status(e)
@@ -43,3 +72,9 @@ impl From<std::io::Error> for ApiError {
         Self::IoError((Status::InternalServerError, format!("IO Error: {e}")))
     }
 }
+
+impl From<RetryAfter> for ApiError {
+    fn from(e: RetryAfter) -> Self {
+        Self::RateLimited(e)
+    }
+}
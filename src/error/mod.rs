@@ -1,2 +1,4 @@
 mod api_error;
+mod retry_after;
 pub use api_error::ApiError;
+pub use retry_after::RetryAfter;
@@ -0,0 +1,18 @@
+use rocket::{
+    http::{Header, Status},
+    request::Request,
+    response::{self, Responder, Response},
+};
+
+/// A `429 Too Many Requests` response with a `Retry-After` header.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryAfter(pub u64);
+
+impl<'r> Responder<'r, 'static> for RetryAfter {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .status(Status::TooManyRequests)
+            .header(Header::new("Retry-After", self.0.to_string()))
+            .ok()
+    }
+}
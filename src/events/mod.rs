@@ -0,0 +1,270 @@
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    serde::{json, Deserialize},
+    tokio::sync::broadcast::{self, error::RecvError},
+};
+use rocket_sync_db_pools::rusqlite::{params, Connection};
+
+use crate::{
+    api::data::events::{Action, ChangeEvent, Resource},
+    database::{Connections, MyDatabase},
+    error::ApiError,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `events` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct EventsConfig {
+    /// The number of events kept in the database for replaying with `Last-Event-ID`.
+    pub backlog: u32,
+    /// The number of events a slow subscriber can fall behind before it's caught up from the database.
+    pub capacity: usize,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            backlog: 1000,
+            capacity: 256,
+        }
+    }
+}
+
+/// Broadcasts recorded changes to every subscriber of `GET /events`.
+///
+/// Clones share the same channel, so background jobs can send events too.
+#[derive(Clone)]
+pub struct Events {
+    config: EventsConfig,
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl Events {
+    fn new(config: EventsConfig) -> Self {
+        let (sender, _) = broadcast::channel(config.capacity.max(1));
+        Self { config, sender }
+    }
+
+    /// The log to record changes in, it can be moved into `MyDatabase::run`.
+    pub fn log(&self) -> EventLog {
+        EventLog {
+            backlog: self.config.backlog,
+        }
+    }
+
+    /// Send recorded events to all subscribers.
+    ///
+    /// This must only be called once the transaction they were recorded in has been committed.
+    pub fn send<I: IntoIterator<Item = ChangeEvent>>(&self, events: I) {
+        for event in events {
+            // there being no subscribers isn't an error
+            let _ = self.sender.send(event);
+        }
+    }
+
+    /// Subscribe to the events after `last_id`, or to those after now without one.
+    ///
+    /// The subscription only takes a connection while it reads the database, not while it waits.
+    pub async fn subscribe(
+        &self,
+        connections: Connections,
+        last_id: Option<u64>,
+    ) -> Result<Subscription> {
+        // subscribe before reading the database, so nothing falls between the two
+        let receiver = self.sender.subscribe();
+
+        let (last_id, behind) = match last_id {
+            Some(last_id) => (last_id, true),
+            None => (Self::latest(&connections).await?, false),
+        };
+
+        Ok(Subscription {
+            connections,
+            receiver,
+            last_id,
+            behind,
+            overtaking: None,
+        })
+    }
+
+    /// A connection that's released as soon as it's dropped.
+    async fn connection(connections: &Connections) -> Result<MyDatabase> {
+        connections
+            .get()
+            .await
+            .ok_or(ApiError::Status(Status::ServiceUnavailable))
+    }
+
+    /// The id of the newest persisted event, `0` without any.
+    async fn latest(connections: &Connections) -> Result<u64> {
+        Ok(Self::connection(connections)
+            .await?
+            .run(|conn| {
+                conn.query_row("SELECT COALESCE(MAX(id), 0) FROM events", [], |row| {
+                    row.get(0)
+                })
+            })
+            .await?)
+    }
+
+    /// The persisted events after `id`, oldest first.
+    pub async fn since(connections: &Connections, id: u64) -> Result<Vec<ChangeEvent>> {
+        let db = Self::connection(connections).await?;
+        db.run(move |conn| -> Result<Vec<ChangeEvent>> {
+            conn.prepare("SELECT * FROM events WHERE id > ? ORDER BY id")?
+                .query_map(params![id], ChangeEvent::try_from_row)?
+                .map(|v| v.map_err(ApiError::from))
+                .collect()
+        })
+        .await
+    }
+}
+
+/// Records changes in the `events` table.
+///
+/// Events are recorded in the transaction that makes the change, so both are committed or neither is.
+#[derive(Debug, Clone, Copy)]
+pub struct EventLog {
+    backlog: u32,
+}
+
+impl EventLog {
+    /// Record a change, the event should be sent with `Events::send` after the commit.
+    pub fn record<T: Into<String>>(
+        &self,
+        conn: &Connection,
+        resource: Resource,
+        action: Action,
+        target: T,
+    ) -> Result<ChangeEvent> {
+        let event = conn
+            .prepare_cached(
+                "INSERT INTO events (resource, action, target) VALUES (?1, ?2, ?3) RETURNING *",
+            )?
+            .query_row(
+                params![
+                    <&'static str>::from(resource),
+                    <&'static str>::from(action),
+                    target.into()
+                ],
+                ChangeEvent::try_from_row,
+            )?;
+
+        self.trim(conn, event.id)?;
+
+        Ok(event)
+    }
+
+    /// Record the same change to many resources with a single insert.
+    pub fn record_all(
+        &self,
+        conn: &Connection,
+        resource: Resource,
+        action: Action,
+        targets: &[String],
+    ) -> Result<Vec<ChangeEvent>> {
+        if targets.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let targets =
+            json::to_string(&targets).map_err(|_| ApiError::Status(Status::InternalServerError))?;
+
+        let mut events = conn
+            .prepare_cached(
+                "INSERT INTO events (resource, action, target)
+                SELECT ?1, ?2, value FROM json_each(?3) ORDER BY key
+                RETURNING *",
+            )?
+            .query_map(
+                params![
+                    <&'static str>::from(resource),
+                    <&'static str>::from(action),
+                    targets
+                ],
+                ChangeEvent::try_from_row,
+            )?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        events.sort_by_key(|event| event.id);
+
+        if let Some(last) = events.last() {
+            self.trim(conn, last.id)?;
+        }
+
+        Ok(events)
+    }
+
+    /// Drop the events that fell out of the backlog.
+    fn trim(&self, conn: &Connection, last_id: u64) -> Result<()> {
+        conn.prepare_cached("DELETE FROM events WHERE id <= ?")?
+            .execute(params![last_id.saturating_sub(self.backlog as u64)])?;
+
+        Ok(())
+    }
+}
+
+/// The events after some id, in the order of their ids.
+pub struct Subscription {
+    connections: Connections,
+    receiver: broadcast::Receiver<ChangeEvent>,
+    last_id: u64,
+    // the events after `last_id` have to be read from the database
+    behind: bool,
+    // an event sent before an earlier one, in case the earlier one is gone from the database
+    overtaking: Option<ChangeEvent>,
+}
+
+impl Subscription {
+    /// The next events, `None` once there will be no more.
+    ///
+    /// This is cancel safe, nothing is missed if it's dropped before it completes.
+    pub async fn next(&mut self) -> Result<Option<Vec<ChangeEvent>>> {
+        loop {
+            if self.behind {
+                let mut missed = Events::since(&self.connections, self.last_id).await?;
+                self.behind = false;
+
+                // the event that overtook the others might already be gone from the backlog
+                if let Some(overtaking) = self.overtaking.take() {
+                    if !missed.iter().any(|event| event.id == overtaking.id) {
+                        missed.push(overtaking);
+                        missed.sort_by_key(|event| event.id);
+                    }
+                }
+
+                if let Some(last) = missed.last() {
+                    self.last_id = last.id;
+                    return Ok(Some(missed));
+                }
+                continue;
+            }
+
+            match self.receiver.recv().await {
+                Ok(event) if event.id <= self.last_id => {}
+                Ok(event) if event.id == self.last_id + 1 => {
+                    self.last_id = event.id;
+                    return Ok(Some(vec![event]));
+                }
+                // events are sent after their commits, so a later one can overtake an earlier one
+                Ok(event) => {
+                    self.overtaking = Some(event);
+                    self.behind = true;
+                }
+                // we fell behind, catch up from the database
+                Err(RecvError::Lagged(_)) => self.behind = true,
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Change Events", |rocket| async {
+        let config: EventsConfig = rocket.figment().extract_inner("events").unwrap_or_default();
+
+        rocket.manage(Events::new(config))
+    })
+}
@@ -0,0 +1,110 @@
+use rocket_sync_db_pools::rusqlite::{self, Connection};
+use uuid::Uuid;
+
+use crate::api::data::{
+    events::Resource,
+    external_ids::{ExternalId, ExternalSource},
+    permissions::Permission,
+};
+
+/// The column of `external_ids` that links to a resource, `None` if it can't have external ids.
+pub fn column(resource: Resource) -> Option<&'static str> {
+    match resource {
+        Resource::Artist => Some("artist_id"),
+        Resource::Album => Some("album_id"),
+        Resource::Track => Some("track_id"),
+        _ => None,
+    }
+}
+
+/// The permission needed to change an artist's, album's or track's ids.
+pub fn write_permission(resource: Resource) -> Permission {
+    match resource {
+        Resource::Artist => Permission::ArtistWrite,
+        Resource::Album => Permission::AlbumWrite,
+        _ => Permission::TrackWrite,
+    }
+}
+
+/// Whether a source has ids for a kind of resource.
+pub fn accepts(source: ExternalSource, resource: Resource) -> bool {
+    matches!(
+        (source, resource),
+        (
+            ExternalSource::Musicbrainz,
+            Resource::Artist | Resource::Album | Resource::Track
+        ) | (ExternalSource::Isrc, Resource::Track)
+            | (ExternalSource::Upc, Resource::Album)
+            | (ExternalSource::Discogs, Resource::Artist | Resource::Album)
+    )
+}
+
+/// An id in the form it's stored in, `None` if it isn't a valid id of the source.
+pub fn normalize(source: ExternalSource, value: &str) -> Option<String> {
+    let value = value.trim();
+    match source {
+        ExternalSource::Musicbrainz => Uuid::try_parse(value)
+            .ok()
+            .map(|mbid| mbid.hyphenated().to_string()),
+        ExternalSource::Isrc => isrc(value),
+        ExternalSource::Upc => upc(value),
+        ExternalSource::Discogs => value
+            .parse::<u64>()
+            .ok()
+            .filter(|id| *id > 0 && !value.starts_with('+'))
+            .map(|id| id.to_string()),
+    }
+}
+
+/// `CC-XXX-YY-NNNNN`: a country code, an alphanumeric registrant code, the year & a designation code.
+fn isrc(value: &str) -> Option<String> {
+    let isrc = value.replace('-', "").to_ascii_uppercase();
+    let bytes = isrc.as_bytes();
+    (bytes.len() == 12
+        && bytes[..2].iter().all(u8::is_ascii_uppercase)
+        && bytes[2..5].iter().all(u8::is_ascii_alphanumeric)
+        && bytes[5..].iter().all(u8::is_ascii_digit))
+    .then_some(isrc)
+}
+
+/// A UPC-A (12 digits) or EAN-13 barcode with a valid check digit, as an EAN-13.
+fn upc(value: &str) -> Option<String> {
+    let digits = value.replace([' ', '-'], "");
+    if !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let ean = match digits.len() {
+        12 => format!("0{digits}"),
+        13 => digits,
+        _ => return None,
+    };
+
+    // GS1: the digits are weighted 1, 3, 1, ... from the left of an EAN-13
+    let sum: u32 = ean.bytes().take(12).enumerate().fold(0, |sum, (i, digit)| {
+        sum + (digit - b'0') as u32 * if i % 2 == 0 { 1 } else { 3 }
+    });
+    let check = (10 - sum % 10) % 10;
+    ((ean.as_bytes()[12] - b'0') as u32 == check).then_some(ean)
+}
+
+/// ` AND <table>.id IN (...)`, the resources with an id from `source`, bind the normalized id.
+pub fn filter(resource: Resource, source: ExternalSource) -> String {
+    let (table, column) = match resource {
+        Resource::Artist => ("artists", "artist_id"),
+        Resource::Album => ("albums", "album_id"),
+        _ => ("tracks", "track_id"),
+    };
+    format!(
+        " AND {table}.id IN (SELECT {column} FROM external_ids WHERE source = '{}' AND value = ?)",
+        <&'static str>::from(source)
+    )
+}
+
+/// A resource's external ids, by source.
+pub fn read(conn: &Connection, column: &str, id: &str) -> rusqlite::Result<Vec<ExternalId>> {
+    conn.prepare(&format!(
+        "SELECT source, value FROM external_ids WHERE {column} = ? ORDER BY source, value"
+    ))?
+    .query_map([id], ExternalId::try_from_row)?
+    .collect()
+}
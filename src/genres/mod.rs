@@ -0,0 +1,139 @@
+use rocket::http::Status;
+use rocket_sync_db_pools::rusqlite::{self, params, Connection, OptionalExtension};
+
+use crate::{api::data::genres::Genre, error::ApiError};
+
+/// Insert a genre, `409 Conflict` if the name is already an alias.
+pub fn create(conn: &Connection, id: &str) -> Result<(), ApiError> {
+    let aliased: bool = conn
+        .prepare_cached("SELECT EXISTS(SELECT 1 FROM genre_aliases WHERE alias = ?)")?
+        .query_row(params![id], |row| row.get(0))?;
+    if aliased {
+        Err(ApiError::RusqliteError((
+            Status::Conflict,
+            "Genre Is An Alias".to_string(),
+        )))?
+    }
+
+    conn.prepare_cached("INSERT INTO genres (id) VALUES (?1)")?
+        .execute(params![id])?;
+
+    Ok(())
+}
+
+/// The genre a name refers to, the genre with that id or the genre it's an alias of.
+pub fn resolve(conn: &Connection, name: &str) -> rusqlite::Result<Option<String>> {
+    conn.prepare_cached(
+        "SELECT id FROM genres WHERE id = ?1
+        UNION ALL SELECT genre_id FROM genre_aliases WHERE alias = ?1 LIMIT 1",
+    )?
+    .query_row([name], |row| row.get(0))
+    .optional()
+}
+
+/// Whether a name is taken by a genre or an alias, case insensitively.
+pub fn taken(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM genres WHERE id = ?1 COLLATE NOCASE)
+        OR EXISTS(SELECT 1 FROM genre_aliases WHERE alias = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+}
+
+/// A subquery of the genres `count` names refer to, & everything below them in the hierarchy with `descendants`.
+///
+/// Bind one name per `?`.
+pub fn filter(count: usize, descendants: bool) -> String {
+    if count == 0 {
+        return "()".to_string();
+    }
+
+    let names = vec!["(?)"; count].join(", ");
+    let descendants = match descendants {
+        true => " UNION SELECT genre_parents.genre_id FROM genre_parents JOIN matched ON genre_parents.parent_id = matched.id",
+        false => "",
+    };
+    format!(
+        "(WITH RECURSIVE names(name) AS (VALUES {names}),
+        matched(id) AS (SELECT COALESCE(genre_aliases.genre_id, names.name) FROM names
+            LEFT JOIN genre_aliases ON genre_aliases.alias = names.name{descendants})
+        SELECT id FROM matched)"
+    )
+}
+
+/// Whether making `parent` a parent of `genre` would make a cycle, i.e. `parent` is `genre` or below it.
+pub fn cycles(conn: &Connection, genre: &str, parent: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "WITH RECURSIVE descendants(id) AS (SELECT ?1
+            UNION SELECT genre_parents.genre_id FROM genre_parents JOIN descendants ON genre_parents.parent_id = descendants.id)
+        SELECT EXISTS(SELECT 1 FROM descendants WHERE id = ?2)",
+        params![genre, parent],
+        |row| row.get(0),
+    )
+}
+
+/// A genre with its parents, children & aliases.
+pub fn read(conn: &Connection, id: &str) -> rusqlite::Result<Genre> {
+    let list = |sql: &str| -> rusqlite::Result<Vec<String>> {
+        conn.prepare(sql)?
+            .query_map([id], |row| row.get(0))?
+            .collect()
+    };
+
+    Ok(Genre {
+        id: conn.query_row("SELECT id FROM genres WHERE id = ?", [id], |row| row.get(0))?,
+        parents: list("SELECT parent_id FROM genre_parents WHERE genre_id = ? ORDER BY parent_id")?,
+        children: list("SELECT genre_id FROM genre_parents WHERE parent_id = ? ORDER BY genre_id")?,
+        aliases: list("SELECT alias FROM genre_aliases WHERE genre_id = ? ORDER BY alias")?,
+    })
+}
+
+/// Move everything tagged `from` over to `into`, then replace `from` with an alias of `into`.
+///
+/// `from`'s parents & children become `into`'s, unless that would make a cycle.
+pub fn merge(conn: &Connection, from: &str, into: &str) -> rusqlite::Result<()> {
+    for (table, column) in [
+        ("artist_genres", "artist_id"),
+        ("album_genres", "album_id"),
+        ("track_genres", "track_id"),
+    ] {
+        conn.execute(
+            &format!("INSERT OR IGNORE INTO {table} ({column}, genre_id) SELECT {column}, ?2 FROM {table} WHERE genre_id = ?1"),
+            params![from, into],
+        )?;
+    }
+
+    let Genre {
+        parents, children, ..
+    } = read(conn, from)?;
+    for parent in parents {
+        if !cycles(conn, into, &parent)? {
+            conn.execute(
+                "INSERT OR IGNORE INTO genre_parents (genre_id, parent_id) VALUES (?1, ?2)",
+                params![into, parent],
+            )?;
+        }
+    }
+    for child in children {
+        if !cycles(conn, &child, into)? {
+            conn.execute(
+                "INSERT OR IGNORE INTO genre_parents (genre_id, parent_id) VALUES (?1, ?2)",
+                params![child, into],
+            )?;
+        }
+    }
+
+    conn.execute(
+        "UPDATE genre_aliases SET genre_id = ?2 WHERE genre_id = ?1",
+        params![from, into],
+    )?;
+    // the links to `from` go with it
+    conn.execute("DELETE FROM genres WHERE id = ?", [from])?;
+    conn.execute(
+        "INSERT INTO genre_aliases (alias, genre_id) VALUES (?1, ?2)",
+        params![from, into],
+    )?;
+
+    Ok(())
+}
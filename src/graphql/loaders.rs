@@ -0,0 +1,397 @@
+//! Loads what fields refer to by id, one query for each kind of field however many objects have it.
+
+use async_graphql::dataloader::Loader;
+use rocket_sync_db_pools::rusqlite::{self, params_from_iter, Connection, Row};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    api::data::{
+        albums::Album,
+        artists::{Artist, ArtistAlias, ArtistRelation},
+        events::Resource,
+        external_ids::ExternalId,
+        genres::Genre,
+        lyrics::Lyrics,
+        tracks::Track,
+    },
+    database::MyDatabase,
+    external_ids, lyrics,
+};
+
+/// The loader of every key below, shared by a request's fields.
+pub struct Catalog(pub Arc<MyDatabase>);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct GenreId(pub String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ArtistId(pub String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct AlbumId(pub String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct TrackId(pub String);
+
+/// The ids of an artist's albums, which an [`Artist`] doesn't list.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ArtistAlbums(pub String);
+
+/// An artist's other names.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ArtistAliases(pub String);
+
+/// How an artist is related to others, e.g. the groups it's a member of.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ArtistRelations(pub String);
+
+/// How others are related to an artist, e.g. its members.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ArtistRelated(pub String);
+
+/// The ids an artist, album or track has in outside databases.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExternalIds(pub Resource, pub String);
+
+/// A track's lyrics in each language.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct TrackLyrics(pub String);
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+/// The rows of `sql` grouped by its first column, `{}` is replaced by a placeholder for each id.
+fn grouped<T>(
+    conn: &Connection,
+    sql: &str,
+    ids: &[String],
+    from_row: fn(&Row) -> rusqlite::Result<T>,
+) -> rusqlite::Result<HashMap<String, Vec<T>>> {
+    let mut grouped: HashMap<String, Vec<T>> = HashMap::new();
+    for pair in conn
+        .prepare(&sql.replace("{}", &placeholders(ids.len())))?
+        .query_map(params_from_iter(ids), |row| {
+            Ok((row.get(0)?, from_row(row)?))
+        })?
+    {
+        let (id, value) = pair?;
+        grouped.entry(id).or_default().push(value);
+    }
+    Ok(grouped)
+}
+
+/// The second column of `sql` grouped by the first, `{}` is replaced by a placeholder for each id.
+fn pairs(
+    conn: &Connection,
+    sql: &str,
+    ids: &[String],
+) -> rusqlite::Result<HashMap<String, Vec<String>>> {
+    grouped(conn, sql, ids, |row| row.get(1))
+}
+
+/// The rows of a type's `SELECT` where `column` is one of the ids.
+fn rows<T>(
+    conn: &Connection,
+    select: &str,
+    column: &str,
+    ids: &[String],
+    from_row: fn(&Row) -> rusqlite::Result<T>,
+) -> rusqlite::Result<Vec<T>> {
+    conn.prepare(&format!(
+        "{select} AND {column} IN ({}) GROUP BY {column}",
+        placeholders(ids.len())
+    ))?
+    .query_map(params_from_iter(ids), from_row)?
+    .collect()
+}
+
+impl Loader<GenreId> for Catalog {
+    type Value = Genre;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[GenreId]) -> Result<HashMap<GenreId, Genre>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        Ok(self
+            .0
+            .run(move |conn| -> rusqlite::Result<HashMap<GenreId, Genre>> {
+                let mut parents = pairs(
+                    conn,
+                    "SELECT genre_id, parent_id FROM genre_parents WHERE genre_id IN ({}) ORDER BY parent_id",
+                    &ids,
+                )?;
+                let mut children = pairs(
+                    conn,
+                    "SELECT parent_id, genre_id FROM genre_parents WHERE parent_id IN ({}) ORDER BY genre_id",
+                    &ids,
+                )?;
+                let mut aliases = pairs(
+                    conn,
+                    "SELECT genre_id, alias FROM genre_aliases WHERE genre_id IN ({}) ORDER BY alias",
+                    &ids,
+                )?;
+
+                conn.prepare(&format!(
+                    "SELECT id FROM genres WHERE id IN ({})",
+                    placeholders(ids.len())
+                ))?
+                .query_map(params_from_iter(&ids), |row| row.get::<usize, String>(0))?
+                .map(|id| {
+                    let id = id?;
+                    Ok((
+                        GenreId(id.clone()),
+                        Genre {
+                            parents: parents.remove(&id).unwrap_or_default(),
+                            children: children.remove(&id).unwrap_or_default(),
+                            aliases: aliases.remove(&id).unwrap_or_default(),
+                            id,
+                        },
+                    ))
+                })
+                .collect()
+            })
+            .await?)
+    }
+}
+
+impl Loader<ArtistId> for Catalog {
+    type Value = Artist;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[ArtistId]) -> Result<HashMap<ArtistId, Artist>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        let artists = self
+            .0
+            .run(move |conn| {
+                rows(
+                    conn,
+                    Artist::SELECT,
+                    "artists.id",
+                    &ids,
+                    Artist::try_from_row,
+                )
+            })
+            .await?;
+
+        Ok(artists
+            .into_iter()
+            .map(|artist| (ArtistId(artist.id.clone()), artist))
+            .collect())
+    }
+}
+
+impl Loader<AlbumId> for Catalog {
+    type Value = Album;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[AlbumId]) -> Result<HashMap<AlbumId, Album>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        let albums = self
+            .0
+            .run(move |conn| rows(conn, Album::SELECT, "albums.id", &ids, Album::try_from_row))
+            .await?;
+
+        Ok(albums
+            .into_iter()
+            .map(|album| (AlbumId(album.id.clone()), album))
+            .collect())
+    }
+}
+
+impl Loader<TrackId> for Catalog {
+    type Value = Track;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[TrackId]) -> Result<HashMap<TrackId, Track>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        let tracks = self
+            .0
+            .run(move |conn| rows(conn, Track::SELECT, "tracks.id", &ids, Track::try_from_row))
+            .await?;
+
+        Ok(tracks
+            .into_iter()
+            .map(|track| (TrackId(track.id.clone()), track))
+            .collect())
+    }
+}
+
+impl Loader<ArtistAlbums> for Catalog {
+    type Value = Vec<String>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ArtistAlbums],
+    ) -> Result<HashMap<ArtistAlbums, Vec<String>>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        let albums = self
+            .0
+            .run(move |conn| {
+                pairs(
+                    conn,
+                    "SELECT artist_id, album_id FROM artist_albums WHERE artist_id IN ({}) ORDER BY album_id",
+                    &ids,
+                )
+            })
+            .await?;
+
+        Ok(albums
+            .into_iter()
+            .map(|(artist, albums)| (ArtistAlbums(artist), albums))
+            .collect())
+    }
+}
+
+impl Loader<ArtistAliases> for Catalog {
+    type Value = Vec<ArtistAlias>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ArtistAliases],
+    ) -> Result<HashMap<ArtistAliases, Vec<ArtistAlias>>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        let aliases = self
+            .0
+            .run(move |conn| {
+                grouped(
+                    conn,
+                    "SELECT artist_id, name, kind, locale FROM artist_aliases WHERE artist_id IN ({}) ORDER BY name",
+                    &ids,
+                    ArtistAlias::try_from_row,
+                )
+            })
+            .await?;
+
+        Ok(aliases
+            .into_iter()
+            .map(|(artist, aliases)| (ArtistAliases(artist), aliases))
+            .collect())
+    }
+}
+
+impl Loader<ArtistRelations> for Catalog {
+    type Value = Vec<ArtistRelation>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ArtistRelations],
+    ) -> Result<HashMap<ArtistRelations, Vec<ArtistRelation>>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        let relations = self
+            .0
+            .run(move |conn| {
+                grouped(
+                    conn,
+                    "SELECT artist_id, kind, related_id AS artist FROM artist_relations WHERE artist_id IN ({}) ORDER BY kind, related_id",
+                    &ids,
+                    ArtistRelation::try_from_row,
+                )
+            })
+            .await?;
+
+        Ok(relations
+            .into_iter()
+            .map(|(artist, relations)| (ArtistRelations(artist), relations))
+            .collect())
+    }
+}
+
+impl Loader<ArtistRelated> for Catalog {
+    type Value = Vec<ArtistRelation>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ArtistRelated],
+    ) -> Result<HashMap<ArtistRelated, Vec<ArtistRelation>>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        let related = self
+            .0
+            .run(move |conn| {
+                grouped(
+                    conn,
+                    "SELECT related_id, kind, artist_id AS artist FROM artist_relations WHERE related_id IN ({}) ORDER BY kind, artist_id",
+                    &ids,
+                    ArtistRelation::try_from_row,
+                )
+            })
+            .await?;
+
+        Ok(related
+            .into_iter()
+            .map(|(artist, related)| (ArtistRelated(artist), related))
+            .collect())
+    }
+}
+
+impl Loader<ExternalIds> for Catalog {
+    type Value = Vec<ExternalId>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ExternalIds],
+    ) -> Result<HashMap<ExternalIds, Vec<ExternalId>>, Self::Error> {
+        let mut columns: HashMap<Resource, Vec<String>> = HashMap::new();
+        for ExternalIds(resource, id) in keys {
+            columns.entry(*resource).or_default().push(id.clone());
+        }
+
+        // a query for each kind of resource, they're linked by different columns
+        Ok(self
+            .0
+            .run(move |conn| -> rusqlite::Result<HashMap<ExternalIds, Vec<ExternalId>>> {
+                let mut found = HashMap::new();
+                for (resource, ids) in columns {
+                    let Some(column) = external_ids::column(resource) else {
+                        continue;
+                    };
+                    let sql = format!(
+                        "SELECT {column}, source, value FROM external_ids WHERE {column} IN ({{}}) ORDER BY source, value"
+                    );
+                    found.extend(
+                        grouped(conn, &sql, &ids, ExternalId::try_from_row)?
+                            .into_iter()
+                            .map(|(id, external)| (ExternalIds(resource, id), external)),
+                    );
+                }
+                Ok(found)
+            })
+            .await?)
+    }
+}
+
+impl Loader<TrackLyrics> for Catalog {
+    type Value = Vec<Lyrics>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[TrackLyrics],
+    ) -> Result<HashMap<TrackLyrics, Vec<Lyrics>>, Self::Error> {
+        let ids: Vec<String> = keys.iter().map(|key| key.0.clone()).collect();
+
+        // read track by track, the lyrics set on a track stand in for lyrics stored in `und`
+        Ok(self
+            .0
+            .run(
+                move |conn| -> rusqlite::Result<HashMap<TrackLyrics, Vec<Lyrics>>> {
+                    ids.into_iter()
+                        .map(|id| Ok((TrackLyrics(id.clone()), lyrics::read(conn, &id, None)?)))
+                        .collect()
+                },
+            )
+            .await?)
+    }
+}
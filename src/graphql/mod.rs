@@ -0,0 +1,97 @@
+//! The `/graphql` schema, the music library & its users with the permissions of the REST endpoints.
+
+use async_graphql::{
+    dataloader::DataLoader, Context, EmptySubscription, Guard, Request, Response, Schema,
+};
+use rocket::{fairing::AdHoc, serde::Deserialize, tokio};
+use std::sync::Arc;
+
+use crate::{
+    api::data::{permissions::Permission, users::User},
+    audio::Blobs,
+    database::MyDatabase,
+    events::Events,
+};
+
+mod loaders;
+mod mutation;
+mod objects;
+mod query;
+
+use loaders::Catalog;
+use mutation::Mutation;
+use query::Query;
+
+pub type TunaSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// The `graphql` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct GraphqlConfig {
+    /// How deeply fields can be nested.
+    pub depth: usize,
+    /// The most fields a request can select, nested fields included.
+    pub complexity: usize,
+}
+
+impl Default for GraphqlConfig {
+    fn default() -> Self {
+        Self {
+            depth: 8,
+            complexity: 500,
+        }
+    }
+}
+
+/// Rejects a field unless the user has the permission, the one its REST endpoint needs.
+pub struct Requires(pub Permission);
+
+impl Guard for Requires {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if ctx.data::<User>()?.permissions.contains(&self.0) {
+            Ok(())
+        } else {
+            Err(format!("Forbidden requires permission `{}`", self.0).into())
+        }
+    }
+}
+
+/// Run a request as `user`, what its fields refer to is loaded in batches by one [`Catalog`].
+pub async fn execute(
+    schema: &TunaSchema,
+    db: MyDatabase,
+    user: User,
+    events: Events,
+    blobs: Blobs,
+    request: Request,
+) -> Response {
+    let db = Arc::new(db);
+    let catalog = DataLoader::new(Catalog(db.clone()), tokio::spawn);
+
+    schema
+        .execute(
+            request
+                .data(db)
+                .data(user)
+                .data(events)
+                .data(blobs)
+                .data(catalog),
+        )
+        .await
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("GraphQL", |rocket| async {
+        let config: GraphqlConfig = rocket
+            .figment()
+            .extract_inner("graphql")
+            .unwrap_or_default();
+
+        rocket.manage(
+            Schema::build(Query, Mutation, EmptySubscription)
+                .limit_depth(config.depth)
+                .limit_complexity(config.complexity)
+                .finish(),
+        )
+    })
+}
@@ -0,0 +1,774 @@
+use async_graphql::{Context, Guard, InputObject, Object, Result};
+use rocket::http::Status;
+use rocket_sync_db_pools::rusqlite::{params, Connection, Error::QueryReturnedNoRows};
+use std::sync::Arc;
+
+use crate::{
+    api::data::{
+        albums::{self, AlbumKind, ReleaseDate},
+        artists::{self, ArtistAliasKind, ArtistRelationKind},
+        events::{Action, ChangeEvent, Resource},
+        external_ids::ExternalSource,
+        lyrics::LyricLine,
+        permissions::Permission,
+        tracks,
+        users::User,
+    },
+    audio::Blobs,
+    database::MyDatabase,
+    error::ApiError,
+    events::{EventLog, Events},
+    external_ids, genres,
+    graphql::{
+        objects::{Album, Artist, Genre, Track},
+        Requires,
+    },
+    lyrics,
+};
+
+#[derive(InputObject)]
+pub struct ArtistInput {
+    pub id: String,
+    pub name: String,
+    /// The name to sort by, the name itself if not given
+    pub sort_name: Option<String>,
+    #[graphql(default)]
+    pub genres: Vec<String>,
+    #[graphql(default)]
+    pub bio: String,
+}
+
+#[derive(InputObject)]
+pub struct AlbumInput {
+    pub id: String,
+    pub name: String,
+    /// The year, 0 for none, ignored if `releaseDate` is given
+    pub release: Option<u16>,
+    pub release_date: Option<ReleaseDate>,
+    #[graphql(default)]
+    pub kind: AlbumKind,
+    pub label: Option<String>,
+    pub catalog_number: Option<String>,
+    /// The original album this is an edition of
+    pub edition_of: Option<String>,
+    #[graphql(default)]
+    pub artists: Vec<String>,
+    #[graphql(default)]
+    pub tracks: Vec<String>,
+    #[graphql(default)]
+    pub genres: Vec<String>,
+}
+
+#[derive(InputObject)]
+pub struct TrackInput {
+    pub id: String,
+    pub name: String,
+    /// The year, 0 for none, ignored if `releaseDate` is given
+    pub release: Option<u16>,
+    pub release_date: Option<ReleaseDate>,
+    #[graphql(default)]
+    pub albums: Vec<String>,
+    #[graphql(default)]
+    pub lyrics: String,
+    #[graphql(default)]
+    pub genres: Vec<String>,
+}
+
+#[derive(InputObject)]
+pub struct ArtistAliasInput {
+    pub name: String,
+    #[graphql(default)]
+    pub kind: ArtistAliasKind,
+    /// The language & script of the name, e.g. `ja-Latn`
+    pub locale: Option<String>,
+}
+
+#[derive(InputObject)]
+pub struct LyricLineInput {
+    /// Milliseconds from the start of the track, missing if the lyrics aren't synced
+    pub time: Option<u32>,
+    pub text: String,
+}
+
+/// Run the write in a transaction, recording the creation of what it returns in the same one.
+async fn create<T: Send + 'static>(
+    ctx: &Context<'_>,
+    resource: Resource,
+    write: impl FnOnce(&Connection) -> std::result::Result<T, ApiError> + Send + 'static,
+    id: fn(&T) -> String,
+) -> Result<T> {
+    let events = ctx.data::<Events>()?;
+    let log = events.log();
+
+    let (created, event) = ctx
+        .data::<Arc<MyDatabase>>()?
+        .run(move |conn| -> std::result::Result<_, ApiError> {
+            let tx = conn.transaction()?;
+            let created = write(&tx)?;
+            let event = log.record(&tx, resource, Action::Create, id(&created))?;
+            tx.commit()?;
+            Ok((created, event))
+        })
+        .await?;
+
+    events.send([event]);
+
+    Ok(created)
+}
+
+/// Run the write in a transaction, sending the events it records in the same one once it's committed.
+async fn record<T: Send + 'static>(
+    ctx: &Context<'_>,
+    write: impl FnOnce(&Connection, EventLog) -> std::result::Result<(T, Vec<ChangeEvent>), ApiError>
+        + Send
+        + 'static,
+) -> Result<T> {
+    let events = ctx.data::<Events>()?;
+    let log = events.log();
+
+    let (written, recorded) = ctx
+        .data::<Arc<MyDatabase>>()?
+        .run(move |conn| -> std::result::Result<_, ApiError> {
+            let tx = conn.transaction()?;
+            let written = write(&tx, log)?;
+            tx.commit()?;
+            Ok(written)
+        })
+        .await?;
+
+    events.send(recorded);
+
+    Ok(written)
+}
+
+/// Run the change in a transaction, recording it in the same one, returning what it changed.
+async fn change(
+    ctx: &Context<'_>,
+    resource: Resource,
+    action: Action,
+    target: String,
+    write: impl FnOnce(&Connection, &str) -> std::result::Result<(), ApiError> + Send + 'static,
+) -> Result<String> {
+    record(ctx, move |conn, log| {
+        write(conn, &target)?;
+        let event = log.record(conn, resource, action, target)?;
+        Ok((event.target.clone(), vec![event]))
+    })
+    .await
+}
+
+/// Delete a row by its id, `404 Not Found` if there's none.
+fn delete(conn: &Connection, table: &str, id: &str) -> std::result::Result<(), ApiError> {
+    if conn.execute(&format!("DELETE FROM {table} WHERE id = ?"), [id])? == 0 {
+        Err(Status::NotFound)?
+    }
+    Ok(())
+}
+
+/// A `404 Not Found` if there's no row with the id.
+fn exists(conn: &Connection, table: &str, id: &str) -> std::result::Result<(), ApiError> {
+    if let Err(QueryReturnedNoRows) =
+        conn.query_row(&format!("SELECT 1 FROM {table} WHERE id = ?"), [id], |_| {
+            Ok(())
+        })
+    {
+        Err(Status::NotFound)?
+    }
+    Ok(())
+}
+
+/// Resolve a genre's id, a `404 Not Found` if no genre or alias has that name.
+fn resolve(conn: &Connection, name: &str) -> std::result::Result<String, ApiError> {
+    genres::resolve(conn, name)?.ok_or(ApiError::Status(Status::NotFound))
+}
+
+/// The write endpoints, each returning what it created, or the id or username of what it deleted or changed.
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    /// Like `POST /genre/<genre>`
+    #[graphql(guard = "Requires(Permission::GenreWrite)")]
+    async fn create_genre(&self, ctx: &Context<'_>, id: String) -> Result<Genre> {
+        let genre = create(
+            ctx,
+            Resource::Genre,
+            move |conn| {
+                genres::create(conn, &id)?;
+                Ok(genres::read(conn, &id)?)
+            },
+            |genre| genre.id.clone(),
+        )
+        .await?;
+
+        Ok(Genre(genre))
+    }
+
+    /// Like `POST /artist`
+    #[graphql(guard = "Requires(Permission::ArtistWrite)")]
+    async fn create_artist(&self, ctx: &Context<'_>, artist: ArtistInput) -> Result<Artist> {
+        let mut artist = artists::Artist {
+            id: artist.id,
+            name: artist.name,
+            sort_name: artist.sort_name,
+            genres: artist.genres,
+            bio: artist.bio,
+        };
+
+        let artist = create(
+            ctx,
+            Resource::Artist,
+            move |conn| {
+                artist.insert(conn)?;
+                Ok(artist)
+            },
+            |artist| artist.id.clone(),
+        )
+        .await?;
+
+        Ok(Artist(artist))
+    }
+
+    /// Like `POST /album`
+    #[graphql(guard = "Requires(Permission::AlbumWrite)")]
+    async fn create_album(&self, ctx: &Context<'_>, album: AlbumInput) -> Result<Album> {
+        let mut album = albums::Album {
+            id: album.id,
+            name: album.name,
+            release: ReleaseDate::from_fields(album.release, album.release_date)
+                .map_err(|year| format!("invalid release year `{year}`"))?,
+            kind: album.kind,
+            label: album.label,
+            catalog_number: album.catalog_number,
+            edition_of: album.edition_of,
+            editions: Vec::new(),
+            artists: album.artists,
+            tracks: album.tracks,
+            genres: album.genres,
+            loudness: None,
+        };
+
+        let album = create(
+            ctx,
+            Resource::Album,
+            move |conn| {
+                album.insert(conn)?;
+                Ok(album)
+            },
+            |album| album.id.clone(),
+        )
+        .await?;
+
+        Ok(Album(album))
+    }
+
+    /// Like `POST /track`
+    #[graphql(guard = "Requires(Permission::TrackWrite)")]
+    async fn create_track(&self, ctx: &Context<'_>, track: TrackInput) -> Result<Track> {
+        let mut track = tracks::Track {
+            id: track.id,
+            name: track.name,
+            release: ReleaseDate::from_fields(track.release, track.release_date)
+                .map_err(|year| format!("invalid release year `{year}`"))?,
+            duration: 0,
+            albums: track.albums,
+            artists: Vec::new(),
+            lyrics: track.lyrics,
+            genres: track.genres,
+            has_audio: false,
+            loudness: None,
+        };
+
+        let track = create(
+            ctx,
+            Resource::Track,
+            move |conn| {
+                track.insert(conn)?;
+                Ok(track)
+            },
+            |track| track.id.clone(),
+        )
+        .await?;
+
+        Ok(Track(track))
+    }
+
+    /// Like `DELETE /genre/<genre>`
+    #[graphql(guard = "Requires(Permission::GenreDelete)")]
+    async fn delete_genre(&self, ctx: &Context<'_>, id: String) -> Result<String> {
+        change(ctx, Resource::Genre, Action::Delete, id, |conn, id| {
+            delete(conn, "genres", id)
+        })
+        .await
+    }
+
+    /// Like `DELETE /artist/<id>`
+    #[graphql(guard = "Requires(Permission::ArtistDelete)")]
+    async fn delete_artist(&self, ctx: &Context<'_>, id: String) -> Result<String> {
+        change(ctx, Resource::Artist, Action::Delete, id, |conn, id| {
+            delete(conn, "artists", id)
+        })
+        .await
+    }
+
+    /// Like `DELETE /album/<id>`
+    #[graphql(guard = "Requires(Permission::AlbumDelete)")]
+    async fn delete_album(&self, ctx: &Context<'_>, id: String) -> Result<String> {
+        change(ctx, Resource::Album, Action::Delete, id, |conn, id| {
+            delete(conn, "albums", id)
+        })
+        .await
+    }
+
+    /// Like `DELETE /track/<id>`, its audio is deleted too
+    #[graphql(guard = "Requires(Permission::TrackDelete)")]
+    async fn delete_track(&self, ctx: &Context<'_>, id: String) -> Result<String> {
+        let events = ctx.data::<Events>()?;
+        let db = ctx.data::<Arc<MyDatabase>>()?;
+        let recorded = ctx
+            .data::<Blobs>()?
+            .delete_track(db, events.log(), id.clone())
+            .await?;
+        events.send(recorded);
+
+        Ok(id)
+    }
+
+    /// Like `POST /genre/<genre>/parent/<parent>`
+    #[graphql(guard = "Requires(Permission::GenreWrite)")]
+    async fn add_genre_parent(
+        &self,
+        ctx: &Context<'_>,
+        genre: String,
+        parent: String,
+    ) -> Result<String> {
+        record(ctx, move |conn, log| {
+            let genre = resolve(conn, &genre)?;
+            let parent = resolve(conn, &parent)?;
+            if genres::cycles(conn, &genre, &parent)? {
+                Err(ApiError::RusqliteError((
+                    Status::Conflict,
+                    "Genre Cycle".to_string(),
+                )))?
+            }
+
+            conn.execute(
+                "INSERT INTO genre_parents (genre_id, parent_id) VALUES (?1, ?2)",
+                params![genre, parent],
+            )?;
+            let event = log.record(conn, Resource::Genre, Action::Update, genre.clone())?;
+            Ok((genre, vec![event]))
+        })
+        .await
+    }
+
+    /// Like `DELETE /genre/<genre>/parent/<parent>`
+    #[graphql(guard = "Requires(Permission::GenreWrite)")]
+    async fn delete_genre_parent(
+        &self,
+        ctx: &Context<'_>,
+        genre: String,
+        parent: String,
+    ) -> Result<String> {
+        record(ctx, move |conn, log| {
+            let genre = resolve(conn, &genre)?;
+            let parent = resolve(conn, &parent)?;
+            if conn.execute(
+                "DELETE FROM genre_parents WHERE genre_id = ?1 AND parent_id = ?2",
+                params![genre, parent],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            let event = log.record(conn, Resource::Genre, Action::Update, genre.clone())?;
+            Ok((genre, vec![event]))
+        })
+        .await
+    }
+
+    /// Like `POST /genre/<genre>/alias/<alias>`
+    #[graphql(guard = "Requires(Permission::GenreWrite)")]
+    async fn add_genre_alias(
+        &self,
+        ctx: &Context<'_>,
+        genre: String,
+        alias: String,
+    ) -> Result<String> {
+        record(ctx, move |conn, log| {
+            let genre = resolve(conn, &genre)?;
+            if genres::taken(conn, &alias)? {
+                Err(ApiError::RusqliteError((
+                    Status::Conflict,
+                    "Genre Name Taken".to_string(),
+                )))?
+            }
+
+            conn.execute(
+                "INSERT INTO genre_aliases (alias, genre_id) VALUES (?1, ?2)",
+                params![alias, genre],
+            )?;
+            let event = log.record(conn, Resource::Genre, Action::Update, genre.clone())?;
+            Ok((genre, vec![event]))
+        })
+        .await
+    }
+
+    /// Like `DELETE /genre/<genre>/alias/<alias>`
+    #[graphql(guard = "Requires(Permission::GenreWrite)")]
+    async fn delete_genre_alias(
+        &self,
+        ctx: &Context<'_>,
+        genre: String,
+        alias: String,
+    ) -> Result<String> {
+        record(ctx, move |conn, log| {
+            let genre = resolve(conn, &genre)?;
+            if conn.execute(
+                "DELETE FROM genre_aliases WHERE alias = ?1 AND genre_id = ?2",
+                params![alias, genre],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            let event = log.record(conn, Resource::Genre, Action::Update, genre.clone())?;
+            Ok((genre, vec![event]))
+        })
+        .await
+    }
+
+    /// Like `POST /genre/<genre>/merge/<into>`, returns the genre it was merged into
+    #[graphql(guard = "Requires(Permission::GenreWrite).and(Requires(Permission::GenreDelete))")]
+    async fn merge_genre(&self, ctx: &Context<'_>, genre: String, into: String) -> Result<String> {
+        record(ctx, move |conn, log| {
+            exists(conn, "genres", &genre)?;
+            let into = resolve(conn, &into)?;
+            if into == genre {
+                Err(Status::BadRequest)?
+            }
+
+            genres::merge(conn, &genre, &into)?;
+            let recorded = vec![
+                log.record(conn, Resource::Genre, Action::Delete, genre)?,
+                log.record(conn, Resource::Genre, Action::Update, into.clone())?,
+            ];
+            Ok((into, recorded))
+        })
+        .await
+    }
+
+    /// Like `POST /artist/<id>/alias`
+    #[graphql(guard = "Requires(Permission::ArtistWrite)")]
+    async fn add_artist_alias(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        alias: ArtistAliasInput,
+    ) -> Result<String> {
+        change(ctx, Resource::Artist, Action::Update, id, move |conn, id| {
+            exists(conn, "artists", id)?;
+            conn.execute(
+                "INSERT INTO artist_aliases (artist_id, name, kind, locale) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    id,
+                    alias.name,
+                    <&'static str>::from(alias.kind),
+                    alias.locale
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Like `DELETE /artist/<id>/alias/<name>`
+    #[graphql(guard = "Requires(Permission::ArtistWrite)")]
+    async fn delete_artist_alias(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        name: String,
+    ) -> Result<String> {
+        change(
+            ctx,
+            Resource::Artist,
+            Action::Update,
+            id,
+            move |conn, id| {
+                if conn.execute(
+                    "DELETE FROM artist_aliases WHERE artist_id = ?1 AND name = ?2",
+                    params![id, name],
+                )? == 0
+                {
+                    Err(Status::NotFound)?
+                }
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// Like `POST /artist/<id>/relation`, `id` is `kind` `artist`
+    #[graphql(guard = "Requires(Permission::ArtistWrite)")]
+    async fn add_artist_relation(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        kind: ArtistRelationKind,
+        artist: String,
+    ) -> Result<String> {
+        if artist == id {
+            Err(ApiError::Status(Status::BadRequest))?
+        }
+
+        record(ctx, move |conn, log| {
+            exists(conn, "artists", &id)?;
+            exists(conn, "artists", &artist)?;
+
+            // being the same person goes both ways
+            let reversed: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM artist_relations WHERE artist_id = ?1 AND related_id = ?2 AND kind = ?3)",
+                params![artist, id, <&'static str>::from(kind)],
+                |row| row.get(0),
+            )?;
+            if reversed && kind == ArtistRelationKind::SamePersonAs {
+                Err(Status::Conflict)?
+            }
+
+            conn.execute(
+                "INSERT INTO artist_relations (artist_id, related_id, kind) VALUES (?1, ?2, ?3)",
+                params![id, artist, <&'static str>::from(kind)],
+            )?;
+            let recorded = log.record_all(
+                conn,
+                Resource::Artist,
+                Action::Update,
+                &[id.clone(), artist],
+            )?;
+            Ok((id, recorded))
+        })
+        .await
+    }
+
+    /// Like `DELETE /artist/<id>/relation/<related>`, only the relationships of `kind` if given
+    #[graphql(guard = "Requires(Permission::ArtistWrite)")]
+    async fn delete_artist_relation(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        related: String,
+        kind: Option<ArtistRelationKind>,
+    ) -> Result<String> {
+        let kind = kind.map(<&'static str>::from);
+
+        record(ctx, move |conn, log| {
+            if conn.execute(
+                "DELETE FROM artist_relations WHERE artist_id = ?1 AND related_id = ?2 AND (?3 IS NULL OR kind = ?3)",
+                params![id, related, kind],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            let recorded = log.record_all(
+                conn,
+                Resource::Artist,
+                Action::Update,
+                &[id.clone(), related],
+            )?;
+            Ok((id, recorded))
+        })
+        .await
+    }
+
+    /// Like `POST /artist/<id>/merge/<into>`, returns the artist it was merged into
+    #[graphql(guard = "Requires(Permission::ArtistWrite).and(Requires(Permission::ArtistDelete))")]
+    async fn merge_artist(&self, ctx: &Context<'_>, id: String, into: String) -> Result<String> {
+        if id == into {
+            Err(ApiError::Status(Status::BadRequest))?
+        }
+
+        record(ctx, move |conn, log| {
+            exists(conn, "artists", &id)?;
+            exists(conn, "artists", &into)?;
+            crate::artists::merge(conn, &id, &into)?;
+            let recorded = vec![
+                log.record(conn, Resource::Artist, Action::Delete, id)?,
+                log.record(conn, Resource::Artist, Action::Update, into.clone())?,
+            ];
+            Ok((into, recorded))
+        })
+        .await
+    }
+
+    /// Like `POST /<resource>/<id>/external`, needs `ArtistWrite`, `AlbumWrite` or `TrackWrite` for the resource
+    async fn add_external_id(
+        &self,
+        ctx: &Context<'_>,
+        resource: Resource,
+        id: String,
+        source: ExternalSource,
+        value: String,
+    ) -> Result<String> {
+        let column = external_ids::column(resource).ok_or(ApiError::Status(Status::NotFound))?;
+        Requires(external_ids::write_permission(resource))
+            .check(ctx)
+            .await?;
+        if !external_ids::accepts(source, resource) {
+            Err(ApiError::Status(Status::BadRequest))?
+        }
+        let value =
+            external_ids::normalize(source, &value).ok_or(ApiError::Status(Status::BadRequest))?;
+
+        change(ctx, resource, Action::Update, id, move |conn, id| {
+            exists(conn, &format!("{resource}s"), id)?;
+            conn.execute(
+                &format!("INSERT INTO external_ids (source, value, {column}) VALUES (?1, ?2, ?3)"),
+                params![<&'static str>::from(source), value, id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Like `DELETE /<resource>/<id>/external/<source>/<value>`, needs `ArtistWrite`, `AlbumWrite` or `TrackWrite` for the resource
+    async fn delete_external_id(
+        &self,
+        ctx: &Context<'_>,
+        resource: Resource,
+        id: String,
+        source: ExternalSource,
+        value: String,
+    ) -> Result<String> {
+        let column = external_ids::column(resource).ok_or(ApiError::Status(Status::NotFound))?;
+        Requires(external_ids::write_permission(resource))
+            .check(ctx)
+            .await?;
+        let value =
+            external_ids::normalize(source, &value).ok_or(ApiError::Status(Status::NotFound))?;
+
+        change(ctx, resource, Action::Update, id, move |conn, id| {
+            if conn.execute(
+                &format!(
+                    "DELETE FROM external_ids WHERE source = ?1 AND value = ?2 AND {column} = ?3"
+                ),
+                params![<&'static str>::from(source), value, id],
+            )? == 0
+            {
+                Err(Status::NotFound)?
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Like `PUT /track/<id>/lyrics/<lang>` with `format` `json`
+    #[graphql(guard = "Requires(Permission::TrackWrite)")]
+    async fn set_lyrics(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        language: String,
+        lines: Vec<LyricLineInput>,
+    ) -> Result<String> {
+        let language = lyrics::language(&language).ok_or(ApiError::Status(Status::BadRequest))?;
+        let lines: Vec<LyricLine> = lines
+            .into_iter()
+            .map(|LyricLineInput { time, text }| LyricLine { time, text })
+            .collect();
+
+        change(ctx, Resource::Track, Action::Update, id, move |conn, id| {
+            exists(conn, "tracks", id)?;
+            lyrics::write(conn, id, &language, &lines)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Like `DELETE /track/<id>/lyrics/<lang>`
+    #[graphql(guard = "Requires(Permission::TrackWrite)")]
+    async fn delete_lyrics(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        language: String,
+    ) -> Result<String> {
+        let language = lyrics::language(&language).ok_or(ApiError::Status(Status::NotFound))?;
+
+        change(ctx, Resource::Track, Action::Update, id, move |conn, id| {
+            if !lyrics::delete(conn, id, &language)? {
+                Err(Status::NotFound)?
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Like `DELETE /user/<username>`, you don't need `UserDelete` to delete yourself
+    async fn delete_user(&self, ctx: &Context<'_>, username: String) -> Result<String> {
+        let user = ctx.data::<User>()?.clone();
+        change(
+            ctx,
+            Resource::User,
+            Action::Delete,
+            username,
+            move |conn, username| user.delete_user(conn, username),
+        )
+        .await
+    }
+
+    /// Like `POST /permission/<username>`
+    #[graphql(guard = "Requires(Permission::PermissionAdd)")]
+    async fn add_permissions(
+        &self,
+        ctx: &Context<'_>,
+        username: String,
+        permissions: Vec<Permission>,
+    ) -> Result<String> {
+        let user = ctx.data::<User>()?.clone();
+        change(
+            ctx,
+            Resource::User,
+            Action::Update,
+            username,
+            move |conn, username| user.grant(conn, username, &permissions),
+        )
+        .await
+    }
+
+    /// Like `DELETE /permission/<username>`
+    #[graphql(guard = "Requires(Permission::PermissionDelete)")]
+    async fn delete_permissions(
+        &self,
+        ctx: &Context<'_>,
+        username: String,
+        permissions: Vec<Permission>,
+    ) -> Result<String> {
+        let user = ctx.data::<User>()?.clone();
+        change(
+            ctx,
+            Resource::User,
+            Action::Update,
+            username,
+            move |conn, username| user.revoke(conn, username, &permissions),
+        )
+        .await
+    }
+
+    /// Like `PUT /user/<username>/quota`, a `null` quota is unlimited
+    #[graphql(guard = "Requires(Permission::QuotaWrite)")]
+    async fn set_quota(
+        &self,
+        ctx: &Context<'_>,
+        username: String,
+        quota: Option<u64>,
+    ) -> Result<String> {
+        let user = ctx.data::<User>()?.clone();
+        change(
+            ctx,
+            Resource::User,
+            Action::Update,
+            username,
+            move |conn, username| user.set_quota(conn, username, quota),
+        )
+        .await
+    }
+}
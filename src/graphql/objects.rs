@@ -0,0 +1,326 @@
+//! The types of the schema, the REST endpoints' types with the ids they list resolved.
+
+use async_graphql::{dataloader::DataLoader, dataloader::Loader, Context, Object, Result};
+use std::hash::Hash;
+
+use crate::{
+    api::data::{
+        albums::{self, AlbumKind, ReleaseDate},
+        artists::{self, ArtistAlias, ArtistRelationKind},
+        events::Resource,
+        external_ids::ExternalId,
+        genres,
+        lyrics::Lyrics,
+        permissions::Permission,
+        tracks, users,
+    },
+    graphql::{
+        loaders::{
+            AlbumId, ArtistAlbums, ArtistAliases, ArtistId, ArtistRelated, ArtistRelations,
+            Catalog, ExternalIds, GenreId, TrackId, TrackLyrics,
+        },
+        Requires,
+    },
+    lyrics,
+};
+
+/// What the keys refer to, in their order & without the ones that don't exist.
+pub async fn load<K, T>(ctx: &Context<'_>, keys: impl IntoIterator<Item = K>) -> Result<Vec<T>>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    Catalog: Loader<K, Value = T, Error = async_graphql::Error>,
+{
+    let keys: Vec<K> = keys.into_iter().collect();
+    let mut found = ctx
+        .data::<DataLoader<Catalog>>()?
+        .load_many(keys.iter().cloned())
+        .await?;
+
+    Ok(keys.iter().filter_map(|key| found.remove(key)).collect())
+}
+
+async fn genres(ctx: &Context<'_>, ids: &[String]) -> Result<Vec<Genre>> {
+    let genres = load(ctx, ids.iter().cloned().map(GenreId)).await?;
+    Ok(genres.into_iter().map(Genre).collect())
+}
+
+async fn artists(ctx: &Context<'_>, ids: &[String]) -> Result<Vec<Artist>> {
+    let artists = load(ctx, ids.iter().cloned().map(ArtistId)).await?;
+    Ok(artists.into_iter().map(Artist).collect())
+}
+
+async fn albums(ctx: &Context<'_>, ids: &[String]) -> Result<Vec<Album>> {
+    let albums = load(ctx, ids.iter().cloned().map(AlbumId)).await?;
+    Ok(albums.into_iter().map(Album).collect())
+}
+
+async fn tracks(ctx: &Context<'_>, ids: &[String]) -> Result<Vec<Track>> {
+    let tracks = load(ctx, ids.iter().cloned().map(TrackId)).await?;
+    Ok(tracks.into_iter().map(Track).collect())
+}
+
+/// The values of keys that list what an object has, nothing if it has none.
+async fn listed<K, T>(ctx: &Context<'_>, key: K) -> Result<Vec<T>>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    Catalog: Loader<K, Value = Vec<T>, Error = async_graphql::Error>,
+{
+    Ok(ctx
+        .data::<DataLoader<Catalog>>()?
+        .load_one(key)
+        .await?
+        .unwrap_or_default())
+}
+
+pub struct Genre(pub genres::Genre);
+
+#[Object]
+impl Genre {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    /// The genres this is a kind of
+    async fn parents(&self, ctx: &Context<'_>) -> Result<Vec<Genre>> {
+        genres(ctx, &self.0.parents).await
+    }
+
+    /// The genres that are a kind of this
+    async fn children(&self, ctx: &Context<'_>) -> Result<Vec<Genre>> {
+        genres(ctx, &self.0.children).await
+    }
+
+    /// Other names for this genre, matched case insensitively
+    async fn aliases(&self) -> &[String] {
+        &self.0.aliases
+    }
+}
+
+pub struct Artist(pub artists::Artist);
+
+#[Object]
+impl Artist {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The name to sort by, the name itself if not given
+    async fn sort_name(&self) -> Option<&str> {
+        self.0.sort_name.as_deref()
+    }
+
+    async fn bio(&self) -> &str {
+        &self.0.bio
+    }
+
+    #[graphql(guard = "Requires(Permission::GenreRead)")]
+    async fn genres(&self, ctx: &Context<'_>) -> Result<Vec<Genre>> {
+        genres(ctx, &self.0.genres).await
+    }
+
+    #[graphql(guard = "Requires(Permission::AlbumRead)")]
+    async fn albums(&self, ctx: &Context<'_>) -> Result<Vec<Album>> {
+        let ids = listed(ctx, ArtistAlbums(self.0.id.clone())).await?;
+        albums(ctx, &ids).await
+    }
+
+    /// Other names for this artist
+    async fn aliases(&self, ctx: &Context<'_>) -> Result<Vec<ArtistAlias>> {
+        listed(ctx, ArtistAliases(self.0.id.clone())).await
+    }
+
+    /// How this artist is related to others, e.g. the groups it's a member of
+    async fn relations(&self, ctx: &Context<'_>) -> Result<Vec<ArtistRelation>> {
+        let relations = listed(ctx, ArtistRelations(self.0.id.clone())).await?;
+        Ok(relations.into_iter().map(ArtistRelation).collect())
+    }
+
+    /// How others are related to this artist, e.g. its members
+    async fn related(&self, ctx: &Context<'_>) -> Result<Vec<ArtistRelation>> {
+        let related = listed(ctx, ArtistRelated(self.0.id.clone())).await?;
+        Ok(related.into_iter().map(ArtistRelation).collect())
+    }
+
+    /// Its ids in outside databases
+    async fn external_ids(&self, ctx: &Context<'_>) -> Result<Vec<ExternalId>> {
+        listed(ctx, ExternalIds(Resource::Artist, self.0.id.clone())).await
+    }
+}
+
+/// A relationship between two artists, read as "artist is `kind` related artist".
+pub struct ArtistRelation(pub artists::ArtistRelation);
+
+#[Object]
+impl ArtistRelation {
+    async fn kind(&self) -> ArtistRelationKind {
+        self.0.kind
+    }
+
+    /// The other artist
+    async fn artist(&self, ctx: &Context<'_>) -> Result<Option<Artist>> {
+        let artist = artists(ctx, std::slice::from_ref(&self.0.artist)).await?;
+        Ok(artist.into_iter().next())
+    }
+}
+
+pub struct Album(pub albums::Album);
+
+#[Object]
+impl Album {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The year, missing if it's unknown
+    async fn release(&self) -> Option<u16> {
+        self.0.release.map(|date| date.year)
+    }
+
+    /// As precise as it's known, `1997`, `1997-05` or `1997-05-21`
+    async fn release_date(&self) -> Option<ReleaseDate> {
+        self.0.release
+    }
+
+    async fn kind(&self) -> AlbumKind {
+        self.0.kind
+    }
+
+    async fn label(&self) -> Option<&str> {
+        self.0.label.as_deref()
+    }
+
+    async fn catalog_number(&self) -> Option<&str> {
+        self.0.catalog_number.as_deref()
+    }
+
+    /// The original album this is an edition of
+    async fn edition_of(&self, ctx: &Context<'_>) -> Result<Option<Album>> {
+        let original = albums(ctx, self.0.edition_of.as_slice()).await?;
+        Ok(original.into_iter().next())
+    }
+
+    /// The albums that are editions of this one
+    async fn editions(&self, ctx: &Context<'_>) -> Result<Vec<Album>> {
+        albums(ctx, &self.0.editions).await
+    }
+
+    #[graphql(guard = "Requires(Permission::ArtistRead)")]
+    async fn artists(&self, ctx: &Context<'_>) -> Result<Vec<Artist>> {
+        artists(ctx, &self.0.artists).await
+    }
+
+    #[graphql(guard = "Requires(Permission::TrackRead)")]
+    async fn tracks(&self, ctx: &Context<'_>) -> Result<Vec<Track>> {
+        tracks(ctx, &self.0.tracks).await
+    }
+
+    #[graphql(guard = "Requires(Permission::GenreRead)")]
+    async fn genres(&self, ctx: &Context<'_>) -> Result<Vec<Genre>> {
+        genres(ctx, &self.0.genres).await
+    }
+
+    /// Its ids in outside databases
+    async fn external_ids(&self, ctx: &Context<'_>) -> Result<Vec<ExternalId>> {
+        listed(ctx, ExternalIds(Resource::Album, self.0.id.clone())).await
+    }
+}
+
+pub struct Track(pub tracks::Track);
+
+#[Object]
+impl Track {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The year, missing if it's unknown
+    async fn release(&self) -> Option<u16> {
+        self.0.release.map(|date| date.year)
+    }
+
+    /// As precise as it's known, `1997`, `1997-05` or `1997-05-21`
+    async fn release_date(&self) -> Option<ReleaseDate> {
+        self.0.release
+    }
+
+    /// Seconds, 0 until it has audio
+    async fn duration(&self) -> u32 {
+        self.0.duration
+    }
+
+    async fn lyrics(&self) -> &str {
+        &self.0.lyrics
+    }
+
+    /// Its lyrics in each language, only those in `language` if given
+    async fn lyrics_versions(
+        &self,
+        ctx: &Context<'_>,
+        language: Option<String>,
+    ) -> Result<Vec<Lyrics>> {
+        let language = language
+            .map(|language| {
+                lyrics::language(&language).ok_or(format!("invalid language `{language}`"))
+            })
+            .transpose()?;
+        let versions = listed(ctx, TrackLyrics(self.0.id.clone())).await?;
+
+        Ok(versions
+            .into_iter()
+            .filter(|lyrics| {
+                language
+                    .as_ref()
+                    .is_none_or(|language| lyrics.language == *language)
+            })
+            .collect())
+    }
+
+    async fn has_audio(&self) -> bool {
+        self.0.has_audio
+    }
+
+    #[graphql(guard = "Requires(Permission::AlbumRead)")]
+    async fn albums(&self, ctx: &Context<'_>) -> Result<Vec<Album>> {
+        albums(ctx, &self.0.albums).await
+    }
+
+    #[graphql(guard = "Requires(Permission::ArtistRead)")]
+    async fn artists(&self, ctx: &Context<'_>) -> Result<Vec<Artist>> {
+        artists(ctx, &self.0.artists).await
+    }
+
+    #[graphql(guard = "Requires(Permission::GenreRead)")]
+    async fn genres(&self, ctx: &Context<'_>) -> Result<Vec<Genre>> {
+        genres(ctx, &self.0.genres).await
+    }
+
+    /// Its ids in outside databases
+    async fn external_ids(&self, ctx: &Context<'_>) -> Result<Vec<ExternalId>> {
+        listed(ctx, ExternalIds(Resource::Track, self.0.id.clone())).await
+    }
+}
+
+pub struct User(pub users::User);
+
+#[Object]
+impl User {
+    async fn username(&self) -> &str {
+        &self.0.username
+    }
+
+    async fn permissions(&self) -> &[Permission] {
+        &self.0.permissions
+    }
+}
@@ -0,0 +1,107 @@
+use async_graphql::{Context, Object, Result};
+use std::sync::Arc;
+
+use crate::{
+    api::data::{
+        albums::AlbumFilter, artists::ArtistFilter, genres::GenreFilter, permissions::Permission,
+        tracks::TrackFilter, users::UserFilter,
+    },
+    database::MyDatabase,
+    graphql::{
+        loaders::GenreId,
+        objects::{load, Album, Artist, Genre, Track, User},
+        Requires,
+    },
+};
+
+/// The getters of the REST endpoints, with the same filters & an `offset` to page through them.
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Like `GET /genre`
+    #[graphql(guard = "Requires(Permission::GenreRead)")]
+    async fn genres(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: GenreFilter,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<Genre>> {
+        let ids = ctx
+            .data::<Arc<MyDatabase>>()?
+            .run(move |conn| filter.select(conn, limit, offset))
+            .await?;
+
+        let genres = load(ctx, ids.into_iter().map(GenreId)).await?;
+        Ok(genres.into_iter().map(Genre).collect())
+    }
+
+    /// Like `GET /artist`
+    #[graphql(guard = "Requires(Permission::ArtistRead)")]
+    async fn artists(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: ArtistFilter,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<Artist>> {
+        let artists = ctx
+            .data::<Arc<MyDatabase>>()?
+            .run(move |conn| filter.select(conn, limit, offset))
+            .await?;
+
+        Ok(artists.into_iter().map(Artist).collect())
+    }
+
+    /// Like `GET /album`
+    #[graphql(guard = "Requires(Permission::AlbumRead)")]
+    async fn albums(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: AlbumFilter,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<Album>> {
+        let albums = ctx
+            .data::<Arc<MyDatabase>>()?
+            .run(move |conn| filter.select(conn, limit, offset))
+            .await?;
+
+        Ok(albums.into_iter().map(Album).collect())
+    }
+
+    /// Like `GET /track`
+    #[graphql(guard = "Requires(Permission::TrackRead)")]
+    async fn tracks(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: TrackFilter,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<Track>> {
+        let tracks = ctx
+            .data::<Arc<MyDatabase>>()?
+            .run(move |conn| filter.select(conn, limit, offset))
+            .await?;
+
+        Ok(tracks.into_iter().map(Track).collect())
+    }
+
+    /// Like `GET /user`
+    #[graphql(guard = "Requires(Permission::UserRead)")]
+    async fn users(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: UserFilter,
+        limit: Option<u16>,
+        offset: Option<u32>,
+    ) -> Result<Vec<User>> {
+        let users = ctx
+            .data::<Arc<MyDatabase>>()?
+            .run(move |conn| filter.select(conn, limit, offset))
+            .await?;
+
+        Ok(users.into_iter().map(User).collect())
+    }
+}
@@ -0,0 +1,725 @@
+use id3::{Tag, TagLike};
+use rocket::{
+    data::{ByteUnit, ToByteUnit},
+    fairing::AdHoc,
+    http::Status,
+    serde::Deserialize,
+    tokio::{runtime::Handle, task::spawn_blocking},
+};
+use rocket_sync_db_pools::rusqlite::{params, Connection, OptionalExtension};
+use tempfile::TempDir;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, Read, Seek},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    api::data::{
+        albums::ReleaseDate,
+        events::{Action, ChangeEvent, Resource},
+        imports::{ImportEntry, ImportJob, ImportSkip, ImportStatus},
+    },
+    audio::{self, Blobs},
+    database::Connections,
+    error::ApiError,
+    events::{EventLog, Events},
+    lyrics,
+    storage::hash,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `import` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct ImportConfig {
+    /// The server-side directories (and their children) that can be imported.
+    pub roots: Vec<PathBuf>,
+    /// The maximum size of an uploaded archive, before & after it's extracted.
+    pub archive_limit: ByteUnit,
+    /// The number of finished imports kept to be looked up, older ones are forgotten when a new one starts.
+    pub keep: usize,
+}
+
+impl Default for ImportConfig {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            archive_limit: 1.gibibytes(),
+            keep: 100,
+        }
+    }
+}
+
+/// Where the files of an import come from.
+pub enum ImportSource {
+    Directory(PathBuf),
+    /// An uploaded archive extracted to a temporary directory, removed once the import is done.
+    Archive(TempDir),
+}
+
+impl ImportSource {
+    fn path(&self) -> &Path {
+        match self {
+            ImportSource::Directory(path) => path,
+            ImportSource::Archive(dir) => dir.path(),
+        }
+    }
+}
+
+/// Keeps track of running & finished imports.
+pub struct Imports {
+    config: ImportConfig,
+    jobs: Mutex<Jobs>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    by_id: HashMap<String, Arc<Mutex<ImportJob>>>,
+    /// The ids, oldest first.
+    started: VecDeque<String>,
+}
+
+impl Jobs {
+    fn insert(&mut self, job: Arc<Mutex<ImportJob>>) {
+        let id = job.lock().unwrap().id.clone();
+        self.started.push_back(id.clone());
+        self.by_id.insert(id, job);
+    }
+
+    /// Forget the oldest finished jobs, until only `keep` are left.
+    fn evict(&mut self, keep: usize) {
+        let finished =
+            |job: &Arc<Mutex<ImportJob>>| job.lock().unwrap().status != ImportStatus::Running;
+
+        let mut excess = self
+            .by_id
+            .values()
+            .filter(|job| finished(job))
+            .count()
+            .saturating_sub(keep);
+
+        let by_id = &mut self.by_id;
+        self.started.retain(|id| {
+            if excess == 0 || !by_id.get(id).is_some_and(finished) {
+                return true;
+            }
+            by_id.remove(id);
+            excess -= 1;
+            false
+        });
+    }
+}
+
+impl Imports {
+    pub fn config(&self) -> &ImportConfig {
+        &self.config
+    }
+
+    /// Resolve a requested directory, it has to be inside one of the configured roots.
+    pub fn directory(&self, path: &str) -> Result<PathBuf> {
+        let path = Path::new(path)
+            .canonicalize()
+            .map_err(|_| Status::NotFound)?;
+
+        if !path.is_dir() {
+            Err(Status::NotFound)?
+        }
+
+        if !self
+            .config
+            .roots
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .any(|root| path.starts_with(root))
+        {
+            Err(Status::Forbidden)?
+        }
+
+        Ok(path)
+    }
+
+    pub fn get(&self, id: &str) -> Option<ImportJob> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .by_id
+            .get(id)
+            .map(|job| job.lock().unwrap().clone())
+    }
+
+    /// Start importing in the background, the returned job is a snapshot of its initial state.
+    ///
+    /// A connection is only taken for each file's transaction.
+    pub fn start(
+        &self,
+        connections: Connections,
+        events: Events,
+        blobs: Blobs,
+        creator: String,
+        name: String,
+        source: ImportSource,
+        dry_run: bool,
+    ) -> ImportJob {
+        let job = ImportJob::new(Uuid::new_v4().to_string(), creator, name, dry_run);
+        let shared = Arc::new(Mutex::new(job.clone()));
+
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.evict(self.config.keep);
+            jobs.insert(shared.clone());
+        }
+
+        rocket::tokio::spawn(run(connections, events, blobs, shared, source, dry_run));
+
+        job
+    }
+}
+
+/// A reader that fails once more than `remaining` bytes have been read through it.
+struct Capped<'a, R> {
+    inner: R,
+    remaining: &'a mut u64,
+}
+
+impl<R: Read> Read for Capped<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        *self.remaining = self.remaining.checked_sub(read as u64).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                "the extracted archive is larger than the limit",
+            )
+        })?;
+        Ok(read)
+    }
+}
+
+/// Extract an uploaded tar (optionally gzipped) or zip archive to a temporary directory.
+///
+/// More than `limit` extracted bytes is a `413 Payload Too Large`.
+pub async fn extract(archive: PathBuf, limit: ByteUnit) -> Result<TempDir> {
+    spawn_blocking(move || -> io::Result<TempDir> {
+        let dir = tempfile::tempdir()?;
+        let mut file = File::open(archive)?;
+        let mut remaining = limit.as_u64();
+
+        let mut magic = [0u8; 4];
+        let read = file.read(&mut magic)?;
+        file.rewind()?;
+
+        match &magic[..read] {
+            [b'P', b'K', 3, 4] => {
+                let mut zip = zip::ZipArchive::new(file)?;
+                for i in 0..zip.len() {
+                    let mut entry = zip.by_index(i)?;
+                    // entries with absolute paths or `..` would escape the directory
+                    let Some(path) = entry.enclosed_name().map(|name| dir.path().join(name)) else {
+                        continue;
+                    };
+
+                    if entry.is_dir() {
+                        fs::create_dir_all(&path)?;
+                        continue;
+                    }
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+
+                    let mut capped = Capped {
+                        inner: &mut entry,
+                        remaining: &mut remaining,
+                    };
+                    io::copy(&mut capped, &mut File::create(&path)?)?;
+                }
+            }
+            [0x1f, 0x8b, ..] => tar::Archive::new(Capped {
+                inner: flate2::read::GzDecoder::new(file),
+                remaining: &mut remaining,
+            })
+            .unpack(dir.path())?,
+            _ => tar::Archive::new(Capped {
+                inner: file,
+                remaining: &mut remaining,
+            })
+            .unpack(dir.path())?,
+        }
+
+        Ok(dir)
+    })
+    .await
+    .map_err(io::Error::other)?
+    .map_err(|e| match e.kind() {
+        io::ErrorKind::FileTooLarge => {
+            ApiError::IoError((Status::PayloadTooLarge, format!("Archive Error: {e}")))
+        }
+        _ => ApiError::IoError((Status::BadRequest, format!("Archive Error: {e}"))),
+    })
+}
+
+/// Lookup keys for what an import has created, so a dry run can match against it.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Planned {
+    Genre(String),
+    Artist(String),
+    Album(String, Option<String>),
+    Track(String, Option<String>),
+    Audio(String),
+}
+
+#[derive(Default)]
+struct Imported {
+    created: Vec<ImportEntry>,
+    matched: Vec<ImportEntry>,
+    planned: Vec<(Planned, String)>,
+    recorded: Vec<ChangeEvent>,
+}
+
+impl Imported {
+    fn created(&mut self, resource: Resource, id: &str, name: &str, key: Planned) {
+        self.created.push(ImportEntry {
+            resource,
+            id: id.to_string(),
+            name: name.to_string(),
+        });
+        self.planned.push((key, id.to_string()));
+    }
+
+    fn matched(&mut self, resource: Resource, id: &str, name: &str) {
+        self.matched.push(ImportEntry {
+            resource,
+            id: id.to_string(),
+            name: name.to_string(),
+        });
+    }
+}
+
+fn storage_error(e: ApiError) -> String {
+    format!("Storage Error: {e:?}")
+}
+
+/// Read the tags of a single file & create or match everything it belongs to.
+///
+/// Errors are the reason the file was skipped, nothing is written when one is returned.
+///
+/// Runs on a blocking thread with the blob lock held, the storage is reached through `handle`.
+fn import_file(
+    conn: &mut Connection,
+    handle: &Handle,
+    blobs: &Blobs,
+    log: EventLog,
+    uploader: &str,
+    file: &Path,
+    name: &str,
+    planned: &HashMap<Planned, String>,
+    dry_run: bool,
+) -> std::result::Result<Imported, String> {
+    let db_error = |e| format!("Rusqlite Error: {e}");
+
+    if !file
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"))
+    {
+        Err("not an mp3 file")?
+    }
+
+    let tag = id3::no_tag_ok(Tag::read_from_path(file))
+        .map_err(|e| format!("unreadable tags: {e}"))?
+        .unwrap_or_default();
+
+    let title = match tag.title().map(str::trim) {
+        Some(title) if !title.is_empty() => title.to_string(),
+        _ => file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or("no title")?,
+    };
+    let artist = tag
+        .album_artist()
+        .or(tag.artist())
+        .map(str::trim)
+        .filter(|artist| !artist.is_empty());
+    let album = tag.album().map(str::trim).filter(|album| !album.is_empty());
+    let genre = tag
+        .genre_parsed()
+        .map(|genre| genre.trim().to_string())
+        .filter(|genre| !genre.is_empty());
+    // the release or recording date, older tags only have a year
+    let release = tag
+        .date_released()
+        .or(tag.date_recorded())
+        .and_then(|date| ReleaseDate::new(u16::try_from(date.year).ok()?, date.month, date.day))
+        .or_else(|| {
+            tag.year()
+                .and_then(|year| ReleaseDate::new(u16::try_from(year).ok()?, None, None))
+        });
+
+    let tx = conn.transaction().map_err(db_error)?;
+    let mut imported = Imported::default();
+
+    let genre = match genre {
+        None => None,
+        Some(genre) => {
+            let key = Planned::Genre(genre.to_lowercase());
+            let existing = tx
+                .query_row(
+                    "SELECT id FROM genres WHERE id = ?1 COLLATE NOCASE
+                    UNION ALL SELECT genre_id FROM genre_aliases WHERE alias = ?1 LIMIT 1",
+                    params![genre],
+                    |row| row.get::<usize, String>(0),
+                )
+                .optional()
+                .map_err(db_error)?
+                .or_else(|| planned.get(&key).cloned());
+
+            match existing {
+                Some(id) => {
+                    imported.matched(Resource::Genre, &id, &id);
+                    Some(id)
+                }
+                None => {
+                    if !dry_run {
+                        tx.execute("INSERT INTO genres (id) VALUES (?)", params![genre])
+                            .map_err(db_error)?;
+                    }
+                    imported.created(Resource::Genre, &genre, &genre, key);
+                    Some(genre)
+                }
+            }
+        }
+    };
+
+    let artist = match artist {
+        None => None,
+        Some(artist) => {
+            let key = Planned::Artist(artist.to_lowercase());
+            let existing = tx
+                .query_row(
+                    "SELECT id FROM artists WHERE name = ?1 COLLATE NOCASE
+                    UNION ALL SELECT artist_id FROM artist_aliases WHERE name = ?1 COLLATE NOCASE LIMIT 1",
+                    params![artist],
+                    |row| row.get::<usize, String>(0),
+                )
+                .optional()
+                .map_err(db_error)?
+                .or_else(|| planned.get(&key).cloned());
+
+            match existing {
+                Some(id) => {
+                    imported.matched(Resource::Artist, &id, artist);
+                    Some(id)
+                }
+                None => {
+                    let id = Uuid::new_v4().to_string();
+                    if !dry_run {
+                        tx.execute(
+                            "INSERT INTO artists (id, name) VALUES (?1, ?2)",
+                            params![id, artist],
+                        )
+                        .map_err(db_error)?;
+                        if let Some(genre) = &genre {
+                            tx.execute(
+                                "INSERT INTO artist_genres (artist_id, genre_id) VALUES (?1, ?2)",
+                                params![id, genre],
+                            )
+                            .map_err(db_error)?;
+                        }
+                    }
+                    imported.created(Resource::Artist, &id, artist, key);
+                    Some(id)
+                }
+            }
+        }
+    };
+
+    let album = match album {
+        None => None,
+        Some(album) => {
+            let key = Planned::Album(album.to_lowercase(), artist.clone());
+            let existing = tx
+                .query_row(
+                    "SELECT albums.id FROM albums
+                    LEFT JOIN artist_albums ON albums.id = artist_albums.album_id
+                    WHERE albums.name = ?1 COLLATE NOCASE AND (?2 IS NULL OR artist_albums.artist_id = ?2)",
+                    params![album, artist],
+                    |row| row.get::<usize, String>(0),
+                )
+                .optional()
+                .map_err(db_error)?
+                .or_else(|| planned.get(&key).cloned());
+
+            match existing {
+                Some(id) => {
+                    imported.matched(Resource::Album, &id, album);
+                    Some(id)
+                }
+                None => {
+                    let id = Uuid::new_v4().to_string();
+                    if !dry_run {
+                        tx.execute(
+                            "INSERT INTO albums (id, name, release) VALUES (?1, ?2, ?3)",
+                            params![id, album, release],
+                        )
+                        .map_err(db_error)?;
+                        if let Some(artist) = &artist {
+                            tx.execute(
+                                "INSERT INTO artist_albums (artist_id, album_id) VALUES (?1, ?2)",
+                                params![artist, id],
+                            )
+                            .map_err(db_error)?;
+                        }
+                        if let Some(genre) = &genre {
+                            tx.execute(
+                                "INSERT INTO album_genres (album_id, genre_id) VALUES (?1, ?2)",
+                                params![id, genre],
+                            )
+                            .map_err(db_error)?;
+                        }
+                    }
+                    imported.created(Resource::Album, &id, album, key);
+                    Some(id)
+                }
+            }
+        }
+    };
+
+    let key = Planned::Track(title.to_lowercase(), album.clone());
+    let existing = match &album {
+        Some(album) => tx
+            .query_row(
+                "SELECT tracks.id FROM tracks
+                JOIN album_tracks ON tracks.id = album_tracks.track_id
+                WHERE tracks.name = ?1 COLLATE NOCASE AND album_tracks.album_id = ?2",
+                params![title, album],
+                |row| row.get::<usize, String>(0),
+            )
+            .optional()
+            .map_err(db_error)?,
+        // without an album there's no telling which of the tracks with that name the file is
+        None => {
+            if tx
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM tracks WHERE name = ? COLLATE NOCASE)",
+                    params![title],
+                    |row| row.get::<usize, bool>(0),
+                )
+                .map_err(db_error)?
+            {
+                Err(format!(
+                    "no album tag to tell it apart from the existing tracks named {title}"
+                ))?
+            }
+            None
+        }
+    }
+    .or_else(|| planned.get(&key).cloned());
+
+    let track = match existing {
+        Some(id) => {
+            let has_audio = tx
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM track_audio WHERE track_id = ?)",
+                    params![id],
+                    |row| row.get::<usize, bool>(0),
+                )
+                .map_err(db_error)?;
+            if has_audio || planned.contains_key(&Planned::Audio(id.clone())) {
+                Err(format!("{title} already has audio"))?
+            }
+            imported.matched(Resource::Track, &id, &title);
+            id
+        }
+        None => {
+            let id = Uuid::new_v4().to_string();
+            if !dry_run {
+                tx.execute(
+                    "INSERT INTO tracks (id, name, release) VALUES (?1, ?2, ?3)",
+                    params![id, title, release],
+                )
+                .map_err(db_error)?;
+                if let Some(album) = &album {
+                    tx.execute(
+                        "INSERT INTO album_tracks (album_id, track_id) VALUES (?1, ?2)",
+                        params![album, id],
+                    )
+                    .map_err(db_error)?;
+                }
+                if let Some(genre) = &genre {
+                    tx.execute(
+                        "INSERT INTO track_genres (track_id, genre_id) VALUES (?1, ?2)",
+                        params![id, genre],
+                    )
+                    .map_err(db_error)?;
+                }
+            }
+            imported.created(Resource::Track, &id, &title, key);
+            id
+        }
+    };
+
+    imported.created(Resource::Audio, &track, name, Planned::Audio(track.clone()));
+
+    if !dry_run {
+        let open = || -> std::result::Result<_, String> {
+            let source = File::open(file).map_err(|e| format!("IO Error: {e}"))?;
+            Ok(Box::pin(rocket::tokio::fs::File::from_std(source)))
+        };
+        let (size, sha256) = handle.block_on(hash(open()?)).map_err(storage_error)?;
+        audio::check_quota(&tx, uploader, &track, size).map_err(|e| match e {
+            ApiError::IoError((_, message)) => message,
+            e => storage_error(e),
+        })?;
+        let corrupt = audio::is_corrupt(&tx, &sha256).map_err(storage_error)?;
+        handle
+            .block_on(blobs.store(&sha256, open()?, corrupt))
+            .map_err(storage_error)?;
+        audio::link(&tx, &track, &sha256, size, "audio/mpeg", Some(uploader))
+            .map_err(storage_error)?;
+        lyrics::write_tagged(&tx, &track, &tag).map_err(db_error)?;
+        imported.recorded = imported
+            .created
+            .iter()
+            .map(|entry| log.record(&tx, entry.resource, Action::Create, entry.id.clone()))
+            .collect::<Result<_>>()
+            .map_err(storage_error)?;
+        tx.commit().map_err(db_error)?;
+    }
+
+    Ok(imported)
+}
+
+/// Import every file in the source, updating the job as it goes.
+async fn run(
+    connections: Connections,
+    events: Events,
+    blobs: Blobs,
+    job: Arc<Mutex<ImportJob>>,
+    source: ImportSource,
+    dry_run: bool,
+) {
+    let root = source.path().to_path_buf();
+    let walked = spawn_blocking(move || {
+        let mut files = Vec::new();
+        let mut skipped = Vec::new();
+        for entry in WalkDir::new(&root).sort_by_file_name() {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() => files.push(entry.into_path()),
+                Ok(_) => {}
+                Err(e) => skipped.push(ImportSkip {
+                    file: e
+                        .path()
+                        .and_then(|path| path.strip_prefix(&root).ok())
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_default(),
+                    reason: format!("IO Error: {e}"),
+                }),
+            }
+        }
+        (files, skipped)
+    })
+    .await;
+
+    let files = match walked {
+        Ok((files, skipped)) => {
+            let mut job = job.lock().unwrap();
+            job.total = files.len();
+            job.skipped = skipped;
+            files
+        }
+        Err(e) => {
+            let mut job = job.lock().unwrap();
+            job.status = ImportStatus::Failed;
+            job.error = Some(format!("Walk Error: {e}"));
+            return;
+        }
+    };
+
+    // the audio counts towards the quota of whoever started the import
+    let uploader = job.lock().unwrap().creator.clone();
+    let mut planned = HashMap::new();
+    for file in files {
+        let name = file
+            .strip_prefix(source.path())
+            .unwrap_or(&file)
+            .display()
+            .to_string();
+
+        let (result, returned) = {
+            let name = name.clone();
+            let blobs = blobs.clone();
+            let uploader = uploader.clone();
+            let handle = Handle::current();
+            let log = events.log();
+            let _lock = blobs.lock().await;
+            // only held for the file's transaction
+            let Some(db) = connections.get().await else {
+                let mut job = job.lock().unwrap();
+                job.processed += 1;
+                job.skipped.push(ImportSkip {
+                    file: name,
+                    reason: "Database Error: no connection".to_string(),
+                });
+                continue;
+            };
+            db.run(move |conn| {
+                let result = import_file(
+                    &mut conn.0,
+                    &handle,
+                    &blobs,
+                    log,
+                    &uploader,
+                    &file,
+                    &name,
+                    &planned,
+                    dry_run,
+                );
+                (result, planned)
+            })
+            .await
+        };
+        planned = returned;
+
+        let imported = match result {
+            Ok(imported) => imported,
+            Err(reason) => {
+                let mut job = job.lock().unwrap();
+                job.processed += 1;
+                job.skipped.push(ImportSkip { file: name, reason });
+                continue;
+            }
+        };
+
+        events.send(imported.recorded);
+
+        planned.extend(imported.planned);
+
+        let mut job = job.lock().unwrap();
+        job.processed += 1;
+        for entry in imported.matched {
+            if !job.created.contains(&entry) && !job.matched.contains(&entry) {
+                job.matched.push(entry);
+            }
+        }
+        job.created.extend(imported.created);
+    }
+
+    blobs.analyze();
+    job.lock().unwrap().status = ImportStatus::Finished;
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Imports", |rocket| async {
+        let config: ImportConfig = rocket.figment().extract_inner("import").unwrap_or_default();
+
+        rocket.manage(Imports {
+            config,
+            jobs: Mutex::new(Jobs::default()),
+        })
+    })
+}
@@ -0,0 +1,287 @@
+use id3::{
+    frame::{SynchronisedLyricsType, TimestampFormat},
+    Tag,
+};
+use rocket_sync_db_pools::rusqlite::{self, params, Connection};
+
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use crate::api::data::lyrics::{LyricLine, Lyrics};
+
+/// The language of the lyrics set on the track itself & of tags that don't say.
+pub const UNDETERMINED: &str = "und";
+
+/// A language code in lowercase, `None` if it isn't 1 to 35 letters, digits & dashes.
+pub fn language(language: &str) -> Option<String> {
+    (!language.is_empty()
+        && language.len() <= 35
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-'))
+    .then(|| language.to_ascii_lowercase())
+}
+
+fn lyrics(language: String, lines: Vec<LyricLine>) -> Lyrics {
+    Lyrics {
+        language,
+        synced: !lines.is_empty() && lines.iter().all(|line| line.time.is_some()),
+        lines,
+    }
+}
+
+/// Plain lyrics, a line per line.
+pub fn parse_text(text: &str) -> Vec<LyricLine> {
+    text.lines()
+        .map(|line| LyricLine {
+            time: None,
+            text: line.trim_end().to_string(),
+        })
+        .collect()
+}
+
+pub fn to_text(lyrics: &Lyrics) -> String {
+    lyrics.lines.iter().fold(String::new(), |mut text, line| {
+        text.push_str(&line.text);
+        text.push('\n');
+        text
+    })
+}
+
+/// A `mm:ss.xx` timestamp in milliseconds.
+fn parse_time(time: &str) -> Option<u32> {
+    let (minutes, seconds) = time.split_once(':')?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    if minutes.is_empty()
+        || seconds.len() != 2
+        || ![minutes, seconds, fraction]
+            .iter()
+            .all(|part| part.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    // as many digits as there are, `.5` is half a second
+    let fraction = format!("{fraction:0<3}")[..3].parse::<u32>().ok()?;
+    minutes
+        .parse::<u32>()
+        .ok()?
+        .checked_mul(60_000)?
+        .checked_add(seconds.parse::<u32>().ok()? * 1000 + fraction)
+}
+
+/// LRC lyrics, sorted by time.
+///
+/// A line can have more than one timestamp, word timestamps (`<mm:ss.xx>`) are dropped & `[offset:]` is applied.
+/// Lyrics without any timestamps are read as plain text.
+pub fn parse_lrc(text: &str) -> Vec<LyricLine> {
+    let mut offset = 0i64;
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        let mut tagged = false;
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|rest| rest.split_once(']'))
+        {
+            match parse_time(tag.trim()) {
+                Some(time) => times.push(time),
+                None => {
+                    if let Some(value) = tag.strip_prefix("offset:") {
+                        offset = value.trim().parse().unwrap_or(0);
+                    }
+                    tagged = true;
+                }
+            }
+            rest = after;
+        }
+
+        // metadata e.g. `[ar:...]`
+        if times.is_empty() && tagged {
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut words = rest;
+        while let Some(start) = words.find('<') {
+            match words[start..].find('>') {
+                Some(end) if parse_time(&words[start + 1..start + end]).is_some() => {
+                    text.push_str(&words[..start]);
+                    words = &words[start + end + 1..];
+                }
+                _ => break,
+            }
+        }
+        text.push_str(words);
+        let text = text.trim().to_string();
+
+        match times.is_empty() {
+            true => lines.push((None, text)),
+            false => lines.extend(times.into_iter().map(|time| (Some(time), text.clone()))),
+        }
+    }
+
+    if lines.iter().all(|(time, _)| time.is_none()) {
+        return parse_text(text);
+    }
+
+    // a positive offset makes the lyrics come sooner
+    let mut lines = lines
+        .into_iter()
+        .filter_map(|(time, text)| {
+            let time = (time? as i64 - offset).clamp(0, u32::MAX as i64) as u32;
+            Some(LyricLine {
+                time: Some(time),
+                text,
+            })
+        })
+        .collect::<Vec<_>>();
+    lines.sort_by_key(|line| line.time);
+    lines
+}
+
+/// LRC with a `[la:]` tag, unsynced lines are left without timestamps.
+pub fn to_lrc(lyrics: &Lyrics) -> String {
+    let mut lrc = format!("[la:{}]\n", lyrics.language);
+    for line in lyrics.lines.iter() {
+        if let Some(time) = line.time {
+            let _ = write!(
+                lrc,
+                "[{:02}:{:02}.{:02}]",
+                time / 60_000,
+                time / 1000 % 60,
+                time % 1000 / 10
+            );
+        }
+        lrc.push_str(&line.text);
+        lrc.push('\n');
+    }
+    lrc
+}
+
+/// The lyrics in a file's ID3 tag, by language.
+///
+/// Synced lyrics (SYLT) with millisecond timestamps are preferred over unsynced lyrics (USLT) in the same language.
+pub fn from_tag(tag: &Tag) -> Vec<Lyrics> {
+    let tag_language = |lang: &str| {
+        language(lang.trim_end_matches('\0'))
+            .filter(|lang| lang != "xxx")
+            .unwrap_or_else(|| UNDETERMINED.to_string())
+    };
+
+    let mut languages = BTreeMap::new();
+    for synced in tag.synchronised_lyrics().filter(|synced| {
+        synced.timestamp_format == TimestampFormat::Ms
+            && matches!(
+                synced.content_type,
+                SynchronisedLyricsType::Lyrics | SynchronisedLyricsType::Transcription
+            )
+    }) {
+        let mut lines = synced
+            .content
+            .iter()
+            .map(|(time, text)| LyricLine {
+                time: Some(*time),
+                text: text.trim().to_string(),
+            })
+            .collect::<Vec<_>>();
+        lines.sort_by_key(|line| line.time);
+        languages.entry(tag_language(&synced.lang)).or_insert(lines);
+    }
+
+    for unsynced in tag.lyrics() {
+        languages
+            .entry(tag_language(&unsynced.lang))
+            .or_insert_with(|| parse_text(&unsynced.text));
+    }
+
+    languages
+        .into_iter()
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|(language, lines)| lyrics(language, lines))
+        .collect()
+}
+
+/// A track's lyrics, in `language` or in every language.
+///
+/// The lyrics set on the track itself are `und`, unless lyrics in `und` were stored.
+pub fn read(
+    conn: &Connection,
+    track: &str,
+    language: Option<&str>,
+) -> rusqlite::Result<Vec<Lyrics>> {
+    let mut languages = BTreeMap::<String, Vec<LyricLine>>::new();
+    let mut statement = conn.prepare(
+        "SELECT language, time, text FROM track_lyrics
+        WHERE track_id = ?1 AND (?2 IS NULL OR language = ?2) ORDER BY language, line",
+    )?;
+    let mut rows = statement.query(params![track, language])?;
+    while let Some(row) = rows.next()? {
+        languages.entry(row.get(0)?).or_default().push(LyricLine {
+            time: row.get(1)?,
+            text: row.get(2)?,
+        });
+    }
+
+    if !languages.contains_key(UNDETERMINED) && language.is_none_or(|l| l == UNDETERMINED) {
+        let plain: String =
+            conn.query_row("SELECT lyrics FROM tracks WHERE id = ?", [track], |row| {
+                row.get(0)
+            })?;
+        if !plain.trim().is_empty() {
+            languages.insert(UNDETERMINED.to_string(), parse_text(&plain));
+        }
+    }
+
+    Ok(languages
+        .into_iter()
+        .map(|(language, lines)| lyrics(language, lines))
+        .collect())
+}
+
+/// Replace a track's lyrics in one language.
+pub fn write(
+    conn: &Connection,
+    track: &str,
+    language: &str,
+    lines: &[LyricLine],
+) -> rusqlite::Result<()> {
+    delete(conn, track, language)?;
+    for (line, LyricLine { time, text }) in lines.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO track_lyrics (track_id, language, line, time, text) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![track, language, line, time, text],
+        )?;
+    }
+    Ok(())
+}
+
+/// Remove a track's lyrics in one language, returning whether it had any.
+pub fn delete(conn: &Connection, track: &str, language: &str) -> rusqlite::Result<bool> {
+    Ok(conn.execute(
+        "DELETE FROM track_lyrics WHERE track_id = ?1 AND language = ?2",
+        params![track, language],
+    )? > 0)
+}
+
+/// Store the lyrics in a tag, in the languages the track has no lyrics in yet.
+pub fn write_tagged(conn: &Connection, track: &str, tag: &Tag) -> rusqlite::Result<()> {
+    for Lyrics {
+        language, lines, ..
+    } in from_tag(tag)
+    {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM track_lyrics WHERE track_id = ?1 AND language = ?2)",
+            params![track, language],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            write(conn, track, &language, &lines)?;
+        }
+    }
+    Ok(())
+}
+
+/// The ID3 tag of an audio file, if it has one that can be read.
+pub fn read_tag(file: &Path) -> Option<Tag> {
+    id3::no_tag_ok(Tag::read_from_path(file)).ok().flatten()
+}
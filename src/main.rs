@@ -6,9 +6,24 @@ extern crate rocket;
 use std::{fs, path::Path};
 
 mod api;
+mod artists;
+mod audio;
+mod backups;
+mod catalog;
 mod database;
 mod docs;
+mod enrichment;
 mod error;
+mod events;
+mod external_ids;
+mod genres;
+mod graphql;
+mod imports;
+mod lyrics;
+mod rate_limit;
+mod signing;
+mod storage;
+mod webhooks;
 
 #[get("/")]
 fn index() -> &'static str {
@@ -22,8 +37,8 @@ fn create_working_dir() {
             fs::create_dir_all(path).expect("Failed to create directory");
         }
     }
+    // the storage directories come from `Rocket.toml`, so they're created by its fairing
     ensure_dir("./database/sqlite");
-    ensure_dir("./database/audio");
 }
 
 #[launch]
@@ -32,6 +47,17 @@ fn rocket() -> _ {
 
     rocket::build()
         .attach(database::fairing())
+        .attach(signing::fairing())
+        .attach(storage::fairing())
+        .attach(audio::fairing())
+        .attach(rate_limit::fairing())
+        .attach(events::fairing())
+        .attach(webhooks::fairing())
+        .attach(imports::fairing())
+        .attach(catalog::fairing())
+        .attach(enrichment::fairing())
+        .attach(graphql::fairing())
+        .attach(backups::fairing())
         .attach(api::fairing())
         .attach(docs::fairing())
         .mount("/", routes![index])
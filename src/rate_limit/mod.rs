@@ -0,0 +1,398 @@
+use rocket::{
+    fairing::AdHoc,
+    http::{Header, Status},
+    outcome::Outcome,
+    request::{self, FromRequest, Request},
+    serde::Deserialize,
+    tokio::sync::mpsc::{unbounded_channel, UnboundedSender},
+};
+use rocket_sync_db_pools::rusqlite::params;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    database::{Connections, MyDatabase},
+    error::{ApiError, RetryAfter},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The number of buckets kept in memory before idle ones are dropped early.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Seconds between dropping idle buckets.
+const PRUNE_INTERVAL: u64 = 60;
+
+/// A token bucket: `capacity` requests, with one more every `refill` seconds.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BucketConfig {
+    pub capacity: u32,
+    pub refill: f64,
+}
+
+/// The limits for a single route, keyed by its function name in `Rocket.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RouteConfig {
+    pub ip: Option<BucketConfig>,
+    pub username: Option<BucketConfig>,
+}
+
+/// The `rate_limit` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RateLimitConfig {
+    /// Store counters in SQLite so restarts don't reset them.
+    pub persist: bool,
+    /// Failed logins allowed before the backoff kicks in.
+    pub backoff_free: u32,
+    /// The first backoff in seconds, doubled on every further failure.
+    pub backoff_base: f64,
+    /// The longest backoff in seconds, failed logins are forgotten this long after the last one.
+    pub backoff_max: f64,
+    pub routes: HashMap<String, RouteConfig>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            persist: false,
+            backoff_free: 3,
+            backoff_base: 1.0,
+            backoff_max: 900.0,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    tokens: f64,
+    updated: f64,
+    failures: u32,
+    blocked_until: f64,
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+fn retry_after(seconds: f64) -> RetryAfter {
+    RetryAfter(seconds.ceil().max(1.0) as u64)
+}
+
+/// A bucket to store, or `None` to delete it.
+type Change = (String, Option<Bucket>);
+
+/// The in-memory counters shared by every request.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    /// When idle buckets were last dropped, in seconds since the epoch.
+    pruned: AtomicU64,
+    persist: OnceLock<UnboundedSender<Change>>,
+}
+
+impl RateLimiter {
+    fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            pruned: AtomicU64::new(0),
+            persist: OnceLock::new(),
+        }
+    }
+
+    /// Whether forgetting a bucket changes nothing: it has refilled and isn't backing off.
+    fn idle(&self, key: &str, bucket: &Bucket, now: f64) -> bool {
+        if bucket.blocked_until > now {
+            return false;
+        }
+
+        let Some((route, rest)) = key.split_once(':') else {
+            return true;
+        };
+        if route == "login" {
+            // failures are kept until a success, or for `backoff_max` after the last one
+            return bucket.failures == 0 || now - bucket.updated > self.config.backoff_max;
+        }
+
+        let route = self.config.routes.get(route);
+        let config = match rest.split_once(':') {
+            Some(("ip", _)) => route.and_then(|c| c.ip),
+            Some(("username", _)) => route.and_then(|c| c.username),
+            _ => None,
+        };
+        config.is_none_or(|c| {
+            bucket.tokens + (now - bucket.updated) / c.refill >= c.capacity as f64
+        })
+    }
+
+    fn send(&self, change: Change) {
+        if let Some(sender) = self.persist.get() {
+            let _ = sender.send(change);
+        }
+    }
+
+    /// Drop the idle buckets, from the database too when they're persisted.
+    fn prune(&self, buckets: &mut HashMap<String, Bucket>, now: f64) {
+        self.pruned.store(now as u64, Ordering::Relaxed);
+        buckets.retain(|key, bucket| {
+            let idle = self.idle(key, bucket, now);
+            if idle {
+                self.send((key.clone(), None));
+            }
+            !idle
+        });
+    }
+
+    fn update<F, T>(&self, key: String, f: F) -> T
+    where
+        F: FnOnce(&mut Bucket, f64) -> T,
+    {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = now();
+
+        let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+            tokens: f64::MAX,
+            updated: now,
+            ..Default::default()
+        });
+        let result = f(bucket, now);
+        let bucket = *bucket;
+
+        let idle = self.idle(&key, &bucket, now);
+        if idle {
+            buckets.remove(&key);
+        }
+        self.send((key, (!idle).then_some(bucket)));
+
+        let pruned = self.pruned.load(Ordering::Relaxed);
+        if buckets.len() > MAX_BUCKETS || now as u64 >= pruned + PRUNE_INTERVAL {
+            self.prune(&mut buckets, now);
+        }
+
+        result
+    }
+
+    /// Take one token from the bucket for `key`.
+    fn take(&self, key: String, config: BucketConfig) -> std::result::Result<(), RetryAfter> {
+        self.update(key, |bucket, now| {
+            let capacity = config.capacity as f64;
+            let refilled = (now - bucket.updated) / config.refill;
+            bucket.tokens = (bucket.tokens + refilled).min(capacity);
+            bucket.updated = now;
+
+            if bucket.tokens < 1.0 {
+                return Err(retry_after((1.0 - bucket.tokens) * config.refill));
+            }
+
+            bucket.tokens -= 1.0;
+            Ok(())
+        })
+    }
+}
+
+/// A request guard enforcing the limits configured for the current route.
+///
+/// The per-ip bucket is checked when the guard runs, the per-username bucket and
+/// the login backoff are checked by the handler once it has parsed the body.
+pub struct RateLimit<'r> {
+    limiter: &'r RateLimiter,
+    route: &'r str,
+    config: Option<&'r RouteConfig>,
+}
+
+impl<'r> RateLimit<'r> {
+    /// Take a token from the per-username bucket for this route.
+    pub fn username(&self, username: &str) -> Result<()> {
+        let Some(bucket) = self.config.and_then(|c| c.username) else {
+            return Ok(());
+        };
+
+        Ok(self
+            .limiter
+            .take(format!("{}:username:{}", self.route, username), bucket)?)
+    }
+
+    /// Fail if the user is still backing off from previous failed logins.
+    pub fn login_allowed(&self, username: &str) -> Result<()> {
+        self.limiter
+            .update(format!("login:{}", username), |bucket, now| {
+                if bucket.blocked_until > now {
+                    Err(retry_after(bucket.blocked_until - now))?
+                }
+                Ok(())
+            })
+    }
+
+    /// Record a failed login, blocking the user for exponentially longer each time.
+    pub fn login_failed(&self, username: &str) {
+        let config = &self.limiter.config;
+        self.limiter
+            .update(format!("login:{}", username), |bucket, now| {
+                bucket.failures += 1;
+                bucket.updated = now;
+                if bucket.failures > config.backoff_free {
+                    let exponent = (bucket.failures - config.backoff_free - 1).min(32);
+                    let backoff =
+                        (config.backoff_base * 2f64.powi(exponent as i32)).min(config.backoff_max);
+                    bucket.blocked_until = now + backoff;
+                }
+            })
+    }
+
+    /// Forget previous failed logins.
+    pub fn login_succeeded(&self, username: &str) {
+        self.limiter
+            .update(format!("login:{}", username), |bucket, _| {
+                bucket.failures = 0;
+                bucket.blocked_until = 0.0;
+            })
+    }
+}
+
+/// The `Retry-After` of a request rejected by the [`RateLimit`] guard, used by the response fairing.
+struct Rejected(Option<RetryAfter>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RateLimit<'r> {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let Some(limiter) = request.rocket().state::<RateLimiter>() else {
+            return Outcome::Error((
+                Status::InternalServerError,
+                ApiError::Status(Status::InternalServerError),
+            ));
+        };
+
+        let route = request
+            .route()
+            .and_then(|r| r.name.as_deref())
+            .unwrap_or_default();
+        let config = limiter.config.routes.get(route);
+
+        if let (Some(bucket), Some(ip)) = (config.and_then(|c| c.ip), request.client_ip()) {
+            if let Err(e) = limiter.take(format!("{}:ip:{}", route, ip), bucket) {
+                request.local_cache(|| Rejected(Some(e)));
+                return Outcome::Error((Status::TooManyRequests, ApiError::from(e)));
+            }
+        }
+
+        Outcome::Success(RateLimit {
+            limiter,
+            route,
+            config,
+        })
+    }
+}
+
+/// Write a batch of changes in one transaction.
+async fn flush(db: &MyDatabase, changes: Vec<Change>) -> Result<()> {
+    db.run(move |conn| -> Result<()> {
+        let tx = conn.transaction()?;
+        for (key, bucket) in changes {
+            match bucket {
+                Some(bucket) => tx.execute(
+                    "INSERT OR REPLACE INTO rate_limits (key, tokens, updated, failures, blocked_until) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![key, bucket.tokens, bucket.updated, bucket.failures, bucket.blocked_until],
+                )?,
+                None => tx.execute("DELETE FROM rate_limits WHERE key = ?", [key])?,
+            };
+        }
+        tx.commit()?;
+        Ok(())
+    })
+    .await
+}
+
+async fn load(db: &MyDatabase, limiter: &RateLimiter) -> Result<()> {
+    let rows = db
+        .run(|conn| -> Result<Vec<(String, Bucket)>> {
+            conn.prepare("SELECT key, tokens, updated, failures, blocked_until FROM rate_limits")?
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        Bucket {
+                            tokens: row.get(1)?,
+                            updated: row.get(2)?,
+                            failures: row.get(3)?,
+                            blocked_until: row.get(4)?,
+                        },
+                    ))
+                })?
+                .map(|v| v.map_err(ApiError::from))
+                .collect()
+        })
+        .await?;
+
+    limiter.buckets.lock().unwrap().extend(rows);
+    Ok(())
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Rate Limiting", |rocket| async {
+        let config: RateLimitConfig = rocket
+            .figment()
+            .extract_inner("rate_limit")
+            .unwrap_or_default();
+
+        rocket
+            .manage(RateLimiter::new(config))
+            .attach(AdHoc::on_liftoff("Rate Limit Persistence", |rocket| {
+                Box::pin(async move {
+                    let limiter = rocket.state::<RateLimiter>().expect("Manage RateLimiter");
+                    if !limiter.config.persist {
+                        return;
+                    }
+
+                    let connections = Connections::new(rocket);
+                    let db = connections.get().await.expect("Mount Database");
+                    load(&db, limiter)
+                        .await
+                        .expect("Failed to load rate limits");
+                    drop(db);
+
+                    let (sender, mut receiver) = unbounded_channel::<Change>();
+                    let _ = limiter.persist.set(sender);
+
+                    rocket::tokio::spawn(async move {
+                        while let Some(change) = receiver.recv().await {
+                            let mut changes = vec![change];
+                            while let Ok(change) = receiver.try_recv() {
+                                changes.push(change);
+                            }
+
+                            let Some(db) = connections.get().await else {
+                                error!("Failed to persist rate limits: no database connection");
+                                continue;
+                            };
+                            if let Err(e) = flush(&db, changes).await {
+                                error!("Failed to persist rate limits: {:?}", e);
+                            }
+                        }
+                    });
+                })
+            }))
+            .attach(AdHoc::on_response("Rate Limit Headers", |request, response| {
+                Box::pin(async move {
+                    if let Rejected(Some(RetryAfter(seconds))) = request.local_cache(|| Rejected(None)) {
+                        response.set_status(Status::TooManyRequests);
+                        response.set_header(Header::new("Retry-After", seconds.to_string()));
+                    }
+                })
+            }))
+    })
+}
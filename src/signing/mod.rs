@@ -0,0 +1,83 @@
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use rocket::fairing::AdHoc;
+use rocket_sync_db_pools::rusqlite::params;
+use sha2::Sha256;
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::database::MyDatabase;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+/// Signs links so they work without a login until they expire, for clients that can't send the token cookie.
+///
+/// The key is generated once & kept in the database, so links outlive restarts.
+#[derive(Clone)]
+pub struct Signer {
+    key: Arc<Vec<u8>>,
+}
+
+impl Signer {
+    fn mac(&self, payload: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC can take a key of any size");
+        mac.update(payload.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// Sign `payload` for `seconds` from now, returning when it expires & the signature.
+    pub fn sign(&self, payload: &str, seconds: u64) -> (u64, String) {
+        let expires = now().saturating_add(seconds);
+        (expires, self.signature(payload, expires))
+    }
+
+    /// The signature of `payload` until `expires` (unix seconds).
+    pub fn signature(&self, payload: &str, expires: u64) -> String {
+        HEXLOWER.encode(&self.mac(payload, expires).finalize().into_bytes())
+    }
+
+    /// Whether `signature` was made by [`Signer::sign`] for `payload` & hasn't expired.
+    pub fn verify(&self, payload: &str, expires: u64, signature: &str) -> bool {
+        let Ok(signature) = HEXLOWER.decode(signature.as_bytes()) else {
+            return false;
+        };
+        expires >= now() && self.mac(payload, expires).verify_slice(&signature).is_ok()
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Signing", |rocket| async {
+        // after the database fairing's migrations
+        rocket.attach(AdHoc::on_ignite("Signing Key", |rocket| async {
+            let db = MyDatabase::get_one(&rocket).await.expect("Mount Database");
+            let key = db
+                .run(|conn| {
+                    let mut key = vec![0; 32];
+                    rand::thread_rng().fill_bytes(&mut key);
+                    conn.execute(
+                        "INSERT OR IGNORE INTO signing_key (id, key) VALUES (0, ?)",
+                        params![key],
+                    )?;
+                    conn.query_row("SELECT key FROM signing_key WHERE id = 0", [], |row| {
+                        row.get::<usize, Vec<u8>>(0)
+                    })
+                })
+                .await
+                .expect("Failed to load the signing key");
+
+            rocket.manage(Signer { key: Arc::new(key) })
+        }))
+    })
+}
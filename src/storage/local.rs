@@ -0,0 +1,115 @@
+use rocket::{
+    data::ByteUnit,
+    fs::NamedFile,
+    http::{ContentType, Status},
+    tokio::{
+        fs::{self, File},
+        io::{self, AsyncReadExt},
+    },
+};
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use std::path::PathBuf;
+
+use crate::{
+    error::ApiError,
+    storage::{Backend, Download, Reader},
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Files in a directory on the server.
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[rocket::async_trait]
+impl Backend for LocalBackend {
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(fs::try_exists(self.path(key)).await?)
+    }
+
+    async fn put(&self, key: &str, reader: Reader<'_>, limit: ByteUnit) -> Result<u64> {
+        // write next to the file & rename it into place, so it's never seen half written
+        let partial = self.root.join(format!(".{}.partial", Uuid::new_v4()));
+        let written = io::copy(
+            &mut reader.take(limit.as_u64().saturating_add(1)),
+            &mut File::create(&partial).await?,
+        )
+        .await;
+
+        match written {
+            Ok(written) if written <= limit.as_u64() => {
+                let path = self.path(key);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::rename(&partial, path).await?;
+                Ok(written)
+            }
+            result => {
+                fs::remove_file(&partial).await?;
+                result?;
+                Err(Status::PayloadTooLarge)?
+            }
+        }
+    }
+
+    async fn open(&self, key: &str) -> Result<Option<(u64, Reader<'static>)>> {
+        let file = match File::open(self.path(key)).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => Err(e)?,
+        };
+
+        Ok(Some((file.metadata().await?.len(), Box::pin(file))))
+    }
+
+    async fn download(&self, key: &str, content_type: &ContentType) -> Result<Option<Download>> {
+        Ok(NamedFile::open(self.path(key))
+            .await
+            .ok()
+            .map(|file| Download::File((content_type.clone(), file))))
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        match fs::remove_file(self.path(key)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e)?,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let root = self.root.clone();
+        rocket::tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
+            let mut keys = Vec::new();
+            for entry in WalkDir::new(&root).min_depth(1) {
+                let entry = entry.map_err(io::Error::from)?;
+                // partial files are hidden
+                if !entry.file_type().is_file()
+                    || entry.file_name().to_string_lossy().starts_with('.')
+                {
+                    continue;
+                }
+                if let Ok(key) = entry.path().strip_prefix(&root) {
+                    keys.push(key.to_string_lossy().to_string());
+                }
+            }
+            Ok(keys)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
@@ -0,0 +1,189 @@
+use data_encoding::HEXLOWER;
+use rocket::{
+    data::ByteUnit,
+    fairing::AdHoc,
+    fs::NamedFile,
+    http::ContentType,
+    response::Redirect,
+    serde::Deserialize,
+    tokio::io::{AsyncRead, AsyncReadExt},
+};
+use sha2::{Digest, Sha256};
+
+use std::{fs, path::PathBuf, pin::Pin, sync::Arc};
+
+use crate::error::ApiError;
+
+mod local;
+mod s3;
+
+pub use local::LocalBackend;
+pub use s3::{S3Backend, S3Config};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+pub type Reader<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+/// Which backend stores the files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Local,
+    S3,
+}
+
+/// The `storage` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct StorageConfig {
+    pub backend: BackendKind,
+    /// The audio directory, or the key prefix of the audio in the bucket.
+    pub audio: PathBuf,
+    /// The image directory, or the key prefix of the images in the bucket.
+    pub images: PathBuf,
+    /// Required when the backend is `s3`.
+    pub s3: Option<S3Config>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendKind::Local,
+            audio: PathBuf::from("database/audio"),
+            images: PathBuf::from("database/images"),
+            s3: None,
+        }
+    }
+}
+
+/// How a client gets a stored file.
+#[derive(Responder)]
+pub enum Download {
+    File((ContentType, NamedFile)),
+    /// A short-lived link straight to the file, so it doesn't pass through the server.
+    Redirect(Redirect),
+    /// A file changed on the way out, e.g. with a gain applied.
+    Bytes((ContentType, Vec<u8>)),
+}
+
+/// Somewhere files can be kept, addressed by key.
+#[rocket::async_trait]
+pub trait Backend: Send + Sync {
+    async fn exists(&self, key: &str) -> Result<bool>;
+
+    /// Write everything the reader yields to `key`, replacing what was there.
+    ///
+    /// Nothing is replaced if the reader yields more than `limit` bytes, that's a `413 Payload Too Large`.
+    /// Returns the number of bytes written.
+    async fn put(&self, key: &str, reader: Reader<'_>, limit: ByteUnit) -> Result<u64>;
+
+    /// Read a file along with its size.
+    async fn open(&self, key: &str) -> Result<Option<(u64, Reader<'static>)>>;
+
+    /// Respond with a file, served as `content_type`.
+    async fn download(&self, key: &str, content_type: &ContentType) -> Result<Option<Download>>;
+
+    /// Returns whether there was anything to delete.
+    async fn delete(&self, key: &str) -> Result<bool>;
+
+    /// Every stored key, files still being written aren't included.
+    async fn list(&self) -> Result<Vec<String>>;
+}
+
+/// The backends files are kept in.
+#[derive(Clone)]
+pub struct Storage {
+    pub audio: Arc<dyn Backend>,
+}
+
+impl Storage {
+    /// Audio is content-addressed, split into directories by the first byte of the hash.
+    pub fn blob_key(hash: &str) -> String {
+        format!("{}/{hash}", &hash[..2])
+    }
+
+    /// A blob's cached waveform peaks are kept next to it.
+    pub fn peaks_key(hash: &str) -> String {
+        format!("{}.peaks", Self::blob_key(hash))
+    }
+
+    /// As are its HLS segments, the ones of variants transcoded to a `bitrate` (kbit/s) in a directory each.
+    pub fn segment_key(hash: &str, bitrate: u32, segment: u64) -> String {
+        match bitrate {
+            0 => format!("{}.hls/{segment}.mp3", Self::blob_key(hash)),
+            _ => format!("{}.hls/{bitrate}k/{segment}.mp3", Self::blob_key(hash)),
+        }
+    }
+
+    /// The key of the blob a key belongs to, itself for a blob.
+    pub fn blob_of(key: &str) -> &str {
+        key.split_once('.').map_or(key, |(blob, _)| blob)
+    }
+}
+
+/// The size & SHA-256 of everything a reader yields.
+pub async fn hash(mut reader: Reader<'_>) -> Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut buffer).await? {
+            0 => break,
+            read => {
+                hasher.update(&buffer[..read]);
+                size += read as u64;
+            }
+        }
+    }
+
+    Ok((size, HEXLOWER.encode(&hasher.finalize())))
+}
+
+/// The size & SHA-256 of a file, if it exists.
+///
+/// The size is what was read, not what the backend reported, so a truncated file shows up.
+pub async fn digest(backend: &dyn Backend, key: &str) -> Result<Option<(u64, String)>> {
+    let Some((_, reader)) = backend.open(key).await? else {
+        return Ok(None);
+    };
+
+    hash(reader).await.map(Some)
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Storage", |rocket| async {
+        let config: StorageConfig = match rocket.figment().extract_inner("storage") {
+            Ok(config) => config,
+            Err(e) if e.missing() => StorageConfig::default(),
+            Err(e) => {
+                rocket::config::pretty_print_error(e);
+                return Err(rocket);
+            }
+        };
+
+        let audio: Arc<dyn Backend> = match config.backend {
+            BackendKind::Local => {
+                for dir in [&config.audio, &config.images] {
+                    fs::create_dir_all(dir).expect("Failed to create storage directory");
+                }
+                Arc::new(LocalBackend::new(config.audio))
+            }
+            BackendKind::S3 => {
+                let Some(s3) = config.s3 else {
+                    error!("`storage.s3` is required when `storage.backend` is `s3`");
+                    return Err(rocket);
+                };
+                match S3Backend::connect(s3, config.audio).await {
+                    Ok(backend) => Arc::new(backend),
+                    Err(e) => {
+                        error!("Failed to connect to the S3 bucket: {:?}", e);
+                        return Err(rocket);
+                    }
+                }
+            }
+        };
+
+        Ok(rocket.manage(Storage { audio }))
+    })
+}
@@ -0,0 +1,264 @@
+use rocket::{
+    fairing::AdHoc,
+    futures::future::join_all,
+    http::Status,
+    serde::{json, Deserialize},
+    tokio::{select, sync::Notify, time::sleep},
+};
+use rocket_sync_db_pools::rusqlite::params;
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    api::data::{
+        events::{Action, ChangeEvent, Resource},
+        webhooks::signature,
+    },
+    database::{Connections, MyDatabase},
+    error::ApiError,
+    events::Events,
+};
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// The `webhooks` table from `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct WebhooksConfig {
+    /// Attempts before a delivery is given up on.
+    pub max_attempts: u32,
+    /// Seconds before the first retry, doubled after every failed attempt.
+    pub backoff: u64,
+    /// Seconds to wait for a receiver to respond.
+    pub timeout: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: 10,
+            timeout: 10,
+        }
+    }
+}
+
+/// Shared with the endpoints so new deliveries are sent right away.
+pub struct Webhooks {
+    notify: Arc<Notify>,
+}
+
+impl Webhooks {
+    /// Wake the dispatcher to send any due deliveries.
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+}
+
+/// Whether a webhook can subscribe to an event type: `<resource>.<action>`, `<resource>.*` or `*`.
+pub fn subscribable(event: &str) -> bool {
+    event == "*"
+        || event.split_once('.').is_some_and(|(resource, action)| {
+            resource.parse::<Resource>().is_ok()
+                && (action == "*" || action.parse::<Action>().is_ok())
+        })
+}
+
+/// Queue a delivery of the event for every webhook subscribed to it.
+///
+/// Webhooks only receive events their creator is allowed to read.
+async fn fan_out(db: &MyDatabase, event: ChangeEvent) -> Result<()> {
+    let payload =
+        json::to_string(&event).map_err(|_| ApiError::Status(Status::InternalServerError))?;
+
+    db.run(move |conn| {
+        conn.execute(
+            "INSERT INTO webhook_deliveries (webhook_id, event, payload)
+            SELECT webhooks.id, ?1, ?2 FROM webhooks
+            WHERE (NOT EXISTS (SELECT 1 FROM webhook_events WHERE webhook_events.webhook_id = webhooks.id)
+                OR EXISTS (SELECT 1 FROM webhook_events WHERE webhook_events.webhook_id = webhooks.id AND webhook_events.event IN (?1, ?3, '*')))
+            AND EXISTS (SELECT 1 FROM user_permissions WHERE user_permissions.username = webhooks.creator AND user_permissions.id = ?4)",
+            params![
+                event.event_type(),
+                payload,
+                format!("{}.*", event.resource),
+                <&'static str>::from(event.resource.read_permission()),
+            ],
+        )
+    })
+    .await?;
+
+    Ok(())
+}
+
+struct Due {
+    id: u64,
+    url: String,
+    secret: String,
+    event: String,
+    payload: String,
+}
+
+/// A connection that's released as soon as it's dropped.
+async fn connection(connections: &Connections) -> Result<MyDatabase> {
+    connections
+        .get()
+        .await
+        .ok_or(ApiError::Status(Status::ServiceUnavailable))
+}
+
+/// Attempt every delivery whose next attempt is due.
+///
+/// No connection is held while waiting for the receivers.
+async fn deliver_due(
+    connections: &Connections,
+    client: &reqwest::Client,
+    config: &WebhooksConfig,
+) -> Result<()> {
+    let due = connection(connections)
+        .await?
+        .run(|conn| -> Result<Vec<Due>> {
+            conn.prepare(
+                "SELECT webhook_deliveries.id, webhooks.url, webhooks.secret, webhook_deliveries.event, webhook_deliveries.payload
+                FROM webhook_deliveries
+                JOIN webhooks ON webhook_deliveries.webhook_id = webhooks.id
+                WHERE webhook_deliveries.next_attempt <= strftime('%s', 'now')",
+            )?
+            .query_map([], |row| {
+                Ok(Due {
+                    id: row.get(0)?,
+                    url: row.get(1)?,
+                    secret: row.get(2)?,
+                    event: row.get(3)?,
+                    payload: row.get(4)?,
+                })
+            })?
+            .map(|v| v.map_err(ApiError::from))
+            .collect()
+        })
+        .await?;
+
+    let attempts = due.into_iter().map(|due| async move {
+        let response = client
+            .post(&due.url)
+            .header("Content-Type", "application/json")
+            .header("X-Tuna-Event", &due.event)
+            .header("X-Tuna-Delivery", due.id.to_string())
+            .header(
+                "X-Tuna-Signature",
+                signature(&due.secret, due.payload.as_bytes()),
+            )
+            .body(due.payload)
+            .send()
+            .await;
+
+        let (status, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("Receiver responded with {}", response.status())),
+            ),
+            Err(e) => (None, Some(format!("Request Error: {e}"))),
+        };
+
+        (due.id, status, error)
+    });
+
+    let results = join_all(attempts).await;
+    if results.is_empty() {
+        return Ok(());
+    }
+
+    let db = connection(connections).await?;
+    let max_attempts = config.max_attempts;
+    let backoff = config.backoff;
+    for (id, status, error) in results {
+        db.run(move |conn| {
+            conn.execute(
+                "UPDATE webhook_deliveries SET attempts = attempts + 1, status = ?1, error = ?2, delivered = ?3,
+                next_attempt = CASE
+                    WHEN ?3 OR attempts + 1 >= ?4 THEN NULL
+                    ELSE strftime('%s', 'now') + (?5 << attempts)
+                END
+                WHERE id = ?6",
+                params![status, error, error.is_none(), max_attempts, backoff, id],
+            )
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Webhooks", |rocket| async {
+        let config: WebhooksConfig = rocket
+            .figment()
+            .extract_inner("webhooks")
+            .unwrap_or_default();
+        let notify = Arc::new(Notify::new());
+
+        rocket
+            .manage(Webhooks {
+                notify: notify.clone(),
+            })
+            .attach(AdHoc::on_liftoff("Webhook Dispatcher", move |rocket| {
+                Box::pin(async move {
+                    let connections = Connections::new(rocket);
+                    let events = rocket.state::<Events>().expect("Manage Events");
+                    let mut subscription = events
+                        .subscribe(connections.clone(), None)
+                        .await
+                        .expect("Failed to read events");
+                    let mut shutdown = rocket.shutdown();
+                    let client = reqwest::Client::builder()
+                        .timeout(Duration::from_secs(config.timeout))
+                        .build()
+                        .expect("Failed to build webhook client");
+
+                    rocket::tokio::spawn(async move {
+                        // events received while there was no connection to queue them with
+                        let mut pending = Vec::new();
+                        loop {
+                            let received = select! {
+                                received = subscription.next() => match received {
+                                    Ok(Some(received)) => received,
+                                    Ok(None) => break,
+                                    Err(e) => {
+                                        error!("Failed to read events: {:?}", e);
+                                        Vec::new()
+                                    }
+                                },
+                                _ = notify.notified() => Vec::new(),
+                                _ = sleep(Duration::from_secs(1)) => Vec::new(),
+                                _ = &mut shutdown => break,
+                            };
+
+                            pending.extend(received);
+
+                            if !pending.is_empty() {
+                                match connections.get().await {
+                                    Some(db) => {
+                                        for event in pending.drain(..) {
+                                            if let Err(e) = fan_out(&db, event).await {
+                                                error!("Failed to queue webhook deliveries: {:?}", e);
+                                            }
+                                        }
+                                    }
+                                    None => error!(
+                                        "Failed to queue webhook deliveries: no database connection"
+                                    ),
+                                }
+                            }
+
+                            if let Err(e) = deliver_due(&connections, &client, &config).await {
+                                error!("Failed to deliver webhooks: {:?}", e);
+                            }
+                        }
+                    });
+                })
+            }))
+    })
+}
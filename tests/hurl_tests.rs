@@ -1,25 +1,244 @@
+use std::collections::HashMap;
 use std::env;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
 use std::process::{Command, Stdio};
+use std::thread;
+
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
 
 #[test]
 fn hurl_tests() {
+    run_hurl(
+        &[
+            "tests/users.hurl",
+            "tests/invites.hurl",
+            "tests/permissions.hurl",
+            "tests/tokens.hurl",
+            "tests/totp.hurl",
+            "tests/genres.hurl",
+            "tests/genre_hierarchy.hurl",
+            "tests/artists.hurl",
+            "tests/artist_relations.hurl",
+            "tests/albums.hurl",
+            "tests/tracks.hurl",
+            "tests/releases.hurl",
+            "tests/batch.hurl",
+            "tests/external_ids.hurl",
+            "tests/enrichment.hurl",
+            "tests/graphql.hurl",
+            "tests/audio.hurl",
+            "tests/uploads.hurl",
+            "tests/loudness.hurl",
+            "tests/peaks.hurl",
+            "tests/hls.hurl",
+            "tests/shares.hurl",
+            "tests/lyrics.hurl",
+            "tests/duplicates.hurl",
+            "tests/quotas.hurl",
+            "tests/imports.hurl",
+            "tests/catalog.hurl",
+            "tests/events.hurl",
+            "tests/webhooks.hurl",
+            "tests/backups.hurl",
+            "tests/rate_limits.hurl",
+        ],
+        None,
+    );
+}
+
+/// The tests that touch audio, with it stored in an S3 bucket.
+///
+/// Needs an S3-compatible server, e.g. `docker run -p 9000:9000 minio/minio server /data`.
+/// Run with `cargo test -- --ignored`, `TUNA_S3_ENDPOINT` overrides `http://127.0.0.1:9000`.
+#[test]
+#[ignore]
+fn hurl_tests_s3() {
+    let endpoint =
+        env::var("TUNA_S3_ENDPOINT").unwrap_or_else(|_| "http://127.0.0.1:9000".to_string());
+
+    run_hurl(
+        &[
+            "tests/audio.hurl",
+            "tests/uploads.hurl",
+            "tests/loudness.hurl",
+            "tests/peaks.hurl",
+            "tests/hls.hurl",
+            "tests/shares.hurl",
+            "tests/imports.hurl",
+            "tests/catalog.hurl",
+            "tests/backups.hurl",
+        ],
+        Some(format!(
+            r#"{{backend="s3",audio="audio",s3={{endpoint="{endpoint}",bucket="tuna-tests",access_key="minioadmin",secret_key="minioadmin"}}}}"#
+        )),
+    );
+}
+
+/// A request to one of the mock servers.
+struct Request {
+    method: String,
+    target: String,
+    /// By their lowercase names.
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Read a request with a `Content-Length` body, or none.
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split(' ');
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => {
+                headers.insert(name.to_lowercase(), value.trim().to_string());
+            }
+            None => break,
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(Request {
+        method,
+        target,
+        headers,
+        body,
+    })
+}
+
+/// Run the server & then each of the test files against it, `storage` overrides the `storage` config.
+fn run_hurl(tests: &[&str], storage: Option<String>) {
     // Set the HURL_url environment variable
     env::set_var("HURL_url", "http://127.0.0.1:8000");
 
+    // Accept webhook deliveries for the tests, `GET <path>?secret=<secret>` lists the ones POSTed to
+    // the path, with their signature checked against the secret
+    let receiver = TcpListener::bind("127.0.0.1:8001").expect("Failed to bind webhook receiver");
+    env::set_var("HURL_receiver", "http://127.0.0.1:8001");
+    thread::spawn(move || {
+        let mut deliveries: Vec<Request> = Vec::new();
+        for mut stream in receiver.incoming().flatten() {
+            let Some(request) = read_request(&stream) else {
+                continue;
+            };
+
+            let body = match request.method.as_str() {
+                "POST" => {
+                    deliveries.push(request);
+                    Vec::new()
+                }
+                _ => {
+                    let (path, query) = request
+                        .target
+                        .split_once('?')
+                        .unwrap_or((&request.target, ""));
+                    let secret = query.strip_prefix("secret=").unwrap_or_default();
+                    let received: Vec<_> = deliveries
+                        .iter()
+                        .filter(|delivery| delivery.target == path)
+                        .map(|delivery| {
+                            let header = |name| delivery.headers.get(name).cloned();
+                            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                                .expect("HMAC can take a key of any size");
+                            mac.update(&delivery.body);
+                            let signature =
+                                format!("sha256={}", HEXLOWER.encode(&mac.finalize().into_bytes()));
+
+                            json!({
+                                "event": header("x-tuna-event"),
+                                "delivery": header("x-tuna-delivery"),
+                                "content_type": header("content-type"),
+                                "signed": header("x-tuna-signature") == Some(signature),
+                                "body": serde_json::from_slice::<Value>(&delivery.body).ok(),
+                            })
+                        })
+                        .collect();
+                    Value::from(received).to_string().into_bytes()
+                }
+            };
+
+            let _ = stream.write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .as_bytes(),
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    // Serve recorded MusicBrainz responses for enrichment, `tests/musicbrainz/<entity>/<mbid>.json`
+    // for lookups & `tests/musicbrainz/<entity>/search.json` for every search
+    let musicbrainz = TcpListener::bind("127.0.0.1:8002").expect("Failed to bind MusicBrainz mock");
+    thread::spawn(move || {
+        for mut stream in musicbrainz.incoming().flatten() {
+            let mut request = String::new();
+            let _ = BufReader::new(&stream).read_line(&mut request);
+            let target = request.split(' ').nth(1).unwrap_or_default();
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let segments: Vec<&str> = path
+                .trim_start_matches("/ws/2/")
+                .split('/')
+                .filter(|segment| !segment.is_empty() && !segment.contains(".."))
+                .collect();
+
+            let file = match segments[..] {
+                [entity] if query.contains("query=") => {
+                    Some(format!("tests/musicbrainz/{entity}/search.json"))
+                }
+                [entity, mbid] => Some(format!("tests/musicbrainz/{entity}/{mbid}.json")),
+                _ => None,
+            };
+            let (status, body) = match file.and_then(|file| std::fs::read(file).ok()) {
+                Some(body) => ("200 OK", body),
+                None => ("404 Not Found", br#"{"error":"Not Found"}"#.to_vec()),
+            };
+
+            let _ = stream.write_all(
+                format!(
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .as_bytes(),
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+
     // Start cargo in the background
-    let mut cargo_process = Command::new("cargo")
-        .arg("run")
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to start cargo");
+    let mut cargo = Command::new("cargo");
+    cargo.arg("run").stdout(Stdio::piped());
+    // a short backlog, so `tests/events.hurl` can see old events fall out of it
+    cargo.env("ROCKET_EVENTS", "{backlog=5}");
+    if let Some(storage) = &storage {
+        cargo.env("ROCKET_STORAGE", storage);
+    }
+    let mut cargo_process = cargo.spawn().expect("Failed to start cargo");
 
     // capture the PID
     let cargo_pid = cargo_process.id();
 
     // wait for rocket
-    let lines = BufReader::new(match cargo_process.stdout {
-        Some(ref mut v) => v,
+    let mut lines = BufReader::new(match cargo_process.stdout.take() {
+        Some(v) => v,
         None => {
             cargo_process
                 .kill()
@@ -29,7 +248,7 @@ fn hurl_tests() {
     })
     .lines();
 
-    for line in lines {
+    for line in lines.by_ref() {
         match line {
             Ok(line) => {
                 if line.contains("Rocket has launched") {
@@ -45,23 +264,18 @@ fn hurl_tests() {
         }
     }
 
-    // Define the test files
-    let tests = vec![
-        "tests/users.hurl",
-        "tests/invites.hurl",
-        "tests/permissions.hurl",
-        "tests/tokens.hurl",
-        "tests/genres.hurl",
-        "tests/artists.hurl",
-        "tests/albums.hurl",
-        "tests/tracks.hurl",
-        "tests/audio.hurl",
-    ];
+    // keep reading so rocket never blocks on a full pipe
+    thread::spawn(move || lines.for_each(drop));
 
     // Run all application tests
     for test in tests {
-        let status = Command::new("hurl")
-            .arg("--very-verbose")
+        let mut hurl = Command::new("hurl");
+        hurl.arg("--very-verbose");
+        // audio downloads redirect to the bucket
+        if storage.is_some() {
+            hurl.arg("--location");
+        }
+        let status = hurl
             .arg(test)
             .status()
             .expect("Failed to execute hurl command");
@@ -79,4 +293,7 @@ fn hurl_tests() {
     cargo_process
         .kill()
         .unwrap_or_else(|_| panic!("Failed to kill cargo: pid = {}", cargo_pid));
+    cargo_process
+        .wait()
+        .unwrap_or_else(|_| panic!("Failed to wait on cargo: pid = {}", cargo_pid));
 }